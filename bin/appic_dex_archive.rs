@@ -0,0 +1,103 @@
+// The archive canister spawned by the DEX once its event log grows past the configured threshold,
+// see `appic_dex::archive`. It stores the events sent by the DEX and serves them by their index in
+// the event log of the DEX.
+
+use std::cell::RefCell;
+
+use appic_dex::{
+    archive::types::ArchivedEvent,
+    candid_types::events::{CandidEvent, GetEventsArg},
+    icrc3::{event_to_block, MAX_BLOCKS_PER_RESPONSE},
+};
+use candid::Nat;
+use ic_cdk::{query, update};
+use ic_stable_structures::{
+    memory_manager::{MemoryId, MemoryManager, VirtualMemory},
+    BTreeMap, DefaultMemoryImpl,
+};
+use icrc_ledger_types::icrc3::blocks::{BlockWithId, GetBlocksRequest, GetBlocksResult};
+use num_traits::ToPrimitive;
+use serde_bytes::ByteBuf;
+
+const MAX_EVENTS_PER_RESPONSE: u64 = 100;
+
+const EVENTS_MEMORY_ID: MemoryId = MemoryId::new(0);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+
+    static EVENTS: RefCell<BTreeMap<u64, ArchivedEvent, VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(BTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(EVENTS_MEMORY_ID))));
+}
+
+// Stores minicbor encoded events, only the DEX (the controller of the archive) can append.
+// Appending an event that is already stored is a no-op, so batches can be sent again.
+#[update]
+fn append_events(events: Vec<ByteBuf>) {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        panic!("only controllers are allowed to call this method");
+    }
+    EVENTS.with(|stored| {
+        let mut stored = stored.borrow_mut();
+        for bytes in events {
+            let event: ArchivedEvent = minicbor::decode(&bytes)
+                .unwrap_or_else(|e| panic!("failed to decode archived event: {e}"));
+            if !stored.contains_key(&event.index) {
+                stored.insert(event.index, event);
+            }
+        }
+    });
+}
+
+// Archived events with an index in `[start, start + length)`, capped at 100 per response
+#[query]
+fn get_events(args: GetEventsArg) -> Vec<CandidEvent> {
+    let end = args
+        .start
+        .saturating_add(args.length.min(MAX_EVENTS_PER_RESPONSE));
+    EVENTS.with(|stored| {
+        stored
+            .borrow()
+            .range(args.start..end)
+            .map(|(_, archived)| CandidEvent::from(archived.event))
+            .collect()
+    })
+}
+
+// ICRC-3 blocks of the archived events, block ids are the event indexes
+#[query]
+fn icrc3_get_blocks(args: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    EVENTS.with(|stored| {
+        let stored = stored.borrow();
+        let log_length = stored
+            .last_key_value()
+            .map(|(index, _)| index + 1)
+            .unwrap_or_default();
+        let mut remaining = MAX_BLOCKS_PER_RESPONSE;
+        let mut blocks = vec![];
+
+        for request in args {
+            let start = request.start.0.to_u64().unwrap_or(u64::MAX);
+            let length = request.length.0.to_u64().unwrap_or(u64::MAX).min(remaining);
+            for (index, archived) in stored.range(start..start.saturating_add(length)) {
+                blocks.push(BlockWithId {
+                    id: Nat::from(index),
+                    block: event_to_block(&archived.event, archived.parent_hash),
+                });
+                remaining -= 1;
+            }
+        }
+
+        GetBlocksResult {
+            log_length: Nat::from(log_length),
+            blocks,
+            archived_blocks: vec![],
+        }
+    })
+}
+
+fn main() {}
+
+ic_cdk::export_candid!();
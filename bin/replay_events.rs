@@ -0,0 +1,112 @@
+// Rebuilds the state of the DEX from its event log and compares it with a snapshot of the live
+// canister, see `appic_dex::replay`.
+// Every file holds a reply of the canister as printed by `dfx canister call --output raw`:
+//   --events FILE           a `get_events` reply of the DEX or of an archive, files are replayed in
+//                           the given order and the first one should start at event 0
+//   --pools FILE            a `get_pools` reply
+//   --ticks POOL=FILE       a `get_active_ticks` reply, POOL being `token0,token1,fee`
+//   --balances USER=FILE    a `user_balances` reply
+// Exits with 1 when a divergence is found.
+
+use appic_dex::{
+    candid_types::{
+        events::{CandidEvent, GetEventsResult},
+        pool::{CandidPoolId, CandidPoolState},
+        tick::CandidTickInfo,
+        Balance,
+    },
+    replay::{compare_with_snapshot, replay_events, StateSnapshot},
+};
+use candid::{CandidType, Nat, Principal};
+use serde::de::DeserializeOwned;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: replay_events --events FILE... [--pools FILE] [--ticks TOKEN0,TOKEN1,FEE=FILE]... \
+         [--balances USER=FILE]..."
+    );
+    std::process::exit(2);
+}
+
+fn read_reply<T: CandidType + DeserializeOwned>(path: &str) -> Result<T, String> {
+    let reply = std::fs::read_to_string(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+    let bytes = hex::decode(reply.trim()).map_err(|e| format!("{path} is not hex encoded: {e}"))?;
+    candid::decode_one(&bytes).map_err(|e| format!("failed to decode {path}: {e}"))
+}
+
+fn read_events(path: &str) -> Vec<CandidEvent> {
+    // the DEX replies with a page of events, archives with the events only
+    match read_reply::<GetEventsResult>(path) {
+        Ok(result) => {
+            if !result.archived_events.is_empty() {
+                eprintln!(
+                    "warning: {path} refers to archived events, they should be passed before it"
+                );
+            }
+            result.events
+        }
+        Err(_) => read_reply::<Vec<CandidEvent>>(path).unwrap_or_else(|e| panic!("{e}")),
+    }
+}
+
+fn parse_pool_id(pool: &str) -> CandidPoolId {
+    let parts: Vec<&str> = pool.split(',').collect();
+    let [token0, token1, fee] = parts.as_slice() else {
+        usage()
+    };
+    CandidPoolId {
+        token0: Principal::from_text(token0).expect("expected a valid principal"),
+        token1: Principal::from_text(token1).expect("expected a valid principal"),
+        fee: Nat::from(fee.parse::<u32>().expect("expected a fee in pips")),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut events = vec![];
+    let mut snapshot = StateSnapshot::default();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match flag.as_str() {
+            "--events" => events.extend(read_events(value)),
+            "--pools" => {
+                snapshot.pools = read_reply::<Vec<(CandidPoolId, CandidPoolState)>>(value)
+                    .unwrap_or_else(|e| panic!("{e}"))
+            }
+            "--ticks" => {
+                let (pool, path) = value.split_once('=').unwrap_or_else(|| usage());
+                let ticks =
+                    read_reply::<Vec<CandidTickInfo>>(path).unwrap_or_else(|e| panic!("{e}"));
+                snapshot.ticks.push((parse_pool_id(pool), ticks));
+            }
+            "--balances" => {
+                let (user, path) = value.split_once('=').unwrap_or_else(|| usage());
+                let user = Principal::from_text(user).expect("expected a valid principal");
+                let balances = read_reply::<Vec<Balance>>(path).unwrap_or_else(|e| panic!("{e}"));
+                snapshot.balances.push((user, balances));
+            }
+            _ => usage(),
+        }
+    }
+    if events.is_empty() {
+        usage();
+    }
+
+    let mut divergences = replay_events(0, &events);
+    divergences.extend(compare_with_snapshot(&snapshot));
+
+    for divergence in &divergences {
+        println!("{divergence:?}");
+    }
+    println!(
+        "replayed {} events, found {} divergences",
+        events.len(),
+        divergences.len()
+    );
+
+    if !divergences.is_empty() {
+        std::process::exit(1);
+    }
+}
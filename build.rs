@@ -0,0 +1,39 @@
+// Embeds the gzipped wasm of the archive canister spawned by `archive::archive_events`. The path
+// can be overridden with APPIC_DEX_ARCHIVE_WASM_PATH. Release wasm builds fail when the archive
+// wasm is missing, except for the build of the archive itself which sets
+// APPIC_DEX_SKIP_ARCHIVE_WASM, other builds embed an empty file and archiving stays disabled.
+
+use std::{env, fs, path::PathBuf};
+
+const DEFAULT_ARCHIVE_WASM_PATH: &str =
+    "target/wasm32-unknown-unknown/release/appic_dex_archive.wasm.gz";
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=APPIC_DEX_ARCHIVE_WASM_PATH");
+    println!("cargo:rerun-if-env-changed=APPIC_DEX_SKIP_ARCHIVE_WASM");
+
+    let archive_wasm_path = env::var("APPIC_DEX_ARCHIVE_WASM_PATH")
+        .unwrap_or_else(|_| DEFAULT_ARCHIVE_WASM_PATH.to_string());
+    println!("cargo:rerun-if-changed={archive_wasm_path}");
+
+    let is_release_wasm = env::var("PROFILE").is_ok_and(|profile| profile == "release")
+        && env::var("TARGET").is_ok_and(|target| target.starts_with("wasm32"));
+    let skip_archive_wasm = env::var("APPIC_DEX_SKIP_ARCHIVE_WASM").is_ok();
+
+    let out_path = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"))
+        .join("appic_dex_archive.wasm.gz");
+    let wasm = match fs::read(&archive_wasm_path) {
+        Ok(wasm) => wasm,
+        Err(err) if is_release_wasm && !skip_archive_wasm => panic!(
+            "failed to read the archive wasm at {archive_wasm_path}: {err}, build the \
+             appic_dex_archive binary first or set APPIC_DEX_SKIP_ARCHIVE_WASM"
+        ),
+        Err(_) => {
+            println!(
+                "cargo:warning=archive wasm not found at {archive_wasm_path}, archiving is disabled"
+            );
+            vec![]
+        }
+    };
+    fs::write(out_path, wasm).expect("failed to write the archive wasm");
+}
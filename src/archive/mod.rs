@@ -0,0 +1,236 @@
+// a module for moving old events out of the main canister. Once the canister holds more events
+// than the configured threshold, the oldest ones are sent in batches to archive canisters spawned
+// from the wasm embedded in this crate and removed from the main canister. Archives serve
+// `get_events` and `icrc3_get_blocks` for their range, and the main canister returns callbacks to
+// them for the ranges it no longer holds.
+// An archive is recorded as soon as its canister is created, so a failed installation is retried in
+// the same canister by the next run instead of spawning another one. Events are only removed once
+// the archive acknowledged them, appending is idempotent so a batch with an unknown outcome is sent
+// again by the next run.
+
+use std::cell::Cell;
+
+use candid::Principal;
+use ic_canister_log::log;
+use ic_cdk::api::management_canister::main::{
+    create_canister, install_code, CanisterInstallMode, CanisterSettings, CreateCanisterArgument,
+    InstallCodeArgument,
+};
+use serde_bytes::ByteBuf;
+
+use crate::{
+    logs::INFO,
+    state::{mutate_state, read_state},
+};
+use types::{ArchiveConfig, ArchiveInfo, ArchivedEvent};
+
+pub mod types;
+
+#[cfg(test)]
+mod tests;
+
+/// Gzipped wasm of the `appic_dex_archive` binary, empty when the crate was built without it, see
+/// `build.rs`.
+pub static ARCHIVE_WASM: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/appic_dex_archive.wasm.gz"));
+
+thread_local! {
+    static IS_ARCHIVING: Cell<bool> = const { Cell::new(false) };
+}
+
+// makes sure only one archiving run is in flight
+struct ArchivingGuard;
+
+impl ArchivingGuard {
+    fn new() -> Option<Self> {
+        IS_ARCHIVING.with(|is_archiving| {
+            if is_archiving.get() {
+                return None;
+            }
+            is_archiving.set(true);
+            Some(ArchivingGuard)
+        })
+    }
+}
+
+impl Drop for ArchivingGuard {
+    fn drop(&mut self) {
+        IS_ARCHIVING.with(|is_archiving| is_archiving.set(false));
+    }
+}
+
+/// Parts of `[start, start + length)` stored in archives as `(archive, start, length)`, one entry
+/// per archive.
+pub fn archived_ranges(start: u64, length: u64) -> Vec<(Principal, u64, u64)> {
+    let end = start.saturating_add(length);
+    read_state(|s| s.get_archives())
+        .into_iter()
+        .filter_map(|(archive_start, archive)| {
+            let from = start.max(archive_start);
+            let to = end.min(archive.end);
+            (from < to).then_some((archive.canister_id, from, to - from))
+        })
+        .collect()
+}
+
+/// Range of the oldest held events to archive in this run, None below the trigger threshold.
+pub fn events_to_archive(config: &ArchiveConfig) -> Option<(u64, u64)> {
+    let (first_held, held) = read_state(|s| (s.first_held_event_index(), s.held_event_count()));
+    if held == 0 || held < config.trigger_threshold {
+        return None;
+    }
+    Some((
+        first_held,
+        first_held + config.num_events_to_archive.min(held),
+    ))
+}
+
+/// Archived events of `[start, end)`, with the hash of the block before each of them.
+pub fn archived_events_batch(start: u64, end: u64) -> Vec<ArchivedEvent> {
+    read_state(|s| {
+        s.get_events(start, end - start)
+            .into_iter()
+            .zip(start..end)
+            .map(|(event, index)| ArchivedEvent {
+                index,
+                event,
+                parent_hash: index
+                    .checked_sub(1)
+                    .and_then(|parent| s.get_block_hash(parent)),
+            })
+            .collect()
+    })
+}
+
+/// Archives the oldest events once the canister holds more than the configured threshold, called
+/// by a timer.
+pub async fn archive_events() {
+    if ARCHIVE_WASM.is_empty() {
+        return;
+    }
+    let Some(_guard) = ArchivingGuard::new() else {
+        return;
+    };
+    let config = read_state(|s| s.get_archive_config());
+    let Some((mut next, archive_end)) = events_to_archive(&config) else {
+        return;
+    };
+
+    while next < archive_end {
+        let (archive_start, archive) = match writable_archive(&config, next).await {
+            Ok(archive) => archive,
+            Err(error) => {
+                log!(
+                    INFO,
+                    "[archive_events]: failed to set up an archive: {error}"
+                );
+                return;
+            }
+        };
+        let capacity = config
+            .max_events_per_archive
+            .saturating_sub(archive.end - archive_start);
+        let batch_end = archive_end
+            .min(next.saturating_add(config.max_events_per_call.max(1)))
+            .min(next + capacity);
+
+        let batch = archived_events_batch(next, batch_end)
+            .iter()
+            .map(|event| {
+                let mut buf = vec![];
+                minicbor::encode(event, &mut buf).expect("minicbor encoding should always succeed");
+                ByteBuf::from(buf)
+            })
+            .collect::<Vec<ByteBuf>>();
+
+        if let Err((code, message)) =
+            ic_cdk::call::<_, ()>(archive.canister_id, "append_events", (batch,)).await
+        {
+            log!(
+                INFO,
+                "[archive_events]: failed to send events {next}..{batch_end} to {}: {code:?} {message}",
+                archive.canister_id
+            );
+            return;
+        }
+
+        mutate_state(|s| s.record_archived_events(archive_start, batch_end));
+        log!(
+            INFO,
+            "[archive_events]: archived events {next}..{batch_end} to {}",
+            archive.canister_id
+        );
+        next = batch_end;
+    }
+}
+
+// the last archive if it is not full, otherwise a new archive starting at `next`
+async fn writable_archive(config: &ArchiveConfig, next: u64) -> Result<(u64, ArchiveInfo), String> {
+    if let Some((start, archive)) = read_state(|s| s.last_archive()) {
+        if !archive.is_installed() {
+            // the canister was created by an earlier run that failed to install the wasm
+            return install_archive(start, archive, CanisterInstallMode::Reinstall).await;
+        }
+        if archive.end - start < config.max_events_per_archive {
+            return Ok((start, archive));
+        }
+    }
+
+    let canister_id = create_archive_canister(config.cycles_for_archive_creation).await?;
+    let archive = ArchiveInfo {
+        canister_id,
+        end: next,
+        installed: Some(false),
+    };
+    mutate_state(|s| s.set_archive(next, archive));
+    log!(
+        INFO,
+        "[archive_events]: created archive {canister_id} at event {next}"
+    );
+
+    install_archive(next, archive, CanisterInstallMode::Install).await
+}
+
+async fn create_archive_canister(cycles: u64) -> Result<Principal, String> {
+    let (record,) = create_canister(
+        CreateCanisterArgument {
+            settings: Some(CanisterSettings {
+                controllers: Some(vec![ic_cdk::id()]),
+                ..Default::default()
+            }),
+        },
+        cycles as u128,
+    )
+    .await
+    .map_err(|(code, message)| format!("create_canister failed: {code:?} {message}"))?;
+    Ok(record.canister_id)
+}
+
+// installs the archive wasm in a recorded archive canister and marks it as installed, no events
+// were sent to the canister before so reinstalling it loses nothing
+async fn install_archive(
+    start: u64,
+    archive: ArchiveInfo,
+    mode: CanisterInstallMode,
+) -> Result<(u64, ArchiveInfo), String> {
+    install_code(InstallCodeArgument {
+        mode,
+        canister_id: archive.canister_id,
+        wasm_module: ARCHIVE_WASM.to_vec(),
+        arg: candid::encode_args(()).expect("encoding an empty argument should always succeed"),
+    })
+    .await
+    .map_err(|(code, message)| {
+        format!(
+            "install_code in {} failed: {code:?} {message}",
+            archive.canister_id
+        )
+    })?;
+
+    let archive = ArchiveInfo {
+        installed: Some(true),
+        ..archive
+    };
+    mutate_state(|s| s.set_archive(start, archive));
+    Ok((start, archive))
+}
@@ -0,0 +1,201 @@
+use candid::{Nat, Principal};
+use ethnum::U256;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+
+use super::{
+    archived_events_batch, archived_ranges, events_to_archive,
+    types::{ArchiveConfig, ArchiveInfo},
+};
+use crate::{
+    candid_types::archive::{CandidArchiveConfig, SetArchiveConfigError},
+    events::{Event, EventType},
+    icrc3::{get_archives, get_blocks},
+    state::{mutate_state, read_state},
+};
+
+fn principal(byte: u8) -> Principal {
+    Principal::from_slice(&[byte])
+}
+
+fn record_events(count: u64) {
+    for i in 0..count {
+        mutate_state(|s| {
+            s.record_event(Event {
+                timestamp: i,
+                payload: EventType::InternalTransfer {
+                    token: principal(1),
+                    from: principal(2),
+                    to: principal(3),
+                    amount: U256::from(i + 1),
+                    spender: None,
+                },
+            })
+        });
+    }
+}
+
+fn archive(canister_id: Principal, end: u64) -> ArchiveInfo {
+    ArchiveInfo {
+        canister_id,
+        end,
+        installed: Some(true),
+    }
+}
+
+#[test]
+fn archived_ranges_should_be_split_by_archive() {
+    mutate_state(|s| {
+        s.set_archive(0, archive(principal(10), 100));
+        s.set_archive(100, archive(principal(11), 150));
+    });
+
+    assert_eq!(
+        archived_ranges(50, 200),
+        vec![(principal(10), 50, 50), (principal(11), 100, 50)]
+    );
+    assert_eq!(archived_ranges(120, 10), vec![(principal(11), 120, 10)]);
+    assert_eq!(archived_ranges(150, 10), vec![]);
+    assert_eq!(archived_ranges(0, 0), vec![]);
+
+    let archives = get_archives(None);
+    assert_eq!(archives.len(), 2);
+    assert_eq!(archives[1].start, Nat::from(100_u8));
+    assert_eq!(archives[1].end, Nat::from(149_u8));
+    assert_eq!(get_archives(Some(principal(10))).len(), 1);
+}
+
+#[test]
+fn archiving_should_keep_event_indexes_and_hash_chain() {
+    record_events(10);
+    let hash_of_5 = read_state(|s| s.get_block_hash(5)).unwrap();
+
+    let batch = archived_events_batch(0, 6);
+    assert_eq!(batch.len(), 6);
+    assert_eq!(batch[0].parent_hash, None);
+    assert_eq!(batch[5].index, 5);
+    assert_eq!(batch[5].parent_hash, read_state(|s| s.get_block_hash(4)));
+
+    mutate_state(|s| {
+        s.set_archive(0, archive(principal(10), 0));
+        s.record_archived_events(0, 6);
+    });
+
+    read_state(|s| {
+        assert_eq!(s.total_event_count(), 10);
+        assert_eq!(s.first_held_event_index(), 6);
+        assert_eq!(s.held_event_count(), 4);
+        assert_eq!(s.get_events(0, 100).len(), 4);
+        assert!(s.get_event(5).is_none());
+        assert!(s.get_event(6).is_some());
+        // the hash of the last archived block is kept to chain the first held one
+        assert_eq!(s.get_block_hash(4), None);
+        assert_eq!(s.get_block_hash(5), Some(hash_of_5));
+    });
+
+    record_events(1);
+    read_state(|s| {
+        assert_eq!(s.total_event_count(), 11);
+        assert!(s.get_event(10).is_some());
+        assert_eq!(s.last_block_hash().map(|(index, _)| index), Some(10));
+    });
+
+    let result = get_blocks(vec![GetBlocksRequest {
+        start: Nat::from(0_u8),
+        length: Nat::from(20_u8),
+    }]);
+    assert_eq!(result.log_length, Nat::from(11_u8));
+    assert_eq!(result.blocks.len(), 5);
+    assert_eq!(result.blocks[0].id, Nat::from(6_u8));
+    assert_eq!(result.archived_blocks.len(), 1);
+    assert_eq!(result.archived_blocks[0].args[0].start, Nat::from(0_u8));
+    assert_eq!(result.archived_blocks[0].args[0].length, Nat::from(6_u8));
+    assert_eq!(
+        result.archived_blocks[0].callback.canister_id,
+        principal(10)
+    );
+}
+
+#[test]
+fn events_should_be_archived_above_threshold() {
+    let config = ArchiveConfig {
+        trigger_threshold: 10,
+        num_events_to_archive: 4,
+        ..ArchiveConfig::default()
+    };
+
+    record_events(9);
+    assert_eq!(events_to_archive(&config), None);
+
+    record_events(1);
+    assert_eq!(events_to_archive(&config), Some((0, 4)));
+
+    mutate_state(|s| {
+        s.set_archive(0, archive(principal(10), 0));
+        s.record_archived_events(0, 4);
+    });
+    assert_eq!(events_to_archive(&config), None);
+
+    record_events(4);
+    assert_eq!(events_to_archive(&config), Some((4, 8)));
+}
+
+#[test]
+fn archive_config_should_be_validated() {
+    let config = CandidArchiveConfig::from(ArchiveConfig::default());
+    assert_eq!(
+        ArchiveConfig::try_from(config.clone()),
+        Ok(ArchiveConfig::default())
+    );
+
+    assert_eq!(
+        ArchiveConfig::try_from(CandidArchiveConfig {
+            max_events_per_call: 0,
+            ..config.clone()
+        }),
+        Err(SetArchiveConfigError::InvalidEventCounts)
+    );
+    assert_eq!(
+        ArchiveConfig::try_from(CandidArchiveConfig {
+            num_events_to_archive: config.trigger_threshold + 1,
+            ..config.clone()
+        }),
+        Err(SetArchiveConfigError::InvalidEventCounts)
+    );
+    assert_eq!(
+        ArchiveConfig::try_from(CandidArchiveConfig {
+            cycles_for_archive_creation: Nat::from(u128::MAX),
+            ..config
+        }),
+        Err(SetArchiveConfigError::InvalidCycles)
+    );
+}
+
+#[test]
+fn archives_recorded_before_install_tracking_should_be_installed() {
+    #[derive(minicbor::Encode)]
+    struct LegacyArchiveInfo {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        canister_id: Principal,
+        #[n(1)]
+        end: u64,
+    }
+
+    let mut buf = vec![];
+    minicbor::encode(
+        LegacyArchiveInfo {
+            canister_id: principal(10),
+            end: 5,
+        },
+        &mut buf,
+    )
+    .unwrap();
+    let decoded: ArchiveInfo = minicbor::decode(&buf).unwrap();
+    assert_eq!(decoded.installed, None);
+    assert!(decoded.is_installed());
+
+    assert!(!ArchiveInfo {
+        installed: Some(false),
+        ..decoded
+    }
+    .is_installed());
+}
@@ -0,0 +1,68 @@
+use candid::Principal;
+use minicbor::{Decode, Encode};
+
+use crate::{events::Event, icrc3::types::BlockHash};
+
+/// When and how events are moved out of the main canister.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveConfig {
+    // archiving starts once the main canister holds this many events
+    #[n(0)]
+    pub trigger_threshold: u64,
+    // oldest events shipped by one archiving run
+    #[n(1)]
+    pub num_events_to_archive: u64,
+    // events sent to an archive in a single `append_events` call
+    #[n(2)]
+    pub max_events_per_call: u64,
+    // a new archive is spawned once the last one holds this many events
+    #[n(3)]
+    pub max_events_per_archive: u64,
+    // cycles attached to the creation of an archive canister
+    #[n(4)]
+    pub cycles_for_archive_creation: u64,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            trigger_threshold: 100_000,
+            num_events_to_archive: 10_000,
+            max_events_per_call: 1_000,
+            max_events_per_archive: 10_000_000,
+            cycles_for_archive_creation: 2_000_000_000_000,
+        }
+    }
+}
+
+/// An archive canister, stored under the index of its first event. The archive holds the events
+/// in `[start, end)`.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchiveInfo {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub canister_id: Principal,
+    #[n(1)]
+    pub end: u64,
+    /// False from the creation of the canister until the archive wasm is installed in it, None for
+    /// archives recorded once installed.
+    #[n(2)]
+    pub installed: Option<bool>,
+}
+
+impl ArchiveInfo {
+    pub fn is_installed(&self) -> bool {
+        self.installed != Some(false)
+    }
+}
+
+/// An event as sent to and stored by an archive canister, along with the hash of the block before
+/// it so the archive can serve ICRC-3 blocks.
+#[derive(Encode, Decode, Debug, PartialEq, Eq)]
+pub struct ArchivedEvent {
+    #[n(0)]
+    pub index: u64,
+    #[n(1)]
+    pub event: Event,
+    #[n(2)]
+    pub parent_hash: Option<BlockHash>,
+}
@@ -1 +1,33 @@
 pub mod types;
+
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Subaccount;
+
+/// Deterministic subaccount of the canister where `user` can deposit with a plain ICRC-1 transfer.
+/// It holds the length prefixed principal bytes, so it is unique per principal and never equal to
+/// the default subaccount of the canister main account.
+pub fn deposit_subaccount(user: Principal) -> Subaccount {
+    let principal_bytes = user.as_slice();
+    let mut subaccount = [0_u8; 32];
+    subaccount[0] = principal_bytes.len() as u8;
+    subaccount[1..1 + principal_bytes.len()].copy_from_slice(principal_bytes);
+    subaccount
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deposit_subaccounts_should_be_unique_and_not_default() {
+        let user_a = Principal::from_slice(&[1]);
+        let user_b = Principal::from_slice(&[1, 0]);
+        let user_c = Principal::from_slice(&[7; 29]);
+
+        assert_ne!(deposit_subaccount(user_a), deposit_subaccount(user_b));
+        assert_ne!(deposit_subaccount(user_a), [0_u8; 32]);
+        assert_eq!(deposit_subaccount(user_c), deposit_subaccount(user_c));
+        assert_eq!(deposit_subaccount(user_c)[0], 29);
+        assert_eq!(&deposit_subaccount(user_c)[1..30], &[7; 29]);
+    }
+}
@@ -13,3 +13,32 @@ pub struct UserBalanceKey {
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct UserBalance(#[cbor(n(0), with = "crate::cbor::u256")] pub U256);
+
+/// Allowance of `spender` over the internal balance of `owner` in `token`
+#[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct InternalAllowanceKey {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub owner: Principal,
+    #[cbor(n(1), with = "crate::cbor::principal")]
+    pub spender: Principal,
+    #[cbor(n(2), with = "crate::cbor::principal")]
+    pub token: Principal,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, Default)]
+pub struct InternalAllowance {
+    #[cbor(n(0), with = "crate::cbor::u256")]
+    pub amount: U256,
+    #[n(1)]
+    pub expires_at: Option<u64>,
+}
+
+impl InternalAllowance {
+    /// The amount that can still be spent at `now`, zero once the allowance expired
+    pub fn available(&self, now: u64) -> U256 {
+        match self.expires_at {
+            Some(expires_at) if expires_at <= now => U256::ZERO,
+            _ => self.amount,
+        }
+    }
+}
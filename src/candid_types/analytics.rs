@@ -0,0 +1,221 @@
+use crate::{
+    historical::{
+        aggregates::{PoolAnalytics, PoolSortBy, PoolStats, TokenPrice},
+        types::{ProtocolStats, TokenBucket, TokenStats},
+    },
+    libraries::safe_cast::u256_to_nat,
+    tokens::metadata::PriceStatus,
+};
+
+use super::{pool::CandidPoolId, *};
+
+/// Activity of a token across all its pools, with its daily history.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidTokenStats {
+    pub token: Principal,
+    pub pool_count: u64,
+    pub swap_count: u64,
+    pub swap_volume: Nat,
+    pub fees_generated: Nat,
+    pub reserves: Nat,
+    pub daily_frame: Vec<CandidTokenBucket>,
+}
+
+impl CandidTokenStats {
+    pub fn new(token: Principal, stats: TokenStats, daily_frame: Vec<TokenBucket>) -> Self {
+        Self {
+            token,
+            pool_count: stats.pool_count,
+            swap_count: stats.swap_count,
+            swap_volume: u256_to_nat(stats.swap_volume),
+            fees_generated: u256_to_nat(stats.fees_generated),
+            reserves: u256_to_nat(stats.reserves),
+            daily_frame: daily_frame
+                .into_iter()
+                .map(CandidTokenBucket::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidTokenBucket {
+    pub start_timestamp: u64,
+    pub end_timestamp: u64,
+    pub swap_count: u64,
+    pub swap_volume: Nat,
+    pub fees_generated: Nat,
+    pub reserves: Nat,
+}
+
+impl From<TokenBucket> for CandidTokenBucket {
+    fn from(value: TokenBucket) -> Self {
+        Self {
+            start_timestamp: value.start_timestamp,
+            end_timestamp: value.end_timestamp,
+            swap_count: value.swap_count,
+            swap_volume: u256_to_nat(value.swap_volume),
+            fees_generated: u256_to_nat(value.fees_generated),
+            reserves: u256_to_nat(value.reserves),
+        }
+    }
+}
+
+/// Volume, fees and reserves of a token across all its pools.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidTokenVolume {
+    pub token: Principal,
+    pub swap_volume: Nat,
+    pub fees_generated: Nat,
+    pub reserves: Nat,
+}
+
+/// Protocol wide activity, swaps and users being counted from `tracked_since` (in seconds).
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidProtocolStats {
+    pub tracked_since: u64,
+    pub pool_count: u64,
+    pub swap_count: u64,
+    pub unique_users: u64,
+    pub tokens: Vec<CandidTokenVolume>,
+}
+
+impl CandidProtocolStats {
+    pub fn new(stats: ProtocolStats, tokens: Vec<(Principal, TokenStats)>) -> Self {
+        Self {
+            tracked_since: stats.tracked_since,
+            pool_count: stats.pool_count,
+            swap_count: stats.swap_count,
+            unique_users: stats.unique_users,
+            tokens: tokens
+                .into_iter()
+                .map(|(token, stats)| CandidTokenVolume {
+                    token,
+                    swap_volume: u256_to_nat(stats.swap_volume),
+                    fees_generated: u256_to_nat(stats.fees_generated),
+                    reserves: u256_to_nat(stats.reserves),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidPoolSortBy {
+    SwapCount,
+    UniqueTraders,
+    LiquidityChanges,
+}
+
+impl From<CandidPoolSortBy> for PoolSortBy {
+    fn from(value: CandidPoolSortBy) -> Self {
+        match value {
+            CandidPoolSortBy::SwapCount => PoolSortBy::SwapCount,
+            CandidPoolSortBy::UniqueTraders => PoolSortBy::UniqueTraders,
+            CandidPoolSortBy::LiquidityChanges => PoolSortBy::LiquidityChanges,
+        }
+    }
+}
+
+/// Activity of a pool over the last 24 hours.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidPoolStats {
+    pub pool_id: CandidPoolId,
+    pub swap_count: u64,
+    pub unique_traders: u64,
+    pub mint_count: u64,
+    pub burn_count: u64,
+    pub swap_volume0: Nat,
+    pub swap_volume1: Nat,
+    pub fees_generated0: Nat,
+    pub fees_generated1: Nat,
+    pub reserve0: Nat,
+    pub reserve1: Nat,
+}
+
+impl From<PoolStats> for CandidPoolStats {
+    fn from(value: PoolStats) -> Self {
+        Self {
+            pool_id: value.pool_id.into(),
+            swap_count: value.swap_count,
+            unique_traders: value.unique_traders,
+            mint_count: value.mint_count,
+            burn_count: value.burn_count,
+            swap_volume0: u256_to_nat(value.swap_volume0),
+            swap_volume1: u256_to_nat(value.swap_volume1),
+            fees_generated0: u256_to_nat(value.fees_generated0),
+            fees_generated1: u256_to_nat(value.fees_generated1),
+            reserve0: u256_to_nat(value.reserve0),
+            reserve1: u256_to_nat(value.reserve1),
+        }
+    }
+}
+
+/// Usd valuation of a pool, values are null when the price or decimals of a token are missing and
+/// computed from stale prices otherwise.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidPoolAnalytics {
+    pub stats: CandidPoolStats,
+    pub token0: CandidTokenPrice,
+    pub token1: CandidTokenPrice,
+    pub tvl_usd: Option<f64>,
+    pub volume_24h_usd: Option<f64>,
+    pub fees_24h_usd: Option<f64>,
+    pub fee_apr: Option<f64>,
+}
+
+impl From<PoolAnalytics> for CandidPoolAnalytics {
+    fn from(value: PoolAnalytics) -> Self {
+        Self {
+            stats: value.stats.into(),
+            token0: value.token0.into(),
+            token1: value.token1.into(),
+            tvl_usd: value.tvl_usd,
+            volume_24h_usd: value.volume_24h_usd,
+            fees_24h_usd: value.fees_24h_usd,
+            fee_apr: value.fee_apr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidTokenPrice {
+    pub token: Principal,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    pub usd_price: Option<f64>,
+    pub price_updated_at: Option<u64>,
+    pub status: CandidPriceStatus,
+}
+
+impl From<TokenPrice> for CandidTokenPrice {
+    fn from(value: TokenPrice) -> Self {
+        let known = value.metadata.is_some();
+        let metadata = value.metadata.unwrap_or_default();
+        Self {
+            token: value.token,
+            symbol: metadata.symbol,
+            decimals: known.then_some(metadata.decimals),
+            usd_price: metadata.usd_price,
+            price_updated_at: metadata.price_updated_at,
+            status: value.status.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidPriceStatus {
+    Fresh,
+    Stale,
+    Missing,
+}
+
+impl From<PriceStatus> for CandidPriceStatus {
+    fn from(value: PriceStatus) -> Self {
+        match value {
+            PriceStatus::Fresh => CandidPriceStatus::Fresh,
+            PriceStatus::Stale => CandidPriceStatus::Stale,
+            PriceStatus::Missing => CandidPriceStatus::Missing,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use candid::{CandidType, Nat};
+use serde::Deserialize;
+
+use crate::archive::types::ArchiveConfig;
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CandidArchiveConfig {
+    pub trigger_threshold: u64,
+    pub num_events_to_archive: u64,
+    pub max_events_per_call: u64,
+    pub max_events_per_archive: u64,
+    pub cycles_for_archive_creation: Nat,
+}
+
+impl From<ArchiveConfig> for CandidArchiveConfig {
+    fn from(value: ArchiveConfig) -> Self {
+        Self {
+            trigger_threshold: value.trigger_threshold,
+            num_events_to_archive: value.num_events_to_archive,
+            max_events_per_call: value.max_events_per_call,
+            max_events_per_archive: value.max_events_per_archive,
+            cycles_for_archive_creation: Nat::from(value.cycles_for_archive_creation),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SetArchiveConfigError {
+    // event counts should be positive and a run should not archive more than the threshold
+    InvalidEventCounts,
+    InvalidCycles,
+}
+
+impl TryFrom<CandidArchiveConfig> for ArchiveConfig {
+    type Error = SetArchiveConfigError;
+
+    fn try_from(value: CandidArchiveConfig) -> Result<Self, Self::Error> {
+        if value.num_events_to_archive == 0
+            || value.max_events_per_call == 0
+            || value.max_events_per_archive == 0
+            || value.num_events_to_archive > value.trigger_threshold
+        {
+            return Err(SetArchiveConfigError::InvalidEventCounts);
+        }
+        Ok(ArchiveConfig {
+            trigger_threshold: value.trigger_threshold,
+            num_events_to_archive: value.num_events_to_archive,
+            max_events_per_call: value.max_events_per_call,
+            max_events_per_archive: value.max_events_per_archive,
+            cycles_for_archive_creation: u64::try_from(value.cycles_for_archive_creation.0)
+                .map_err(|_| SetArchiveConfigError::InvalidCycles)?,
+        })
+    }
+}
@@ -1,9 +1,17 @@
-use candid::{CandidType, Nat, Principal};
+use candid::{CandidType, Int, Nat, Principal};
 use serde::Deserialize;
 
-use crate::{events::Event, libraries::safe_cast::u256_to_nat, validation};
+use crate::{
+    events::{index::EventKind, Event, EventType, SwapHop},
+    icrc_client::memo::MemoKind,
+    libraries::safe_cast::{i256_to_int, u256_to_nat},
+    validation,
+};
 
-use super::{pool::CandidPoolId, position::CandidPositionKey};
+use super::{
+    pool::{CandidCreationFee, CandidPoolId},
+    position::CandidPositionKey,
+};
 
 #[derive(CandidType, Deserialize, Debug, Clone)]
 pub struct GetEventsArg {
@@ -15,6 +23,123 @@ pub struct GetEventsArg {
 pub struct GetEventsResult {
     pub events: Vec<CandidEvent>,
     pub total_event_count: u64,
+    /// Parts of the requested range stored in archive canisters.
+    pub archived_events: Vec<ArchivedEventsRange>,
+}
+
+candid::define_function!(pub ArchivedEventsFn : (GetEventsArg) -> (Vec<CandidEvent>) query);
+
+/// Events to fetch by calling `callback` on an archive canister with `start` and `length`.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ArchivedEventsRange {
+    pub start: u64,
+    pub length: u64,
+    pub callback: ArchivedEventsFn,
+}
+
+/// Arguments of `get_events_filtered`, unset filters match every event.
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct GetEventsFilteredArgs {
+    pub principal: Option<Principal>,
+    pub pool_id: Option<CandidPoolId>,
+    pub event_types: Option<Vec<CandidEventKind>>,
+    pub start_time: Option<u64>, // inclusive, in nanoseconds
+    pub end_time: Option<u64>,   // exclusive, in nanoseconds
+    /// The `next_cursor` of the previous page, None for the first page.
+    pub cursor: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct GetEventsFilteredResult {
+    pub events: Vec<CandidIndexedEvent>,
+    /// Set when more events could match, pass it as the cursor of the next call.
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct CandidIndexedEvent {
+    /// The index of the event in `get_events`.
+    pub index: u64,
+    pub event: CandidEvent,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidEventKind {
+    CreatedPool,
+    MintedPosition,
+    IncreasedLiquidity,
+    BurntPosition,
+    DecreasedLiquidity,
+    CollectedFees,
+    Swap,
+    Donated,
+    InternalTransfer,
+    Deposited,
+    Withdrawn,
+    WithdrawalRolledBack,
+    TransferFeeUpdated,
+}
+
+impl From<CandidEventKind> for EventKind {
+    fn from(value: CandidEventKind) -> Self {
+        match value {
+            CandidEventKind::CreatedPool => Self::CreatedPool,
+            CandidEventKind::MintedPosition => Self::MintedPosition,
+            CandidEventKind::IncreasedLiquidity => Self::IncreasedLiquidity,
+            CandidEventKind::BurntPosition => Self::BurntPosition,
+            CandidEventKind::DecreasedLiquidity => Self::DecreasedLiquidity,
+            CandidEventKind::CollectedFees => Self::CollectedFees,
+            CandidEventKind::Swap => Self::Swap,
+            CandidEventKind::Donated => Self::Donated,
+            CandidEventKind::InternalTransfer => Self::InternalTransfer,
+            CandidEventKind::Deposited => Self::Deposited,
+            CandidEventKind::Withdrawn => Self::Withdrawn,
+            CandidEventKind::WithdrawalRolledBack => Self::WithdrawalRolledBack,
+            CandidEventKind::TransferFeeUpdated => Self::TransferFeeUpdated,
+        }
+    }
+}
+
+/// Summary of a `Swap` event returned by `get_user_trades`.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct CandidUserTrade {
+    /// The index of the event in `get_events`.
+    pub event_index: u64,
+    pub timestamp: u64,
+    pub token_in: Principal,
+    pub token_out: Principal,
+    pub amount_in: Nat,
+    pub amount_out: Nat,
+    pub pools: Vec<CandidPoolId>, // in path order
+}
+
+impl CandidUserTrade {
+    /// None for events other than swaps.
+    pub fn from_event(event_index: u64, event: Event) -> Option<Self> {
+        let EventType::Swap {
+            final_amount_in,
+            final_amount_out,
+            swap_args,
+            ..
+        } = event.payload
+        else {
+            return None;
+        };
+        Some(Self {
+            event_index,
+            timestamp: event.timestamp,
+            token_in: swap_args.token_in(),
+            token_out: swap_args.token_out(),
+            amount_in: u256_to_nat(final_amount_in),
+            amount_out: u256_to_nat(final_amount_out),
+            pools: swap_args
+                .pool_ids()
+                .into_iter()
+                .map(CandidPoolId::from)
+                .collect(),
+        })
+    }
 }
 
 /// The event describing the  minter state transition.
@@ -24,6 +149,11 @@ pub enum CandidEventType {
         token0: Principal,
         token1: Principal,
         pool_fee: Nat,
+        sqrt_price_x96: Option<Nat>, // the initial price, None for older events
+        token0_transfer_fee: Option<Nat>,
+        token1_transfer_fee: Option<Nat>,
+        creator: Option<Principal>, // None for events recorded before the pool creation policy
+        creation_fee: Option<CandidCreationFee>,
     },
     MintedPosition {
         created_position: CandidPositionKey,
@@ -66,7 +196,89 @@ pub enum CandidEventType {
         token_out: Principal,
         swap_type: SwapType,
         principal: Principal,
+        swap_fees: Vec<Nat>, // fee in pips charged by each hop, empty for older events
+        hops: Vec<CandidSwapHop>, // empty for older events
     },
+    Donated {
+        pool_id: CandidPoolId,
+        amount0: Nat,
+        amount1: Nat,
+        principal: Principal,
+    },
+    InternalTransfer {
+        token: Principal,
+        from: Principal,
+        to: Principal,
+        amount: Nat,
+        spender: Option<Principal>,
+    },
+    Deposited {
+        token: Principal,
+        user: Principal,
+        amount: Nat,
+        block_index: Option<u64>,
+        memo: CandidMemoKind,
+        balance: Nat,
+    },
+    Withdrawn {
+        token: Principal,
+        user: Principal,
+        amount: Nat, // transfer fee included
+        transfer_fee: Nat,
+        block_index: Option<u64>, // None while the outcome of the transfer is unknown
+        memo: CandidMemoKind,
+        balance: Nat,
+        uncovered_cost: Option<Nat>, // transfer cost the user balance could not cover
+    },
+    WithdrawalRolledBack {
+        token: Principal,
+        user: Principal,
+        amount: Nat,
+        memo: CandidMemoKind,
+        balance: Nat,
+        reason: String,
+    },
+    TransferFeeUpdated {
+        token: Principal,
+        transfer_fee: Nat,
+    },
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidMemoKind {
+    MintPosition,
+    IncreasePosition,
+    SwapIn,
+    Deposit,
+    Donate,
+    NotifiedDeposit,
+    BurnPosition,
+    DecreasePosition,
+    SwapOut,
+    WithdrawBalance,
+    Refund,
+    CollectFees,
+    Withdraw,
+}
+
+impl From<MemoKind> for CandidMemoKind {
+    fn from(value: MemoKind) -> Self {
+        match value {
+            MemoKind::MintPosition => Self::MintPosition,
+            MemoKind::IncreasePosition => Self::IncreasePosition,
+            MemoKind::SwapIn => Self::SwapIn,
+            MemoKind::Deposit => Self::Deposit,
+            MemoKind::Donate => Self::Donate,
+            MemoKind::NotifiedDeposit => Self::NotifiedDeposit,
+            MemoKind::BurnPosition => Self::BurnPosition,
+            MemoKind::DecreasePosition => Self::DecreasePosition,
+            MemoKind::SwapOut => Self::SwapOut,
+            MemoKind::WithdrawBalance => Self::WithdrawBalance,
+            MemoKind::Refund => Self::Refund,
+            MemoKind::CollectFees => Self::CollectFees,
+            MemoKind::Withdraw => Self::Withdraw,
+        }
+    }
 }
 
 #[derive(CandidType, Deserialize, Debug, Clone)]
@@ -77,6 +289,28 @@ pub enum SwapType {
     ExactOutput(Vec<CandidPoolId>),
 }
 
+/// A single pool swap of a `Swap` event, the deltas are from the point of view of the pool.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct CandidSwapHop {
+    pub pool_id: CandidPoolId,
+    pub zero_for_one: bool,
+    pub amount_specified: Int, // negative for exact input, positive for exact output
+    pub amount0_delta: Int,
+    pub amount1_delta: Int,
+}
+
+impl From<SwapHop> for CandidSwapHop {
+    fn from(value: SwapHop) -> Self {
+        Self {
+            pool_id: value.pool_id.into(),
+            zero_for_one: value.zero_for_one,
+            amount_specified: i256_to_int(value.amount_specified),
+            amount0_delta: i256_to_int(value.amount0_delta),
+            amount1_delta: i256_to_int(value.amount1_delta),
+        }
+    }
+}
+
 #[derive(CandidType, Deserialize, Debug, Clone)]
 pub struct CandidEvent {
     /// The canister time at which the minter generated this event.
@@ -92,10 +326,20 @@ impl From<Event> for CandidEvent {
                 token0,
                 token1,
                 pool_fee,
+                sqrt_price_x96,
+                token0_transfer_fee,
+                token1_transfer_fee,
+                creator,
+                creation_fee,
             } => CandidEventType::CreatedPool {
                 token0,
                 token1,
                 pool_fee: pool_fee.into(),
+                sqrt_price_x96: sqrt_price_x96.map(u256_to_nat),
+                token0_transfer_fee: token0_transfer_fee.map(u256_to_nat),
+                token1_transfer_fee: token1_transfer_fee.map(u256_to_nat),
+                creator,
+                creation_fee: creation_fee.map(CandidCreationFee::from),
             },
             crate::events::EventType::MintedPosition {
                 created_position,
@@ -166,6 +410,8 @@ impl From<Event> for CandidEvent {
                 final_amount_out,
                 swap_args,
                 principal,
+                swap_fees,
+                hops,
             } => {
                 let (swap_type, token_in, token_out) = match swap_args {
                     validation::swap_args::ValidatedSwapArgs::ExactInputSingle {
@@ -235,8 +481,98 @@ impl From<Event> for CandidEvent {
                     token_out,
                     swap_type,
                     principal,
+                    swap_fees: swap_fees
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(Nat::from)
+                        .collect(),
+                    hops: hops
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(CandidSwapHop::from)
+                        .collect(),
                 }
             }
+            crate::events::EventType::Donated {
+                pool_id,
+                amount0,
+                amount1,
+                principal,
+            } => CandidEventType::Donated {
+                pool_id: pool_id.into(),
+                amount0: u256_to_nat(amount0),
+                amount1: u256_to_nat(amount1),
+                principal,
+            },
+            crate::events::EventType::InternalTransfer {
+                token,
+                from,
+                to,
+                amount,
+                spender,
+            } => CandidEventType::InternalTransfer {
+                token,
+                from,
+                to,
+                amount: u256_to_nat(amount),
+                spender,
+            },
+            crate::events::EventType::Deposited {
+                token,
+                user,
+                amount,
+                block_index,
+                memo,
+                balance,
+            } => CandidEventType::Deposited {
+                token,
+                user,
+                amount: u256_to_nat(amount),
+                block_index,
+                memo: memo.into(),
+                balance: u256_to_nat(balance),
+            },
+            crate::events::EventType::Withdrawn {
+                token,
+                user,
+                amount,
+                transfer_fee,
+                block_index,
+                memo,
+                balance,
+                uncovered_cost,
+            } => CandidEventType::Withdrawn {
+                token,
+                user,
+                amount: u256_to_nat(amount),
+                transfer_fee: u256_to_nat(transfer_fee),
+                block_index,
+                memo: memo.into(),
+                balance: u256_to_nat(balance),
+                uncovered_cost: uncovered_cost.map(u256_to_nat),
+            },
+            crate::events::EventType::WithdrawalRolledBack {
+                token,
+                user,
+                amount,
+                memo,
+                balance,
+                reason,
+            } => CandidEventType::WithdrawalRolledBack {
+                token,
+                user,
+                amount: u256_to_nat(amount),
+                memo: memo.into(),
+                balance: u256_to_nat(balance),
+                reason,
+            },
+            crate::events::EventType::TransferFeeUpdated {
+                token,
+                transfer_fee,
+            } => CandidEventType::TransferFeeUpdated {
+                token,
+                transfer_fee: u256_to_nat(transfer_fee),
+            },
         };
         Self {
             timestamp: value.timestamp,
@@ -0,0 +1,210 @@
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::{
+    journal::types::{
+        DepositSource, DepositStatus, JournalWithdrawal, Operation, OperationId, OperationKind,
+        OperationStatus, PendingDeposit, WithdrawalStatus,
+    },
+    libraries::safe_cast::u256_to_nat,
+};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidOperationKind {
+    MintPosition,
+    IncreaseLiquidity,
+    Burn,
+    DecreaseLiquidity,
+    Swap,
+    CollectFees,
+    Donate,
+    Deposit,
+    Withdraw,
+    CreatePoolAndMint,
+}
+
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidOperationStatus {
+    Started,
+    DepositReceived,
+    Executed,
+    WithdrawalPending,
+    WithdrawalFailed, // the amount stays in the user balance
+    Completed,
+    DepositPending,
+    DepositUnresolved, // nothing was credited, see the pending deposit
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidWithdrawalStatus {
+    InFlight,
+    Retrying { next_retry_at: u64 },
+    Failed,
+    Cancelled, // the user moved the funds before the retry
+    Completed { block_index: u64 },
+    OutcomeUnknown { next_retry_at: u64 }, // still debited, sent again with identical arguments
+    Unresolved, // still debited, outcome unknown past the ledger deduplication window
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidJournalWithdrawal {
+    pub token: Principal,
+    pub to: Account,
+    pub amount: Nat, // debited from the user balance, transfer fee included
+    pub transfer_fee: Nat,
+    pub status: CandidWithdrawalStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidDepositStatus {
+    OutcomeUnknown { next_retry_at: u64 }, // not credited yet, sent again with identical arguments
+    Unresolved, // not credited, outcome unknown past the ledger deduplication window
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidPendingDeposit {
+    pub token: Principal,
+    pub from: Account, // the deposit subaccount for notified deposits
+    pub amount: Nat,
+    pub status: CandidDepositStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at_time: u64,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidOperation {
+    pub id: u64,
+    pub owner: Principal,
+    pub kind: CandidOperationKind,
+    pub status: CandidOperationStatus,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub deposits: Vec<Balance>,
+    pub withdrawals: Vec<CandidJournalWithdrawal>,
+    pub event_ids: Vec<u64>, // events recorded by the operation, see get_events
+    pub pending_deposit: Option<CandidPendingDeposit>,
+}
+
+impl From<OperationKind> for CandidOperationKind {
+    fn from(value: OperationKind) -> Self {
+        match value {
+            OperationKind::MintPosition => Self::MintPosition,
+            OperationKind::IncreaseLiquidity => Self::IncreaseLiquidity,
+            OperationKind::Burn => Self::Burn,
+            OperationKind::DecreaseLiquidity => Self::DecreaseLiquidity,
+            OperationKind::Swap => Self::Swap,
+            OperationKind::CollectFees => Self::CollectFees,
+            OperationKind::Donate => Self::Donate,
+            OperationKind::Deposit => Self::Deposit,
+            OperationKind::Withdraw => Self::Withdraw,
+            OperationKind::CreatePoolAndMint => Self::CreatePoolAndMint,
+        }
+    }
+}
+
+impl From<OperationStatus> for CandidOperationStatus {
+    fn from(value: OperationStatus) -> Self {
+        match value {
+            OperationStatus::Started => Self::Started,
+            OperationStatus::DepositReceived => Self::DepositReceived,
+            OperationStatus::Executed => Self::Executed,
+            OperationStatus::WithdrawalPending => Self::WithdrawalPending,
+            OperationStatus::WithdrawalFailed => Self::WithdrawalFailed,
+            OperationStatus::Completed => Self::Completed,
+            OperationStatus::DepositPending => Self::DepositPending,
+            OperationStatus::DepositUnresolved => Self::DepositUnresolved,
+        }
+    }
+}
+
+impl From<WithdrawalStatus> for CandidWithdrawalStatus {
+    fn from(value: WithdrawalStatus) -> Self {
+        match value {
+            WithdrawalStatus::InFlight => Self::InFlight,
+            WithdrawalStatus::Retrying { next_retry_at } => Self::Retrying { next_retry_at },
+            WithdrawalStatus::Failed => Self::Failed,
+            WithdrawalStatus::Cancelled => Self::Cancelled,
+            WithdrawalStatus::Completed { block_index } => Self::Completed { block_index },
+            WithdrawalStatus::OutcomeUnknown { next_retry_at } => {
+                Self::OutcomeUnknown { next_retry_at }
+            }
+            WithdrawalStatus::Unresolved => Self::Unresolved,
+        }
+    }
+}
+
+impl From<JournalWithdrawal> for CandidJournalWithdrawal {
+    fn from(value: JournalWithdrawal) -> Self {
+        CandidJournalWithdrawal {
+            token: value.token,
+            to: value.to(),
+            amount: u256_to_nat(value.amount),
+            transfer_fee: u256_to_nat(value.transfer_fee),
+            status: value.status.into(),
+            attempts: value.attempts,
+            last_error: value.last_error,
+            created_at_time: value.created_at_time,
+        }
+    }
+}
+
+impl From<DepositStatus> for CandidDepositStatus {
+    fn from(value: DepositStatus) -> Self {
+        match value {
+            DepositStatus::OutcomeUnknown { next_retry_at } => {
+                Self::OutcomeUnknown { next_retry_at }
+            }
+            DepositStatus::Unresolved => Self::Unresolved,
+        }
+    }
+}
+
+impl From<PendingDeposit> for CandidPendingDeposit {
+    fn from(value: PendingDeposit) -> Self {
+        let from = match value.source {
+            DepositSource::TransferFrom { owner, subaccount } => Account { owner, subaccount },
+            DepositSource::Subaccount { subaccount, .. } => Account {
+                owner: ic_cdk::id(),
+                subaccount: Some(subaccount),
+            },
+        };
+        CandidPendingDeposit {
+            token: value.token,
+            from,
+            amount: u256_to_nat(value.amount),
+            status: value.status.into(),
+            attempts: value.attempts,
+            last_error: value.last_error,
+            created_at_time: value.created_at_time,
+        }
+    }
+}
+
+impl From<(OperationId, Operation)> for CandidOperation {
+    fn from((id, operation): (OperationId, Operation)) -> Self {
+        CandidOperation {
+            id,
+            owner: operation.owner,
+            kind: operation.kind.into(),
+            status: operation.status.into(),
+            started_at: operation.started_at,
+            updated_at: operation.updated_at,
+            deposits: operation
+                .deposits
+                .into_iter()
+                .map(|deposit| Balance {
+                    token: deposit.token,
+                    amount: u256_to_nat(deposit.amount),
+                })
+                .collect(),
+            withdrawals: operation.withdrawals.into_iter().map(Into::into).collect(),
+            event_ids: operation.event_ids,
+            pending_deposit: operation.pending_deposit.map(Into::into),
+        }
+    }
+}
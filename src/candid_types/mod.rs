@@ -10,13 +10,18 @@ use crate::{
     pool::types::{PoolFee, PoolId},
 };
 
+pub mod analytics;
+pub mod archive;
 pub mod events;
+pub mod journal;
 pub mod pool;
 pub mod pool_history;
 pub mod position;
 pub mod quote;
+pub mod reconciliation;
 pub mod swap;
 pub mod tick;
+pub mod token;
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Balance {
@@ -35,35 +40,112 @@ pub struct DepositArgs {
     pub token: Principal,
     pub amount: Nat,
     pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct WithdrawArgs {
     pub token: Principal,
     pub amount: Nat,
+    pub request_id: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct InternalTransferArgs {
+    pub token: Principal,
+    pub to: Principal,
+    pub amount: Nat,
+    pub request_id: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApproveInternalArgs {
+    pub token: Principal,
+    pub spender: Principal,
+    pub amount: Nat,
+    pub expected_allowance: Option<Nat>,
+    pub expires_at: Option<u64>,
+    pub request_id: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct TransferFromInternalArgs {
+    pub token: Principal,
+    pub from: Principal,
+    pub to: Principal,
+    pub amount: Nat,
+    pub request_id: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct InternalAllowanceArgs {
+    pub token: Principal,
+    pub owner: Principal,
+    pub spender: Principal,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CandidInternalAllowance {
+    pub allowance: Nat,
+    pub expires_at: Option<u64>,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub enum DepositError {
     LockedPrincipal,
+    RequestInProgress, // a call with the same request_id is still being processed
+    TokenPaused,
     AmountTooLow { min_withdrawal_amount: Nat },
     InsufficientFunds { balance: Nat }, // not enough balance in user wallet
     InsufficientAllowance { allowance: Nat },
-    TemporarilyUnavailable(String),
+    TemporarilyUnavailable(String), // nothing was sent, the deposit can be retried
     InvalidDestination(String),
     AmountOverflow,
+    OutcomeUnknown { operation_id: u64 }, // credited once the journal resolves the transfer
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum NotifyDepositError {
+    LockedPrincipal,
+    RequestInProgress,
+    AmountTooLow { balance: Nat, transfer_fee: Nat }, // deposit subaccount can not pay the sweep fee
+    TemporarilyUnavailable(String),
+    AmountOverflow,
+    DepositError(DepositError),
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
 pub enum WithdrawError {
     LockedPrincipal,
+    RequestInProgress,
+    TokenPaused,
     AmountTooLow { min_withdrawal_amount: Nat },
     InsufficientBalance { balance: Nat }, // user has insufficient balance
     InsufficientAllowance { allowance: Nat },
-    TemporarilyUnavailable(String),
+    TemporarilyUnavailable(String), // nothing was sent, the withdrawal can be retried
     InvalidDestination(String),
     FeeUnknown,
     AmountOverflow,
+    OutcomeUnknown { operation_id: u64 }, // still debited, the journal sends the transfer again
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum InternalTransferError {
+    LockedPrincipal,
+    RequestInProgress,
+    InvalidRecipient, // anonymous or the sender itself
+    ZeroAmount,
+    AmountOverflow,
+    InsufficientBalance { balance: Nat },
+    InsufficientAllowance { allowance: Nat }, // spender allowance in transfer_from_internal
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ApproveInternalError {
+    InvalidSpender, // anonymous or the owner itself
+    AmountOverflow,
+    Expired { ledger_time: u64 },
+    AllowanceChanged { current_allowance: Nat },
 }
 
 impl From<LedgerTransferError> for WithdrawError {
@@ -80,6 +162,13 @@ impl From<LedgerTransferError> for WithdrawError {
             }
             LedgerTransferError::BadFee { .. } => WithdrawError::FeeUnknown,
             LedgerTransferError::FeeUnknown => Self::FeeUnknown,
+            LedgerTransferError::OutcomeUnknown { .. } => {
+                panic!("Bug: a withdrawal with an unknown outcome is journaled by `_withdraw`")
+            }
+            LedgerTransferError::TooOld { ledger } => Self::TemporarilyUnavailable(format!(
+                "withdrawal outside of the {} ledger deduplication window",
+                ledger.to_text()
+            )),
             LedgerTransferError::AmountTooLow {
                 minimum_amount,
                 failed_amount,
@@ -109,6 +198,12 @@ impl From<LedgerTransferError> for DepositError {
                 panic!("Bug: Fee is not required for deposit")
             }
             LedgerTransferError::FeeUnknown => panic!("Bug: Fee is not required for deposit"),
+            LedgerTransferError::OutcomeUnknown { .. } => {
+                panic!("Bug: a deposit with an unknown outcome is journaled by its caller")
+            }
+            LedgerTransferError::TooOld { .. } => {
+                panic!("Bug: deposits are sent right after their creation")
+            }
             LedgerTransferError::AmountTooLow {
                 minimum_amount,
                 failed_amount,
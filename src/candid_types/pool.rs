@@ -1,4 +1,14 @@
-use crate::{libraries::safe_cast::u256_to_nat, pool::types::PoolState};
+use crate::{
+    libraries::safe_cast::{big_uint_to_u256, u256_to_nat},
+    pool::{
+        dynamic_fee::DynamicFeeConfigError,
+        invariants::{InvariantReport, InvariantViolation},
+        policy::{CreationFee, PoolCreationPolicy, TokenFilter},
+        types::{DynamicFeeConfig, PoolState},
+    },
+};
+
+use super::position::MintPositionError;
 
 use super::*;
 
@@ -8,15 +18,83 @@ pub struct CreatePoolArgs {
     pub token_b: Principal,
     pub fee: Nat,
     pub sqrt_price_x96: Nat,
+    pub request_id: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
 pub enum CreatePoolError {
+    LockedPrincipal,
+    RequestInProgress, // a call with the same request_id is still being processed
+    CreationFeeNotPaid { token: Principal, amount: Nat },
+    DelistedToken(Principal),
     DuplicatedTokens,
+    InitialPositionRequired, // the policy has a minimum initial liquidity, see create_pool_and_mint
     InvalidFeeAmount,
     InvalidSqrtPriceX96,
     InvalidToken(Principal),
     PoolAlreadyExists,
+    TokenNotAllowed(Principal),
+}
+
+/// Creates a pool and mints its first position from the internal balance of the caller.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CreatePoolAndMintArgs {
+    pub pool: CreatePoolArgs,
+    pub tick_lower: Int,
+    pub tick_upper: Int,
+    pub amount0_max: Nat,
+    pub amount1_max: Nat,
+}
+
+/// The first position of a pool created by `create_and_initialize_pool_with_position`, the amounts
+/// missing from the internal balance of the caller are deposited from `from_subaccount`.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct InitialPositionArgs {
+    pub tick_lower: Int,
+    pub tick_upper: Int,
+    pub amount0_max: Nat,
+    pub amount1_max: Nat,
+    pub from_subaccount: Option<Subaccount>,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CreatePoolAndMintResult {
+    pub pool_id: CandidPoolId,
+    pub liquidity: Nat,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub enum CreatePoolAndMintError {
+    LockedPrincipal,
+    RequestInProgress,
+    CreatePool(CreatePoolError),
+    MintPosition(MintPositionError),
+    LiquidityBelowMinimum { min_liquidity: Nat },
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidCreationFee {
+    pub token: Principal,
+    pub amount: Nat,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidTokenFilter {
+    Allowlist(Vec<Principal>),
+    Denylist(Vec<Principal>),
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidPoolCreationPolicy {
+    pub creation_fee: Option<CandidCreationFee>, // paid from the internal balance of the creator
+    pub token_filter: Option<CandidTokenFilter>,
+    pub min_initial_liquidity: Option<Nat>, // pools can only be created with create_pool_and_mint
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub enum SetPoolCreationPolicyError {
+    InvalidCreationFee,
+    InvalidMinInitialLiquidity,
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
@@ -26,7 +104,7 @@ pub struct CandidPoolId {
     pub fee: Nat,
 }
 
-#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
 pub struct CandidPoolState {
     pub sqrt_price_x96: Nat,           // Current price in Q64.96 format
     pub tick: Int,                     // Current tick index
@@ -44,6 +122,125 @@ pub struct CandidPoolState {
     pub pool_reserves1: Nat,
     pub generated_swap_fee0: Nat,
     pub generated_swap_fee1: Nat,
+    pub fee_on_transfer: bool, // one of the tokens is credited through balance differences
+    pub creator: Option<Principal>, // None for pools created before creators were recorded
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct DonateArgs {
+    pub pool: CandidPoolId,
+    pub amount0: Nat,
+    pub amount1: Nat,
+    pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum DonateError {
+    LockedPrincipal,
+    RequestInProgress,
+    InvalidPoolFee,
+    PoolNotInitialized,
+    InvalidAmount,
+    NoInRangeLiquidity, // donations are shared by in range liquidity, so there should be some
+    DepositError(DepositError),
+    InsufficientBalance,
+    FeeGrowthOverflow,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidDynamicFeeConfig {
+    pub min_fee: Nat,        // lower bound of the LP fee in pips
+    pub max_fee: Nat,        // upper bound of the LP fee in pips
+    pub fee_per_tick: Nat,   // pips added for each tick of average hourly movement
+    pub lookback_hours: Nat, // hourly buckets used to measure volatility
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidPoolFee {
+    pub fee_tier: Nat,         // static fee of the pool id
+    pub current_lp_fee: Nat,   // LP fee the next swap would be charged
+    pub current_swap_fee: Nat, // LP fee combined with protocol fee
+    pub dynamic_fee: Option<CandidDynamicFeeConfig>,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub enum SetDynamicFeeError {
+    PoolNotFound,
+    InvalidFeeBounds,
+    InvalidLookback,
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidInvariantViolation {
+    LiquidityNetNotZero {
+        sum: Int,
+    },
+    LiquidityNetOverflow,
+    TickNotInBitmap {
+        tick: Int,
+        liquidity_gross: Nat,
+    },
+    UninitializedTickInBitmap {
+        tick: Int,
+    },
+    ActiveLiquidityMismatch {
+        pool_liquidity: Nat,
+        ticks_liquidity: Int,
+    },
+    PositionLiquidityMismatch {
+        tick: Int,
+        tick_liquidity_gross: Nat,
+        positions_liquidity_gross: Nat,
+        tick_liquidity_net: Int,
+        positions_liquidity_net: Int,
+    },
+    NegativeReserve {
+        token: Principal,
+        reserve: Nat,
+    },
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidInvariantReport {
+    pub pool: CandidPoolId,
+    pub checked_ticks: u64,
+    pub checked_positions: u64,
+    pub violations: Vec<CandidInvariantViolation>,
+}
+
+impl From<DynamicFeeConfigError> for SetDynamicFeeError {
+    fn from(value: DynamicFeeConfigError) -> Self {
+        match value {
+            DynamicFeeConfigError::InvalidFeeBounds => Self::InvalidFeeBounds,
+            DynamicFeeConfigError::InvalidLookback => Self::InvalidLookback,
+        }
+    }
+}
+
+impl TryFrom<CandidDynamicFeeConfig> for DynamicFeeConfig {
+    type Error = SetDynamicFeeError;
+
+    fn try_from(value: CandidDynamicFeeConfig) -> Result<Self, Self::Error> {
+        let to_u32 = |n: Nat, err: SetDynamicFeeError| u32::try_from(n.0).map_err(|_| err);
+        Ok(DynamicFeeConfig {
+            min_fee: to_u32(value.min_fee, SetDynamicFeeError::InvalidFeeBounds)?,
+            max_fee: to_u32(value.max_fee, SetDynamicFeeError::InvalidFeeBounds)?,
+            fee_per_tick: to_u32(value.fee_per_tick, SetDynamicFeeError::InvalidFeeBounds)?,
+            lookback_hours: to_u32(value.lookback_hours, SetDynamicFeeError::InvalidLookback)?,
+        })
+    }
+}
+
+impl From<DynamicFeeConfig> for CandidDynamicFeeConfig {
+    fn from(value: DynamicFeeConfig) -> Self {
+        CandidDynamicFeeConfig {
+            min_fee: value.min_fee.into(),
+            max_fee: value.max_fee.into(),
+            fee_per_tick: value.fee_per_tick.into(),
+            lookback_hours: value.lookback_hours.into(),
+        }
+    }
 }
 
 impl TryFrom<CandidPoolId> for PoolId {
@@ -83,10 +280,77 @@ impl From<PoolState> for CandidPoolState {
             pool_reserves1: u256_to_nat(value.pool_reserve1),
             generated_swap_fee0: u256_to_nat(value.generated_swap_fee0),
             generated_swap_fee1: u256_to_nat(value.generated_swap_fee1),
+            fee_on_transfer: false,
+            creator: value.creator,
+        }
+    }
+}
+
+impl From<CreationFee> for CandidCreationFee {
+    fn from(value: CreationFee) -> Self {
+        CandidCreationFee {
+            token: value.token,
+            amount: u256_to_nat(value.amount),
         }
     }
 }
 
+impl From<TokenFilter> for CandidTokenFilter {
+    fn from(value: TokenFilter) -> Self {
+        match value {
+            TokenFilter::Allowlist(tokens) => Self::Allowlist(tokens),
+            TokenFilter::Denylist(tokens) => Self::Denylist(tokens),
+        }
+    }
+}
+
+impl From<PoolCreationPolicy> for CandidPoolCreationPolicy {
+    fn from(value: PoolCreationPolicy) -> Self {
+        CandidPoolCreationPolicy {
+            creation_fee: value.creation_fee.map(CandidCreationFee::from),
+            token_filter: value.token_filter.map(CandidTokenFilter::from),
+            min_initial_liquidity: value.min_initial_liquidity.map(Nat::from),
+        }
+    }
+}
+
+impl TryFrom<CandidPoolCreationPolicy> for PoolCreationPolicy {
+    type Error = SetPoolCreationPolicyError;
+
+    fn try_from(value: CandidPoolCreationPolicy) -> Result<Self, Self::Error> {
+        let creation_fee = value
+            .creation_fee
+            .map(|fee| {
+                let amount = big_uint_to_u256(fee.amount.0)
+                    .map_err(|_| SetPoolCreationPolicyError::InvalidCreationFee)?;
+                if amount == 0 {
+                    return Err(SetPoolCreationPolicyError::InvalidCreationFee);
+                }
+                Ok(CreationFee {
+                    token: fee.token,
+                    amount,
+                })
+            })
+            .transpose()?;
+        let token_filter = value.token_filter.map(|filter| match filter {
+            CandidTokenFilter::Allowlist(tokens) => TokenFilter::Allowlist(tokens),
+            CandidTokenFilter::Denylist(tokens) => TokenFilter::Denylist(tokens),
+        });
+        let min_initial_liquidity = value
+            .min_initial_liquidity
+            .map(|liquidity| match u128::try_from(liquidity.0) {
+                Ok(liquidity) if liquidity > 0 => Ok(liquidity),
+                _ => Err(SetPoolCreationPolicyError::InvalidMinInitialLiquidity),
+            })
+            .transpose()?;
+        Ok(PoolCreationPolicy {
+            creation_fee,
+            token_filter,
+            min_initial_liquidity,
+        })
+    }
+}
+
 impl From<PoolId> for CandidPoolId {
     fn from(value: PoolId) -> CandidPoolId {
         let fee: Nat = value.fee.0.into();
@@ -98,3 +362,59 @@ impl From<PoolId> for CandidPoolId {
         }
     }
 }
+
+impl From<InvariantViolation> for CandidInvariantViolation {
+    fn from(value: InvariantViolation) -> Self {
+        match value {
+            InvariantViolation::LiquidityNetNotZero { sum } => {
+                Self::LiquidityNetNotZero { sum: sum.into() }
+            }
+            InvariantViolation::LiquidityNetOverflow => Self::LiquidityNetOverflow,
+            InvariantViolation::TickNotInBitmap {
+                tick,
+                liquidity_gross,
+            } => Self::TickNotInBitmap {
+                tick: tick.into(),
+                liquidity_gross: liquidity_gross.into(),
+            },
+            InvariantViolation::UninitializedTickInBitmap { tick } => {
+                Self::UninitializedTickInBitmap { tick: tick.into() }
+            }
+            InvariantViolation::ActiveLiquidityMismatch {
+                pool_liquidity,
+                ticks_liquidity,
+            } => Self::ActiveLiquidityMismatch {
+                pool_liquidity: pool_liquidity.into(),
+                ticks_liquidity: ticks_liquidity.into(),
+            },
+            InvariantViolation::PositionLiquidityMismatch {
+                tick,
+                tick_liquidity_gross,
+                positions_liquidity_gross,
+                tick_liquidity_net,
+                positions_liquidity_net,
+            } => Self::PositionLiquidityMismatch {
+                tick: tick.into(),
+                tick_liquidity_gross: tick_liquidity_gross.into(),
+                positions_liquidity_gross: positions_liquidity_gross.into(),
+                tick_liquidity_net: tick_liquidity_net.into(),
+                positions_liquidity_net: positions_liquidity_net.into(),
+            },
+            InvariantViolation::NegativeReserve { token, reserve } => Self::NegativeReserve {
+                token,
+                reserve: u256_to_nat(reserve),
+            },
+        }
+    }
+}
+
+impl From<InvariantReport> for CandidInvariantReport {
+    fn from(value: InvariantReport) -> Self {
+        CandidInvariantReport {
+            pool: value.pool_id.into(),
+            checked_ticks: value.checked_ticks,
+            checked_positions: value.checked_positions,
+            violations: value.violations.into_iter().map(Into::into).collect(),
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use crate::{
-    historical::types::{HistoryBucket, PoolHistory},
+    historical::{
+        sqrt_price_x96_to_price,
+        types::{HistoryBucket, PoolHistory},
+        TimeFrame,
+    },
     libraries::safe_cast::u256_to_nat,
 };
 
@@ -23,6 +27,11 @@ pub struct CandidHistoryBucket {
     pub last_sqrtx96_price: Nat,
     pub inrange_liquidity: Nat,
     pub active_tick: Int,
+    /// Activity counts, None for buckets opened before they were counted.
+    pub swap_count: Option<u64>,
+    pub unique_traders: Option<u64>,
+    pub mint_count: Option<u64>,
+    pub burn_count: Option<u64>,
 }
 
 /// Stores historical data for a pool across multiple timeframes.
@@ -83,6 +92,93 @@ impl From<HistoryBucket> for CandidHistoryBucket {
             last_sqrtx96_price: u256_to_nat(value.last_sqrtx96_price),
             inrange_liquidity: value.inrange_liquidity.into(),
             active_tick: value.active_tick.into(),
+            swap_count: value.swap_count,
+            unique_traders: value.unique_traders,
+            mint_count: value.mint_count,
+            burn_count: value.burn_count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidTimeFrame {
+    FiveMinutes,
+    FifteenMinutes,
+    Hourly,
+    FourHours,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl From<CandidTimeFrame> for TimeFrame {
+    fn from(value: CandidTimeFrame) -> Self {
+        match value {
+            CandidTimeFrame::FiveMinutes => TimeFrame::FiveMinutes,
+            CandidTimeFrame::FifteenMinutes => TimeFrame::FifteenMinutes,
+            CandidTimeFrame::Hourly => TimeFrame::Hourly,
+            CandidTimeFrame::FourHours => TimeFrame::FourHours,
+            CandidTimeFrame::Daily => TimeFrame::Daily,
+            CandidTimeFrame::Weekly => TimeFrame::Weekly,
+            CandidTimeFrame::Monthly => TimeFrame::Monthly,
+            CandidTimeFrame::Yearly => TimeFrame::Yearly,
+        }
+    }
+}
+
+/// Open, high, low and close prices of a pool during a bucket.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+pub struct CandidCandle {
+    pub start_timestamp: u64, // in seconds
+    pub end_timestamp: u64,
+    pub open_sqrtx96_price: Nat,
+    pub high_sqrtx96_price: Nat,
+    pub low_sqrtx96_price: Nat,
+    pub close_sqrtx96_price: Nat,
+    pub open_tick: Int,
+    pub high_tick: Int,
+    pub low_tick: Int,
+    pub close_tick: Int,
+    /// Prices of token0 in token1 in whole tokens, None when the decimals of a token are unknown.
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub close: Option<f64>,
+    pub volume_token0: Nat,
+    pub volume_token1: Nat,
+}
+
+impl CandidCandle {
+    /// Buckets captured before candles were tracked only have a close, it is used for the open,
+    /// high and low.
+    pub fn from_bucket(bucket: HistoryBucket, decimals: Option<(u8, u8)>) -> Self {
+        let close = bucket.last_sqrtx96_price;
+        let open = bucket.open_sqrtx96_price.unwrap_or(close);
+        let high = bucket.high_sqrtx96_price.unwrap_or(close);
+        let low = bucket.low_sqrtx96_price.unwrap_or(close);
+        let to_price = |sqrt_price_x96| {
+            decimals.map(|(decimals0, decimals1)| {
+                sqrt_price_x96_to_price(sqrt_price_x96, decimals0, decimals1)
+            })
+        };
+        Self {
+            start_timestamp: bucket.start_timestamp,
+            end_timestamp: bucket.end_timestamp,
+            open_sqrtx96_price: u256_to_nat(open),
+            high_sqrtx96_price: u256_to_nat(high),
+            low_sqrtx96_price: u256_to_nat(low),
+            close_sqrtx96_price: u256_to_nat(close),
+            open_tick: bucket.open_tick.unwrap_or(bucket.active_tick).into(),
+            high_tick: bucket.high_tick.unwrap_or(bucket.active_tick).into(),
+            low_tick: bucket.low_tick.unwrap_or(bucket.active_tick).into(),
+            close_tick: bucket.active_tick.into(),
+            open: to_price(open),
+            high: to_price(high),
+            low: to_price(low),
+            close: to_price(close),
+            volume_token0: u256_to_nat(bucket.swap_volume_token0_during_bucket),
+            volume_token1: u256_to_nat(bucket.swap_volume_token1_during_bucket),
         }
     }
 }
@@ -63,11 +63,13 @@ pub struct BurnPositionArgs {
     pub pool: CandidPoolId,
     pub amount0_min: Nat,
     pub amount1_min: Nat,
+    pub request_id: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
 pub enum BurnPositionError {
     LockedPrincipal,
+    RequestInProgress,
     PositionNotFound,
     PoolNotInitialized,
     InvalidTick,
@@ -89,11 +91,13 @@ pub struct MintPositionArgs {
     pub amount0_max: Nat,
     pub amount1_max: Nat,
     pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize, Eq, PartialEq)]
 pub enum MintPositionError {
     LockedPrincipal,
+    RequestInProgress,
     InvalidPoolFee,
     PoolNotInitialized,
     PositionAlreadyExists,
@@ -116,11 +120,13 @@ pub struct IncreaseLiquidityArgs {
     pub amount0_max: Nat,
     pub amount1_max: Nat,
     pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
 pub enum IncreaseLiquidityError {
     LockedPrincipal,
+    RequestInProgress,
     InvalidPoolFee,
     PoolNotInitialized,
     InvalidTick,
@@ -143,11 +149,13 @@ pub struct DecreaseLiquidityArgs {
     pub liquidity: Nat,
     pub amount0_min: Nat,
     pub amount1_min: Nat,
+    pub request_id: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
 pub enum DecreaseLiquidityError {
     LockedPrincipal,
+    RequestInProgress,
     PositionNotFound,
     PoolNotInitialized,
     InvalidTick,
@@ -171,6 +179,7 @@ pub struct CollectFeesSuccess {
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
 pub enum CollectFeesError {
     LockedPrincipal,
+    RequestInProgress,
     PositionNotFound,
     FeeOverflow,
     NoFeeToCollect,
@@ -1,4 +1,4 @@
-use crate::pool::swap::InnerSwapError;
+use crate::{libraries::safe_cast::u256_to_nat, pool::swap::InnerSwapError, quote::QuoteDetails};
 
 use super::{pool::CandidPoolId, swap::CandidPathKey, *};
 
@@ -26,6 +26,13 @@ pub enum QuoteArgs {
     QuoteExactOutput(QuoteExactParams),
 }
 
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+// quoted amount with the fee in pips charged by each hop of the path
+pub struct CandidQuoteDetails {
+    pub amount: Nat,
+    pub swap_fees: Vec<Nat>,
+}
+
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
 pub enum QuoteError {
     InvalidAmount,
@@ -51,3 +58,12 @@ impl From<InnerSwapError> for QuoteError {
         }
     }
 }
+
+impl From<QuoteDetails> for CandidQuoteDetails {
+    fn from(value: QuoteDetails) -> Self {
+        CandidQuoteDetails {
+            amount: u256_to_nat(value.amount),
+            swap_fees: value.swap_fees.into_iter().map(Nat::from).collect(),
+        }
+    }
+}
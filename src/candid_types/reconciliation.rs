@@ -0,0 +1,34 @@
+use crate::{libraries::safe_cast::u256_to_nat, reconciliation::types::TokenReconciliation};
+
+use super::*;
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidTokenReconciliation {
+    pub token: Principal,
+    pub checked_at: u64,
+    pub user_balances: Nat,
+    pub pool_reserves: Nat,
+    pub protocol_balance: Nat,
+    pub liabilities: Nat, // sum of the above
+    pub ledger_balance: Nat,
+    pub surplus: Nat,
+    pub deficit: Nat,
+    pub last_deficit_at: Option<u64>,
+}
+
+impl From<(Principal, TokenReconciliation)> for CandidTokenReconciliation {
+    fn from((token, reconciliation): (Principal, TokenReconciliation)) -> Self {
+        CandidTokenReconciliation {
+            token,
+            checked_at: reconciliation.checked_at,
+            user_balances: u256_to_nat(reconciliation.user_balances),
+            pool_reserves: u256_to_nat(reconciliation.pool_reserves),
+            protocol_balance: u256_to_nat(reconciliation.protocol_balance),
+            liabilities: u256_to_nat(reconciliation.liabilities()),
+            ledger_balance: u256_to_nat(reconciliation.ledger_balance),
+            surplus: u256_to_nat(reconciliation.surplus()),
+            deficit: u256_to_nat(reconciliation.deficit()),
+            last_deficit_at: reconciliation.last_deficit_at,
+        }
+    }
+}
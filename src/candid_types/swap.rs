@@ -34,6 +34,7 @@ pub struct ExactInputSingleParams {
     pub amount_in: Nat,
     pub amount_out_minimum: Nat,
     pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
 }
 
 /// Parameters for a multi-hop exact-input swap
@@ -44,6 +45,7 @@ pub struct ExactInputParams {
     pub amount_in: Nat,
     pub amount_out_minimum: Nat,
     pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
 }
 
 /// Parameters for a single-hop exact-output swap
@@ -54,6 +56,7 @@ pub struct ExactOutputSingleParams {
     pub amount_out: Nat,
     pub amount_in_maximum: Nat,
     pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
 }
 
 /// notice Parameters for a multi-hop exact-output swap
@@ -64,6 +67,7 @@ pub struct ExactOutputParams {
     pub amount_out: Nat,
     pub amount_in_maximum: Nat,
     pub from_subaccount: Option<Subaccount>,
+    pub request_id: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
@@ -74,6 +78,17 @@ pub enum SwapArgs {
     ExactOutput(ExactOutputParams),
 }
 
+impl SwapArgs {
+    pub fn request_id(&self) -> Option<Vec<u8>> {
+        match self {
+            SwapArgs::ExactInputSingle(params) => params.request_id.clone(),
+            SwapArgs::ExactInput(params) => params.request_id.clone(),
+            SwapArgs::ExactOutputSingle(params) => params.request_id.clone(),
+            SwapArgs::ExactOutput(params) => params.request_id.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
 pub enum SwapFailedReason {
     PriceLimitAlreadyExceeded, // means there is a bug, should not happen
@@ -92,6 +107,7 @@ pub enum SwapFailedReason {
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
 pub enum SwapError {
     LockedPrincipal,
+    RequestInProgress,
     InvalidPoolFee,
     PoolNotInitialized,
     NoInRangeLiquidity,
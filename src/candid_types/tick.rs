@@ -5,7 +5,7 @@ use crate::{
 
 use super::*;
 
-#[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
 pub struct CandidTickInfo {
     pub tick: Int,                      // Tick number
     pub liquidity_gross: Nat,           // Total liquidity at this tick
@@ -0,0 +1,160 @@
+use crate::{
+    libraries::safe_cast::u256_to_nat,
+    tokens::{
+        accounting::HAIRCUT_DENOMINATOR,
+        types::{
+            AccountingMode, TokenMetadata, TokenSettings, TokenValidationConfig, ValidationStrategy,
+        },
+    },
+};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidAccountingMode {
+    Standard,
+    BalanceDifference, // credits the measured change of the canister balance
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidTokenSettings {
+    pub accounting_mode: CandidAccountingMode,
+    pub transfer_haircut: Nat, // share of every transfer lost on the way in pips
+    pub paused_at: Option<u64>, // deposits and withdrawals of the token are paused since
+    pub pause_on_deficit: Option<bool>,
+    pub delisted_at: Option<u64>, // no new pool can be created with the token since
+}
+
+/// Entry of the token registry, with the flags set by the controllers.
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct CandidToken {
+    pub token: Principal,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: u8,
+    pub logo: Option<String>,
+    pub standards: Option<Vec<String>>,
+    pub transfer_fee: Option<Nat>,
+    pub usd_price: Option<f64>,
+    pub price_updated_at: Option<u64>,
+    pub refreshed_at: Option<u64>,
+    pub paused_at: Option<u64>,
+    pub delisted_at: Option<u64>,
+}
+
+impl CandidToken {
+    pub fn new(token: Principal, metadata: TokenMetadata, settings: TokenSettings) -> Self {
+        Self {
+            token,
+            name: metadata.name,
+            symbol: metadata.symbol,
+            decimals: metadata.decimals,
+            logo: metadata.logo,
+            standards: metadata.standards,
+            transfer_fee: metadata.transfer_fee.map(u256_to_nat),
+            usd_price: metadata.usd_price,
+            price_updated_at: metadata.price_updated_at,
+            refreshed_at: metadata.refreshed_at,
+            paused_at: settings.paused_at,
+            delisted_at: settings.delisted_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SetTokenSettingsError {
+    InvalidTransferHaircut,
+}
+
+impl From<AccountingMode> for CandidAccountingMode {
+    fn from(value: AccountingMode) -> Self {
+        match value {
+            AccountingMode::Standard => Self::Standard,
+            AccountingMode::BalanceDifference => Self::BalanceDifference,
+        }
+    }
+}
+
+impl From<CandidAccountingMode> for AccountingMode {
+    fn from(value: CandidAccountingMode) -> Self {
+        match value {
+            CandidAccountingMode::Standard => Self::Standard,
+            CandidAccountingMode::BalanceDifference => Self::BalanceDifference,
+        }
+    }
+}
+
+impl From<TokenSettings> for CandidTokenSettings {
+    fn from(value: TokenSettings) -> Self {
+        CandidTokenSettings {
+            accounting_mode: value.accounting_mode.into(),
+            transfer_haircut: value.transfer_haircut.into(),
+            paused_at: value.paused_at,
+            pause_on_deficit: value.pause_on_deficit,
+            delisted_at: value.delisted_at,
+        }
+    }
+}
+
+impl TryFrom<CandidTokenSettings> for TokenSettings {
+    type Error = SetTokenSettingsError;
+
+    fn try_from(value: CandidTokenSettings) -> Result<Self, Self::Error> {
+        let transfer_haircut = u32::try_from(value.transfer_haircut.0)
+            .map_err(|_| SetTokenSettingsError::InvalidTransferHaircut)?;
+
+        // a token losing everything on transfer can not be supported
+        if transfer_haircut >= HAIRCUT_DENOMINATOR {
+            return Err(SetTokenSettingsError::InvalidTransferHaircut);
+        }
+
+        Ok(TokenSettings {
+            accounting_mode: value.accounting_mode.into(),
+            transfer_haircut,
+            paused_at: value.paused_at,
+            pause_on_deficit: value.pause_on_deficit,
+            delisted_at: value.delisted_at,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CandidValidationStrategy {
+    Proxy,
+    Direct,
+    ProxyThenDirect, // the ledger is queried when the proxy fails
+}
+
+#[derive(Debug, Clone, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CandidTokenValidationConfig {
+    pub strategy: CandidValidationStrategy,
+    pub probe_allowance: bool,
+}
+
+impl From<TokenValidationConfig> for CandidTokenValidationConfig {
+    fn from(value: TokenValidationConfig) -> Self {
+        let strategy = match value.strategy {
+            ValidationStrategy::Proxy => CandidValidationStrategy::Proxy,
+            ValidationStrategy::Direct => CandidValidationStrategy::Direct,
+            ValidationStrategy::ProxyThenDirect => CandidValidationStrategy::ProxyThenDirect,
+        };
+        CandidTokenValidationConfig {
+            strategy,
+            probe_allowance: value.probe_allowance,
+        }
+    }
+}
+
+impl From<CandidTokenValidationConfig> for TokenValidationConfig {
+    fn from(value: CandidTokenValidationConfig) -> Self {
+        let strategy = match value.strategy {
+            CandidValidationStrategy::Proxy => ValidationStrategy::Proxy,
+            CandidValidationStrategy::Direct => ValidationStrategy::Direct,
+            CandidValidationStrategy::ProxyThenDirect => ValidationStrategy::ProxyThenDirect,
+        };
+        TokenValidationConfig {
+            strategy,
+            probe_allowance: value.probe_allowance,
+        }
+    }
+}
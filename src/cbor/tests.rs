@@ -21,12 +21,24 @@ struct U256Container {
     pub value: u256,
 }
 
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+struct OptU256Container {
+    #[cbor(n(0), with = "crate::cbor::u256::option")]
+    pub value: Option<u256>,
+}
+
 #[derive(Debug, PartialEq, Eq, Encode, Decode)]
 struct U128Container {
     #[cbor(n(0), with = "crate::cbor::u128")]
     pub value: u128,
 }
 
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+struct OptU128Container {
+    #[cbor(n(0), with = "crate::cbor::u128::option")]
+    pub value: Option<u128>,
+}
+
 #[derive(Debug, PartialEq, Eq, Encode, Decode)]
 struct I128Container {
     #[cbor(n(0), with = "crate::cbor::i128")]
@@ -57,6 +69,12 @@ struct OptPrincipalContainer {
     pub value: Option<Principal>,
 }
 
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
+struct VecPrincipalContainer {
+    #[cbor(n(0), with = "crate::cbor::principal::vec")]
+    pub value: Vec<Principal>,
+}
+
 proptest! {
     #[test]
     fn u256_encoding_roundtrip((hi, lo) in (any::<u128>(), any::<u128>())) {
@@ -72,6 +90,13 @@ proptest! {
         })?;
     }
 
+    #[test]
+    fn opt_u256_encoding_roundtrip(v in proptest::option::of((any::<u128>(), any::<u128>()))) {
+        check_roundtrip(&OptU256Container {
+            value: v.map(|(hi, lo)| U256([hi, lo])),
+        })?;
+    }
+
     #[test]
     fn u128_encoding_roundtrip(n in any::<u128>()) {
         check_roundtrip(&U128Container {
@@ -86,6 +111,13 @@ proptest! {
         })?;
     }
 
+    #[test]
+    fn opt_u128_encoding_roundtrip(v in proptest::option::of(any::<u128>())) {
+        check_roundtrip(&OptU128Container {
+            value: v,
+        })?;
+    }
+
      #[test]
     fn i128_encoding_roundtrip(n in any::<i128>()) {
         check_roundtrip(&I128Container {
@@ -129,4 +161,11 @@ proptest! {
             value: p.map(|principal| Principal::from_slice(&principal)),
         })?;
     }
+
+    #[test]
+    fn vec_principal_encoding_roundtrip(p in pvec(pvec(any::<u8>(), 0..30), 0..10)) {
+        check_roundtrip(&VecPrincipalContainer {
+            value: p.iter().map(|principal| Principal::from_slice(principal)).collect(),
+        })?;
+    }
 }
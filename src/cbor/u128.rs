@@ -54,3 +54,24 @@ pub fn encode<Ctx, W: Write>(
     }
     Ok(())
 }
+
+pub mod option {
+    use super::*;
+    use minicbor::{Decode, Encode};
+
+    #[derive(Encode, Decode)]
+    #[cbor(transparent)]
+    struct CborU128(#[cbor(n(0), with = "crate::cbor::u128")] pub u128);
+
+    pub fn decode<Ctx>(d: &mut Decoder<'_>, ctx: &mut Ctx) -> Result<Option<u128>, Error> {
+        Ok(Option::<CborU128>::decode(d, ctx)?.map(|n| n.0))
+    }
+
+    pub fn encode<Ctx, W: Write>(
+        v: &Option<u128>,
+        e: &mut Encoder<W>,
+        ctx: &mut Ctx,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        (*v).map(CborU128).encode(e, ctx)
+    }
+}
@@ -55,3 +55,24 @@ pub fn encode<Ctx, W: Write>(
     }
     Ok(())
 }
+
+pub mod option {
+    use super::*;
+    use minicbor::{Decode, Encode};
+
+    #[derive(Encode, Decode)]
+    #[cbor(transparent)]
+    struct CborU256(#[cbor(n(0), with = "crate::cbor::u256")] pub u256);
+
+    pub fn decode<Ctx>(d: &mut Decoder<'_>, ctx: &mut Ctx) -> Result<Option<u256>, Error> {
+        Ok(Option::<CborU256>::decode(d, ctx)?.map(|n| n.0))
+    }
+
+    pub fn encode<Ctx, W: Write>(
+        v: &Option<u256>,
+        e: &mut Encoder<W>,
+        ctx: &mut Ctx,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        (*v).map(CborU256).encode(e, ctx)
+    }
+}
@@ -5,6 +5,7 @@ use crate::{
     balances::types::{UserBalance, UserBalanceKey},
     candid_types::position::CollectFeesError,
     events::{Event, EventType},
+    historical::{pools_before, update_pool_history},
     libraries::balance_delta::BalanceDelta,
     pool::{
         modify_liquidity::{modify_liquidity, ModifyLiquidityParams},
@@ -86,7 +87,9 @@ pub fn execute_collect_fees(
             },
             UserBalance(final_balance.amount1().as_u256()),
         );
+        let pools_before = pools_before(s, &event);
         s.apply_modify_liquidity_buffer_state(success_result.buffer_state);
+        update_pool_history(s, pools_before, &event);
 
         s.record_event(event);
     });
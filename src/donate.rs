@@ -0,0 +1,109 @@
+use candid::Principal;
+use ethnum::U256;
+
+use crate::{
+    balances::types::{UserBalance, UserBalanceKey},
+    candid_types::pool::DonateError,
+    events::{Event, EventType},
+    historical::{pools_before, update_pool_history},
+    libraries::{constants::Q128, full_math::mul_div},
+    state::{mutate_state, read_state},
+    validation::donate_args::ValidatedDonateArgs,
+};
+
+/// Donates tokens from the caller's internal balance to the liquidity providers that are in range
+/// at the current price. The donated amounts are added to the pool's global fee growth, so they
+/// are collected by positions exactly like swap fees, and they are counted as generated fees in
+/// the pool history.
+pub fn execute_donate(
+    caller: Principal,
+    validated_args: ValidatedDonateArgs,
+    timestamp: u64,
+) -> Result<(), DonateError> {
+    let pool_id = validated_args.pool_id;
+    let mut pool = read_state(|s| s.get_pool(&pool_id)).ok_or(DonateError::PoolNotInitialized)?;
+
+    // in range liquidity may have changed while the deposit was in flight
+    if pool.liquidity == 0 {
+        return Err(DonateError::NoInRangeLiquidity);
+    }
+
+    let token0_key = UserBalanceKey {
+        user: caller,
+        token: pool_id.token0,
+    };
+    let token1_key = UserBalanceKey {
+        user: caller,
+        token: pool_id.token1,
+    };
+
+    let (balance0, balance1) = read_state(|s| {
+        (
+            s.get_user_balance(&token0_key).0,
+            s.get_user_balance(&token1_key).0,
+        )
+    });
+
+    let balance0_after = balance0
+        .checked_sub(validated_args.amount0)
+        .ok_or(DonateError::InsufficientBalance)?;
+    let balance1_after = balance1
+        .checked_sub(validated_args.amount1)
+        .ok_or(DonateError::InsufficientBalance)?;
+
+    // fee_growth_global += amount * Q128 / liquidity, overflow of fee growth is accepted like in
+    // swaps, since only the difference between two growths is ever used
+    let liquidity = U256::from(pool.liquidity);
+    let fee_growth_delta0 = mul_div(validated_args.amount0, *Q128, liquidity)
+        .map_err(|_| DonateError::FeeGrowthOverflow)?;
+    let fee_growth_delta1 = mul_div(validated_args.amount1, *Q128, liquidity)
+        .map_err(|_| DonateError::FeeGrowthOverflow)?;
+
+    pool.fee_growth_global_0_x128 = pool
+        .fee_growth_global_0_x128
+        .wrapping_add(fee_growth_delta0);
+    pool.fee_growth_global_1_x128 = pool
+        .fee_growth_global_1_x128
+        .wrapping_add(fee_growth_delta1);
+
+    pool.pool_reserve0 = pool
+        .pool_reserve0
+        .checked_add(validated_args.amount0)
+        .ok_or(DonateError::InvalidAmount)?;
+    pool.pool_reserve1 = pool
+        .pool_reserve1
+        .checked_add(validated_args.amount1)
+        .ok_or(DonateError::InvalidAmount)?;
+
+    // donations are income for liquidity providers, so they show up in fee history
+    pool.generated_swap_fee0 = pool
+        .generated_swap_fee0
+        .checked_add(validated_args.amount0)
+        .unwrap_or(U256::MAX);
+    pool.generated_swap_fee1 = pool
+        .generated_swap_fee1
+        .checked_add(validated_args.amount1)
+        .unwrap_or(U256::MAX);
+
+    let event = Event {
+        timestamp,
+        payload: EventType::Donated {
+            pool_id: pool_id.clone(),
+            amount0: validated_args.amount0,
+            amount1: validated_args.amount1,
+            principal: caller,
+        },
+    };
+
+    // Batch state updates
+    mutate_state(|s| {
+        s.update_user_balance(token0_key, UserBalance(balance0_after));
+        s.update_user_balance(token1_key, UserBalance(balance1_after));
+        let pools_before = pools_before(s, &event);
+        s.set_pool(pool_id, pool);
+        update_pool_history(s, pools_before, &event);
+        s.record_event(event);
+    });
+
+    Ok(())
+}
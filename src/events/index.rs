@@ -0,0 +1,216 @@
+use candid::Principal;
+use minicbor::{Decode, Encode};
+
+use crate::pool::types::{PoolFee, PoolId};
+
+use super::{Event, EventType};
+
+/// The type of an event, without its data.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventKind {
+    #[n(0)]
+    CreatedPool,
+    #[n(1)]
+    MintedPosition,
+    #[n(2)]
+    IncreasedLiquidity,
+    #[n(3)]
+    BurntPosition,
+    #[n(4)]
+    DecreasedLiquidity,
+    #[n(5)]
+    CollectedFees,
+    #[n(6)]
+    Swap,
+    #[n(7)]
+    Donated,
+    #[n(8)]
+    InternalTransfer,
+    #[n(9)]
+    Deposited,
+    #[n(10)]
+    Withdrawn,
+    #[n(11)]
+    WithdrawalRolledBack,
+    #[n(12)]
+    TransferFeeUpdated,
+}
+
+/// A secondary index over the held events, see `State::record_event`.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IndexedBy {
+    #[n(0)]
+    Principal(#[cbor(n(0), with = "crate::cbor::principal")] Principal),
+    #[n(1)]
+    Pool(#[n(0)] PoolId),
+    #[n(2)]
+    Kind(#[n(0)] EventKind),
+}
+
+/// Entry of an index, the events of an index are ordered by event index.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventIndexKey {
+    #[n(0)]
+    pub indexed_by: IndexedBy,
+    #[n(1)]
+    pub event_index: u64,
+}
+
+impl EventType {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            EventType::CreatedPool { .. } => EventKind::CreatedPool,
+            EventType::MintedPosition { .. } => EventKind::MintedPosition,
+            EventType::IncreasedLiquidity { .. } => EventKind::IncreasedLiquidity,
+            EventType::BurntPosition { .. } => EventKind::BurntPosition,
+            EventType::DecreasedLiquidity { .. } => EventKind::DecreasedLiquidity,
+            EventType::CollectedFees { .. } => EventKind::CollectedFees,
+            EventType::Swap { .. } => EventKind::Swap,
+            EventType::Donated { .. } => EventKind::Donated,
+            EventType::InternalTransfer { .. } => EventKind::InternalTransfer,
+            EventType::Deposited { .. } => EventKind::Deposited,
+            EventType::Withdrawn { .. } => EventKind::Withdrawn,
+            EventType::WithdrawalRolledBack { .. } => EventKind::WithdrawalRolledBack,
+            EventType::TransferFeeUpdated { .. } => EventKind::TransferFeeUpdated,
+        }
+    }
+
+    /// Users whose positions or balances the event changed, spenders included.
+    pub fn principals(&self) -> Vec<Principal> {
+        match self {
+            EventType::CreatedPool { creator, .. } => creator.iter().copied().collect(),
+            EventType::TransferFeeUpdated { .. } => vec![],
+            EventType::MintedPosition { principal, .. }
+            | EventType::IncreasedLiquidity { principal, .. }
+            | EventType::BurntPosition { principal, .. }
+            | EventType::DecreasedLiquidity { principal, .. }
+            | EventType::CollectedFees { principal, .. }
+            | EventType::Swap { principal, .. }
+            | EventType::Donated { principal, .. } => vec![*principal],
+            EventType::InternalTransfer {
+                from, to, spender, ..
+            } => {
+                let mut principals = vec![*from];
+                for principal in [Some(*to), *spender].into_iter().flatten() {
+                    if !principals.contains(&principal) {
+                        principals.push(principal);
+                    }
+                }
+                principals
+            }
+            EventType::Deposited { user, .. }
+            | EventType::Withdrawn { user, .. }
+            | EventType::WithdrawalRolledBack { user, .. } => vec![*user],
+        }
+    }
+
+    /// Pools the event changed, in path order for swaps.
+    pub fn pool_ids(&self) -> Vec<PoolId> {
+        match self {
+            EventType::CreatedPool {
+                token0,
+                token1,
+                pool_fee,
+                ..
+            } => vec![PoolId {
+                token0: *token0,
+                token1: *token1,
+                fee: PoolFee(*pool_fee),
+            }],
+            EventType::MintedPosition {
+                created_position: position,
+                ..
+            }
+            | EventType::IncreasedLiquidity {
+                modified_position: position,
+                ..
+            }
+            | EventType::BurntPosition {
+                burnt_position: position,
+                ..
+            }
+            | EventType::DecreasedLiquidity {
+                modified_position: position,
+                ..
+            }
+            | EventType::CollectedFees { position, .. } => vec![position.pool_id.clone()],
+            EventType::Swap { swap_args, .. } => {
+                let mut pool_ids = swap_args.pool_ids();
+                // a path can go through the same pool twice
+                let mut seen = vec![];
+                pool_ids.retain(|pool_id| {
+                    let first = !seen.contains(pool_id);
+                    seen.push(pool_id.clone());
+                    first
+                });
+                pool_ids
+            }
+            EventType::Donated { pool_id, .. } => vec![pool_id.clone()],
+            EventType::InternalTransfer { .. }
+            | EventType::Deposited { .. }
+            | EventType::Withdrawn { .. }
+            | EventType::WithdrawalRolledBack { .. }
+            | EventType::TransferFeeUpdated { .. } => vec![],
+        }
+    }
+
+    /// The indexes the event is listed in.
+    pub fn indexed_by(&self) -> Vec<IndexedBy> {
+        let mut indexes: Vec<IndexedBy> = self
+            .principals()
+            .into_iter()
+            .map(IndexedBy::Principal)
+            .collect();
+        indexes.extend(self.pool_ids().into_iter().map(IndexedBy::Pool));
+        indexes.push(IndexedBy::Kind(self.kind()));
+        indexes
+    }
+}
+
+/// Conditions on the events returned by `get_events_filtered`, all set conditions should hold.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    pub principal: Option<Principal>,
+    pub pool_id: Option<PoolId>,
+    /// Any of the kinds, None for all of them.
+    pub kinds: Option<Vec<EventKind>>,
+    /// Inclusive, in nanoseconds.
+    pub start_time: Option<u64>,
+    /// Exclusive, in nanoseconds.
+    pub end_time: Option<u64>,
+}
+
+impl EventFilter {
+    /// The indexes listing every matching event, the principal and pool indexes are the most
+    /// selective. None when all events should be scanned.
+    pub fn indexes(&self) -> Option<Vec<IndexedBy>> {
+        if let Some(principal) = self.principal {
+            return Some(vec![IndexedBy::Principal(principal)]);
+        }
+        if let Some(pool_id) = &self.pool_id {
+            return Some(vec![IndexedBy::Pool(pool_id.clone())]);
+        }
+        self.kinds
+            .as_ref()
+            .map(|kinds| kinds.iter().map(|kind| IndexedBy::Kind(*kind)).collect())
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        self.principal
+            .is_none_or(|principal| event.payload.principals().contains(&principal))
+            && self
+                .pool_id
+                .as_ref()
+                .is_none_or(|pool_id| event.payload.pool_ids().contains(pool_id))
+            && self
+                .kinds
+                .as_ref()
+                .is_none_or(|kinds| kinds.contains(&event.payload.kind()))
+            && self
+                .start_time
+                .is_none_or(|start_time| event.timestamp >= start_time)
+            && self
+                .end_time
+                .is_none_or(|end_time| event.timestamp < end_time)
+    }
+}
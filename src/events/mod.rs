@@ -1,10 +1,19 @@
+pub mod index;
 pub mod storage;
 
+#[cfg(test)]
+mod tests;
+
 use candid::Principal;
-use ethnum::U256;
+use ethnum::{I256, U256};
 use minicbor::{Decode, Encode};
 
-use crate::{position::types::PositionKey, validation::swap_args::ValidatedSwapArgs};
+use crate::{
+    icrc_client::memo::MemoKind,
+    pool::{policy::CreationFee, types::PoolId},
+    position::types::PositionKey,
+    validation::swap_args::ValidatedSwapArgs,
+};
 
 /// The event describing the  minter state transition.
 #[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
@@ -17,6 +26,20 @@ pub enum EventType {
         token1: Principal,
         #[n(2)]
         pool_fee: u32,
+        /// The initial price and transfer fees of the pool, missing for events recorded before
+        /// the event log could be replayed.
+        #[cbor(n(3), with = "crate::cbor::u256::option")]
+        sqrt_price_x96: Option<U256>,
+        #[cbor(n(4), with = "crate::cbor::u256::option")]
+        token0_transfer_fee: Option<U256>,
+        #[cbor(n(5), with = "crate::cbor::u256::option")]
+        token1_transfer_fee: Option<U256>,
+        /// The caller that created the pool and the creation fee it paid, missing for events
+        /// recorded before the pool creation policy.
+        #[cbor(n(6), with = "crate::cbor::principal::option")]
+        creator: Option<Principal>,
+        #[n(7)]
+        creation_fee: Option<CreationFee>,
     },
     #[n(1)]
     MintedPosition {
@@ -91,7 +114,128 @@ pub enum EventType {
         swap_args: ValidatedSwapArgs,
         #[cbor(n(3), with = "crate::cbor::principal")]
         principal: Principal,
+        /// Total fee in pips charged by each hop in path order, missing for events recorded
+        /// before fees could change per swap.
+        #[n(4)]
+        swap_fees: Option<Vec<u32>>,
+        /// The parameters and balance delta of each hop in path order, missing for events
+        /// recorded before the event log could be replayed.
+        #[n(5)]
+        hops: Option<Vec<SwapHop>>,
+    },
+    #[n(7)]
+    Donated {
+        #[n(0)]
+        pool_id: PoolId,
+        #[cbor(n(1), with = "crate::cbor::u256")]
+        amount0: U256,
+        #[cbor(n(2), with = "crate::cbor::u256")]
+        amount1: U256,
+        #[cbor(n(3), with = "crate::cbor::principal")]
+        principal: Principal,
+    },
+    #[n(8)]
+    InternalTransfer {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        token: Principal,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        from: Principal,
+        #[cbor(n(2), with = "crate::cbor::principal")]
+        to: Principal,
+        #[cbor(n(3), with = "crate::cbor::u256")]
+        amount: U256,
+        /// The caller of `transfer_from_internal`, None when the owner transferred itself.
+        #[cbor(n(4), with = "crate::cbor::principal::option")]
+        spender: Option<Principal>,
+    },
+    /// Tokens received from the ledger and credited to the internal balance of `user`.
+    #[n(9)]
+    Deposited {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        token: Principal,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        user: Principal,
+        /// The credited amount, less than the transferred amount for fee-on-transfer tokens.
+        #[cbor(n(2), with = "crate::cbor::u256")]
+        amount: U256,
+        /// None when the ledger did not return the index of the transfer block.
+        #[n(3)]
+        block_index: Option<u64>,
+        #[n(4)]
+        memo: MemoKind,
+        /// The internal balance of `user` after the deposit.
+        #[cbor(n(5), with = "crate::cbor::u256")]
+        balance: U256,
+    },
+    /// Tokens debited from the internal balance of `user` and sent on the ledger, refunds have
+    /// the `Refund` memo kind.
+    #[n(10)]
+    Withdrawn {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        token: Principal,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        user: Principal,
+        /// The debited amount, transfer fee included.
+        #[cbor(n(2), with = "crate::cbor::u256")]
+        amount: U256,
+        #[cbor(n(3), with = "crate::cbor::u256")]
+        transfer_fee: U256,
+        /// None while the outcome of the transfer is unknown, the amount stays debited until the
+        /// journal resolves it.
+        #[n(4)]
+        block_index: Option<u64>,
+        #[n(5)]
+        memo: MemoKind,
+        /// The internal balance of `user` after the withdrawal.
+        #[cbor(n(6), with = "crate::cbor::u256")]
+        balance: U256,
+        /// What the transfer cost on top of `amount` that the balance of `user` could not cover,
+        /// a deficit of the token left to the other holders.
+        #[cbor(n(7), with = "crate::cbor::u256::option")]
+        uncovered_cost: Option<U256>,
+    },
+    /// A withdrawal the ledger rejected, the debited amount was credited back to `user`.
+    #[n(11)]
+    WithdrawalRolledBack {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        token: Principal,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        user: Principal,
+        #[cbor(n(2), with = "crate::cbor::u256")]
+        amount: U256,
+        #[n(3)]
+        memo: MemoKind,
+        /// The internal balance of `user` after the rollback.
+        #[cbor(n(4), with = "crate::cbor::u256")]
+        balance: U256,
+        #[n(5)]
+        reason: String,
     },
+    /// The ledger rejected a transfer with `BadFee`, the transfer fee stored in the pools of
+    /// `token` was updated.
+    #[n(12)]
+    TransferFeeUpdated {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        token: Principal,
+        #[cbor(n(1), with = "crate::cbor::u256")]
+        transfer_fee: U256,
+    },
+}
+
+/// A single pool swap of a `Swap` event, `amount_specified` and `zero_for_one` are the parameters
+/// passed to `swap_inner`, the deltas are from the point of view of the pool.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+pub struct SwapHop {
+    #[n(0)]
+    pub pool_id: PoolId,
+    #[n(1)]
+    pub zero_for_one: bool,
+    #[cbor(n(2), with = "crate::cbor::i256")]
+    pub amount_specified: I256,
+    #[cbor(n(3), with = "crate::cbor::i256")]
+    pub amount0_delta: I256,
+    #[cbor(n(4), with = "crate::cbor::i256")]
+    pub amount1_delta: I256,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq)]
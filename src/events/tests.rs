@@ -0,0 +1,195 @@
+use candid::Principal;
+use ethnum::{I256, U256};
+
+use super::{
+    index::{EventFilter, EventKind},
+    Event, EventType,
+};
+use crate::{
+    archive::types::ArchiveInfo,
+    icrc_client::memo::MemoKind,
+    pool::types::{PoolFee, PoolId},
+    state::{mutate_state, read_state},
+    validation::swap_args::ValidatedSwapArgs,
+};
+
+fn principal(byte: u8) -> Principal {
+    Principal::from_slice(&[byte])
+}
+
+fn pool_id(fee: u32) -> PoolId {
+    PoolId {
+        token0: principal(1),
+        token1: principal(2),
+        fee: PoolFee(fee),
+    }
+}
+
+fn swap(user: Principal, pool_id: PoolId, amount_in: u64) -> EventType {
+    EventType::Swap {
+        final_amount_in: U256::from(amount_in),
+        final_amount_out: U256::from(amount_in / 2),
+        swap_args: ValidatedSwapArgs::ExactInputSingle {
+            pool_id,
+            zero_for_one: true,
+            amount_in: I256::from(amount_in),
+            amount_out_minimum: I256::ZERO,
+            from_subaccount: None,
+            token_in: principal(1),
+            token_out: principal(2),
+        },
+        principal: user,
+        swap_fees: None,
+        hops: None,
+    }
+}
+
+fn deposit(user: Principal, amount: u64) -> EventType {
+    EventType::Deposited {
+        token: principal(1),
+        user,
+        amount: U256::from(amount),
+        block_index: Some(0),
+        memo: MemoKind::Deposit,
+        balance: U256::from(amount),
+    }
+}
+
+fn record(timestamp: u64, payload: EventType) {
+    mutate_state(|s| s.record_event(Event { timestamp, payload }));
+}
+
+fn filtered(filter: EventFilter, cursor: u64, limit: usize) -> (Vec<u64>, Option<u64>) {
+    let (events, next_cursor) =
+        read_state(|s| s.get_filtered_events(&filter, cursor, limit, 1_000));
+    (
+        events.into_iter().map(|(index, _)| index).collect(),
+        next_cursor,
+    )
+}
+
+// events 0 to 5, alice swaps in both pools and bob in the 0.3% pool
+fn record_history() {
+    record(10, deposit(principal(10), 1_000));
+    record(20, swap(principal(10), pool_id(3_000), 100));
+    record(30, deposit(principal(11), 1_000));
+    record(40, swap(principal(11), pool_id(3_000), 200));
+    record(
+        50,
+        EventType::InternalTransfer {
+            token: principal(1),
+            from: principal(11),
+            to: principal(10),
+            amount: U256::from(5_u8),
+            spender: Some(principal(12)),
+        },
+    );
+    record(60, swap(principal(10), pool_id(500), 300));
+}
+
+#[test]
+fn events_should_be_filtered_by_principal_pool_and_type() {
+    record_history();
+
+    let alice = EventFilter {
+        principal: Some(principal(10)),
+        ..Default::default()
+    };
+    assert_eq!(filtered(alice.clone(), 0, 100), (vec![0, 1, 4, 5], None));
+    let spender = EventFilter {
+        principal: Some(principal(12)),
+        ..Default::default()
+    };
+    assert_eq!(filtered(spender, 0, 100), (vec![4], None));
+
+    let pool = EventFilter {
+        pool_id: Some(pool_id(3_000)),
+        ..Default::default()
+    };
+    assert_eq!(filtered(pool, 0, 100), (vec![1, 3], None));
+
+    let alice_in_pool = EventFilter {
+        pool_id: Some(pool_id(500)),
+        ..alice.clone()
+    };
+    assert_eq!(filtered(alice_in_pool, 0, 100), (vec![5], None));
+
+    let kinds = EventFilter {
+        kinds: Some(vec![EventKind::InternalTransfer, EventKind::Deposited]),
+        ..Default::default()
+    };
+    assert_eq!(filtered(kinds, 0, 100), (vec![0, 2, 4], None));
+
+    let time_range = EventFilter {
+        start_time: Some(20),
+        end_time: Some(50),
+        ..Default::default()
+    };
+    assert_eq!(filtered(time_range, 0, 100), (vec![1, 2, 3], None));
+
+    let alice_swaps: Vec<u64> = read_state(|s| s.get_user_swaps(principal(10), 10))
+        .into_iter()
+        .map(|(index, _)| index)
+        .collect();
+    assert_eq!(alice_swaps, vec![5, 1]);
+}
+
+#[test]
+fn filtered_events_should_be_paginated() {
+    record_history();
+
+    let swaps = EventFilter {
+        kinds: Some(vec![EventKind::Swap]),
+        ..Default::default()
+    };
+    assert_eq!(filtered(swaps.clone(), 0, 2), (vec![1, 3], Some(5)));
+    assert_eq!(filtered(swaps.clone(), 5, 2), (vec![5], None));
+
+    // a page stops once too many events were read
+    let alice_transfers = EventFilter {
+        principal: Some(principal(10)),
+        kinds: Some(vec![EventKind::InternalTransfer]),
+        ..Default::default()
+    };
+    let (events, next_cursor) = read_state(|s| s.get_filtered_events(&alice_transfers, 0, 100, 2));
+    assert!(events.is_empty());
+    assert_eq!(next_cursor, Some(4));
+    assert_eq!(filtered(alice_transfers, 4, 100), (vec![4], None));
+}
+
+#[test]
+fn archived_events_should_be_removed_from_the_indexes() {
+    record_history();
+
+    mutate_state(|s| {
+        s.set_archive(
+            0,
+            ArchiveInfo {
+                canister_id: principal(20),
+                end: 0,
+                installed: Some(true),
+            },
+        );
+        s.record_archived_events(0, 3);
+    });
+
+    let alice = EventFilter {
+        principal: Some(principal(10)),
+        ..Default::default()
+    };
+    assert_eq!(filtered(alice.clone(), 0, 100), (vec![4, 5], None));
+    assert_eq!(
+        filtered(EventFilter::default(), 0, 100),
+        (vec![3, 4, 5], None)
+    );
+    let time_range = EventFilter {
+        start_time: Some(10),
+        end_time: Some(45),
+        ..Default::default()
+    };
+    assert_eq!(filtered(time_range, 0, 100), (vec![3], None));
+
+    // indexing again on upgrade does not duplicate entries
+    mutate_state(|s| s.index_held_events());
+    assert_eq!(filtered(alice, 0, 100), (vec![4, 5], None));
+}
@@ -2,7 +2,13 @@
 mod tests;
 
 use candid::Principal;
-use std::{cell::RefCell, collections::HashSet};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
 
 /// A guard to prevent double-spending or concurrent non-swap operations for a principal.
 /// Swap operations can run concurrently with unique swap numbers, but other operations
@@ -18,16 +24,41 @@ pub struct Guard {
     principal: Principal,
     is_swap_guard: bool,
     swap_number: Option<u32>,
+    request_id: Option<Vec<u8>>,
 }
 
+/// Transfers waiting for a token guard in arrival order, as (ticket, waker of the waiting call).
+type TokenQueue = VecDeque<(u64, Option<Waker>)>;
+
 thread_local! {
     static GUARDED_PRINCIPALS: RefCell<HashSet<Guard>> = RefCell::new(HashSet::default());
+    static GUARDED_TOKENS: RefCell<HashSet<Principal>> = RefCell::new(HashSet::default());
+    static TOKEN_QUEUES: RefCell<BTreeMap<Principal, TokenQueue>> =
+        RefCell::new(BTreeMap::default());
+    static NEXT_TICKET: Cell<u64> = const { Cell::new(0) };
 }
 
 /// Errors that can occur when creating a principal guard.
 #[derive(Debug, PartialEq, Eq)]
 pub enum PrincipalGuardError {
     AlreadyProcessing { principal: Principal },
+    DuplicateRequest { principal: Principal }, // the same request id is already being processed
+}
+
+/// Returns an error if a call with the same request id is already in flight for the principal.
+fn check_duplicate_request(
+    guards: &HashSet<Guard>,
+    principal: Principal,
+    request_id: &Option<Vec<u8>>,
+) -> Result<(), PrincipalGuardError> {
+    if request_id.is_some()
+        && guards
+            .iter()
+            .any(|g| g.principal == principal && &g.request_id == request_id)
+    {
+        return Err(PrincipalGuardError::DuplicateRequest { principal });
+    }
+    Ok(())
 }
 
 /// Mutates the guarded principals set using the provided closure.
@@ -42,7 +73,18 @@ impl PrincipalGuard {
     /// Creates a new swap guard for a principal.
     /// Fails if a non-swap guard exists for the principal.
     pub fn new_swap_guard(principal: Principal) -> Result<Self, PrincipalGuardError> {
+        Self::new_swap_guard_for_request(principal, None)
+    }
+
+    /// Creates a new swap guard for a principal, tagged with the caller provided request id.
+    /// Fails with `DuplicateRequest` if a call with the same request id is in flight.
+    pub fn new_swap_guard_for_request(
+        principal: Principal,
+        request_id: Option<Vec<u8>>,
+    ) -> Result<Self, PrincipalGuardError> {
         mutate_guarded_principals(|guards| {
+            check_duplicate_request(guards, principal, &request_id)?;
+
             // Check for non-swap guards
             if guards
                 .iter()
@@ -63,6 +105,7 @@ impl PrincipalGuard {
                 principal,
                 is_swap_guard: true,
                 swap_number: Some(next_swap_number),
+                request_id,
             };
 
             guards.insert(guard.clone());
@@ -73,7 +116,18 @@ impl PrincipalGuard {
     /// Creates a new general guard for a principal.
     /// Fails if any guard (swap or non-swap) exists for the principal.
     pub fn new_general_guard(principal: Principal) -> Result<Self, PrincipalGuardError> {
+        Self::new_general_guard_for_request(principal, None)
+    }
+
+    /// Creates a new general guard for a principal, tagged with the caller provided request id.
+    /// Fails with `DuplicateRequest` if a call with the same request id is in flight.
+    pub fn new_general_guard_for_request(
+        principal: Principal,
+        request_id: Option<Vec<u8>>,
+    ) -> Result<Self, PrincipalGuardError> {
         mutate_guarded_principals(|guards| {
+            check_duplicate_request(guards, principal, &request_id)?;
+
             if guards.iter().any(|g| g.principal == principal) {
                 return Err(PrincipalGuardError::AlreadyProcessing { principal });
             }
@@ -82,6 +136,7 @@ impl PrincipalGuard {
                 principal,
                 is_swap_guard: false,
                 swap_number: None,
+                request_id,
             };
 
             guards.insert(guard.clone());
@@ -112,3 +167,147 @@ impl Drop for PrincipalGuard {
         });
     }
 }
+
+/// A guard serializing the ledger transfers of a token that uses balance difference accounting,
+/// so that the canister balance measured around one transfer is not moved by another one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenGuard {
+    token: Principal,
+}
+
+/// Errors that can occur when creating a token guard.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenGuardError {
+    AlreadyProcessing { token: Principal },
+}
+
+impl TokenGuard {
+    /// Creates a new guard for a token.
+    /// Fails if a transfer of the token is already in flight or waiting for the guard.
+    pub fn new(token: Principal) -> Result<Self, TokenGuardError> {
+        if is_token_queued(&token) || !try_lock_token(token) {
+            return Err(TokenGuardError::AlreadyProcessing { token });
+        }
+        Ok(TokenGuard { token })
+    }
+
+    /// Waits until no other transfer of the token is in flight and takes the guard, calls waiting
+    /// for the same token are served in arrival order.
+    pub fn acquire(token: Principal) -> TokenGuardFuture {
+        TokenGuardFuture {
+            token,
+            ticket: None,
+        }
+    }
+}
+
+impl Drop for TokenGuard {
+    fn drop(&mut self) {
+        GUARDED_TOKENS.with(|s| {
+            s.borrow_mut().remove(&self.token);
+        });
+        wake_next_in_queue(&self.token);
+    }
+}
+
+/// Future of a token guard, holds a place in the queue of the token until it resolves. Dropping it
+/// gives the place up.
+#[derive(Debug)]
+pub struct TokenGuardFuture {
+    token: Principal,
+    ticket: Option<u64>,
+}
+
+impl Future for TokenGuardFuture {
+    type Output = TokenGuard;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<TokenGuard> {
+        let token = self.token;
+        let Some(ticket) = self.ticket else {
+            if !is_token_queued(&token) && try_lock_token(token) {
+                return Poll::Ready(TokenGuard { token });
+            }
+            let ticket = NEXT_TICKET.with(|next| next.replace(next.get() + 1));
+            TOKEN_QUEUES.with(|queues| {
+                queues
+                    .borrow_mut()
+                    .entry(token)
+                    .or_default()
+                    .push_back((ticket, Some(cx.waker().clone())));
+            });
+            self.ticket = Some(ticket);
+            return Poll::Pending;
+        };
+
+        let is_next = TOKEN_QUEUES.with(|queues| {
+            queues
+                .borrow()
+                .get(&token)
+                .and_then(|queue| queue.front())
+                .is_some_and(|(front, _)| *front == ticket)
+        });
+        if is_next && try_lock_token(token) {
+            remove_from_queue(&token, ticket);
+            self.ticket = None;
+            return Poll::Ready(TokenGuard { token });
+        }
+
+        TOKEN_QUEUES.with(|queues| {
+            if let Some(entry) = queues
+                .borrow_mut()
+                .get_mut(&token)
+                .and_then(|queue| queue.iter_mut().find(|(queued, _)| *queued == ticket))
+            {
+                entry.1 = Some(cx.waker().clone());
+            }
+        });
+        Poll::Pending
+    }
+}
+
+impl Drop for TokenGuardFuture {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            remove_from_queue(&self.token, ticket);
+            // the call might have been woken up to take the guard
+            if GUARDED_TOKENS.with(|s| !s.borrow().contains(&self.token)) {
+                wake_next_in_queue(&self.token);
+            }
+        }
+    }
+}
+
+fn try_lock_token(token: Principal) -> bool {
+    GUARDED_TOKENS.with(|s| s.borrow_mut().insert(token))
+}
+
+fn is_token_queued(token: &Principal) -> bool {
+    TOKEN_QUEUES.with(|queues| queues.borrow().contains_key(token))
+}
+
+fn remove_from_queue(token: &Principal, ticket: u64) {
+    TOKEN_QUEUES.with(|queues| {
+        let mut queues = queues.borrow_mut();
+        if let Some(queue) = queues.get_mut(token) {
+            queue.retain(|(queued, _)| *queued != ticket);
+            if queue.is_empty() {
+                queues.remove(token);
+            }
+        }
+    });
+}
+
+// Wakes the call first in the queue of the token. The waker is called once the queue is released,
+// waking can poll the call right away
+fn wake_next_in_queue(token: &Principal) {
+    let waker = TOKEN_QUEUES.with(|queues| {
+        queues
+            .borrow_mut()
+            .get_mut(token)
+            .and_then(|queue| queue.front_mut())
+            .and_then(|(_, waker)| waker.take())
+    });
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
@@ -1,8 +1,19 @@
 #[cfg(test)]
 mod tests {
-    use crate::guard::{mutate_guarded_principals, PrincipalGuard, PrincipalGuardError};
+    use crate::guard::{
+        mutate_guarded_principals, PrincipalGuard, PrincipalGuardError, TokenGuard, TokenGuardError,
+    };
 
     use candid::Principal;
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+    };
 
     // Helper to create a Principal from a byte slice
     fn create_principal(id: u8) -> Principal {
@@ -156,4 +167,123 @@ mod tests {
             Err(PrincipalGuardError::AlreadyProcessing { principal })
         );
     }
+
+    #[test]
+    fn test_token_guard_blocks_concurrent_transfers_of_the_same_token() {
+        let token = create_principal(7);
+        let other_token = create_principal(8);
+
+        let guard = TokenGuard::new(token).unwrap();
+        assert_eq!(
+            TokenGuard::new(token),
+            Err(TokenGuardError::AlreadyProcessing { token })
+        );
+        let _other_guard = TokenGuard::new(other_token).unwrap();
+
+        // Verify the token is released on drop
+        drop(guard);
+        assert!(TokenGuard::new(token).is_ok());
+    }
+
+    // Counts how many times a waiting call was woken up
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_token_guard_queues_concurrent_transfers_in_arrival_order() {
+        let token = create_principal(9);
+        let first_waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let second_waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let (first_waker_ref, second_waker_ref) = (
+            Waker::from(first_waker.clone()),
+            Waker::from(second_waker.clone()),
+        );
+        let mut first_cx = Context::from_waker(&first_waker_ref);
+        let mut second_cx = Context::from_waker(&second_waker_ref);
+
+        let mut holder = pin!(TokenGuard::acquire(token));
+        let Poll::Ready(guard) = holder.as_mut().poll(&mut first_cx) else {
+            panic!("a free token should be acquired right away");
+        };
+
+        let mut first = pin!(TokenGuard::acquire(token));
+        let mut second = pin!(TokenGuard::acquire(token));
+        assert!(first.as_mut().poll(&mut first_cx).is_pending());
+        assert!(second.as_mut().poll(&mut second_cx).is_pending());
+        // calls waiting in line keep the token from being taken
+        assert_eq!(
+            TokenGuard::new(token),
+            Err(TokenGuardError::AlreadyProcessing { token })
+        );
+
+        // only the first call in line is woken up and served
+        drop(guard);
+        assert_eq!(first_waker.0.load(Ordering::SeqCst), 1);
+        assert_eq!(second_waker.0.load(Ordering::SeqCst), 0);
+        assert!(second.as_mut().poll(&mut second_cx).is_pending());
+        let Poll::Ready(guard) = first.as_mut().poll(&mut first_cx) else {
+            panic!("the first call in line should be served");
+        };
+
+        drop(guard);
+        assert_eq!(second_waker.0.load(Ordering::SeqCst), 1);
+        let Poll::Ready(guard) = second.as_mut().poll(&mut second_cx) else {
+            panic!("the second call in line should be served");
+        };
+        drop(guard);
+        assert!(TokenGuard::new(token).is_ok());
+    }
+
+    #[test]
+    fn test_token_guard_gives_up_place_in_line_when_dropped() {
+        let token = create_principal(10);
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker_ref = Waker::from(waker.clone());
+        let mut cx = Context::from_waker(&waker_ref);
+
+        let guard = TokenGuard::new(token).unwrap();
+        let mut cancelled = Box::pin(TokenGuard::acquire(token));
+        let mut waiting = pin!(TokenGuard::acquire(token));
+        assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+
+        // the call behind a cancelled one is served next
+        drop(cancelled);
+        drop(guard);
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+        assert!(waiting.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn test_duplicate_request_id_is_reported_as_in_progress() {
+        clear_guards();
+        let principal = create_principal(1);
+        let request_id = Some(vec![1, 2, 3]);
+
+        let guard =
+            PrincipalGuard::new_swap_guard_for_request(principal, request_id.clone()).unwrap();
+        assert_eq!(
+            PrincipalGuard::new_swap_guard_for_request(principal, request_id.clone()),
+            Err(PrincipalGuardError::DuplicateRequest { principal })
+        );
+        assert_eq!(
+            PrincipalGuard::new_general_guard_for_request(principal, request_id.clone()),
+            Err(PrincipalGuardError::DuplicateRequest { principal })
+        );
+
+        // Other request ids and other principals are not affected
+        let _guard2 = PrincipalGuard::new_swap_guard_for_request(principal, Some(vec![4])).unwrap();
+        let _guard3 = PrincipalGuard::new_swap_guard_for_request(principal, None).unwrap();
+        let _guard4 =
+            PrincipalGuard::new_general_guard_for_request(create_principal(2), request_id.clone())
+                .unwrap();
+
+        drop(guard);
+        assert!(PrincipalGuard::new_swap_guard_for_request(principal, request_id).is_ok());
+    }
 }
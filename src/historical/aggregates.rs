@@ -0,0 +1,396 @@
+// Token and protocol wide aggregates, updated by the same events as the pool history from the
+// change of each pool: volume, fees and reserves of a token are the sum of the changes of its pools
+// Tokens keep a daily history as long as the daily frame of pools, protocol stats are totals
+
+use std::collections::BTreeMap;
+
+use candid::Principal;
+use ethnum::U256;
+
+use crate::{
+    events::{Event, EventType},
+    pool::types::{PoolId, PoolState},
+    state::State,
+    tokens::{metadata::PriceStatus, types::TokenMetadata},
+};
+
+use super::{
+    calculate_start_and_during_bucket_timestamp, nanos_to_seconds,
+    types::{HistoryBucketKey, ProtocolStats, TokenBucket, TokenBucketKey, TokenStats},
+    TimeFrame, MAX_BUCKETS,
+};
+
+/// Activity of a pool over the last 24 hours, see `top_pools`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PoolStats {
+    pub pool_id: PoolId,
+    pub swap_count: u64,
+    pub unique_traders: u64,
+    pub mint_count: u64,
+    pub burn_count: u64,
+    pub swap_volume0: U256,
+    pub swap_volume1: U256,
+    pub fees_generated0: U256,
+    pub fees_generated1: U256,
+    pub reserve0: U256,
+    pub reserve1: U256,
+}
+
+/// Price of a pool token used by `pool_analytics`, None when no metadata is cached.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TokenPrice {
+    pub token: Principal,
+    pub metadata: Option<TokenMetadata>,
+    pub status: PriceStatus,
+}
+
+/// Usd valuation of a pool, values are None when the price or decimals of a token used are
+/// missing, and computed from stale prices otherwise, see the status of `token0` and `token1`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PoolAnalytics {
+    pub stats: PoolStats,
+    pub token0: TokenPrice,
+    pub token1: TokenPrice,
+    pub tvl_usd: Option<f64>,
+    pub volume_24h_usd: Option<f64>,
+    pub fees_24h_usd: Option<f64>,
+    /// Yearly fees of the last 24 hours over the tvl in percent, protocol fees included.
+    pub fee_apr: Option<f64>,
+}
+
+/// Orders of `top_pools`, all descending. Pools of different tokens have no common unit, so they
+/// are only ranked by counts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PoolSortBy {
+    SwapCount,
+    UniqueTraders,
+    LiquidityChanges,
+}
+
+/// All-time amounts of one of the tokens of a pool.
+struct TokenSide {
+    token: Principal,
+    swap_volume: U256,
+    fees_generated: U256,
+    reserves: U256,
+}
+
+fn token_sides(pool_id: &PoolId, pool: &PoolState) -> [TokenSide; 2] {
+    [
+        TokenSide {
+            token: pool_id.token0,
+            swap_volume: pool.swap_volume0_all_time,
+            fees_generated: pool.generated_swap_fee0,
+            reserves: pool.pool_reserve0,
+        },
+        TokenSide {
+            token: pool_id.token1,
+            swap_volume: pool.swap_volume1_all_time,
+            fees_generated: pool.generated_swap_fee1,
+            reserves: pool.pool_reserve1,
+        },
+    ]
+}
+
+/// Change of a token in the pools changed by an event.
+#[derive(Default)]
+struct TokenDelta {
+    swap_volume: U256,
+    fees_generated: U256,
+    reserves_before: U256,
+    reserves_after: U256,
+}
+
+/// Adds the changes of the pools changed by an event to the stats of their tokens and of the
+/// protocol, `pools_before` being the pools before the change.
+pub fn update_aggregates(s: &mut State, pools_before: &[(PoolId, PoolState)], event: &Event) {
+    let timestamp = nanos_to_seconds(event.timestamp);
+    let is_swap = matches!(event.payload, EventType::Swap { .. });
+
+    let mut deltas: BTreeMap<Principal, TokenDelta> = BTreeMap::new();
+    for (pool_id, before) in pools_before {
+        let Some(after) = s.get_pool(pool_id) else {
+            continue;
+        };
+        for (side_before, side_after) in token_sides(pool_id, before)
+            .into_iter()
+            .zip(token_sides(pool_id, &after))
+        {
+            let delta = deltas.entry(side_before.token).or_default();
+            delta.swap_volume = delta.swap_volume.saturating_add(
+                side_after
+                    .swap_volume
+                    .saturating_sub(side_before.swap_volume),
+            );
+            delta.fees_generated = delta.fees_generated.saturating_add(
+                side_after
+                    .fees_generated
+                    .saturating_sub(side_before.fees_generated),
+            );
+            delta.reserves_before = delta.reserves_before.saturating_add(side_before.reserves);
+            delta.reserves_after = delta.reserves_after.saturating_add(side_after.reserves);
+        }
+    }
+
+    for (token, delta) in deltas {
+        let mut stats = s.get_token_stats(&token).unwrap_or_default();
+        stats.swap_count += u64::from(is_swap);
+        stats.swap_volume = stats.swap_volume.saturating_add(delta.swap_volume);
+        stats.fees_generated = stats.fees_generated.saturating_add(delta.fees_generated);
+        stats.reserves = stats
+            .reserves
+            .saturating_add(delta.reserves_after)
+            .saturating_sub(delta.reserves_before);
+
+        let mut bucket = current_token_bucket(s, token, timestamp, &stats);
+        bucket.swap_count += u64::from(is_swap);
+        bucket.swap_volume = bucket.swap_volume.saturating_add(delta.swap_volume);
+        bucket.fees_generated = bucket.fees_generated.saturating_add(delta.fees_generated);
+        bucket.reserves = stats.reserves;
+        s.set_token_bucket(
+            TokenBucketKey {
+                token,
+                start_timestamp: bucket.start_timestamp,
+            },
+            bucket,
+        );
+        s.set_token_stats(token, stats);
+    }
+
+    let mut protocol_stats = s.get_protocol_stats();
+    protocol_stats.swap_count += u64::from(is_swap);
+    for user in event.payload.principals() {
+        if s.add_protocol_user(user) {
+            protocol_stats.unique_users += 1;
+        }
+    }
+    s.set_protocol_stats(protocol_stats);
+}
+
+/// Counts a new pool in the stats of its tokens and of the protocol.
+pub fn record_created_pool(s: &mut State, pool_id: &PoolId) {
+    for token in [pool_id.token0, pool_id.token1] {
+        let mut stats = s.get_token_stats(&token).unwrap_or_default();
+        stats.pool_count += 1;
+        s.set_token_stats(token, stats);
+    }
+    let mut protocol_stats = s.get_protocol_stats();
+    protocol_stats.pool_count += 1;
+    s.set_protocol_stats(protocol_stats);
+}
+
+/// Seeds the aggregates from the all-time volume, fees and reserves of the existing pools, runs
+/// once, on the first install or upgrade with aggregates. Swaps and users are counted from then on.
+pub fn seed_aggregates(s: &mut State, timestamp_secs: u64) {
+    if s.get_protocol_stats().tracked_since != 0 {
+        return;
+    }
+    let pools = s.get_pools();
+    for (pool_id, pool) in &pools {
+        for side in token_sides(pool_id, pool) {
+            let mut stats = s.get_token_stats(&side.token).unwrap_or_default();
+            stats.pool_count += 1;
+            stats.swap_volume = stats.swap_volume.saturating_add(side.swap_volume);
+            stats.fees_generated = stats.fees_generated.saturating_add(side.fees_generated);
+            stats.reserves = stats.reserves.saturating_add(side.reserves);
+            s.set_token_stats(side.token, stats);
+        }
+    }
+    s.set_protocol_stats(ProtocolStats {
+        tracked_since: timestamp_secs,
+        pool_count: pools.len() as u64,
+        ..Default::default()
+    });
+}
+
+/// Opens the current day of every token not opened by an event yet.
+pub fn roll_token_buckets(s: &mut State, timestamp_secs: u64) {
+    for (token, stats) in s.get_all_token_stats() {
+        let bucket = current_token_bucket(s, token, timestamp_secs, &stats);
+        let key = TokenBucketKey {
+            token,
+            start_timestamp: bucket.start_timestamp,
+        };
+        if s.get_token_bucket(&key).is_none() {
+            s.set_token_bucket(key, bucket);
+        }
+    }
+}
+
+/// The pools with the most activity over the last 24 hours, from their hourly buckets.
+pub fn top_pools(
+    s: &State,
+    sort_by: PoolSortBy,
+    limit: usize,
+    timestamp_secs: u64,
+) -> Vec<PoolStats> {
+    let (current_hour, _) =
+        calculate_start_and_during_bucket_timestamp(timestamp_secs, &TimeFrame::Hourly);
+    let from = current_hour.saturating_sub(23 * 60 * 60);
+
+    let mut pools: Vec<PoolStats> = s
+        .get_pools()
+        .into_iter()
+        .map(|(pool_id, pool)| pool_stats(s, pool_id, &pool, from))
+        .collect();
+    pools.sort_by_key(|stats| {
+        std::cmp::Reverse(match sort_by {
+            PoolSortBy::SwapCount => stats.swap_count,
+            PoolSortBy::UniqueTraders => stats.unique_traders,
+            PoolSortBy::LiquidityChanges => stats.mint_count + stats.burn_count,
+        })
+    });
+    pools.truncate(limit);
+    pools
+}
+
+/// Usd tvl from the reserves of a pool and usd volume, fees and fee apr from its last 24 hourly
+/// buckets.
+pub fn pool_analytics(
+    s: &State,
+    pool_id: PoolId,
+    pool: &PoolState,
+    timestamp_nanos: u64,
+) -> PoolAnalytics {
+    let (current_hour, _) = calculate_start_and_during_bucket_timestamp(
+        nanos_to_seconds(timestamp_nanos),
+        &TimeFrame::Hourly,
+    );
+    let stats = pool_stats(
+        s,
+        pool_id.clone(),
+        pool,
+        current_hour.saturating_sub(23 * 60 * 60),
+    );
+
+    let token_price = |token: Principal| {
+        let metadata = s.get_token_metadata(&token);
+        let status = metadata.as_ref().map_or(PriceStatus::Missing, |metadata| {
+            metadata.price_status(timestamp_nanos)
+        });
+        TokenPrice {
+            token,
+            metadata,
+            status,
+        }
+    };
+    let token0 = token_price(pool_id.token0);
+    let token1 = token_price(pool_id.token1);
+
+    let tvl_usd = usd_value(&token0, stats.reserve0)
+        .zip(usd_value(&token1, stats.reserve1))
+        .map(|(value0, value1)| value0 + value1);
+    // only the input side of a swap is counted in the pool volume
+    let volume_24h_usd = usd_value(&token0, stats.swap_volume0)
+        .zip(usd_value(&token1, stats.swap_volume1))
+        .map(|(value0, value1)| value0 + value1);
+    let fees_24h_usd = usd_value(&token0, stats.fees_generated0)
+        .zip(usd_value(&token1, stats.fees_generated1))
+        .map(|(value0, value1)| value0 + value1);
+    let fee_apr = fees_24h_usd
+        .zip(tvl_usd)
+        .filter(|(_, tvl_usd)| *tvl_usd > 0.0)
+        .map(|(fees_24h_usd, tvl_usd)| fees_24h_usd * 365.0 / tvl_usd * 100.0);
+
+    PoolAnalytics {
+        stats,
+        token0,
+        token1,
+        tvl_usd,
+        volume_24h_usd,
+        fees_24h_usd,
+        fee_apr,
+    }
+}
+
+// usd value of an amount in the smallest unit of a token, None without a price
+fn usd_value(price: &TokenPrice, amount: U256) -> Option<f64> {
+    let metadata = price.metadata.as_ref()?;
+    let usd_price = metadata.usd_price?;
+    Some(amount.as_f64() / 10_f64.powi(metadata.decimals as i32) * usd_price)
+}
+
+/// Activity of a pool in its hourly buckets starting from `from`, traders of several hours being
+/// counted once.
+fn pool_stats(s: &State, pool_id: PoolId, pool: &PoolState, from: u64) -> PoolStats {
+    let mut stats = PoolStats {
+        pool_id: pool_id.clone(),
+        swap_count: 0,
+        unique_traders: 0,
+        mint_count: 0,
+        burn_count: 0,
+        swap_volume0: U256::ZERO,
+        swap_volume1: U256::ZERO,
+        fees_generated0: U256::ZERO,
+        fees_generated1: U256::ZERO,
+        reserve0: pool.pool_reserve0,
+        reserve1: pool.pool_reserve1,
+    };
+    let mut traders = std::collections::BTreeSet::new();
+    for bucket in s.get_history_buckets(&pool_id, TimeFrame::Hourly, from, u64::MAX) {
+        stats.swap_count += bucket.swap_count.unwrap_or_default();
+        stats.mint_count += bucket.mint_count.unwrap_or_default();
+        stats.burn_count += bucket.burn_count.unwrap_or_default();
+        stats.swap_volume0 = stats
+            .swap_volume0
+            .saturating_add(bucket.swap_volume_token0_during_bucket);
+        stats.swap_volume1 = stats
+            .swap_volume1
+            .saturating_add(bucket.swap_volume_token1_during_bucket);
+        stats.fees_generated0 = stats
+            .fees_generated0
+            .saturating_add(bucket.fee_generated_token0_during_bucket);
+        stats.fees_generated1 = stats
+            .fees_generated1
+            .saturating_add(bucket.fee_generated_token1_during_bucket);
+        traders.extend(s.get_bucket_traders(&HistoryBucketKey {
+            pool_id: pool_id.clone(),
+            timeframe: TimeFrame::Hourly,
+            start_timestamp: bucket.start_timestamp,
+        }));
+    }
+    stats.unique_traders = traders.len() as u64;
+    stats
+}
+
+/// The day of a token containing `timestamp`, a new day opening at the current reserves. Opening a
+/// day removes the oldest days beyond the retention of the daily pool frame.
+fn current_token_bucket(
+    s: &mut State,
+    token: Principal,
+    timestamp: u64,
+    stats: &TokenStats,
+) -> TokenBucket {
+    let (start_timestamp, end_timestamp) =
+        calculate_start_and_during_bucket_timestamp(timestamp, &TimeFrame::Daily);
+    let key = TokenBucketKey {
+        token,
+        start_timestamp,
+    };
+    if let Some(bucket) = s.get_token_bucket(&key) {
+        return bucket;
+    }
+
+    let max_buckets = MAX_BUCKETS
+        .iter()
+        .find(|(timeframe, _)| *timeframe == TimeFrame::Daily)
+        .map(|(_, max_buckets)| *max_buckets)
+        .unwrap_or_default();
+    let days = s.get_token_buckets(&token, 0, start_timestamp);
+    let excess = (days.len() + 1).saturating_sub(max_buckets);
+    for day in &days[..excess] {
+        s.remove_token_bucket(&TokenBucketKey {
+            token,
+            start_timestamp: day.start_timestamp,
+        });
+    }
+
+    TokenBucket {
+        start_timestamp,
+        end_timestamp,
+        swap_count: 0,
+        swap_volume: U256::ZERO,
+        fees_generated: U256::ZERO,
+        reserves: stats.reserves,
+    }
+}
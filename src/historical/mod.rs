@@ -1,120 +1,248 @@
 // a module for keeping track of historical data in different time pre aggregated buckets to make
 // it easier to show to useres in appic explorer
 // Stored data include swap_volume, fee generation, price movement, and liquidty changes
-// Time buckets include Ten minutes ,Hourly, Daily, Monthly, and Yearly
+// Buckets are updated by the swaps and liquidity changes of a pool, see `update_pool_history`, a
+// timer only opens the buckets of idle pools
+// Time buckets include 5 minutes, 15 minutes, Hourly, 4 hours, Daily, Weekly (starting on Mondays),
+// Monthly and Yearly, months and years following the UTC calendar
 
-// to prevent storage overflow a mechanism automatically removes old data
-// historical storage keeps at max 144 ten minute buckets, 72 hourly bucket, 90 daily bucket, 36 monthly bucket, and 12 yealry
-// buckets
+// to prevent storage overflow a mechanism automatically removes old data, the retention of each
+// timeframe is set in `MAX_BUCKETS`
 
 use crate::{
-    pool::types::PoolState,
-    state::{mutate_state, read_state},
+    events::{Event, EventType},
+    pool::{
+        dynamic_fee::roll_volatility_window,
+        types::{PoolId, PoolState},
+    },
+    state::{mutate_state, read_state, State},
 };
+use aggregates::{roll_token_buckets, update_aggregates};
 use ethnum::U256;
-use types::{HistoryBucket, PoolHistory};
+use minicbor::{Decode, Encode};
+use types::{HistoryBucket, HistoryBucketKey, PoolHistory};
 
+pub mod aggregates;
 pub mod types;
 
-/// Maximum number of buckets for each timeframe.
-const MAX_BUCKETS: [(TimeFrame, usize); 4] = [
+/// Maximum number of buckets for each timeframe, every timeframe listed here is captured.
+const MAX_BUCKETS: [(TimeFrame, usize); 8] = [
+    (TimeFrame::FiveMinutes, 144),   // 12 hours
+    (TimeFrame::FifteenMinutes, 96), // 1 day
     (TimeFrame::Hourly, 48),
+    (TimeFrame::FourHours, 84), // 2 weeks
     (TimeFrame::Daily, 60),
+    (TimeFrame::Weekly, 52),
     (TimeFrame::Monthly, 24),
     (TimeFrame::Yearly, 10),
 ];
 
+const DAY_SECS: u64 = 24 * 60 * 60;
+
 /// Timeframe buckets for historical data.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum TimeFrame {
+    #[n(0)]
+    FiveMinutes,
+    #[n(1)]
+    FifteenMinutes,
+    #[n(2)]
     Hourly,
+    #[n(3)]
+    FourHours,
+    #[n(4)]
     Daily,
+    #[n(5)]
+    Weekly,
+    #[n(6)]
     Monthly,
+    #[n(7)]
     Yearly,
 }
 
 impl PoolHistory {
     /// Get the vector for a specific timeframe.
-    fn get_frame_mut(&mut self, timeframe: TimeFrame) -> &mut Vec<HistoryBucket> {
+    pub fn get_frame_mut(&mut self, timeframe: TimeFrame) -> &mut Vec<HistoryBucket> {
         match timeframe {
+            TimeFrame::FiveMinutes => self.five_minute_frame.get_or_insert_with(Vec::new),
+            TimeFrame::FifteenMinutes => self.fifteen_minute_frame.get_or_insert_with(Vec::new),
             TimeFrame::Hourly => &mut self.hourly_frame,
+            TimeFrame::FourHours => self.four_hour_frame.get_or_insert_with(Vec::new),
             TimeFrame::Daily => &mut self.daily_frame,
+            TimeFrame::Weekly => self.weekly_frame.get_or_insert_with(Vec::new),
             TimeFrame::Monthly => &mut self.monthly_frame,
             TimeFrame::Yearly => &mut self.yearly_frame,
         }
     }
 
-    /// Get the maximum number of buckets for a timeframe.
-    fn get_max_buckets(timeframe: TimeFrame) -> usize {
-        MAX_BUCKETS
-            .iter()
-            .find(|&&(t, _)| t == timeframe)
-            .map(|&(_, max)| max)
-            .unwrap_or(0)
+    /// Takes the vectors of all timeframes.
+    pub fn into_frames(self) -> Vec<(TimeFrame, Vec<HistoryBucket>)> {
+        vec![
+            (
+                TimeFrame::FiveMinutes,
+                self.five_minute_frame.unwrap_or_default(),
+            ),
+            (
+                TimeFrame::FifteenMinutes,
+                self.fifteen_minute_frame.unwrap_or_default(),
+            ),
+            (TimeFrame::Hourly, self.hourly_frame),
+            (
+                TimeFrame::FourHours,
+                self.four_hour_frame.unwrap_or_default(),
+            ),
+            (TimeFrame::Daily, self.daily_frame),
+            (TimeFrame::Weekly, self.weekly_frame.unwrap_or_default()),
+            (TimeFrame::Monthly, self.monthly_frame),
+            (TimeFrame::Yearly, self.yearly_frame),
+        ]
     }
 }
 
-/// Captures historical data for all pools and timeframes.
+/// Rolls the history of all pools and tokens, opening the current bucket of every frame not opened
+/// by an event yet. Open buckets are updated by the events changing the pool, see `update_pool_history`.
 pub fn capture_historical_data() {
     let timestamp_nanos = ic_cdk::api::time();
     let timestamp_secs = nanos_to_seconds(timestamp_nanos);
 
     let pools = read_state(|s| s.get_pools());
 
-    for (pool_id, pool_state) in pools {
-        let mut pool_history = read_state(|s| s.get_pool_history(&pool_id));
-
-        // Capture data for all timeframes
-        for timeframe in [
-            TimeFrame::Hourly,
-            TimeFrame::Daily,
-            TimeFrame::Monthly,
-            TimeFrame::Yearly,
-        ] {
-            capture_bucket(&mut pool_history, &pool_state, timestamp_secs, timeframe);
+    mutate_state(|s| {
+        for (pool_id, pool_state) in pools {
+            // Capture data for all timeframes
+            for (timeframe, max_buckets) in MAX_BUCKETS {
+                capture_bucket(
+                    s,
+                    &pool_id,
+                    &pool_state,
+                    timestamp_secs,
+                    timeframe,
+                    max_buckets,
+                );
+            }
         }
+        roll_token_buckets(s, timestamp_secs);
+    });
+}
+
+/// The pools an event is about to change, taken before the change for `update_pool_history`.
+pub fn pools_before(s: &State, event: &Event) -> Vec<(PoolId, PoolState)> {
+    event
+        .payload
+        .pool_ids()
+        .into_iter()
+        .filter_map(|pool_id| s.get_pool(&pool_id).map(|pool| (pool_id, pool)))
+        .collect()
+}
+
+/// Updates the current buckets of the pools changed by an event, in the same call as the change.
+/// `pools_before` are the pools before the change, a bucket opened by the event opens at the state
+/// before it. Swaps, mints and burns are counted, liquidity increases and decreases counting as
+/// mints and burns. Token and protocol aggregates are updated alongside.
+pub fn update_pool_history(s: &mut State, pools_before: Vec<(PoolId, PoolState)>, event: &Event) {
+    let timestamp = nanos_to_seconds(event.timestamp);
+    update_aggregates(s, &pools_before, event);
+
+    for (pool_id, pool_before) in pools_before {
+        let Some(pool_after) = s.get_pool(&pool_id) else {
+            continue;
+        };
+
+        for (timeframe, max_buckets) in MAX_BUCKETS {
+            let (key, end_timestamp) = current_bucket(&pool_id, timestamp, timeframe);
+
+            let mut bucket = match s.get_history_bucket(&key) {
+                Some(bucket) if bucket.end_timestamp == end_timestamp => bucket,
+                _ => {
+                    let bucket = create_bucket(key.start_timestamp, end_timestamp, &pool_before);
+                    open_bucket(s, key.clone(), bucket.clone(), max_buckets);
+                    bucket
+                }
+            };
+            update_bucket(&mut bucket, &pool_after);
 
-        // Update state with modified history (assuming a write_state function exists)
-        mutate_state(|s| s.set_pool_history(pool_id, pool_history));
+            match &event.payload {
+                EventType::Swap { principal, .. } => {
+                    let new_trader = s.add_bucket_trader(key.clone(), *principal);
+                    bucket.record_swap(new_trader);
+                }
+                EventType::MintedPosition { .. } | EventType::IncreasedLiquidity { .. } => {
+                    bucket.record_mint()
+                }
+                EventType::BurntPosition { .. } | EventType::DecreasedLiquidity { .. } => {
+                    bucket.record_burn()
+                }
+                _ => {}
+            }
+            s.set_history_bucket(key, bucket);
+        }
     }
 }
 
-/// Captures a bucket for a specific timeframe.
+/// Converts a sqrt price to the price of token0 in token1, in whole tokens.
+pub fn sqrt_price_x96_to_price(sqrt_price_x96: U256, decimals0: u8, decimals1: u8) -> f64 {
+    let sqrt_price = sqrt_price_x96.as_f64() / 2_f64.powi(96);
+    sqrt_price * sqrt_price * 10_f64.powi(decimals0 as i32 - decimals1 as i32)
+}
+
+/// Opens the current bucket of a timeframe from pool state, if not open yet.
 fn capture_bucket(
-    pool_history: &mut PoolHistory,
+    s: &mut State,
+    pool_id: &PoolId,
     pool_state: &PoolState,
     timestamp: u64,
     timeframe: TimeFrame,
+    max_buckets: usize,
 ) {
-    let (start_timestamp, during_bucket_timestamp) =
-        calculate_start_and_during_bucket_timestamp(timestamp, &timeframe);
-    let frame = pool_history.get_frame_mut(timeframe);
-
-    if let Some(bucket) = frame.last_mut() {
-        // Update existing bucket if it matches the timeframe
-        if bucket.start_timestamp == start_timestamp
-            && bucket.end_timestamp == during_bucket_timestamp
-        {
-            update_bucket(bucket, pool_state);
-        } else {
-            // Create new bucket
-            frame.push(create_bucket(
-                start_timestamp,
-                during_bucket_timestamp,
-                pool_state,
-            ));
+    let (key, end_timestamp) = current_bucket(pool_id, timestamp, timeframe);
+
+    match s.get_history_bucket(&key) {
+        // already open, kept up to date by events
+        Some(bucket) if bucket.end_timestamp == end_timestamp => {}
+        // Create new bucket, buckets aligned to a previous calendar are replaced
+        _ => {
+            let bucket = create_bucket(key.start_timestamp, end_timestamp, pool_state);
+            open_bucket(s, key, bucket, max_buckets);
         }
-    } else {
-        // Initialize first bucket
-        frame.push(create_bucket(
-            start_timestamp,
-            during_bucket_timestamp,
-            pool_state,
-        ));
     }
+}
 
-    // Limit the number of buckets
-    limit_vec_length(frame, PoolHistory::get_max_buckets(timeframe));
+/// Key and end timestamp of the bucket of a timeframe containing `timestamp`.
+fn current_bucket(
+    pool_id: &PoolId,
+    timestamp: u64,
+    timeframe: TimeFrame,
+) -> (HistoryBucketKey, u64) {
+    let (start_timestamp, end_timestamp) =
+        calculate_start_and_during_bucket_timestamp(timestamp, &timeframe);
+    let key = HistoryBucketKey {
+        pool_id: pool_id.clone(),
+        timeframe,
+        start_timestamp,
+    };
+    (key, end_timestamp)
+}
+
+/// Stores a new bucket and removes the oldest buckets of its frame beyond `max_buckets`.
+fn open_bucket(s: &mut State, key: HistoryBucketKey, bucket: HistoryBucket, max_buckets: usize) {
+    let (pool_id, timeframe, start_timestamp) =
+        (key.pool_id.clone(), key.timeframe, key.start_timestamp);
+    // a bucket aligned to a previous calendar is replaced along with its traders
+    s.remove_history_bucket(&key);
+    s.set_history_bucket(key, bucket);
+    if timeframe == TimeFrame::Hourly {
+        roll_volatility_window(s, &pool_id, start_timestamp);
+    }
+
+    let starts = s.get_history_bucket_starts(&pool_id, timeframe);
+    let excess = starts.len().saturating_sub(max_buckets);
+    for start_timestamp in &starts[..excess] {
+        s.remove_history_bucket(&HistoryBucketKey {
+            pool_id: pool_id.clone(),
+            timeframe,
+            start_timestamp: *start_timestamp,
+        });
+    }
 }
 
 /// Creates a new HistoryBucket from pool state.
@@ -139,6 +267,16 @@ fn create_bucket(
         last_sqrtx96_price: pool_state.sqrt_price_x96,
         inrange_liquidity: pool_state.liquidity,
         active_tick: pool_state.tick,
+        open_sqrtx96_price: Some(pool_state.sqrt_price_x96),
+        high_sqrtx96_price: Some(pool_state.sqrt_price_x96),
+        low_sqrtx96_price: Some(pool_state.sqrt_price_x96),
+        open_tick: Some(pool_state.tick),
+        high_tick: Some(pool_state.tick),
+        low_tick: Some(pool_state.tick),
+        swap_count: Some(0),
+        unique_traders: Some(0),
+        mint_count: Some(0),
+        burn_count: Some(0),
     }
 }
 
@@ -154,9 +292,8 @@ fn update_bucket(bucket: &mut HistoryBucket, pool_state: &PoolState) {
         pool_state.generated_swap_fee1 - bucket.fee_generated_token1_start;
     bucket.token0_reserves = pool_state.pool_reserve0;
     bucket.token1_reserves = pool_state.pool_reserve1;
-    bucket.last_sqrtx96_price = pool_state.sqrt_price_x96;
     bucket.inrange_liquidity = pool_state.liquidity;
-    bucket.active_tick = pool_state.tick;
+    bucket.record_price(pool_state.sqrt_price_x96, pool_state.tick);
 }
 
 /// Calculates start and during_bucket timestamps for a bucket, aligned to the timeframe.
@@ -165,75 +302,631 @@ fn calculate_start_and_during_bucket_timestamp(
     timeframe: &TimeFrame,
 ) -> (u64, u64) {
     let start_time = align_timestamp_to_bucket(timestamp, timeframe);
-    let duration_secs = match timeframe {
-        TimeFrame::Hourly => 60 * 60,            // 1 hour
-        TimeFrame::Daily => 24 * 60 * 60,        // 1 day
-        TimeFrame::Monthly => 30 * 24 * 60 * 60, // Approx 30 days
-        TimeFrame::Yearly => 365 * 24 * 60 * 60, // Approx 365 days
+    let end_time = match timeframe {
+        TimeFrame::FiveMinutes => start_time + 5 * 60,
+        TimeFrame::FifteenMinutes => start_time + 15 * 60,
+        TimeFrame::Hourly => start_time + 60 * 60,
+        TimeFrame::FourHours => start_time + 4 * 60 * 60,
+        TimeFrame::Daily => start_time + DAY_SECS,
+        TimeFrame::Weekly => start_time + 7 * DAY_SECS,
+        TimeFrame::Monthly => {
+            let (year, month) = civil_from_days(start_time / DAY_SECS);
+            let (year, month) = if month == 12 {
+                (year + 1, 1)
+            } else {
+                (year, month + 1)
+            };
+            days_from_civil(year, month) * DAY_SECS
+        }
+        TimeFrame::Yearly => {
+            let (year, _) = civil_from_days(start_time / DAY_SECS);
+            days_from_civil(year + 1, 1) * DAY_SECS
+        }
     };
-    (start_time, start_time + duration_secs)
+    (start_time, end_time)
 }
 
-/// Aligns a timestamp to the start of a bucket.
+/// Aligns a timestamp to the start of a bucket, in UTC.
 fn align_timestamp_to_bucket(timestamp: u64, timeframe: &TimeFrame) -> u64 {
     match timeframe {
+        TimeFrame::FiveMinutes => timestamp - (timestamp % (5 * 60)),
+        TimeFrame::FifteenMinutes => timestamp - (timestamp % (15 * 60)),
         TimeFrame::Hourly => timestamp - (timestamp % (60 * 60)),
-        TimeFrame::Daily => timestamp - (timestamp % (24 * 60 * 60)),
+        TimeFrame::FourHours => timestamp - (timestamp % (4 * 60 * 60)),
+        TimeFrame::Daily => timestamp - (timestamp % DAY_SECS),
+        TimeFrame::Weekly => {
+            // 1970-01-01 was a Thursday, weeks start on Mondays
+            let days = timestamp / DAY_SECS;
+            (days - (days + 3) % 7) * DAY_SECS
+        }
         TimeFrame::Monthly => {
-            // Simplified: Align to nearest 30-day period
-            timestamp - (timestamp % (30 * 24 * 60 * 60))
+            let (year, month) = civil_from_days(timestamp / DAY_SECS);
+            days_from_civil(year, month) * DAY_SECS
         }
         TimeFrame::Yearly => {
-            // Simplified: Align to nearest 365-day period
-            timestamp - (timestamp % (365 * 24 * 60 * 60))
+            let (year, _) = civil_from_days(timestamp / DAY_SECS);
+            days_from_civil(year, 1) * DAY_SECS
         }
     }
 }
 
+/// Year and month (1 to 12) of a day counted from 1970-01-01, see
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // months counted from March
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month)
+}
+
+/// Days from 1970-01-01 to the first day of a month, see
+/// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: u64, month: u64) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
 /// Converts epoch time from nanoseconds to seconds.
 fn nanos_to_seconds(epoch_nanos: u64) -> u64 {
     epoch_nanos / 1_000_000_000
 }
 
-/// Limits the length of a vector to `max_len` by removing elements from the front.
-fn limit_vec_length<T>(vec: &mut Vec<T>, max_len: usize) {
-    if vec.len() > max_len {
-        vec.drain(..vec.len() - max_len);
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{position::types::PositionKey, validation::swap_args::ValidatedSwapArgs};
+    use ethnum::I256;
 
     #[test]
     fn test_timestamp_alignment() {
-        let timestamp = 1677655321_u64; // Arbitrary timestamp
+        let timestamp = 1677655321_u64; // 2023-03-01 07:22:01 UTC, a Wednesday
+        assert_eq!(
+            align_timestamp_to_bucket(timestamp, &TimeFrame::FiveMinutes),
+            1677655200
+        );
+        assert_eq!(
+            align_timestamp_to_bucket(timestamp, &TimeFrame::FifteenMinutes),
+            1677654900
+        );
         assert_eq!(
             align_timestamp_to_bucket(timestamp, &TimeFrame::Hourly),
             1677654000
         ); // Aligns to nearest hour
+        assert_eq!(
+            align_timestamp_to_bucket(timestamp, &TimeFrame::FourHours),
+            1677643200
+        );
         assert_eq!(
             align_timestamp_to_bucket(timestamp, &TimeFrame::Daily),
             1677628800
         ); // Aligns to nearest day
+        assert_eq!(
+            align_timestamp_to_bucket(timestamp, &TimeFrame::Weekly),
+            1677456000
+        ); // Monday 2023-02-27
         assert_eq!(
             align_timestamp_to_bucket(timestamp, &TimeFrame::Monthly),
-            1677024000
-        ); // Aligns to nearest 30-day
+            1677628800
+        ); // 2023-03-01
         assert_eq!(
             align_timestamp_to_bucket(timestamp, &TimeFrame::Yearly),
-            1671408000
-        ); // Aligns to nearest 365-day
+            1672531200
+        ); // 2023-01-01
+    }
+
+    #[test]
+    fn months_and_years_should_follow_the_calendar() {
+        // 2024-02-29 12:00:00 UTC, in a leap year
+        let timestamp = 1709208000_u64;
+        assert_eq!(
+            calculate_start_and_during_bucket_timestamp(timestamp, &TimeFrame::Monthly),
+            (1706745600, 1709251200) // 2024-02-01 to 2024-03-01
+        );
+        assert_eq!(
+            calculate_start_and_during_bucket_timestamp(timestamp, &TimeFrame::Yearly),
+            (1704067200, 1735689600) // 2024-01-01 to 2025-01-01
+        );
+        // 2023-12-31 23:59:59 UTC
+        let timestamp = 1704067199_u64;
+        assert_eq!(
+            calculate_start_and_during_bucket_timestamp(timestamp, &TimeFrame::Monthly),
+            (1701388800, 1704067200) // 2023-12-01 to 2024-01-01
+        );
+        assert_eq!(
+            calculate_start_and_during_bucket_timestamp(timestamp, &TimeFrame::Weekly),
+            (1703462400, 1704067200) // Monday 2023-12-25 to Monday 2024-01-01
+        );
+
+        for days in [0, 59, 365, 10_957, 19_782, 47_482] {
+            let (year, month) = civil_from_days(days);
+            assert!(days_from_civil(year, month) <= days);
+            assert!(days - days_from_civil(year, month) < 31);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1));
+        assert_eq!(civil_from_days(59), (1970, 3));
+        assert_eq!(days_from_civil(2000, 3), 11_017);
+    }
+
+    fn pool_state(sqrt_price_x96: U256, tick: i32) -> PoolState {
+        PoolState {
+            sqrt_price_x96,
+            tick,
+            fee_growth_global_0_x128: U256::ZERO,
+            fee_growth_global_1_x128: U256::ZERO,
+            liquidity: 0,
+            tick_spacing: crate::pool::types::PoolTickSpacing(60),
+            max_liquidity_per_tick: 0,
+            fee_protocol: 0,
+            token0_transfer_fee: U256::ZERO,
+            token1_transfer_fee: U256::ZERO,
+            swap_volume0_all_time: U256::ZERO,
+            swap_volume1_all_time: U256::ZERO,
+            pool_reserve0: U256::ZERO,
+            pool_reserve1: U256::ZERO,
+            generated_swap_fee0: U256::ZERO,
+            generated_swap_fee1: U256::ZERO,
+            creator: None,
+        }
+    }
+
+    // applies an event moving the pool to `pool_after` at `timestamp` seconds
+    fn apply_event(pool_id: &PoolId, pool_after: PoolState, payload: EventType, timestamp: u64) {
+        let event = Event {
+            timestamp: timestamp * 1_000_000_000,
+            payload,
+        };
+        mutate_state(|s| {
+            let pools_before = pools_before(s, &event);
+            s.set_pool(pool_id.clone(), pool_after);
+            update_pool_history(s, pools_before, &event);
+        });
+    }
+
+    fn swap_event(trader: u8, pool_id: &PoolId) -> EventType {
+        EventType::Swap {
+            final_amount_in: U256::ONE,
+            final_amount_out: U256::ONE,
+            swap_args: ValidatedSwapArgs::ExactInputSingle {
+                pool_id: pool_id.clone(),
+                zero_for_one: true,
+                amount_in: I256::ONE,
+                amount_out_minimum: I256::ZERO,
+                from_subaccount: None,
+                token_in: pool_id.token0,
+                token_out: pool_id.token1,
+            },
+            principal: candid::Principal::from_slice(&[trader]),
+            swap_fees: None,
+            hops: None,
+        }
+    }
+
+    fn swap_by(trader: u8, pool_id: &PoolId, sqrt_price_x96: u128, tick: i32, timestamp: u64) {
+        let pool_after = pool_state(U256::from(sqrt_price_x96), tick);
+        apply_event(pool_id, pool_after, swap_event(trader, pool_id), timestamp);
+    }
+
+    // moves the pool to a new price with a swap at `timestamp` seconds
+    fn swap_to(pool_id: &PoolId, sqrt_price_x96: u128, tick: i32, timestamp: u64) {
+        swap_by(1, pool_id, sqrt_price_x96, tick, timestamp);
+    }
+
+    #[test]
+    fn swaps_should_move_the_candles() {
+        let pool_id = PoolId {
+            token0: candid::Principal::from_slice(&[1]),
+            token1: candid::Principal::from_slice(&[2]),
+            fee: crate::pool::types::PoolFee(3_000),
+        };
+        mutate_state(|s| s.set_pool(pool_id.clone(), pool_state(U256::from(1_000_u32), 10)));
+
+        let hour = 1677654000_u64;
+        swap_to(&pool_id, 1_500, 20, hour + 60);
+        swap_to(&pool_id, 800, 5, hour + 120);
+        swap_to(&pool_id, 1_200, 15, hour + 180);
+        // opens the next hourly bucket at the last close
+        swap_to(&pool_id, 1_300, 16, hour + 3_600);
+
+        let hourly =
+            read_state(|s| s.get_history_buckets(&pool_id, TimeFrame::Hourly, hour, hour + 3_600));
+        assert_eq!(hourly.len(), 1);
+        let candle = &hourly[0];
+        assert_eq!(candle.open_sqrtx96_price, Some(U256::from(1_000_u32)));
+        assert_eq!(candle.high_sqrtx96_price, Some(U256::from(1_500_u32)));
+        assert_eq!(candle.low_sqrtx96_price, Some(U256::from(800_u32)));
+        assert_eq!(candle.last_sqrtx96_price, U256::from(1_200_u32));
+        assert_eq!(
+            (
+                candle.open_tick,
+                candle.high_tick,
+                candle.low_tick,
+                candle.active_tick
+            ),
+            (Some(10), Some(20), Some(5), 15)
+        );
+
+        let history = read_state(|s| s.get_pool_history(&pool_id));
+        let next = history.hourly_frame.last().unwrap();
+        assert_eq!(next.start_timestamp, hour + 3_600);
+        assert_eq!(next.open_sqrtx96_price, Some(U256::from(1_200_u32)));
+        assert_eq!(next.low_sqrtx96_price, Some(U256::from(1_200_u32)));
+        assert_eq!(next.high_sqrtx96_price, Some(U256::from(1_300_u32)));
+        // the swaps of both hours are in the same daily bucket
+        let daily = history.daily_frame.last().unwrap();
+        assert_eq!(daily.open_sqrtx96_price, Some(U256::from(1_000_u32)));
+        assert_eq!(daily.high_sqrtx96_price, Some(U256::from(1_500_u32)));
+        assert_eq!(daily.last_sqrtx96_price, U256::from(1_300_u32));
+    }
+
+    #[test]
+    fn volatility_window_should_follow_the_hourly_buckets() {
+        use crate::pool::{
+            dynamic_fee::{apply_dynamic_fee_config, compute_dynamic_fee, effective_lp_fee},
+            types::DynamicFeeConfig,
+        };
+
+        let pool_id = PoolId {
+            token0: candid::Principal::from_slice(&[1]),
+            token1: candid::Principal::from_slice(&[2]),
+            fee: crate::pool::types::PoolFee(3_000),
+        };
+        let config = DynamicFeeConfig {
+            min_fee: 500,
+            max_fee: 100_000,
+            fee_per_tick: 10,
+            lookback_hours: 3,
+        };
+        mutate_state(|s| s.set_pool(pool_id.clone(), pool_state(U256::from(1_000_u32), 0)));
+
+        let hour = 1677654000_u64;
+        swap_to(&pool_id, 1_000, 100, hour + 60);
+        mutate_state(|s| apply_dynamic_fee_config(s, pool_id.clone(), Some(config)));
+
+        // the fee from the window matches the fee over the latest hourly buckets
+        for (i, tick) in [-50, 300, 300, -1_000, 20, 0].into_iter().enumerate() {
+            swap_to(&pool_id, 1_000, tick, hour + (i as u64 + 1) * 3_600 + 60);
+
+            // a swap quotes against a tick that moved since the bucket was updated
+            let mut pool = read_state(|s| s.get_pool(&pool_id)).unwrap();
+            pool.tick += 7;
+            let hourly =
+                read_state(|s| s.get_history_buckets(&pool_id, TimeFrame::Hourly, 0, u64::MAX));
+            let skip = hourly.len().saturating_sub(config.lookback_hours as usize);
+            let ticks: Vec<i32> = hourly[skip..]
+                .iter()
+                .map(|bucket| bucket.active_tick)
+                .collect();
+            assert_eq!(
+                effective_lp_fee(&pool_id, &pool),
+                compute_dynamic_fee(&config, &ticks, pool.tick)
+            );
+        }
+    }
+
+    #[test]
+    fn events_should_update_the_current_buckets_and_their_counts() {
+        let pool_id = PoolId {
+            token0: candid::Principal::from_slice(&[1]),
+            token1: candid::Principal::from_slice(&[2]),
+            fee: crate::pool::types::PoolFee(3_000),
+        };
+        mutate_state(|s| s.set_pool(pool_id.clone(), pool_state(U256::from(1_000_u32), 10)));
+        let position = PositionKey {
+            owner: candid::Principal::from_slice(&[7]),
+            pool_id: pool_id.clone(),
+            tick_lower: -60,
+            tick_upper: 60,
+        };
+        let hour = 1677654000_u64;
+
+        let mut minted = pool_state(U256::from(1_000_u32), 10);
+        minted.liquidity = 500;
+        minted.pool_reserve0 = U256::from(40_u32);
+        let payload = EventType::MintedPosition {
+            created_position: position.clone(),
+            liquidity: 500,
+            amount0_paid: U256::from(40_u32),
+            amount1_paid: U256::ZERO,
+            principal: position.owner,
+        };
+        apply_event(&pool_id, minted, payload, hour + 10);
+
+        swap_by(3, &pool_id, 1_100, 12, hour + 20);
+        swap_by(4, &pool_id, 1_200, 14, hour + 30);
+        swap_by(3, &pool_id, 1_050, 11, hour + 40);
+
+        let payload = EventType::BurntPosition {
+            burnt_position: position.clone(),
+            liquidity: 500,
+            amount0_received: U256::ZERO,
+            amount1_received: U256::ZERO,
+            principal: position.owner,
+        };
+        apply_event(
+            &pool_id,
+            pool_state(U256::from(1_050_u32), 11),
+            payload,
+            hour + 50,
+        );
+
+        // the timer does not overwrite buckets opened by events
+        let pool = read_state(|s| s.get_pool(&pool_id)).unwrap();
+        mutate_state(|s| {
+            capture_bucket(s, &pool_id, &pool, hour + 60, TimeFrame::Hourly, 48);
+        });
+
+        let bucket =
+            read_state(|s| s.get_history_buckets(&pool_id, TimeFrame::Hourly, hour, hour + 1))
+                .pop()
+                .unwrap();
+        assert_eq!(bucket.swap_count, Some(3));
+        assert_eq!(bucket.unique_traders, Some(2));
+        assert_eq!(bucket.mint_count, Some(1));
+        assert_eq!(bucket.burn_count, Some(1));
+        assert_eq!(bucket.inrange_liquidity, 0);
+        assert_eq!(bucket.high_sqrtx96_price, Some(U256::from(1_200_u32)));
+        assert_eq!(bucket.last_sqrtx96_price, U256::from(1_050_u32));
+
+        // a trader of the previous hour is new in the next one
+        swap_by(3, &pool_id, 1_000, 10, hour + 3_600);
+        let next = read_state(|s| {
+            s.get_history_buckets(&pool_id, TimeFrame::Hourly, hour + 3_600, u64::MAX)
+        })
+        .pop()
+        .unwrap();
+        assert_eq!(
+            (next.swap_count, next.unique_traders, next.mint_count),
+            (Some(1), Some(1), Some(0))
+        );
+        let daily = read_state(|s| s.get_pool_history(&pool_id))
+            .daily_frame
+            .pop()
+            .unwrap();
+        assert_eq!((daily.swap_count, daily.unique_traders), (Some(4), Some(2)));
+    }
+
+    #[test]
+    fn events_should_update_token_and_protocol_aggregates() {
+        use aggregates::{record_created_pool, seed_aggregates, top_pools, PoolSortBy};
+
+        let token = |id| candid::Principal::from_slice(&[id]);
+        let pool_a = PoolId {
+            token0: token(1),
+            token1: token(2),
+            fee: crate::pool::types::PoolFee(3_000),
+        };
+        let pool_b = PoolId {
+            token0: token(1),
+            token1: token(3),
+            fee: crate::pool::types::PoolFee(500),
+        };
+        let hour = 1677654000_u64;
+        let mut existing = pool_state(U256::from(1_000_u32), 10);
+        existing.swap_volume0_all_time = U256::from(50_u32);
+        existing.pool_reserve0 = U256::from(100_u32);
+        mutate_state(|s| {
+            s.set_pool(pool_a.clone(), existing);
+            seed_aggregates(s, hour);
+            s.set_pool(pool_b.clone(), pool_state(U256::from(1_000_u32), 10));
+            record_created_pool(s, &pool_b);
+        });
+
+        let mut swapped = pool_state(U256::from(1_100_u32), 12);
+        swapped.swap_volume0_all_time = U256::from(30_u32);
+        swapped.swap_volume1_all_time = U256::from(20_u32);
+        swapped.generated_swap_fee0 = U256::from(1_u32);
+        swapped.pool_reserve0 = U256::from(30_u32);
+        apply_event(&pool_b, swapped.clone(), swap_event(5, &pool_b), hour + 10);
+        apply_event(&pool_b, swapped, swap_event(5, &pool_b), hour + 20);
+
+        let stats = read_state(|s| s.get_token_stats(&token(1))).unwrap();
+        assert_eq!(stats.pool_count, 2);
+        assert_eq!(stats.swap_count, 2);
+        assert_eq!(stats.swap_volume, U256::from(80_u32));
+        assert_eq!(stats.fees_generated, U256::from(1_u32));
+        assert_eq!(stats.reserves, U256::from(130_u32));
+        let stats = read_state(|s| s.get_token_stats(&token(2))).unwrap();
+        assert_eq!((stats.pool_count, stats.swap_count), (1, 0));
+        let stats = read_state(|s| s.get_token_stats(&token(3))).unwrap();
+        assert_eq!(stats.swap_volume, U256::from(20_u32));
+
+        let days = read_state(|s| s.get_token_buckets(&token(1), 0, u64::MAX));
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].swap_count, 2);
+        assert_eq!(days[0].swap_volume, U256::from(30_u32));
+        assert_eq!(days[0].reserves, U256::from(130_u32));
+
+        let protocol_stats = read_state(|s| s.get_protocol_stats());
+        assert_eq!(protocol_stats.tracked_since, hour);
+        assert_eq!(protocol_stats.pool_count, 2);
+        assert_eq!(protocol_stats.swap_count, 2);
+        assert_eq!(protocol_stats.unique_users, 1);
+
+        let top = read_state(|s| top_pools(s, PoolSortBy::SwapCount, 1, hour + 30));
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].pool_id, pool_b);
+        assert_eq!((top[0].swap_count, top[0].unique_traders), (2, 1));
+        assert_eq!(top[0].swap_volume0, U256::from(30_u32));
+
+        // the next day opens at the current reserves, seeding again changes nothing
+        mutate_state(|s| {
+            aggregates::roll_token_buckets(s, hour + DAY_SECS);
+            seed_aggregates(s, hour + DAY_SECS);
+        });
+        let days = read_state(|s| s.get_token_buckets(&token(1), 0, u64::MAX));
+        assert_eq!(days.len(), 2);
+        assert_eq!(
+            (days[1].swap_count, days[1].reserves),
+            (0, U256::from(130_u32))
+        );
+        assert_eq!(read_state(|s| s.get_protocol_stats()).pool_count, 2);
+    }
+
+    #[test]
+    fn pools_should_be_valued_with_explicit_price_status() {
+        use crate::tokens::{metadata::PriceStatus, types::TokenMetadata};
+        use aggregates::pool_analytics;
+
+        let pool_id = PoolId {
+            token0: candid::Principal::from_slice(&[1]),
+            token1: candid::Principal::from_slice(&[2]),
+            fee: crate::pool::types::PoolFee(3_000),
+        };
+        let hour = 1677654000_u64;
+        let now = (hour + 120) * 1_000_000_000;
+        mutate_state(|s| s.set_pool(pool_id.clone(), pool_state(U256::from(1_000_u32), 10)));
+        let mut swapped = pool_state(U256::from(1_100_u32), 12);
+        swapped.swap_volume0_all_time = U256::from(2_000_000_u32);
+        swapped.generated_swap_fee0 = U256::from(6_000_u32);
+        swapped.pool_reserve0 = U256::from(10_000_000_u32);
+        swapped.pool_reserve1 = U256::from(5_000_000_000_u64);
+        apply_event(
+            &pool_id,
+            swapped.clone(),
+            swap_event(5, &pool_id),
+            hour + 60,
+        );
+
+        // token1 decimals known from before metadata was cached, without a price
+        mutate_state(|s| {
+            s.set_token_metadata(
+                pool_id.token0,
+                TokenMetadata {
+                    symbol: Some("AAA".to_string()),
+                    decimals: 6,
+                    usd_price: Some(2.0),
+                    price_updated_at: Some(now - 60 * 1_000_000_000),
+                    ..Default::default()
+                },
+            );
+            s.set_legacy_token_decimals(pool_id.token1, 8);
+            s.migrate_token_decimals();
+        });
+        let analytics = read_state(|s| pool_analytics(s, pool_id.clone(), &swapped, now));
+        assert_eq!(analytics.token0.status, PriceStatus::Fresh);
+        assert_eq!(analytics.token1.status, PriceStatus::Missing);
+        assert_eq!(
+            read_state(|s| s.get_token_decimals(&pool_id.token1)),
+            Some(8)
+        );
+        assert_eq!(analytics.volume_24h_usd, None);
+        assert_eq!(analytics.tvl_usd, None);
+
+        mutate_state(|s| {
+            s.set_token_metadata(
+                pool_id.token1,
+                TokenMetadata {
+                    symbol: Some("BBB".to_string()),
+                    decimals: 8,
+                    usd_price: Some(0.5),
+                    price_updated_at: Some(now - 2 * 60 * 60 * 1_000_000_000),
+                    ..Default::default()
+                },
+            );
+        });
+        let analytics = read_state(|s| pool_analytics(s, pool_id.clone(), &swapped, now));
+        assert_eq!(analytics.token1.status, PriceStatus::Stale);
+        // 10 AAA at 2 usd and 50 BBB at 0.5 usd
+        assert_eq!(analytics.tvl_usd, Some(45.0));
+        assert_eq!(analytics.volume_24h_usd, Some(4.0));
+        assert_eq!(analytics.fees_24h_usd, Some(0.012));
+        let fee_apr = analytics.fee_apr.unwrap();
+        assert!((fee_apr - 0.012 * 365.0 / 45.0 * 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn old_buckets_should_be_pruned() {
+        let pool_id = PoolId {
+            token0: candid::Principal::from_slice(&[1]),
+            token1: candid::Principal::from_slice(&[2]),
+            fee: crate::pool::types::PoolFee(3_000),
+        };
+        let pool = pool_state(U256::from(1_000_u32), 10);
+        let hour = 1677654000_u64;
+
+        mutate_state(|s| {
+            for i in 0..5 {
+                capture_bucket(s, &pool_id, &pool, hour + i * 3_600, TimeFrame::Hourly, 3);
+                // captures within the same hour keep the bucket
+                capture_bucket(
+                    s,
+                    &pool_id,
+                    &pool,
+                    hour + i * 3_600 + 600,
+                    TimeFrame::Hourly,
+                    3,
+                );
+            }
+        });
+
+        assert_eq!(
+            read_state(|s| s.get_history_bucket_starts(&pool_id, TimeFrame::Hourly)),
+            vec![hour + 2 * 3_600, hour + 3 * 3_600, hour + 4 * 3_600]
+        );
+        assert_eq!(
+            read_state(|s| s.get_history_bucket_starts(&pool_id, TimeFrame::Daily)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn pool_histories_should_be_migrated_to_the_bucket_map() {
+        let pool_id = PoolId {
+            token0: candid::Principal::from_slice(&[1]),
+            token1: candid::Principal::from_slice(&[2]),
+            fee: crate::pool::types::PoolFee(3_000),
+        };
+        let pool = pool_state(U256::from(1_000_u32), 10);
+        let hour = 1677654000_u64;
+        let legacy_history = PoolHistory {
+            hourly_frame: vec![
+                create_bucket(hour, hour + 3_600, &pool),
+                create_bucket(hour + 3_600, hour + 7_200, &pool),
+            ],
+            daily_frame: vec![create_bucket(1677628800, 1677715200, &pool)],
+            five_minute_frame: Some(vec![create_bucket(hour, hour + 300, &pool)]),
+            ..Default::default()
+        };
+
+        mutate_state(|s| {
+            s.set_legacy_pool_history(pool_id.clone(), legacy_history.clone());
+            s.migrate_pool_history();
+        });
+        assert_eq!(read_state(|s| s.get_pool_history(&pool_id)), legacy_history);
+
+        // the legacy histories are cleared, migrating again keeps the buckets
+        mutate_state(|s| s.migrate_pool_history());
+        assert_eq!(read_state(|s| s.get_pool_history(&pool_id)), legacy_history);
+        assert_eq!(
+            read_state(|s| s.get_history_buckets(&pool_id, TimeFrame::Hourly, hour + 1, u64::MAX))
+                .len(),
+            1
+        );
     }
 
     #[test]
-    fn test_limit_vec_length() {
-        let mut vec: Vec<i32> = (0..200).collect();
-        limit_vec_length(&mut vec, 144);
-        assert_eq!(vec.len(), 144);
-        assert_eq!(vec[0], 56); // First 56 elements removed
-        assert_eq!(vec[143], 199); // Last element preserved
+    fn sqrt_prices_should_convert_to_whole_token_prices() {
+        let sqrt_price_1_1 = U256::from(79228162514264337593543950336_u128);
+        assert_eq!(sqrt_price_x96_to_price(sqrt_price_1_1, 18, 18), 1.0);
+        // 1 token0 with 8 decimals for 1 token1 with 6 decimals
+        assert!((sqrt_price_x96_to_price(sqrt_price_1_1 / 10, 8, 6) - 1.0).abs() < 1e-12);
+        assert_eq!(sqrt_price_x96_to_price(sqrt_price_1_1 * 2, 6, 6), 4.0);
     }
 
     #[test]
@@ -1,6 +1,11 @@
+use candid::Principal;
 use ethnum::U256;
 use minicbor::{Decode, Encode};
 
+use crate::pool::types::PoolId;
+
+use super::TimeFrame;
+
 /// Historical data bucket for a specific timeframe.
 #[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
 pub struct HistoryBucket {
@@ -34,9 +39,87 @@ pub struct HistoryBucket {
     pub inrange_liquidity: u128,
     #[n(14)]
     pub active_tick: i32,
+    /// Price and tick when the bucket was opened, the close being `last_sqrtx96_price` and
+    /// `active_tick`. Missing for buckets captured before candles were tracked.
+    #[cbor(n(15), with = "crate::cbor::u256::option")]
+    pub open_sqrtx96_price: Option<U256>,
+    #[cbor(n(16), with = "crate::cbor::u256::option")]
+    pub high_sqrtx96_price: Option<U256>,
+    #[cbor(n(17), with = "crate::cbor::u256::option")]
+    pub low_sqrtx96_price: Option<U256>,
+    #[n(18)]
+    pub open_tick: Option<i32>,
+    #[n(19)]
+    pub high_tick: Option<i32>,
+    #[n(20)]
+    pub low_tick: Option<i32>,
+    /// Activity during the bucket, mints and burns including liquidity increases and decreases.
+    /// Missing for buckets opened before activity was counted.
+    #[n(21)]
+    pub swap_count: Option<u64>,
+    #[n(22)]
+    pub unique_traders: Option<u64>,
+    #[n(23)]
+    pub mint_count: Option<u64>,
+    #[n(24)]
+    pub burn_count: Option<u64>,
+}
+
+impl HistoryBucket {
+    /// Moves the close of the bucket to the given price and widens its high and low.
+    pub fn record_price(&mut self, sqrt_price_x96: U256, tick: i32) {
+        self.high_sqrtx96_price = Some(
+            self.high_sqrtx96_price
+                .map_or(sqrt_price_x96, |high| high.max(sqrt_price_x96)),
+        );
+        self.low_sqrtx96_price = Some(
+            self.low_sqrtx96_price
+                .map_or(sqrt_price_x96, |low| low.min(sqrt_price_x96)),
+        );
+        self.high_tick = Some(self.high_tick.map_or(tick, |high| high.max(tick)));
+        self.low_tick = Some(self.low_tick.map_or(tick, |low| low.min(tick)));
+        self.last_sqrtx96_price = sqrt_price_x96;
+        self.active_tick = tick;
+    }
+
+    /// Counts a swap, `new_trader` being whether the swapper had not traded in the bucket yet.
+    pub fn record_swap(&mut self, new_trader: bool) {
+        *self.swap_count.get_or_insert(0) += 1;
+        *self.unique_traders.get_or_insert(0) += u64::from(new_trader);
+    }
+
+    pub fn record_mint(&mut self) {
+        *self.mint_count.get_or_insert(0) += 1;
+    }
+
+    pub fn record_burn(&mut self) {
+        *self.burn_count.get_or_insert(0) += 1;
+    }
+}
+
+/// Key of a bucket in the history of a pool, the buckets of a frame are ordered by start time.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct HistoryBucketKey {
+    #[n(0)]
+    pub pool_id: PoolId,
+    #[n(1)]
+    pub timeframe: TimeFrame,
+    #[n(2)]
+    pub start_timestamp: u64,
+}
+
+/// A user having swapped in a bucket, for counting unique traders.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct BucketTraderKey {
+    #[n(0)]
+    pub bucket: HistoryBucketKey,
+    #[cbor(n(1), with = "crate::cbor::principal")]
+    pub trader: Principal,
 }
 
-/// Stores historical data for a pool across multiple timeframes.
+/// Stores historical data for a pool across multiple timeframes. Assembled from the history
+/// buckets for `get_pool_history`, stored as a single value per pool before buckets were keyed
+/// by `HistoryBucketKey`.
 #[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
 pub struct PoolHistory {
     #[n(0)]
@@ -47,4 +130,70 @@ pub struct PoolHistory {
     pub monthly_frame: Vec<HistoryBucket>,
     #[n(3)]
     pub yearly_frame: Vec<HistoryBucket>,
+    /// Finer and weekly frames, missing for histories stored before they were captured.
+    #[n(4)]
+    pub five_minute_frame: Option<Vec<HistoryBucket>>,
+    #[n(5)]
+    pub fifteen_minute_frame: Option<Vec<HistoryBucket>>,
+    #[n(6)]
+    pub four_hour_frame: Option<Vec<HistoryBucket>>,
+    #[n(7)]
+    pub weekly_frame: Option<Vec<HistoryBucket>>,
+}
+
+/// Activity of a token across all its pools since aggregates were tracked, the volume, fees and
+/// reserves of pools existing before being included.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, Default)]
+pub struct TokenStats {
+    #[n(0)]
+    pub pool_count: u64,
+    #[n(1)]
+    pub swap_count: u64,
+    #[cbor(n(2), with = "crate::cbor::u256")]
+    pub swap_volume: U256,
+    #[cbor(n(3), with = "crate::cbor::u256")]
+    pub fees_generated: U256,
+    #[cbor(n(4), with = "crate::cbor::u256")]
+    pub reserves: U256,
+}
+
+/// Daily activity of a token across all its pools, `reserves` being the reserves at the last
+/// update of the day.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug)]
+pub struct TokenBucket {
+    #[n(0)]
+    pub start_timestamp: u64,
+    #[n(1)]
+    pub end_timestamp: u64,
+    #[n(2)]
+    pub swap_count: u64,
+    #[cbor(n(3), with = "crate::cbor::u256")]
+    pub swap_volume: U256,
+    #[cbor(n(4), with = "crate::cbor::u256")]
+    pub fees_generated: U256,
+    #[cbor(n(5), with = "crate::cbor::u256")]
+    pub reserves: U256,
+}
+
+/// Key of a day in the history of a token, ordered by start time.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct TokenBucketKey {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub token: Principal,
+    #[n(1)]
+    pub start_timestamp: u64,
+}
+
+/// Protocol wide activity, swaps and users being counted from `tracked_since` (in seconds, zero
+/// before aggregates were seeded).
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ProtocolStats {
+    #[n(0)]
+    pub tracked_since: u64,
+    #[n(1)]
+    pub pool_count: u64,
+    #[n(2)]
+    pub swap_count: u64,
+    #[n(3)]
+    pub unique_users: u64,
 }
@@ -0,0 +1,95 @@
+//! The hash tree certifying the tip of the ICRC-3 log, following the IC interface specification:
+//! `fork(labeled("last_block_hash", leaf(hash)), labeled("last_block_index", leaf(leb128(index))))`.
+
+use minicbor::{encode::Write, Encoder};
+use sha2::{Digest, Sha256};
+
+use super::types::BlockHash;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashTree {
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(Vec<u8>, Box<HashTree>),
+    Leaf(Vec<u8>),
+}
+
+impl HashTree {
+    /// Root hash of the tree, the value set as certified data.
+    pub fn digest(&self) -> [u8; 32] {
+        match self {
+            HashTree::Fork(left, right) => {
+                hash_with_domain("ic-hashtree-fork", &[&left.digest(), &right.digest()])
+            }
+            HashTree::Labeled(label, subtree) => {
+                hash_with_domain("ic-hashtree-labeled", &[label, &subtree.digest()])
+            }
+            HashTree::Leaf(value) => hash_with_domain("ic-hashtree-leaf", &[value]),
+        }
+    }
+
+    /// CBOR encoding of the tree returned along with the certificate.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode(&mut Encoder::new(&mut buf))
+            .expect("encoding into a vector should always succeed");
+        buf
+    }
+
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        match self {
+            HashTree::Fork(left, right) => {
+                e.array(3)?.u8(1)?;
+                left.encode(e)?;
+                right.encode(e)
+            }
+            HashTree::Labeled(label, subtree) => {
+                e.array(3)?.u8(2)?.bytes(label)?;
+                subtree.encode(e)
+            }
+            HashTree::Leaf(value) => {
+                e.array(2)?.u8(3)?.bytes(value)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn hash_with_domain(domain: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([domain.len() as u8]);
+    hasher.update(domain.as_bytes());
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+pub fn tip_hash_tree(last_block_index: u64, last_block_hash: BlockHash) -> HashTree {
+    HashTree::Fork(
+        Box::new(HashTree::Labeled(
+            b"last_block_hash".to_vec(),
+            Box::new(HashTree::Leaf(last_block_hash.0.to_vec())),
+        )),
+        Box::new(HashTree::Labeled(
+            b"last_block_index".to_vec(),
+            Box::new(HashTree::Leaf(leb128(last_block_index))),
+        )),
+    )
+}
+
+/// Unsigned LEB128 encoding.
+pub fn leb128(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,480 @@
+//! ICRC-3 view of the event log. Every event is a block with the same index as in `get_events`,
+//! blocks are chained by the hash of their parent and the hash of the last block is certified.
+
+pub mod certification;
+pub mod types;
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+
+use candid::{Int, Nat, Principal};
+use ethnum::U256;
+use icrc_ledger_types::{
+    icrc::generic_value::ICRC3Value,
+    icrc3::{
+        archive::{ICRC3ArchiveInfo, QueryArchiveFn},
+        blocks::{
+            ArchivedBlocks, BlockWithId, GetBlocksRequest, GetBlocksResult, SupportedBlockType,
+        },
+    },
+};
+use num_traits::ToPrimitive;
+use serde_bytes::ByteBuf;
+
+use crate::{
+    archive::archived_ranges,
+    events::{Event, EventType},
+    icrc_client::memo::MemoKind,
+    libraries::safe_cast::u256_to_nat,
+    pool::types::PoolId,
+    position::types::PositionKey,
+    state::read_state,
+};
+
+use certification::tip_hash_tree;
+use types::BlockHash;
+
+pub const MAX_BLOCKS_PER_RESPONSE: u64 = 100;
+
+const BLOCK_TYPES_URL: &str =
+    "https://github.com/Appic-Solutions/appic-dex/blob/main/queries.md#icrc-3-blocks";
+
+pub const CREATE_POOL_BLOCK_TYPE: &str = "dex_create_pool";
+pub const MINT_BLOCK_TYPE: &str = "dex_mint";
+pub const INCREASE_LIQUIDITY_BLOCK_TYPE: &str = "dex_increase_liquidity";
+pub const BURN_BLOCK_TYPE: &str = "dex_burn";
+pub const DECREASE_LIQUIDITY_BLOCK_TYPE: &str = "dex_decrease_liquidity";
+pub const COLLECT_BLOCK_TYPE: &str = "dex_collect";
+pub const SWAP_BLOCK_TYPE: &str = "dex_swap";
+pub const DONATE_BLOCK_TYPE: &str = "dex_donate";
+pub const INTERNAL_TRANSFER_BLOCK_TYPE: &str = "dex_internal_transfer";
+pub const DEPOSIT_BLOCK_TYPE: &str = "dex_deposit";
+pub const WITHDRAW_BLOCK_TYPE: &str = "dex_withdraw";
+pub const WITHDRAWAL_ROLLBACK_BLOCK_TYPE: &str = "dex_withdrawal_rollback";
+pub const TRANSFER_FEE_BLOCK_TYPE: &str = "dex_transfer_fee";
+
+const BLOCK_TYPES: [&str; 13] = [
+    CREATE_POOL_BLOCK_TYPE,
+    MINT_BLOCK_TYPE,
+    INCREASE_LIQUIDITY_BLOCK_TYPE,
+    BURN_BLOCK_TYPE,
+    DECREASE_LIQUIDITY_BLOCK_TYPE,
+    COLLECT_BLOCK_TYPE,
+    SWAP_BLOCK_TYPE,
+    DONATE_BLOCK_TYPE,
+    INTERNAL_TRANSFER_BLOCK_TYPE,
+    DEPOSIT_BLOCK_TYPE,
+    WITHDRAW_BLOCK_TYPE,
+    WITHDRAWAL_ROLLBACK_BLOCK_TYPE,
+    TRANSFER_FEE_BLOCK_TYPE,
+];
+
+pub fn supported_block_types() -> Vec<SupportedBlockType> {
+    BLOCK_TYPES
+        .iter()
+        .map(|block_type| SupportedBlockType {
+            block_type: block_type.to_string(),
+            url: BLOCK_TYPES_URL.to_string(),
+        })
+        .collect()
+}
+
+pub fn block_type(event_type: &EventType) -> &'static str {
+    match event_type {
+        EventType::CreatedPool { .. } => CREATE_POOL_BLOCK_TYPE,
+        EventType::MintedPosition { .. } => MINT_BLOCK_TYPE,
+        EventType::IncreasedLiquidity { .. } => INCREASE_LIQUIDITY_BLOCK_TYPE,
+        EventType::BurntPosition { .. } => BURN_BLOCK_TYPE,
+        EventType::DecreasedLiquidity { .. } => DECREASE_LIQUIDITY_BLOCK_TYPE,
+        EventType::CollectedFees { .. } => COLLECT_BLOCK_TYPE,
+        EventType::Swap { .. } => SWAP_BLOCK_TYPE,
+        EventType::Donated { .. } => DONATE_BLOCK_TYPE,
+        EventType::InternalTransfer { .. } => INTERNAL_TRANSFER_BLOCK_TYPE,
+        EventType::Deposited { .. } => DEPOSIT_BLOCK_TYPE,
+        EventType::Withdrawn { .. } => WITHDRAW_BLOCK_TYPE,
+        EventType::WithdrawalRolledBack { .. } => WITHDRAWAL_ROLLBACK_BLOCK_TYPE,
+        EventType::TransferFeeUpdated { .. } => TRANSFER_FEE_BLOCK_TYPE,
+    }
+}
+
+/// Encodes an event as an ICRC-3 block: `btype`, `ts`, the hash of the previous block as `phash`
+/// (missing for the first block) and the event fields in `tx`.
+pub fn event_to_block(event: &Event, parent_hash: Option<BlockHash>) -> ICRC3Value {
+    let mut block = BTreeMap::from([
+        (
+            "btype".to_string(),
+            ICRC3Value::Text(block_type(&event.payload).to_string()),
+        ),
+        (
+            "ts".to_string(),
+            ICRC3Value::Nat(Nat::from(event.timestamp)),
+        ),
+        (
+            "tx".to_string(),
+            ICRC3Value::Map(transaction(&event.payload)),
+        ),
+    ]);
+    if let Some(parent_hash) = parent_hash {
+        block.insert(
+            "phash".to_string(),
+            ICRC3Value::Blob(ByteBuf::from(parent_hash.0.to_vec())),
+        );
+    }
+    ICRC3Value::Map(block)
+}
+
+pub fn block_hash(event: &Event, parent_hash: Option<BlockHash>) -> BlockHash {
+    BlockHash(event_to_block(event, parent_hash).hash())
+}
+
+fn transaction(event_type: &EventType) -> BTreeMap<String, ICRC3Value> {
+    let mut tx = TransactionBuilder::default();
+    match event_type {
+        EventType::CreatedPool {
+            token0,
+            token1,
+            pool_fee,
+            creator,
+            creation_fee,
+            ..
+        } => {
+            let tx = tx
+                .principal("token0", *token0)
+                .principal("token1", *token1)
+                .nat("fee", Nat::from(*pool_fee));
+            // only recorded since the pool creation policy, older blocks keep their hashes
+            if let Some(creator) = creator {
+                tx.principal("caller", *creator);
+            }
+            match creation_fee {
+                Some(creation_fee) => tx
+                    .principal("creation_fee_token", creation_fee.token)
+                    .amount("creation_fee", creation_fee.amount),
+                None => tx,
+            }
+        }
+        EventType::MintedPosition {
+            created_position,
+            liquidity,
+            amount0_paid,
+            amount1_paid,
+            principal,
+        } => tx
+            .principal("caller", *principal)
+            .position(created_position)
+            .nat("liquidity", Nat::from(*liquidity))
+            .amount("amount0", *amount0_paid)
+            .amount("amount1", *amount1_paid),
+        EventType::IncreasedLiquidity {
+            modified_position,
+            liquidity_delta,
+            amount0_paid,
+            amount1_paid,
+            principal,
+        } => tx
+            .principal("caller", *principal)
+            .position(modified_position)
+            .nat("liquidity", Nat::from(*liquidity_delta))
+            .amount("amount0", *amount0_paid)
+            .amount("amount1", *amount1_paid),
+        EventType::BurntPosition {
+            burnt_position,
+            liquidity,
+            amount0_received,
+            amount1_received,
+            principal,
+        } => tx
+            .principal("caller", *principal)
+            .position(burnt_position)
+            .nat("liquidity", Nat::from(*liquidity))
+            .amount("amount0", *amount0_received)
+            .amount("amount1", *amount1_received),
+        EventType::DecreasedLiquidity {
+            modified_position,
+            liquidity_delta,
+            amount0_received,
+            amount1_received,
+            principal,
+        } => tx
+            .principal("caller", *principal)
+            .position(modified_position)
+            .nat("liquidity", Nat::from(*liquidity_delta))
+            .amount("amount0", *amount0_received)
+            .amount("amount1", *amount1_received),
+        EventType::CollectedFees {
+            position,
+            amount0_collected,
+            amount1_collected,
+            principal,
+        } => tx
+            .principal("caller", *principal)
+            .position(position)
+            .amount("amount0", *amount0_collected)
+            .amount("amount1", *amount1_collected),
+        EventType::Swap {
+            final_amount_in,
+            final_amount_out,
+            swap_args,
+            principal,
+            swap_fees,
+            ..
+        } => {
+            let tx = tx
+                .principal("caller", *principal)
+                .principal("token_in", swap_args.token_in())
+                .principal("token_out", swap_args.token_out())
+                .amount("amount_in", *final_amount_in)
+                .amount("amount_out", *final_amount_out)
+                .value(
+                    "pools",
+                    ICRC3Value::Array(swap_args.pool_ids().iter().map(pool_value).collect()),
+                );
+            match swap_fees {
+                Some(swap_fees) => tx.value(
+                    "fees",
+                    ICRC3Value::Array(
+                        swap_fees
+                            .iter()
+                            .map(|fee| ICRC3Value::Nat(Nat::from(*fee)))
+                            .collect(),
+                    ),
+                ),
+                None => tx,
+            }
+        }
+        EventType::Donated {
+            pool_id,
+            amount0,
+            amount1,
+            principal,
+        } => tx
+            .principal("caller", *principal)
+            .value("pool", pool_value(pool_id))
+            .amount("amount0", *amount0)
+            .amount("amount1", *amount1),
+        EventType::InternalTransfer {
+            token,
+            from,
+            to,
+            amount,
+            spender,
+        } => {
+            let tx = tx
+                .principal("token", *token)
+                .principal("from", *from)
+                .principal("to", *to)
+                .amount("amount", *amount);
+            match spender {
+                Some(spender) => tx.principal("spender", *spender),
+                None => tx,
+            }
+        }
+        EventType::Deposited {
+            token,
+            user,
+            amount,
+            block_index,
+            memo,
+            balance,
+        } => tx
+            .principal("token", *token)
+            .principal("user", *user)
+            .amount("amount", *amount)
+            .block_index(*block_index)
+            .memo(*memo)
+            .amount("balance", *balance),
+        EventType::Withdrawn {
+            token,
+            user,
+            amount,
+            transfer_fee,
+            block_index,
+            memo,
+            balance,
+            uncovered_cost,
+        } => tx
+            .principal("token", *token)
+            .principal("user", *user)
+            .amount("amount", *amount)
+            .amount("transfer_fee", *transfer_fee)
+            .block_index(*block_index)
+            .memo(*memo)
+            .amount("balance", *balance)
+            .optional_amount("uncovered_cost", *uncovered_cost),
+        EventType::WithdrawalRolledBack {
+            token,
+            user,
+            amount,
+            memo,
+            balance,
+            reason,
+        } => tx
+            .principal("token", *token)
+            .principal("user", *user)
+            .amount("amount", *amount)
+            .memo(*memo)
+            .amount("balance", *balance)
+            .value("reason", ICRC3Value::Text(reason.clone())),
+        EventType::TransferFeeUpdated {
+            token,
+            transfer_fee,
+        } => tx
+            .principal("token", *token)
+            .amount("transfer_fee", *transfer_fee),
+    };
+    tx.0
+}
+
+#[derive(Default)]
+struct TransactionBuilder(BTreeMap<String, ICRC3Value>);
+
+impl TransactionBuilder {
+    fn value(&mut self, key: &str, value: ICRC3Value) -> &mut Self {
+        self.0.insert(key.to_string(), value);
+        self
+    }
+
+    fn principal(&mut self, key: &str, principal: Principal) -> &mut Self {
+        self.value(key, principal_value(principal))
+    }
+
+    fn nat(&mut self, key: &str, value: Nat) -> &mut Self {
+        self.value(key, ICRC3Value::Nat(value))
+    }
+
+    fn amount(&mut self, key: &str, amount: U256) -> &mut Self {
+        self.nat(key, u256_to_nat(amount))
+    }
+
+    // missing when None, so blocks of events without the field keep their hash
+    fn optional_amount(&mut self, key: &str, amount: Option<U256>) -> &mut Self {
+        match amount {
+            Some(amount) => self.amount(key, amount),
+            None => self,
+        }
+    }
+
+    // the ledger block of the transfer, missing when unknown
+    fn block_index(&mut self, block_index: Option<u64>) -> &mut Self {
+        match block_index {
+            Some(block_index) => self.nat("block_index", Nat::from(block_index)),
+            None => self,
+        }
+    }
+
+    fn memo(&mut self, memo: MemoKind) -> &mut Self {
+        self.value("memo", ICRC3Value::Text(memo.as_str().to_string()))
+    }
+
+    fn position(&mut self, position: &PositionKey) -> &mut Self {
+        self.value("pool", pool_value(&position.pool_id))
+            .value(
+                "tick_lower",
+                ICRC3Value::Int(Int::from(position.tick_lower)),
+            )
+            .value(
+                "tick_upper",
+                ICRC3Value::Int(Int::from(position.tick_upper)),
+            )
+    }
+}
+
+fn principal_value(principal: Principal) -> ICRC3Value {
+    ICRC3Value::Blob(ByteBuf::from(principal.as_slice().to_vec()))
+}
+
+fn pool_value(pool_id: &PoolId) -> ICRC3Value {
+    ICRC3Value::Map(BTreeMap::from([
+        ("token0".to_string(), principal_value(pool_id.token0)),
+        ("token1".to_string(), principal_value(pool_id.token1)),
+        ("fee".to_string(), ICRC3Value::Nat(Nat::from(pool_id.fee.0))),
+    ]))
+}
+
+/// Blocks of the requested ranges, at most `MAX_BLOCKS_PER_RESPONSE` in total. Blocks of events
+/// moved to archive canisters are returned as callbacks to the archives.
+pub fn get_blocks(requests: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    let mut archived_blocks = vec![];
+    for request in &requests {
+        let start = request.start.0.to_u64().unwrap_or(u64::MAX);
+        let length = request.length.0.to_u64().unwrap_or(u64::MAX);
+        for (canister_id, start, length) in archived_ranges(start, length) {
+            archived_blocks.push(ArchivedBlocks {
+                args: vec![GetBlocksRequest {
+                    start: Nat::from(start),
+                    length: Nat::from(length),
+                }],
+                callback: QueryArchiveFn::new(canister_id, "icrc3_get_blocks"),
+            });
+        }
+    }
+
+    read_state(|s| {
+        let log_length = s.total_event_count();
+        let first_held = s.first_held_event_index();
+        let mut remaining = MAX_BLOCKS_PER_RESPONSE;
+        let mut blocks = vec![];
+
+        for request in requests {
+            let requested_start = request.start.0.to_u64().unwrap_or(u64::MAX);
+            let requested_end = requested_start
+                .saturating_add(request.length.0.to_u64().unwrap_or(u64::MAX))
+                .min(log_length);
+            let start = requested_start.max(first_held);
+            let end = requested_end.min(start.saturating_add(remaining));
+            for index in start..end {
+                let event = s
+                    .get_event(index)
+                    .expect("BUG: held events below the log length should exist");
+                let parent_hash = index
+                    .checked_sub(1)
+                    .and_then(|parent| s.get_block_hash(parent));
+                blocks.push(BlockWithId {
+                    id: Nat::from(index),
+                    block: event_to_block(&event, parent_hash),
+                });
+            }
+            remaining -= end.saturating_sub(start);
+        }
+
+        GetBlocksResult {
+            log_length: Nat::from(log_length),
+            blocks,
+            archived_blocks,
+        }
+    })
+}
+
+/// Archive canisters and the inclusive range of blocks each of them stores, starting after the
+/// archive `from` when given.
+pub fn get_archives(from: Option<Principal>) -> Vec<ICRC3ArchiveInfo> {
+    let archives = read_state(|s| s.get_archives());
+    let skip = from
+        .and_then(|from| {
+            archives
+                .iter()
+                .position(|(_, archive)| archive.canister_id == from)
+        })
+        .map_or(0, |position| position + 1);
+    archives
+        .into_iter()
+        .skip(skip)
+        .filter(|(start, archive)| archive.end > *start)
+        .map(|(start, archive)| ICRC3ArchiveInfo {
+            canister_id: archive.canister_id,
+            start: Nat::from(start),
+            end: Nat::from(archive.end - 1),
+        })
+        .collect()
+}
+
+/// Sets the root hash of the tip hash tree as the certified data of the canister. Certified data
+/// only exists inside a canister, so this is skipped in unit tests.
+pub fn certify_tip(last_block_index: u64, last_block_hash: BlockHash) {
+    let root_hash = tip_hash_tree(last_block_index, last_block_hash).digest();
+    if cfg!(target_arch = "wasm32") {
+        ic_cdk::api::set_certified_data(&root_hash);
+    }
+}
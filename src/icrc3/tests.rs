@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use candid::{Nat, Principal};
+use ethnum::U256;
+use icrc_ledger_types::{icrc::generic_value::ICRC3Value, icrc3::blocks::GetBlocksRequest};
+use serde_bytes::ByteBuf;
+
+use super::{
+    certification::{leb128, tip_hash_tree},
+    event_to_block, get_blocks,
+    types::BlockHash,
+    MAX_BLOCKS_PER_RESPONSE,
+};
+use crate::{
+    events::{Event, EventType},
+    icrc_client::memo::MemoKind,
+    state::{mutate_state, read_state},
+};
+
+fn principal(byte: u8) -> Principal {
+    Principal::from_slice(&[byte])
+}
+
+fn transfer_event(timestamp: u64, amount: u32) -> Event {
+    Event {
+        timestamp,
+        payload: EventType::InternalTransfer {
+            token: principal(1),
+            from: principal(2),
+            to: principal(3),
+            amount: U256::from(amount),
+            spender: None,
+        },
+    }
+}
+
+fn request(start: u64, length: u64) -> GetBlocksRequest {
+    GetBlocksRequest {
+        start: Nat::from(start),
+        length: Nat::from(length),
+    }
+}
+
+#[test]
+fn blocks_should_encode_events() {
+    let block = event_to_block(&transfer_event(7, 100), None);
+
+    let blob = |p: Principal| ICRC3Value::Blob(ByteBuf::from(p.as_slice().to_vec()));
+    assert_eq!(
+        block,
+        ICRC3Value::Map(BTreeMap::from([
+            (
+                "btype".to_string(),
+                ICRC3Value::Text("dex_internal_transfer".to_string())
+            ),
+            ("ts".to_string(), ICRC3Value::Nat(Nat::from(7_u64))),
+            (
+                "tx".to_string(),
+                ICRC3Value::Map(BTreeMap::from([
+                    ("token".to_string(), blob(principal(1))),
+                    ("from".to_string(), blob(principal(2))),
+                    ("to".to_string(), blob(principal(3))),
+                    ("amount".to_string(), ICRC3Value::Nat(Nat::from(100_u32))),
+                ]))
+            ),
+        ]))
+    );
+}
+
+#[test]
+fn withdrawal_blocks_should_carry_ledger_fields() {
+    let withdrawal = |block_index| Event {
+        timestamp: 7,
+        payload: EventType::Withdrawn {
+            token: principal(1),
+            user: principal(2),
+            amount: U256::from(100_u8),
+            transfer_fee: U256::from(10_u8),
+            block_index,
+            memo: MemoKind::Refund,
+            balance: U256::from(50_u8),
+            uncovered_cost: None,
+        },
+    };
+
+    let ICRC3Value::Map(block) = event_to_block(&withdrawal(Some(42)), None) else {
+        panic!("blocks should be maps");
+    };
+    assert_eq!(
+        block.get("btype"),
+        Some(&ICRC3Value::Text("dex_withdraw".to_string()))
+    );
+    let Some(ICRC3Value::Map(tx)) = block.get("tx") else {
+        panic!("tx should be a map");
+    };
+    assert_eq!(
+        tx.get("block_index"),
+        Some(&ICRC3Value::Nat(Nat::from(42_u8)))
+    );
+    assert_eq!(
+        tx.get("memo"),
+        Some(&ICRC3Value::Text("Refund".to_string()))
+    );
+    assert_eq!(tx.get("balance"), Some(&ICRC3Value::Nat(Nat::from(50_u8))));
+    assert_eq!(tx.get("uncovered_cost"), None);
+
+    // unknown outcome, the block index is left out
+    let ICRC3Value::Map(block) = event_to_block(&withdrawal(None), None) else {
+        panic!("blocks should be maps");
+    };
+    let Some(ICRC3Value::Map(tx)) = block.get("tx") else {
+        panic!("tx should be a map");
+    };
+    assert_eq!(tx.get("block_index"), None);
+}
+
+#[test]
+fn recorded_events_should_be_hash_chained() {
+    for i in 0..3 {
+        mutate_state(|s| s.record_event(transfer_event(i, 10 + i as u32)));
+    }
+
+    let result = get_blocks(vec![request(0, 10)]);
+    assert_eq!(result.log_length, Nat::from(3_u8));
+    assert_eq!(result.blocks.len(), 3);
+
+    let mut parent_hash: Option<BlockHash> = None;
+    for (index, block) in result.blocks.into_iter().enumerate() {
+        assert_eq!(block.id, Nat::from(index));
+        let ICRC3Value::Map(fields) = &block.block else {
+            panic!("blocks should be maps");
+        };
+        assert_eq!(
+            fields.get("phash"),
+            parent_hash
+                .map(|hash| ICRC3Value::Blob(ByteBuf::from(hash.0.to_vec())))
+                .as_ref()
+        );
+
+        let hash = BlockHash(block.block.hash());
+        assert_eq!(read_state(|s| s.get_block_hash(index as u64)), Some(hash));
+        parent_hash = Some(hash);
+    }
+
+    // nothing left to hash, the tip stays the same
+    let tip = read_state(|s| s.last_block_hash());
+    assert_eq!(tip, Some((2, parent_hash.unwrap())));
+    assert_eq!(mutate_state(|s| s.hash_pending_blocks()), tip);
+}
+
+#[test]
+fn get_blocks_should_cap_and_skip_missing_blocks() {
+    for i in 0..(MAX_BLOCKS_PER_RESPONSE + 5) {
+        mutate_state(|s| s.record_event(transfer_event(i, 1)));
+    }
+
+    let result = get_blocks(vec![request(0, 60), request(60, 60)]);
+    assert_eq!(result.blocks.len() as u64, MAX_BLOCKS_PER_RESPONSE);
+    assert_eq!(
+        result.blocks.last().unwrap().id,
+        Nat::from(MAX_BLOCKS_PER_RESPONSE - 1)
+    );
+
+    let result = get_blocks(vec![
+        request(MAX_BLOCKS_PER_RESPONSE, 100),
+        request(1_000, 10),
+    ]);
+    assert_eq!(result.blocks.len(), 5);
+    assert_eq!(result.log_length, Nat::from(MAX_BLOCKS_PER_RESPONSE + 5));
+}
+
+#[test]
+fn tip_hash_tree_should_follow_the_interface_specification() {
+    assert_eq!(leb128(0), vec![0]);
+    assert_eq!(leb128(127), vec![0x7f]);
+    assert_eq!(leb128(128), vec![0x80, 0x01]);
+    assert_eq!(leb128(624_485), vec![0xe5, 0x8e, 0x26]);
+
+    let tree = tip_hash_tree(5, BlockHash([1; 32]));
+    let cbor = tree.to_cbor();
+    // [1, [2, "last_block_hash", [3, hash]], [2, "last_block_index", [3, leb128(5)]]]
+    assert_eq!(&cbor[..4], &[0x83, 0x01, 0x83, 0x02]);
+    assert_eq!(cbor[4], 0x40 + 15);
+    assert_eq!(&cbor[5..20], b"last_block_hash");
+    assert_eq!(&cbor[cbor.len() - 4..], &[0x82, 0x03, 0x41, 0x05]);
+
+    assert_ne!(tree.digest(), tip_hash_tree(6, BlockHash([1; 32])).digest());
+}
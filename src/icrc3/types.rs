@@ -0,0 +1,5 @@
+use minicbor::{Decode, Encode};
+
+/// Representation independent hash of an ICRC-3 block, as defined by the ICRC-3 standard.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockHash(#[cbor(n(0), with = "minicbor::bytes")] pub [u8; 32]);
@@ -11,6 +11,19 @@ fn encode<T: minicbor::Encode<()>>(t: &T) -> Vec<u8> {
     encoder.into_writer()
 }
 
+/// Maximum length of a memo accepted by ICRC-1 ledgers.
+pub const MAX_MEMO_LENGTH: usize = 32;
+
+/// Memo of a journaled transfer, the operation and the index of the transfer in it keep transfers
+/// of different operations apart, so the ledger only deduplicates a transfer sent again.
+/// The amount is left out, it is part of the transfer already and would not fit the 32 bytes
+/// ICRC-1 ledgers accept, the encoding takes 16 bytes at most.
+pub fn journaled_memo(kind: MemoKind, operation_id: u64, transfer_index: u32) -> Memo {
+    let memo = encode(&(kind, operation_id, transfer_index));
+    debug_assert!(memo.len() <= MAX_MEMO_LENGTH);
+    Memo::from(memo)
+}
+
 #[derive(Decode, Encode, Debug, Eq, PartialEq, Clone)]
 pub enum DepositMemo {
     /// The pool manager received funds to mint a new position.
@@ -41,6 +54,20 @@ pub enum DepositMemo {
         /// amount
         amount: U256,
     },
+    /// The pool manager received funds to be donated to in range liquidity providers.
+    #[n(4)]
+    Donate {
+        #[cbor(n(0), with = "crate::cbor::u256")]
+        /// amount
+        amount: U256,
+    },
+    /// The pool manager swept funds the user sent to their deposit subaccount.
+    #[n(5)]
+    NotifiedDeposit {
+        #[cbor(n(0), with = "crate::cbor::u256")]
+        /// amount
+        amount: U256,
+    },
 }
 impl DepositMemo {
     pub fn set_amount(&mut self, new_amount: U256) {
@@ -49,6 +76,21 @@ impl DepositMemo {
             DepositMemo::IncreasePosition { amount } => *amount = new_amount,
             DepositMemo::SwapIn { amount } => *amount = new_amount,
             DepositMemo::Deposit { amount } => *amount = new_amount,
+            DepositMemo::Donate { amount } => *amount = new_amount,
+            DepositMemo::NotifiedDeposit { amount } => *amount = new_amount,
+        }
+    }
+}
+
+impl DepositMemo {
+    pub fn kind(&self) -> MemoKind {
+        match self {
+            DepositMemo::MintPosition { .. } => MemoKind::MintPosition,
+            DepositMemo::IncreasePosition { .. } => MemoKind::IncreasePosition,
+            DepositMemo::SwapIn { .. } => MemoKind::SwapIn,
+            DepositMemo::Deposit { .. } => MemoKind::Deposit,
+            DepositMemo::Donate { .. } => MemoKind::Donate,
+            DepositMemo::NotifiedDeposit { .. } => MemoKind::NotifiedDeposit,
         }
     }
 }
@@ -128,3 +170,71 @@ impl WithdrawMemo {
         }
     }
 }
+
+impl WithdrawMemo {
+    pub fn kind(&self) -> MemoKind {
+        match self {
+            WithdrawMemo::BurnPosition { .. } => MemoKind::BurnPosition,
+            WithdrawMemo::DecreasePosition { .. } => MemoKind::DecreasePosition,
+            WithdrawMemo::SwapOut { .. } => MemoKind::SwapOut,
+            WithdrawMemo::WithdrawBalance { .. } => MemoKind::WithdrawBalance,
+            WithdrawMemo::Refund { .. } => MemoKind::Refund,
+            WithdrawMemo::CollectFees { .. } => MemoKind::CollectFees,
+            WithdrawMemo::Withdraw { .. } => MemoKind::Withdraw,
+        }
+    }
+}
+
+/// The variant of a deposit or withdrawal memo without its amount, the reason tokens moved
+/// between a user and the DEX.
+#[derive(Decode, Encode, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum MemoKind {
+    #[n(0)]
+    MintPosition,
+    #[n(1)]
+    IncreasePosition,
+    #[n(2)]
+    SwapIn,
+    #[n(3)]
+    Deposit,
+    #[n(4)]
+    Donate,
+    #[n(5)]
+    NotifiedDeposit,
+    #[n(6)]
+    BurnPosition,
+    #[n(7)]
+    DecreasePosition,
+    #[n(8)]
+    SwapOut,
+    #[n(9)]
+    WithdrawBalance,
+    #[n(10)]
+    Refund,
+    #[n(11)]
+    CollectFees,
+    #[n(12)]
+    Withdraw,
+}
+
+impl MemoKind {
+    /// Name of the kind in ICRC-3 blocks, part of the hashed block so it never changes with a
+    /// rename of the variant.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MintPosition => "MintPosition",
+            Self::IncreasePosition => "IncreasePosition",
+            Self::SwapIn => "SwapIn",
+            Self::Deposit => "Deposit",
+            Self::Donate => "Donate",
+            Self::NotifiedDeposit => "NotifiedDeposit",
+            Self::BurnPosition => "BurnPosition",
+            Self::DecreasePosition => "DecreasePosition",
+            Self::SwapOut => "SwapOut",
+            Self::WithdrawBalance => "WithdrawBalance",
+            Self::Refund => "Refund",
+            Self::CollectFees => "CollectFees",
+            Self::Withdraw => "Withdraw",
+        }
+    }
+}
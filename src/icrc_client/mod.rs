@@ -1,17 +1,26 @@
 pub mod memo;
 
-use candid::{Nat, Principal};
+#[cfg(test)]
+mod tests;
+
+use candid::{CandidType, Deserialize, Nat, Principal};
 use ic_canister_log::log;
+use ic_cdk::api::call::RejectionCode;
 // use ic_canister_log::log;
 use icrc_ledger_client_cdk::{CdkRuntime, ICRC1Client};
 use icrc_ledger_types::{
+    icrc::generic_metadata_value::MetadataValue,
     icrc1::{
-        account::Account,
-        transfer::{Memo, TransferArg, TransferError},
+        account::{Account, Subaccount},
+        transfer::{TransferArg, TransferError},
+    },
+    icrc2::{
+        allowance::{Allowance, AllowanceArgs},
+        transfer_from::{TransferFromArgs, TransferFromError},
     },
-    icrc2::transfer_from::{TransferFromArgs, TransferFromError},
 };
-use memo::{DepositMemo, WithdrawMemo};
+use memo::{journaled_memo, DepositMemo, WithdrawMemo};
+use serde::de::DeserializeOwned;
 
 use crate::logs::DEBUG;
 
@@ -44,11 +53,51 @@ pub enum LedgerTransferError {
         expected_fee: Nat,
     },
     FeeUnknown,
+    // the call was rejected without telling whether the ledger executed the transfer, sending the
+    // same transfer again is deduplicated by the ledger
+    OutcomeUnknown {
+        message: String,
+        ledger: Principal,
+    },
+    // the transfer is outside of the deduplication window of the ledger
+    TooOld {
+        ledger: Principal,
+    },
+}
+
+/// Times a transfer with an unknown outcome is sent within one call before it is left to the
+/// retry timer.
+pub const MAX_TRANSFER_ATTEMPTS: u32 = 3;
+
+/// Makes a journaled transfer deterministic, the ledger deduplicates transfers with identical
+/// arguments, so sending the same transfer again never pays twice.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TransferDedup {
+    pub created_at_time: u64,
+    pub operation_id: u64,
+    pub transfer_index: u32,
+}
+
+// SYS_TRANSIENT and SYS_UNKNOWN rejects do not tell whether the ledger executed the call
+fn is_outcome_unknown(error_code: i32) -> bool {
+    error_code == RejectionCode::SysTransient as i32 || error_code == RejectionCode::Unknown as i32
+}
+
+// entry of `icrc1_supported_standards`, the url of the standard is not needed
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct SupportedStandard {
+    name: String,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TransferIndex(Nat);
 
+impl TransferIndex {
+    pub fn as_u64(&self) -> Option<u64> {
+        u64::try_from(self.0 .0.clone()).ok()
+    }
+}
+
 impl LedgerClient {
     pub fn new(token: Principal) -> Self {
         Self {
@@ -64,6 +113,7 @@ impl LedgerClient {
         from: Account,
         amount: A,
         memo: DepositMemo,
+        dedup: &TransferDedup,
     ) -> Result<TransferIndex, LedgerTransferError> {
         let amount = amount.into();
         match self
@@ -74,9 +124,12 @@ impl LedgerClient {
                 to: ic_cdk::id().into(),
                 amount: amount.clone(),
                 fee: None,
-                memo: Some(Memo::from(memo)),
-                created_at_time: None, // We don't set this field to disable transaction deduplication
-                                       // which is unnecessary in canister-to-canister calls.
+                memo: Some(journaled_memo(
+                    memo.kind(),
+                    dedup.operation_id,
+                    dedup.transfer_index,
+                )),
+                created_at_time: Some(dedup.created_at_time),
             })
             .await
         {
@@ -112,12 +165,15 @@ impl LedgerClient {
                             ledger: self.client.ledger_canister_id,
                         }
                     }
-                    TransferFromError::TooOld => panic!("BUG: transfer too old"),
+                    TransferFromError::TooOld => LedgerTransferError::TooOld {
+                        ledger: self.client.ledger_canister_id,
+                    },
                     TransferFromError::CreatedInFuture { ledger_time } => {
                         panic!("BUG: created in future, ledger time: {ledger_time}")
                     }
                     TransferFromError::Duplicate { duplicate_of } => {
-                        panic!("BUG: duplicate transfer of: {duplicate_of}")
+                        // the same journaled transfer already went through
+                        return Ok(TransferIndex(duplicate_of));
                     }
                     TransferFromError::TemporarilyUnavailable => {
                         LedgerTransferError::TemporarilyUnavailable {
@@ -147,6 +203,12 @@ impl LedgerClient {
                     self.client.ledger_canister_id.to_text()
                 );
                 log!(DEBUG, "[burn]: {err_msg}",);
+                if is_outcome_unknown(error_code) {
+                    return Err(LedgerTransferError::OutcomeUnknown {
+                        message: err_msg,
+                        ledger: self.client.ledger_canister_id,
+                    });
+                }
                 Err(LedgerTransferError::TemporarilyUnavailable {
                     message: err_msg,
                     ledger: self.client.ledger_canister_id,
@@ -161,6 +223,7 @@ impl LedgerClient {
         amount: A,
         memo: WithdrawMemo,
         fee: B,
+        dedup: &TransferDedup,
     ) -> Result<TransferIndex, LedgerTransferError> {
         let amount = amount.into();
         let fee = fee.into();
@@ -171,8 +234,12 @@ impl LedgerClient {
                 to,
                 amount: amount.clone(),
                 fee: Some(fee),
-                memo: Some(Memo::from(memo)),
-                created_at_time: None,
+                memo: Some(journaled_memo(
+                    memo.kind(),
+                    dedup.operation_id,
+                    dedup.transfer_index,
+                )),
+                created_at_time: Some(dedup.created_at_time),
             })
             .await
         {
@@ -193,12 +260,15 @@ impl LedgerClient {
                         panic!("BUG: there should always be enough funds in the pool")
                     }
 
-                    TransferError::TooOld => panic!("BUG: transfer too old"),
+                    TransferError::TooOld => LedgerTransferError::TooOld {
+                        ledger: self.client.ledger_canister_id,
+                    },
                     TransferError::CreatedInFuture { ledger_time } => {
                         panic!("BUG: created in future, ledger time: {ledger_time}")
                     }
                     TransferError::Duplicate { duplicate_of } => {
-                        panic!("BUG: duplicate transfer of: {duplicate_of}")
+                        // the same journaled transfer already went through
+                        return Ok(TransferIndex(duplicate_of));
                     }
                     TransferError::TemporarilyUnavailable => {
                         LedgerTransferError::TemporarilyUnavailable {
@@ -228,6 +298,114 @@ impl LedgerClient {
                     self.client.ledger_canister_id.to_text()
                 );
                 log!(DEBUG, "[withdraw]: {err_msg}",);
+                if is_outcome_unknown(error_code) {
+                    return Err(LedgerTransferError::OutcomeUnknown {
+                        message: err_msg,
+                        ledger: self.client.ledger_canister_id,
+                    });
+                }
+                Err(LedgerTransferError::TemporarilyUnavailable {
+                    message: err_msg,
+                    ledger: self.client.ledger_canister_id,
+                })
+            }
+        }
+    }
+
+    // moves funds sent to a deposit subaccount of the canister into its main account
+    pub async fn sweep_subaccount<A: Into<Nat>, B: Into<Nat>>(
+        &self,
+        from_subaccount: Subaccount,
+        amount: A,
+        memo: DepositMemo,
+        fee: B,
+        dedup: &TransferDedup,
+    ) -> Result<TransferIndex, LedgerTransferError> {
+        let amount = amount.into();
+        match self
+            .client
+            .transfer(TransferArg {
+                from_subaccount: Some(from_subaccount),
+                to: ic_cdk::id().into(),
+                amount: amount.clone(),
+                fee: Some(fee.into()),
+                memo: Some(journaled_memo(
+                    memo.kind(),
+                    dedup.operation_id,
+                    dedup.transfer_index,
+                )),
+                created_at_time: Some(dedup.created_at_time),
+            })
+            .await
+        {
+            Ok(Ok(block_index)) => Ok(TransferIndex(block_index)),
+            Ok(Err(transfer_error)) => {
+                log!(
+                    DEBUG,
+                    "[sweep_subaccount]: failed to transfer with error: {transfer_error:?}",
+                );
+                let transfer_err = match transfer_error {
+                    TransferError::BadFee { expected_fee } => {
+                        LedgerTransferError::BadFee { expected_fee }
+                    }
+                    TransferError::BadBurn { min_burn_amount } => {
+                        LedgerTransferError::AmountTooLow {
+                            minimum_amount: min_burn_amount,
+                            failed_amount: amount,
+                            ledger: self.client.ledger_canister_id,
+                        }
+                    }
+                    TransferError::InsufficientFunds { balance } => {
+                        LedgerTransferError::InsufficientFunds {
+                            balance,
+                            failed_amount: amount,
+                            ledger: self.client.ledger_canister_id,
+                        }
+                    }
+                    TransferError::TooOld => LedgerTransferError::TooOld {
+                        ledger: self.client.ledger_canister_id,
+                    },
+                    TransferError::CreatedInFuture { ledger_time } => {
+                        panic!("BUG: created in future, ledger time: {ledger_time}")
+                    }
+                    TransferError::Duplicate { duplicate_of } => {
+                        // the same journaled sweep already went through
+                        return Ok(TransferIndex(duplicate_of));
+                    }
+                    TransferError::TemporarilyUnavailable => {
+                        LedgerTransferError::TemporarilyUnavailable {
+                            message: format!(
+                                "{} ledger temporarily unavailable, try again",
+                                self.client.ledger_canister_id.to_text()
+                            ),
+                            ledger: self.client.ledger_canister_id,
+                        }
+                    }
+                    TransferError::GenericError {
+                        error_code,
+                        message,
+                    } => LedgerTransferError::TemporarilyUnavailable {
+                        message: format!(
+                        "{} ledger unreachable, error code: {error_code}, with message: {message}",
+                        self.client.ledger_canister_id.to_text()
+                    ),
+                        ledger: self.client.ledger_canister_id,
+                    },
+                };
+                Err(transfer_err)
+            }
+            Err((error_code, message)) => {
+                let err_msg = format!(
+                    "failed to call {} ledger with error_code: {error_code} and message: {message}",
+                    self.client.ledger_canister_id.to_text()
+                );
+                log!(DEBUG, "[sweep_subaccount]: {err_msg}",);
+                if is_outcome_unknown(error_code) {
+                    return Err(LedgerTransferError::OutcomeUnknown {
+                        message: err_msg,
+                        ledger: self.client.ledger_canister_id,
+                    });
+                }
                 Err(LedgerTransferError::TemporarilyUnavailable {
                     message: err_msg,
                     ledger: self.client.ledger_canister_id,
@@ -242,4 +420,50 @@ impl LedgerClient {
             Err(err) => Err(format!("{}, {}", err.0, err.1)),
         }
     }
+
+    // names of the standards the ledger implements, e.g. "ICRC-1" and "ICRC-2"
+    pub async fn supported_standards(&self) -> Result<Vec<String>, String> {
+        let standards: Vec<SupportedStandard> = self.call("icrc1_supported_standards", ()).await?;
+        Ok(standards
+            .into_iter()
+            .map(|standard| standard.name)
+            .collect())
+    }
+
+    pub async fn metadata(&self) -> Result<Vec<(String, MetadataValue)>, String> {
+        self.call("icrc1_metadata", ()).await
+    }
+
+    pub async fn decimals(&self) -> Result<u8, String> {
+        self.call("icrc1_decimals", ()).await
+    }
+
+    pub async fn allowance(&self, args: AllowanceArgs) -> Result<Allowance, String> {
+        self.call("icrc2_allowance", (args,)).await
+    }
+
+    // calls a ledger endpoint not covered by the ICRC-1 client
+    async fn call<I, O>(&self, method: &str, args: I) -> Result<O, String>
+    where
+        I: candid::utils::ArgumentEncoder,
+        O: DeserializeOwned + CandidType,
+    {
+        let result: Result<(O,), _> =
+            ic_cdk::call(self.client.ledger_canister_id, method, args).await;
+        result
+            .map(|(output,)| output)
+            .map_err(|err| format!("{:?}, {}", err.0, err.1))
+    }
+
+    pub async fn balance_of(&self, account: Account) -> Result<Nat, String> {
+        match self.client.balance_of(account).await {
+            Ok(balance) => Ok(balance),
+            Err(err) => Err(format!("{}, {}", err.0, err.1)),
+        }
+    }
+
+    // balance of the canister main account, used for balance difference accounting
+    pub async fn canister_balance(&self) -> Result<Nat, String> {
+        self.balance_of(ic_cdk::id().into()).await
+    }
 }
@@ -0,0 +1,41 @@
+use super::memo::{journaled_memo, MemoKind, MAX_MEMO_LENGTH};
+
+#[test]
+fn journaled_memo_should_fit_icrc1_memo_limit() {
+    let memo = journaled_memo(MemoKind::Withdraw, u64::MAX, u32::MAX);
+    assert!(memo.0.len() <= MAX_MEMO_LENGTH);
+
+    // transfers of different operations or at different indexes are not deduplicated
+    assert_ne!(
+        journaled_memo(MemoKind::Deposit, 1, 0),
+        journaled_memo(MemoKind::Deposit, 2, 0)
+    );
+    assert_ne!(
+        journaled_memo(MemoKind::Deposit, 1, 0),
+        journaled_memo(MemoKind::Deposit, 1, 1)
+    );
+}
+
+#[test]
+fn memo_kind_names_should_be_stable() {
+    // the names are hashed into ICRC-3 blocks
+    let kinds = [
+        (MemoKind::MintPosition, "MintPosition"),
+        (MemoKind::IncreasePosition, "IncreasePosition"),
+        (MemoKind::SwapIn, "SwapIn"),
+        (MemoKind::Deposit, "Deposit"),
+        (MemoKind::Donate, "Donate"),
+        (MemoKind::NotifiedDeposit, "NotifiedDeposit"),
+        (MemoKind::BurnPosition, "BurnPosition"),
+        (MemoKind::DecreasePosition, "DecreasePosition"),
+        (MemoKind::SwapOut, "SwapOut"),
+        (MemoKind::WithdrawBalance, "WithdrawBalance"),
+        (MemoKind::Refund, "Refund"),
+        (MemoKind::CollectFees, "CollectFees"),
+        (MemoKind::Withdraw, "Withdraw"),
+    ];
+    for (kind, name) in kinds {
+        assert_eq!(kind.as_str(), name);
+        assert_eq!(kind.as_str(), format!("{kind:?}"));
+    }
+}
@@ -0,0 +1,263 @@
+// a module for making update calls idempotent. Callers may attach a request id to an update call,
+// the result of the first call made with that id is cached in stable memory and returned as is to
+// any retry of the same request, so that a client retrying after a timeout does not swap, mint or
+// withdraw twice.
+// Cached responses expire after REQUEST_TTL_NANOS and the cache is capped at
+// MAX_CACHED_RESPONSES entries, the oldest entries being evicted first. Only final results are
+// cached, errors a retry may not get again (see `FinalResponse`) let the retry run the call again.
+
+use candid::{CandidType, Principal};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    candid_types::{
+        pool::{CreatePoolAndMintError, CreatePoolError, DonateError},
+        position::{
+            BurnPositionError, CollectFeesError, DecreaseLiquidityError, IncreaseLiquidityError,
+            MintPositionError,
+        },
+        swap::SwapError,
+        ApproveInternalError, DepositError, InternalTransferError, NotifyDepositError,
+        WithdrawError,
+    },
+    state::{mutate_state, read_state},
+};
+use types::{CachedResponse, RequestKey};
+
+pub mod types;
+
+#[cfg(test)]
+mod tests;
+
+/// Time during which a retried request gets the cached response of the first call.
+pub const REQUEST_TTL_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Upper bound of the number of cached responses.
+pub const MAX_CACHED_RESPONSES: u64 = 100_000;
+
+/// Maximum length of a request id in bytes.
+pub const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// Maximum number of expired responses removed by a single call.
+const MAX_PRUNED_PER_CALL: usize = 100;
+
+/// Returns the response cached for the request, if it was made to the same endpoint and did not
+/// expire yet.
+pub fn get_cached_response(key: &RequestKey, method: &str, now: u64) -> Option<Vec<u8>> {
+    read_state(|s| s.get_cached_response(key))
+        .filter(|cached| cached.method == method && !is_expired(cached.created_at, now))
+        .map(|cached| cached.response)
+}
+
+/// Caches the response of a request, replacing any previous response of the same request id.
+pub fn record_response(key: RequestKey, method: &str, response: Vec<u8>, now: u64) {
+    mutate_state(|s| {
+        s.insert_cached_response(
+            key,
+            CachedResponse {
+                method: method.to_string(),
+                created_at: now,
+                response,
+            },
+        )
+    });
+    prune_responses(now);
+}
+
+/// Removes expired responses and evicts the oldest ones above the cap.
+pub fn prune_responses(now: u64) {
+    mutate_state(|s| {
+        let mut pruned = 0;
+        while let Some(oldest) = s.oldest_cached_response() {
+            let over_cap = s.cached_response_count() > MAX_CACHED_RESPONSES;
+            let expired = pruned < MAX_PRUNED_PER_CALL && is_expired(oldest.created_at, now);
+            if !over_cap && !expired {
+                break;
+            }
+            s.remove_cached_response(&oldest.key);
+            pruned += 1;
+        }
+    });
+}
+
+fn is_expired(created_at: u64, now: u64) -> bool {
+    now >= created_at.saturating_add(REQUEST_TTL_NANOS)
+}
+
+/// An update call made with an optional request id.
+pub struct IdempotentRequest {
+    key: Option<RequestKey>,
+    method: &'static str,
+}
+
+impl IdempotentRequest {
+    /// Traps if the request id is longer than MAX_REQUEST_ID_LENGTH.
+    pub fn new(caller: Principal, request_id: Option<Vec<u8>>, method: &'static str) -> Self {
+        if let Some(request_id) = &request_id {
+            if request_id.len() > MAX_REQUEST_ID_LENGTH {
+                ic_cdk::trap(&format!(
+                    "request_id should be at most {MAX_REQUEST_ID_LENGTH} bytes"
+                ));
+            }
+        }
+        Self {
+            key: request_id.map(|request_id| RequestKey { caller, request_id }),
+            method,
+        }
+    }
+
+    pub fn request_id(&self) -> Option<Vec<u8>> {
+        self.key.as_ref().map(|key| key.request_id.clone())
+    }
+
+    /// Returns the result of a previous call made with the same request id.
+    pub fn cached_response<R: CandidType + DeserializeOwned>(&self, now: u64) -> Option<R> {
+        let response = get_cached_response(self.key.as_ref()?, self.method, now)?;
+        candid::decode_one(&response).ok()
+    }
+
+    /// Caches the result of the call, no-op for calls without a request id or a result that is not
+    /// final.
+    pub fn record_response<R: CandidType + FinalResponse>(&self, response: &R, now: u64) {
+        if !response.is_final() {
+            return;
+        }
+        if let Some(key) = &self.key {
+            let response =
+                candid::encode_one(response).expect("candid encoding should always succeed");
+            record_response(key.clone(), self.method, response, now);
+        }
+    }
+}
+
+/// Whether a result is returned as is to every retry of its request. Errors returned before
+/// anything was done, such as a locked principal or an unavailable ledger, are not final and a
+/// retry runs the call again. Errors after a state change are final, e.g. a swap whose output
+/// withdrawal failed or a transfer with an unknown outcome left to the journal.
+pub trait FinalResponse {
+    fn is_final(&self) -> bool;
+}
+
+impl<T, E: FinalResponse> FinalResponse for Result<T, E> {
+    fn is_final(&self) -> bool {
+        match self {
+            Ok(_) => true,
+            Err(error) => error.is_final(),
+        }
+    }
+}
+
+impl FinalResponse for CreatePoolError {
+    fn is_final(&self) -> bool {
+        !matches!(self, Self::LockedPrincipal)
+    }
+}
+
+impl FinalResponse for CreatePoolAndMintError {
+    fn is_final(&self) -> bool {
+        match self {
+            Self::LockedPrincipal => false,
+            Self::CreatePool(error) => error.is_final(),
+            Self::MintPosition(error) => error.is_final(),
+            _ => true,
+        }
+    }
+}
+
+impl FinalResponse for MintPositionError {
+    fn is_final(&self) -> bool {
+        match self {
+            Self::LockedPrincipal => false,
+            Self::DepositError(error) => error.is_final(),
+            _ => true,
+        }
+    }
+}
+
+impl FinalResponse for IncreaseLiquidityError {
+    fn is_final(&self) -> bool {
+        match self {
+            Self::LockedPrincipal => false,
+            Self::DepositError(error) => error.is_final(),
+            _ => true,
+        }
+    }
+}
+
+impl FinalResponse for BurnPositionError {
+    fn is_final(&self) -> bool {
+        !matches!(self, Self::LockedPrincipal)
+    }
+}
+
+impl FinalResponse for DecreaseLiquidityError {
+    fn is_final(&self) -> bool {
+        !matches!(self, Self::LockedPrincipal)
+    }
+}
+
+impl FinalResponse for CollectFeesError {
+    fn is_final(&self) -> bool {
+        !matches!(self, Self::LockedPrincipal)
+    }
+}
+
+impl FinalResponse for SwapError {
+    fn is_final(&self) -> bool {
+        match self {
+            Self::LockedPrincipal => false,
+            Self::DepositError(error) => error.is_final(),
+            _ => true,
+        }
+    }
+}
+
+impl FinalResponse for DonateError {
+    fn is_final(&self) -> bool {
+        match self {
+            Self::LockedPrincipal => false,
+            Self::DepositError(error) => error.is_final(),
+            _ => true,
+        }
+    }
+}
+
+impl FinalResponse for DepositError {
+    fn is_final(&self) -> bool {
+        !matches!(
+            self,
+            Self::LockedPrincipal | Self::TemporarilyUnavailable(_)
+        )
+    }
+}
+
+impl FinalResponse for NotifyDepositError {
+    fn is_final(&self) -> bool {
+        match self {
+            Self::LockedPrincipal | Self::TemporarilyUnavailable(_) => false,
+            Self::DepositError(error) => error.is_final(),
+            _ => true,
+        }
+    }
+}
+
+impl FinalResponse for WithdrawError {
+    fn is_final(&self) -> bool {
+        !matches!(
+            self,
+            Self::LockedPrincipal | Self::TemporarilyUnavailable(_)
+        )
+    }
+}
+
+impl FinalResponse for InternalTransferError {
+    fn is_final(&self) -> bool {
+        !matches!(self, Self::LockedPrincipal)
+    }
+}
+
+impl FinalResponse for ApproveInternalError {
+    fn is_final(&self) -> bool {
+        true
+    }
+}
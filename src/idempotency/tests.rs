@@ -0,0 +1,165 @@
+use candid::Principal;
+
+use super::*;
+use crate::state::read_state;
+
+const NOW: u64 = 1_000_000_000_000;
+
+impl FinalResponse for String {
+    fn is_final(&self) -> bool {
+        true
+    }
+}
+
+fn user() -> Principal {
+    Principal::from_slice(&[1_u8; 29])
+}
+
+fn request(request_id: u8, method: &'static str) -> IdempotentRequest {
+    IdempotentRequest::new(user(), Some(vec![request_id; 16]), method)
+}
+
+#[test]
+fn retried_request_should_get_the_cached_response() {
+    let first = request(1, "swap");
+    assert_eq!(first.cached_response::<Result<u64, String>>(NOW), None);
+    first.record_response(&Result::<u64, String>::Ok(42), NOW);
+
+    let retry = request(1, "swap");
+    assert_eq!(
+        retry.cached_response::<Result<u64, String>>(NOW + 1),
+        Some(Ok(42))
+    );
+    // other request ids, callers and endpoints are not affected
+    assert_eq!(
+        request(2, "swap").cached_response::<Result<u64, String>>(NOW),
+        None
+    );
+    assert_eq!(
+        IdempotentRequest::new(Principal::anonymous(), Some(vec![1; 16]), "swap")
+            .cached_response::<Result<u64, String>>(NOW),
+        None
+    );
+    assert_eq!(
+        request(1, "withdraw").cached_response::<Result<u64, String>>(NOW),
+        None
+    );
+}
+
+#[test]
+fn request_without_id_should_not_be_cached() {
+    let request = IdempotentRequest::new(user(), None, "swap");
+    request.record_response(&Result::<u64, String>::Ok(42), NOW);
+
+    assert_eq!(request.cached_response::<Result<u64, String>>(NOW), None);
+    assert_eq!(read_state(|s| s.cached_response_count()), 0);
+}
+
+#[test]
+fn cached_response_should_expire() {
+    request(1, "swap").record_response(&Result::<u64, String>::Err("failed".to_string()), NOW);
+
+    let expiry = NOW + REQUEST_TTL_NANOS;
+    assert_eq!(
+        request(1, "swap").cached_response::<Result<u64, String>>(expiry - 1),
+        Some(Err("failed".to_string()))
+    );
+    assert_eq!(
+        request(1, "swap").cached_response::<Result<u64, String>>(expiry),
+        None
+    );
+
+    // expired responses are removed when a new one is recorded
+    request(2, "swap").record_response(&Result::<u64, String>::Ok(1), expiry);
+    assert_eq!(read_state(|s| s.cached_response_count()), 1);
+    assert_eq!(
+        read_state(|s| s.oldest_cached_response())
+            .unwrap()
+            .created_at,
+        expiry
+    );
+}
+
+#[test]
+fn recording_a_request_again_should_replace_its_response() {
+    request(1, "swap").record_response(&Result::<u64, String>::Ok(1), NOW);
+    request(1, "swap").record_response(&Result::<u64, String>::Ok(2), NOW + 1);
+
+    assert_eq!(read_state(|s| s.cached_response_count()), 1);
+    assert_eq!(
+        request(1, "swap").cached_response::<Result<u64, String>>(NOW + 1),
+        Some(Ok(2))
+    );
+    assert_eq!(
+        read_state(|s| s.oldest_cached_response())
+            .unwrap()
+            .created_at,
+        NOW + 1
+    );
+}
+
+#[test]
+fn errors_that_are_not_final_should_not_be_cached() {
+    let request = request(1, "swap");
+    request.record_response(
+        &Result::<u64, SwapError>::Err(SwapError::LockedPrincipal),
+        NOW,
+    );
+    request.record_response(
+        &Result::<u64, SwapError>::Err(SwapError::DepositError(
+            DepositError::TemporarilyUnavailable("ledger is stopped".to_string()),
+        )),
+        NOW,
+    );
+    assert_eq!(read_state(|s| s.cached_response_count()), 0);
+
+    // a failed deposit that can not succeed on a retry is final
+    let insufficient_funds = SwapError::DepositError(DepositError::InsufficientFunds {
+        balance: 0_u8.into(),
+    });
+    request.record_response(
+        &Result::<u64, SwapError>::Err(insufficient_funds.clone()),
+        NOW,
+    );
+    assert_eq!(
+        request.cached_response::<Result<u64, SwapError>>(NOW + 1),
+        Some(Err(insufficient_funds))
+    );
+}
+
+#[test]
+fn errors_after_a_state_change_should_be_cached() {
+    // the swap was executed, its output is sent by the withdrawal retry timer
+    let failed_to_withdraw = SwapError::FailedToWithdraw {
+        reason: WithdrawError::TemporarilyUnavailable("ledger is stopped".to_string()),
+        amount_in: 100_u8.into(),
+        amount_out: 99_u8.into(),
+    };
+    request(1, "swap").record_response(
+        &Result::<u64, SwapError>::Err(failed_to_withdraw.clone()),
+        NOW,
+    );
+    assert_eq!(
+        request(1, "swap").cached_response::<Result<u64, SwapError>>(NOW + 1),
+        Some(Err(failed_to_withdraw))
+    );
+
+    // the deposit is journaled, a retry must not pull the funds again
+    let outcome_unknown = SwapError::DepositError(DepositError::OutcomeUnknown { operation_id: 7 });
+    request(2, "swap")
+        .record_response(&Result::<u64, SwapError>::Err(outcome_unknown.clone()), NOW);
+    assert_eq!(
+        request(2, "swap").cached_response::<Result<u64, SwapError>>(NOW + 1),
+        Some(Err(outcome_unknown))
+    );
+
+    let withdrawal_unknown = WithdrawError::OutcomeUnknown { operation_id: 8 };
+    request(1, "withdraw").record_response(
+        &Result::<u64, WithdrawError>::Err(withdrawal_unknown.clone()),
+        NOW,
+    );
+    assert_eq!(
+        request(1, "withdraw").cached_response::<Result<u64, WithdrawError>>(NOW + 1),
+        Some(Err(withdrawal_unknown))
+    );
+}
@@ -0,0 +1,33 @@
+use candid::Principal;
+use minicbor::{Decode, Encode};
+
+/// A request id chosen by the caller, unique per caller.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestKey {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub caller: Principal,
+    #[cbor(n(1), with = "minicbor::bytes")]
+    pub request_id: Vec<u8>,
+}
+
+/// The candid encoded result returned by the first call made with a request id.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct CachedResponse {
+    /// The endpoint that produced the response, a request id reused for another endpoint is
+    /// treated as a new request.
+    #[n(0)]
+    pub method: String,
+    #[n(1)]
+    pub created_at: u64,
+    #[cbor(n(2), with = "minicbor::bytes")]
+    pub response: Vec<u8>,
+}
+
+/// Index of the cached responses ordered by creation time, used for expiring the oldest ones.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestExpiryKey {
+    #[n(0)]
+    pub created_at: u64,
+    #[n(1)]
+    pub key: RequestKey,
+}
@@ -5,6 +5,7 @@ use crate::{
     balances::types::{UserBalance, UserBalanceKey},
     candid_types::position::IncreaseLiquidityError,
     events::{Event, EventType},
+    historical::{pools_before, update_pool_history},
     libraries::{balance_delta::BalanceDelta, slippage_check::validate_max_in},
     mint::calculate_liquidity,
     pool::{
@@ -121,7 +122,9 @@ pub fn execute_increase_liquidity(
             UserBalance(final_balance.amount1().as_u256()),
         );
 
+        let pools_before = pools_before(s, &event);
         s.apply_modify_liquidity_buffer_state(success_result.buffer_state);
+        update_pool_history(s, pools_before, &event);
 
         s.record_event(event);
     });
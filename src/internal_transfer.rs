@@ -0,0 +1,172 @@
+use candid::Principal;
+use ethnum::U256;
+
+use crate::{
+    balances::types::{InternalAllowance, InternalAllowanceKey, UserBalance, UserBalanceKey},
+    candid_types::{ApproveInternalError, InternalTransferError},
+    events::{Event, EventType},
+    libraries::safe_cast::u256_to_nat,
+    state::{mutate_state, read_state},
+};
+
+/// Checks that internal balance can be moved from `from` to `to`, before any guard on the
+/// recipient is taken.
+pub fn validate_internal_transfer(
+    from: Principal,
+    to: Principal,
+    amount: U256,
+) -> Result<(), InternalTransferError> {
+    if to == Principal::anonymous() || to == from {
+        return Err(InternalTransferError::InvalidRecipient);
+    }
+    if amount == U256::ZERO {
+        return Err(InternalTransferError::ZeroAmount);
+    }
+    Ok(())
+}
+
+/// Moves `amount` of `token` from the internal balance of `from` to the internal balance of `to`
+/// without touching the token ledger. When a `spender` is given the amount is taken from the
+/// allowance `from` gave to it with `approve_internal`. Returns the index of the recorded
+/// `InternalTransfer` event.
+pub fn execute_internal_transfer(
+    from: Principal,
+    to: Principal,
+    token: Principal,
+    amount: U256,
+    spender: Option<Principal>,
+    timestamp: u64,
+) -> Result<u64, InternalTransferError> {
+    validate_internal_transfer(from, to, amount)?;
+
+    let from_key = UserBalanceKey { user: from, token };
+    let to_key = UserBalanceKey { user: to, token };
+    let allowance_key = spender.map(|spender| InternalAllowanceKey {
+        owner: from,
+        spender,
+        token,
+    });
+
+    let (from_balance, to_balance, allowance) = read_state(|s| {
+        (
+            s.get_user_balance(&from_key).0,
+            s.get_user_balance(&to_key).0,
+            allowance_key
+                .as_ref()
+                .map(|key| s.get_internal_allowance(key)),
+        )
+    });
+
+    let allowance_after = match &allowance {
+        Some(allowance) => {
+            let available = allowance.available(timestamp);
+            let amount_after = available.checked_sub(amount).ok_or(
+                InternalTransferError::InsufficientAllowance {
+                    allowance: u256_to_nat(available),
+                },
+            )?;
+            Some(InternalAllowance {
+                amount: amount_after,
+                expires_at: allowance.expires_at,
+            })
+        }
+        None => None,
+    };
+
+    let from_balance_after =
+        from_balance
+            .checked_sub(amount)
+            .ok_or(InternalTransferError::InsufficientBalance {
+                balance: u256_to_nat(from_balance),
+            })?;
+    let to_balance_after = to_balance
+        .checked_add(amount)
+        .ok_or(InternalTransferError::AmountOverflow)?;
+
+    let event = Event {
+        timestamp,
+        payload: EventType::InternalTransfer {
+            token,
+            from,
+            to,
+            amount,
+            spender,
+        },
+    };
+
+    // Batch state updates
+    let event_index = mutate_state(|s| {
+        s.update_user_balance(from_key, UserBalance(from_balance_after));
+        s.update_user_balance(to_key, UserBalance(to_balance_after));
+        if let (Some(key), Some(allowance)) = (allowance_key, allowance_after) {
+            s.set_internal_allowance(key, allowance);
+        }
+        let event_index = s.total_event_count();
+        s.record_event(event);
+        event_index
+    });
+
+    Ok(event_index)
+}
+
+/// Sets the allowance of `spender` over the internal balance of `owner` in `token`, replacing the
+/// previous one like ICRC-2 `approve`. When `expected_allowance` is given, the call fails if the
+/// current allowance differs from it.
+pub fn execute_approve_internal(
+    owner: Principal,
+    spender: Principal,
+    token: Principal,
+    amount: U256,
+    expected_allowance: Option<U256>,
+    expires_at: Option<u64>,
+    timestamp: u64,
+) -> Result<(), ApproveInternalError> {
+    if spender == Principal::anonymous() || spender == owner {
+        return Err(ApproveInternalError::InvalidSpender);
+    }
+    if let Some(expires_at) = expires_at {
+        if expires_at <= timestamp {
+            return Err(ApproveInternalError::Expired {
+                ledger_time: timestamp,
+            });
+        }
+    }
+
+    let key = InternalAllowanceKey {
+        owner,
+        spender,
+        token,
+    };
+    let current_allowance = read_state(|s| s.get_internal_allowance(&key)).available(timestamp);
+    if let Some(expected_allowance) = expected_allowance {
+        if expected_allowance != current_allowance {
+            return Err(ApproveInternalError::AllowanceChanged {
+                current_allowance: u256_to_nat(current_allowance),
+            });
+        }
+    }
+
+    mutate_state(|s| s.set_internal_allowance(key, InternalAllowance { amount, expires_at }));
+
+    Ok(())
+}
+
+/// Allowance of `spender` over the internal balance of `owner`, zero if none was given or it expired.
+pub fn get_internal_allowance(
+    owner: Principal,
+    spender: Principal,
+    token: Principal,
+    now: u64,
+) -> InternalAllowance {
+    let allowance = read_state(|s| {
+        s.get_internal_allowance(&InternalAllowanceKey {
+            owner,
+            spender,
+            token,
+        })
+    });
+    if allowance.available(now) == U256::ZERO {
+        return InternalAllowance::default();
+    }
+    allowance
+}
@@ -0,0 +1,468 @@
+// a module for keeping track of multi step update calls(deposit -> execution -> withdrawal) in
+// stable memory, so that a failed or trapped withdrawal is never silently left as an internal
+// credit.
+// Every operation is opened when an update call starts and closed once nothing is pending anymore,
+// operations with failed withdrawals stay open and are retried by a timer with exponential backoff.
+// Transfers carry a deterministic created_at_time and memo, so a transfer with an unknown outcome
+// can be sent again without paying twice, a deposit with an unknown outcome keeps its operation
+// open until the timer resolves it and credits the user.
+// Closed operations are kept for CLOSED_OPERATION_TTL_NANOS and capped at MAX_CLOSED_OPERATIONS,
+// the oldest being pruned whenever an operation is closed
+
+use candid::Principal;
+use ethnum::U256;
+
+use crate::{
+    icrc_client::TransferDedup,
+    state::{mutate_state, read_state, State},
+};
+use types::{
+    DepositStatus, JournalDeposit, JournalWithdrawal, Operation, OperationId, OperationKind,
+    OperationStatus, PendingDeposit, WithdrawalStatus,
+};
+
+pub mod types;
+
+#[cfg(test)]
+mod tests;
+
+/// Delay before the first retry of a failed withdrawal, doubled on every attempt.
+pub const BASE_RETRY_DELAY_NANOS: u64 = 60 * 1_000_000_000;
+
+/// Upper bound of the delay between two retries.
+pub const MAX_RETRY_DELAY_NANOS: u64 = 6 * 60 * 60 * 1_000_000_000;
+
+/// Attempts after which a withdrawal is given up on and left in the user balance.
+pub const MAX_WITHDRAWAL_ATTEMPTS: u32 = 10;
+
+/// Time during which a closed operation can still be looked up with `get_operation`.
+pub const CLOSED_OPERATION_TTL_NANOS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Upper bound of the number of closed operations.
+pub const MAX_CLOSED_OPERATIONS: u64 = 100_000;
+
+/// Maximum number of expired closed operations removed by a single call.
+const MAX_PRUNED_PER_CALL: usize = 100;
+
+/// Delay before retrying a withdrawal that already failed `attempts` times.
+pub fn retry_delay_nanos(attempts: u32) -> u64 {
+    BASE_RETRY_DELAY_NANOS
+        .checked_shl(attempts.saturating_sub(1))
+        .unwrap_or(MAX_RETRY_DELAY_NANOS)
+        .min(MAX_RETRY_DELAY_NANOS)
+}
+
+/// Opens a new operation for `owner` and returns its id.
+pub fn start_operation(owner: Principal, kind: OperationKind, now: u64) -> OperationId {
+    mutate_state(|s| {
+        let operation_id = s.next_operation_id();
+        s.upsert_open_operation(
+            operation_id,
+            Operation {
+                owner,
+                kind,
+                status: OperationStatus::Started,
+                started_at: now,
+                updated_at: now,
+                deposits: vec![],
+                withdrawals: vec![],
+                event_ids: vec![],
+                pending_deposit: None,
+            },
+        );
+        operation_id
+    })
+}
+
+/// Deduplication arguments of a deposit about to be sent for the operation.
+pub fn deposit_dedup(operation_id: OperationId, now: u64) -> TransferDedup {
+    let transfer_index = read_state(|s| s.get_open_operation(operation_id))
+        .map_or(0, |operation| operation.deposits.len() as u32);
+    TransferDedup {
+        created_at_time: now,
+        operation_id,
+        transfer_index,
+    }
+}
+
+/// Records an amount credited to the user balance by the operation.
+pub fn record_deposit(operation_id: OperationId, token: Principal, amount: U256, now: u64) {
+    update_open_operation(operation_id, now, |operation| {
+        operation.deposits.push(JournalDeposit { token, amount });
+        if operation.status == OperationStatus::Started {
+            operation.status = OperationStatus::DepositReceived;
+        }
+    });
+}
+
+/// Records a deposit that may or may not have gone through, nothing is credited to the user until
+/// the identical transfer sent again resolves its outcome.
+pub fn record_deposit_pending(
+    operation_id: OperationId,
+    deposit: PendingDeposit,
+    error: String,
+    now: u64,
+) {
+    update_open_operation(operation_id, now, |operation| {
+        operation.pending_deposit = Some(deposit);
+    });
+    record_deposit_outcome_unknown(operation_id, error, now);
+}
+
+/// Records a pending deposit sent again with identical arguments.
+pub fn record_deposit_resent(operation_id: OperationId, now: u64) {
+    update_pending_deposit(operation_id, now, |deposit| {
+        deposit.attempts += 1;
+    });
+}
+
+/// Schedules the pending deposit to be sent again with backoff.
+pub fn record_deposit_outcome_unknown(operation_id: OperationId, error: String, now: u64) {
+    update_pending_deposit(operation_id, now, |deposit| {
+        deposit.last_error = Some(error);
+        deposit.status = DepositStatus::OutcomeUnknown {
+            next_retry_at: now.saturating_add(retry_delay_nanos(deposit.attempts)),
+        };
+    });
+}
+
+/// Records a pending deposit whose outcome can not be resolved anymore.
+pub fn record_deposit_unresolved(operation_id: OperationId, error: String, now: u64) {
+    update_pending_deposit(operation_id, now, |deposit| {
+        deposit.last_error = Some(error);
+        deposit.status = DepositStatus::Unresolved;
+    });
+}
+
+/// Removes the pending deposit once its outcome is known, a deposit that went through is recorded
+/// with `record_deposit` beforehand.
+pub fn record_deposit_resolved(operation_id: OperationId, now: u64) {
+    update_open_operation(operation_id, now, |operation| {
+        operation.pending_deposit = None;
+    });
+}
+
+/// Links the last recorded event to the operation, called right after a successful execution.
+pub fn record_execution(operation_id: OperationId, now: u64) {
+    let last_event_id = read_state(|s| s.total_event_count().checked_sub(1));
+    update_open_operation(operation_id, now, |operation| {
+        if let Some(event_id) = last_event_id {
+            operation.event_ids.push(event_id);
+        }
+        operation.status = OperationStatus::Executed;
+    });
+}
+
+/// Where a withdrawal is journaled, either as a new withdrawal of an operation or as a retry of
+/// one of its withdrawals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WithdrawalEntry {
+    New(OperationId),
+    Retry(OperationId, usize),
+}
+
+/// Records a withdrawal about to be sent, the amount is already debited from the user balance.
+/// Returns the deduplication arguments of the transfer, the operation and the index of the
+/// withdrawal in it.
+pub fn record_withdrawal_sent(
+    entry: WithdrawalEntry,
+    withdrawal: JournalWithdrawal,
+    now: u64,
+) -> TransferDedup {
+    let operation_id = match entry {
+        WithdrawalEntry::New(operation_id) | WithdrawalEntry::Retry(operation_id, _) => {
+            operation_id
+        }
+    };
+    let mut index = 0;
+    update_open_operation(operation_id, now, |operation| {
+        index = match entry {
+            WithdrawalEntry::Retry(_, index) if index < operation.withdrawals.len() => {
+                let previous = &operation.withdrawals[index];
+                operation.withdrawals[index] = JournalWithdrawal {
+                    attempts: previous.attempts,
+                    last_error: previous.last_error.clone(),
+                    ..withdrawal
+                };
+                index
+            }
+            _ => {
+                operation.withdrawals.push(withdrawal);
+                operation.withdrawals.len() - 1
+            }
+        };
+        operation.withdrawals[index].status = WithdrawalStatus::InFlight;
+        operation.withdrawals[index].attempts += 1;
+        operation.withdrawals[index].created_at_time = Some(now);
+        operation.status = OperationStatus::WithdrawalPending;
+    });
+    TransferDedup {
+        created_at_time: now,
+        operation_id,
+        transfer_index: index as u32,
+    }
+}
+
+/// Records a withdrawal with an unknown outcome sent again with identical arguments.
+pub fn record_withdrawal_resent(operation_id: OperationId, index: usize, now: u64) {
+    update_withdrawal(operation_id, index, now, |_kind, withdrawal| {
+        withdrawal.status = WithdrawalStatus::InFlight;
+        withdrawal.attempts += 1;
+    });
+}
+
+/// Records a transfer that may or may not have gone through, the amount stays debited and the
+/// identical transfer is sent again with backoff, whatever the kind of the operation.
+pub fn record_withdrawal_outcome_unknown(
+    operation_id: OperationId,
+    index: usize,
+    error: String,
+    now: u64,
+) {
+    update_withdrawal(operation_id, index, now, |_kind, withdrawal| {
+        withdrawal.last_error = Some(error);
+        withdrawal.status = WithdrawalStatus::OutcomeUnknown {
+            next_retry_at: now.saturating_add(retry_delay_nanos(withdrawal.attempts)),
+        };
+    });
+}
+
+pub fn record_withdrawal_completed(
+    operation_id: OperationId,
+    index: usize,
+    block_index: u64,
+    now: u64,
+) {
+    update_withdrawal(operation_id, index, now, |_kind, withdrawal| {
+        withdrawal.status = WithdrawalStatus::Completed { block_index };
+    });
+}
+
+/// Records a failed transfer whose amount was credited back to the user. The withdrawal is retried
+/// with backoff, except for explicit withdrawals which report the failure to the caller.
+pub fn record_withdrawal_failed(operation_id: OperationId, index: usize, error: String, now: u64) {
+    update_withdrawal(operation_id, index, now, |kind, withdrawal| {
+        withdrawal.last_error = Some(error);
+        withdrawal.status =
+            if kind == OperationKind::Withdraw || withdrawal.attempts >= MAX_WITHDRAWAL_ATTEMPTS {
+                WithdrawalStatus::Failed
+            } else {
+                WithdrawalStatus::Retrying {
+                    next_retry_at: now.saturating_add(retry_delay_nanos(withdrawal.attempts)),
+                }
+            };
+    });
+}
+
+/// Records a retried withdrawal that could not be sent again, e.g. the user already moved the
+/// funds or the amount does not cover the transfer fee anymore.
+pub fn record_withdrawal_abandoned(
+    operation_id: OperationId,
+    index: usize,
+    status: WithdrawalStatus,
+    error: Option<String>,
+    now: u64,
+) {
+    update_withdrawal(operation_id, index, now, |_kind, withdrawal| {
+        withdrawal.status = status;
+        if error.is_some() {
+            withdrawal.last_error = error;
+        }
+    });
+}
+
+/// Closes the operation if nothing is pending anymore, operations that did nothing are dropped.
+pub fn finish_operation(operation_id: OperationId) {
+    mutate_state(|s| {
+        let Some(mut operation) = s.get_open_operation(operation_id) else {
+            return;
+        };
+        if let Some(status) = operation.pending_status() {
+            operation.status = status;
+            s.upsert_open_operation(operation_id, operation);
+            return;
+        }
+        s.remove_open_operation(operation_id);
+        if !operation.is_noop() {
+            let now = operation.updated_at;
+            operation.status = operation.final_status();
+            s.insert_closed_operation(operation_id, operation);
+            prune_closed_operations(s, now);
+        }
+    });
+}
+
+/// Removes expired closed operations and evicts the oldest ones above the cap. The last closed
+/// operation is kept, it tells `next_operation_id` which ids were already used.
+fn prune_closed_operations(s: &mut State, now: u64) {
+    let mut pruned = 0;
+    while let Some((operation_id, oldest)) = s.oldest_closed_operation() {
+        if Some(operation_id) == s.last_closed_operation_id() {
+            break;
+        }
+        let over_cap = s.closed_operation_count() > MAX_CLOSED_OPERATIONS;
+        let expired = pruned < MAX_PRUNED_PER_CALL
+            && now >= oldest.updated_at.saturating_add(CLOSED_OPERATION_TTL_NANOS);
+        if !over_cap && !expired {
+            break;
+        }
+        s.remove_closed_operation(operation_id);
+        pruned += 1;
+    }
+}
+
+/// Withdrawals waiting for a retry that are due at `now`, as (operation, withdrawal index, owner).
+/// In flight withdrawals are listed as well, they are only due once no call of the
+/// owner is running anymore, which the caller has to make sure of.
+pub fn withdrawals_due(now: u64) -> Vec<(OperationId, usize, Principal)> {
+    read_state(|s| {
+        s.get_open_operations()
+            .into_iter()
+            .flat_map(|(operation_id, operation)| {
+                let owner = operation.owner;
+                operation
+                    .withdrawals
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_index, withdrawal)| is_due(withdrawal, now))
+                    .map(move |(index, _withdrawal)| (operation_id, index, owner))
+            })
+            .collect()
+    })
+}
+
+/// Reads a withdrawal again if it is still due, it may have been retried since it was listed.
+pub fn get_due_withdrawal(
+    operation_id: OperationId,
+    index: usize,
+    now: u64,
+) -> Option<JournalWithdrawal> {
+    read_state(|s| {
+        s.get_open_operation(operation_id)
+            .and_then(|operation| operation.withdrawals.get(index).cloned())
+            .filter(|withdrawal| is_due(withdrawal, now))
+    })
+}
+
+fn is_due(withdrawal: &JournalWithdrawal, now: u64) -> bool {
+    match withdrawal.status {
+        WithdrawalStatus::InFlight => true,
+        WithdrawalStatus::Retrying { next_retry_at }
+        | WithdrawalStatus::OutcomeUnknown { next_retry_at } => next_retry_at <= now,
+        _ => false,
+    }
+}
+
+/// Operations with a pending deposit due at `now`, as (operation, owner).
+pub fn deposits_due(now: u64) -> Vec<(OperationId, Principal)> {
+    read_state(|s| {
+        s.get_open_operations()
+            .into_iter()
+            .filter(|(_id, operation)| {
+                operation
+                    .pending_deposit
+                    .as_ref()
+                    .is_some_and(|deposit| is_deposit_due(deposit, now))
+            })
+            .map(|(operation_id, operation)| (operation_id, operation.owner))
+            .collect()
+    })
+}
+
+/// Reads a pending deposit again if it is still due, it may have been resolved since it was listed.
+pub fn get_due_deposit(operation_id: OperationId, now: u64) -> Option<PendingDeposit> {
+    read_state(|s| {
+        s.get_open_operation(operation_id)
+            .and_then(|operation| operation.pending_deposit)
+            .filter(|deposit| is_deposit_due(deposit, now))
+    })
+}
+
+fn is_deposit_due(deposit: &PendingDeposit, now: u64) -> bool {
+    match deposit.status {
+        DepositStatus::OutcomeUnknown { next_retry_at } => next_retry_at <= now,
+        DepositStatus::Unresolved => false,
+    }
+}
+
+/// Open operations of a user, i.e. operations with a deposit or withdrawal in flight or waiting for
+/// a retry.
+pub fn get_pending_operations(owner: Principal) -> Vec<(OperationId, Operation)> {
+    read_state(|s| {
+        s.get_open_operations()
+            .into_iter()
+            .filter(|(_id, operation)| operation.owner == owner)
+            .collect()
+    })
+}
+
+pub fn get_operation(operation_id: OperationId) -> Option<Operation> {
+    read_state(|s| {
+        s.get_open_operation(operation_id)
+            .or_else(|| s.get_closed_operation(operation_id))
+    })
+}
+
+fn update_open_operation(operation_id: OperationId, now: u64, f: impl FnOnce(&mut Operation)) {
+    mutate_state(|s| {
+        if let Some(mut operation) = s.get_open_operation(operation_id) {
+            f(&mut operation);
+            operation.updated_at = now;
+            s.upsert_open_operation(operation_id, operation);
+        }
+    });
+}
+
+fn update_pending_deposit(
+    operation_id: OperationId,
+    now: u64,
+    f: impl FnOnce(&mut PendingDeposit),
+) {
+    update_open_operation(operation_id, now, |operation| {
+        if let Some(deposit) = operation.pending_deposit.as_mut() {
+            f(deposit);
+        }
+    });
+}
+
+fn update_withdrawal(
+    operation_id: OperationId,
+    index: usize,
+    now: u64,
+    f: impl FnOnce(OperationKind, &mut JournalWithdrawal),
+) {
+    update_open_operation(operation_id, now, |operation| {
+        let kind = operation.kind;
+        if let Some(withdrawal) = operation.withdrawals.get_mut(index) {
+            f(kind, withdrawal);
+        }
+    });
+}
+
+/// Keeps an operation open for the duration of an update call and closes it when dropped, which
+/// also happens when the call returns early with an error.
+pub struct JournaledOperation {
+    id: OperationId,
+}
+
+impl JournaledOperation {
+    pub fn start(owner: Principal, kind: OperationKind, now: u64) -> Self {
+        Self {
+            id: start_operation(owner, kind, now),
+        }
+    }
+
+    pub fn id(&self) -> OperationId {
+        self.id
+    }
+
+    pub fn new_withdrawal(&self) -> WithdrawalEntry {
+        WithdrawalEntry::New(self.id)
+    }
+}
+
+impl Drop for JournaledOperation {
+    fn drop(&mut self) {
+        finish_operation(self.id);
+    }
+}
@@ -0,0 +1,332 @@
+use candid::Principal;
+use ethnum::U256;
+use icrc_ledger_types::icrc1::account::Account;
+
+use super::{types::DepositSource, *};
+use crate::{
+    events::{Event, EventType},
+    icrc_client::memo::{DepositMemo, WithdrawMemo},
+    pool::types::{PoolFee, PoolId},
+};
+
+const NOW: u64 = 1_000_000_000_000;
+
+fn user() -> Principal {
+    Principal::from_slice(&[1_u8; 29])
+}
+
+fn token() -> Principal {
+    Principal::from_slice(&[2_u8; 29])
+}
+
+fn withdrawal(amount: u64) -> JournalWithdrawal {
+    JournalWithdrawal::new(
+        token(),
+        &Account::from(user()),
+        U256::from(amount),
+        U256::from(10_u8),
+        WithdrawMemo::SwapOut {
+            amount: U256::from(amount),
+        },
+    )
+}
+
+fn record_donated_event() {
+    mutate_state(|s| {
+        s.record_event(Event {
+            timestamp: NOW,
+            payload: EventType::Donated {
+                pool_id: PoolId {
+                    token0: token(),
+                    token1: user(),
+                    fee: PoolFee(3_000),
+                },
+                amount0: U256::ONE,
+                amount1: U256::ZERO,
+                principal: user(),
+            },
+        })
+    });
+}
+
+#[test]
+fn retry_delay_should_double_and_be_capped() {
+    assert_eq!(retry_delay_nanos(1), BASE_RETRY_DELAY_NANOS);
+    assert_eq!(retry_delay_nanos(2), 2 * BASE_RETRY_DELAY_NANOS);
+    assert_eq!(retry_delay_nanos(3), 4 * BASE_RETRY_DELAY_NANOS);
+    assert_eq!(retry_delay_nanos(20), MAX_RETRY_DELAY_NANOS);
+    assert_eq!(retry_delay_nanos(u32::MAX), MAX_RETRY_DELAY_NANOS);
+}
+
+#[test]
+fn failed_withdrawal_should_be_retried_and_closed_once_completed() {
+    let operation_id = start_operation(user(), OperationKind::Swap, NOW);
+    record_deposit(operation_id, token(), U256::from(1_000_u32), NOW);
+    record_donated_event();
+    record_execution(operation_id, NOW);
+
+    let dedup = record_withdrawal_sent(WithdrawalEntry::New(operation_id), withdrawal(500), NOW);
+    let index = dedup.transfer_index as usize;
+    record_withdrawal_failed(operation_id, index, "ledger unavailable".to_string(), NOW);
+    finish_operation(operation_id);
+
+    // stays open and shows up as pending for the user
+    let pending = get_pending_operations(user());
+    assert_eq!(pending.len(), 1);
+    let operation = &pending[0].1;
+    assert_eq!(operation.status, OperationStatus::WithdrawalPending);
+    assert_eq!(
+        operation.withdrawals[index].status,
+        WithdrawalStatus::Retrying {
+            next_retry_at: NOW + BASE_RETRY_DELAY_NANOS
+        }
+    );
+
+    // not due before the backoff elapsed
+    assert!(withdrawals_due(NOW).is_empty());
+    let later = NOW + BASE_RETRY_DELAY_NANOS;
+    let due = withdrawals_due(later);
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].0, operation_id);
+    assert_eq!(due[0].2, user());
+    assert!(get_due_withdrawal(operation_id, index, later).is_some());
+
+    // the retry replaces the withdrawal instead of adding a new one
+    let retry_dedup = record_withdrawal_sent(
+        WithdrawalEntry::Retry(operation_id, index),
+        withdrawal(500),
+        later,
+    );
+    assert_eq!(retry_dedup.transfer_index, dedup.transfer_index);
+    // the failed transfer did not go through, the retry is a new transfer
+    assert_eq!(retry_dedup.created_at_time, later);
+    assert!(get_due_withdrawal(operation_id, index, later).is_none());
+    record_withdrawal_completed(operation_id, index, 42, later);
+    finish_operation(operation_id);
+
+    assert!(get_pending_operations(user()).is_empty());
+    let operation = get_operation(operation_id).unwrap();
+    assert_eq!(operation.status, OperationStatus::Completed);
+    assert_eq!(operation.updated_at, later);
+    assert_eq!(operation.withdrawals.len(), 1);
+    assert_eq!(operation.withdrawals[0].attempts, 2);
+    assert_eq!(
+        operation.withdrawals[0].last_error,
+        Some("ledger unavailable".to_string())
+    );
+    assert_eq!(
+        operation.withdrawals[0].status,
+        WithdrawalStatus::Completed { block_index: 42 }
+    );
+    assert_eq!(
+        operation.event_ids,
+        vec![read_state(|s| s.total_event_count()) - 1]
+    );
+}
+
+#[test]
+fn failed_explicit_withdrawal_should_not_be_retried() {
+    let operation_id = start_operation(user(), OperationKind::Withdraw, NOW);
+    let index = record_withdrawal_sent(WithdrawalEntry::New(operation_id), withdrawal(500), NOW)
+        .transfer_index as usize;
+    record_withdrawal_failed(operation_id, index, "bad fee".to_string(), NOW);
+    finish_operation(operation_id);
+
+    assert!(withdrawals_due(u64::MAX).is_empty());
+    assert_eq!(
+        get_operation(operation_id).unwrap().status,
+        OperationStatus::WithdrawalFailed
+    );
+}
+
+#[test]
+fn withdrawal_should_be_given_up_after_max_attempts() {
+    let operation_id = start_operation(user(), OperationKind::Burn, NOW);
+    let index = record_withdrawal_sent(WithdrawalEntry::New(operation_id), withdrawal(500), NOW)
+        .transfer_index as usize;
+    for _ in 1..MAX_WITHDRAWAL_ATTEMPTS {
+        record_withdrawal_failed(operation_id, index, "unavailable".to_string(), NOW);
+        record_withdrawal_sent(
+            WithdrawalEntry::Retry(operation_id, index),
+            withdrawal(500),
+            NOW,
+        );
+    }
+    record_withdrawal_failed(operation_id, index, "unavailable".to_string(), NOW);
+    finish_operation(operation_id);
+
+    let operation = get_operation(operation_id).unwrap();
+    assert_eq!(operation.status, OperationStatus::WithdrawalFailed);
+    assert_eq!(
+        operation.withdrawals[index].attempts,
+        MAX_WITHDRAWAL_ATTEMPTS
+    );
+    assert_eq!(
+        operation.withdrawals[index].status,
+        WithdrawalStatus::Failed
+    );
+}
+
+#[test]
+fn in_flight_withdrawal_should_keep_operation_open_and_be_sent_again() {
+    let dedup = {
+        let operation = JournaledOperation::start(user(), OperationKind::CollectFees, NOW);
+        record_withdrawal_sent(operation.new_withdrawal(), withdrawal(500), NOW)
+    };
+
+    let pending = get_pending_operations(user());
+    assert_eq!(pending.len(), 1);
+    let withdrawal = &pending[0].1.withdrawals[0];
+    assert_eq!(withdrawal.status, WithdrawalStatus::InFlight);
+    assert!(withdrawal.is_outcome_unknown());
+    // left over by a trapped call, sent again with the same deduplication arguments
+    assert_eq!(withdrawals_due(NOW).len(), 1);
+    assert_eq!(withdrawal.dedup(dedup.operation_id, 0), Some(dedup));
+}
+
+#[test]
+fn withdrawal_with_unknown_outcome_should_be_sent_again_with_same_dedup() {
+    let operation_id = start_operation(user(), OperationKind::Withdraw, NOW);
+    let dedup = record_withdrawal_sent(WithdrawalEntry::New(operation_id), withdrawal(500), NOW);
+    let index = dedup.transfer_index as usize;
+    record_withdrawal_outcome_unknown(operation_id, index, "sys transient".to_string(), NOW);
+    finish_operation(operation_id);
+
+    // retried even for explicit withdrawals, the amount is still debited
+    assert!(withdrawals_due(NOW).is_empty());
+    let later = NOW + BASE_RETRY_DELAY_NANOS;
+    let withdrawal = get_due_withdrawal(operation_id, index, later).unwrap();
+    assert!(withdrawal.is_outcome_unknown());
+    assert_eq!(withdrawal.dedup(operation_id, index), Some(dedup));
+
+    record_withdrawal_resent(operation_id, index, later);
+    record_withdrawal_completed(operation_id, index, 7, later);
+    finish_operation(operation_id);
+
+    let operation = get_operation(operation_id).unwrap();
+    assert_eq!(operation.status, OperationStatus::Completed);
+    assert_eq!(operation.withdrawals[index].attempts, 2);
+    assert_eq!(operation.withdrawals[index].created_at_time, Some(NOW));
+}
+
+fn pending_deposit(operation_id: OperationId, amount: u64) -> PendingDeposit {
+    PendingDeposit::new(
+        token(),
+        DepositSource::TransferFrom {
+            owner: user(),
+            subaccount: None,
+        },
+        U256::from(amount),
+        DepositMemo::Deposit {
+            amount: U256::from(amount),
+        },
+        &deposit_dedup(operation_id, NOW),
+    )
+}
+
+#[test]
+fn deposit_with_unknown_outcome_should_be_sent_again_and_credited() {
+    let operation_id = start_operation(user(), OperationKind::MintPosition, NOW);
+    record_deposit(operation_id, token(), U256::from(1_000_u32), NOW);
+    let deposit = pending_deposit(operation_id, 500);
+    record_deposit_pending(operation_id, deposit, "sys transient".to_string(), NOW);
+    finish_operation(operation_id);
+
+    // stays open until the outcome is known
+    let operation = get_operation(operation_id).unwrap();
+    assert_eq!(operation.status, OperationStatus::DepositPending);
+    assert!(deposits_due(NOW).is_empty());
+    let later = NOW + BASE_RETRY_DELAY_NANOS;
+    assert_eq!(deposits_due(later), vec![(operation_id, user())]);
+
+    // sent again with the journaled deduplication arguments, after the credited deposit
+    let deposit = get_due_deposit(operation_id, later).unwrap();
+    assert_eq!(
+        deposit.dedup(operation_id),
+        TransferDedup {
+            created_at_time: NOW,
+            operation_id,
+            transfer_index: 1,
+        }
+    );
+
+    record_deposit_resent(operation_id, later);
+    record_deposit_outcome_unknown(operation_id, "sys transient".to_string(), later);
+    finish_operation(operation_id);
+    assert!(get_due_deposit(operation_id, later + BASE_RETRY_DELAY_NANOS).is_none());
+
+    let latest = later + 2 * BASE_RETRY_DELAY_NANOS;
+    assert_eq!(get_due_deposit(operation_id, latest).unwrap().attempts, 2);
+    record_deposit(operation_id, token(), U256::from(500_u32), latest);
+    record_deposit_resolved(operation_id, latest);
+    finish_operation(operation_id);
+
+    let operation = get_operation(operation_id).unwrap();
+    assert_eq!(operation.status, OperationStatus::Completed);
+    assert_eq!(operation.deposits.len(), 2);
+    assert_eq!(operation.pending_deposit, None);
+    assert!(get_pending_operations(user()).is_empty());
+}
+
+#[test]
+fn unresolved_deposit_should_close_operation() {
+    let operation_id = start_operation(user(), OperationKind::Deposit, NOW);
+    let deposit = pending_deposit(operation_id, 500);
+    record_deposit_pending(operation_id, deposit, "sys transient".to_string(), NOW);
+    record_deposit_unresolved(operation_id, "too old".to_string(), NOW);
+    finish_operation(operation_id);
+
+    let operation = get_operation(operation_id).unwrap();
+    assert_eq!(operation.status, OperationStatus::DepositUnresolved);
+    assert!(get_pending_operations(user()).is_empty());
+    assert!(deposits_due(u64::MAX).is_empty());
+}
+
+#[test]
+fn withdrawal_sent_without_dedup_should_not_be_sent_again() {
+    assert_eq!(withdrawal(500).dedup(0, 0), None);
+}
+
+#[test]
+fn operation_without_effects_should_be_dropped() {
+    let operation_id = {
+        let operation = JournaledOperation::start(user(), OperationKind::Deposit, NOW);
+        operation.id()
+    };
+
+    assert!(get_operation(operation_id).is_none());
+
+    let operation_id = start_operation(user(), OperationKind::Deposit, NOW);
+    record_deposit(operation_id, token(), U256::ONE, NOW);
+    finish_operation(operation_id);
+    assert_eq!(
+        get_operation(operation_id).unwrap().status,
+        OperationStatus::Completed
+    );
+}
+
+#[test]
+fn closed_operations_should_expire_except_the_last_one() {
+    let deposit = |now: u64| {
+        let operation_id = start_operation(user(), OperationKind::Deposit, now);
+        record_deposit(operation_id, token(), U256::ONE, now);
+        finish_operation(operation_id);
+        operation_id
+    };
+
+    let first = deposit(NOW);
+    let second = deposit(NOW + 1);
+    assert!(get_operation(first).is_some());
+
+    // closing an operation after the ttl prunes the expired ones
+    let later = NOW + CLOSED_OPERATION_TTL_NANOS + 1;
+    let third = deposit(later);
+    assert!(get_operation(first).is_none());
+    assert!(get_operation(second).is_none());
+    assert!(get_operation(third).is_some());
+
+    // the last closed operation is kept so its id is not used again
+    let fourth = deposit(later + 2 * CLOSED_OPERATION_TTL_NANOS);
+    assert!(get_operation(fourth).is_some());
+    assert_eq!(fourth, third + 1);
+}
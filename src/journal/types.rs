@@ -0,0 +1,361 @@
+use candid::Principal;
+use ethnum::U256;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use minicbor::{Decode, Encode};
+
+use crate::icrc_client::{
+    memo::{DepositMemo, WithdrawMemo},
+    TransferDedup,
+};
+
+/// Sequential id of a journaled operation.
+pub type OperationId = u64;
+
+/// The update call that started an operation.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationKind {
+    #[n(0)]
+    MintPosition,
+    #[n(1)]
+    IncreaseLiquidity,
+    #[n(2)]
+    Burn,
+    #[n(3)]
+    DecreaseLiquidity,
+    #[n(4)]
+    Swap,
+    #[n(5)]
+    CollectFees,
+    #[n(6)]
+    Donate,
+    #[n(7)]
+    Deposit,
+    #[n(8)]
+    Withdraw,
+    #[n(9)]
+    CreatePoolAndMint,
+}
+
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationStatus {
+    /// Nothing was received or executed yet.
+    #[n(0)]
+    Started,
+    /// At least one deposit was credited to the user.
+    #[n(1)]
+    DepositReceived,
+    /// The state transition was executed and its event recorded.
+    #[n(2)]
+    Executed,
+    /// A withdrawal is in flight or waiting to be retried.
+    #[n(3)]
+    WithdrawalPending,
+    /// A withdrawal was given up on, see the status of its withdrawals.
+    #[n(4)]
+    WithdrawalFailed,
+    /// Every step finished.
+    #[n(5)]
+    Completed,
+    /// A deposit with an unknown outcome is sent again until the ledger tells whether it went
+    /// through.
+    #[n(6)]
+    DepositPending,
+    /// The outcome of a deposit could not be resolved, see its pending deposit.
+    #[n(7)]
+    DepositUnresolved,
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub enum WithdrawalStatus {
+    /// The amount is debited from the user balance and the transfer was sent. Left over by a call
+    /// that trapped, the transfer is sent again with identical arguments.
+    #[n(0)]
+    InFlight,
+    /// The transfer failed and the amount was credited back, it is retried at `next_retry_at`.
+    #[n(1)]
+    Retrying {
+        #[n(0)]
+        next_retry_at: u64,
+    },
+    /// Given up on, the amount stays in the user balance.
+    #[n(2)]
+    Failed,
+    /// The user moved the funds before the retry, nothing left to withdraw.
+    #[n(3)]
+    Cancelled,
+    #[n(4)]
+    Completed {
+        #[n(0)]
+        block_index: u64,
+    },
+    /// The ledger call was rejected without telling whether the transfer went through, the amount
+    /// stays debited and the identical transfer is sent again at `next_retry_at`.
+    #[n(5)]
+    OutcomeUnknown {
+        #[n(0)]
+        next_retry_at: u64,
+    },
+    /// The outcome could not be resolved within the deduplication window of the ledger, the amount
+    /// stays debited until a controller looks into it.
+    #[n(6)]
+    Unresolved,
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct JournalDeposit {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub token: Principal,
+    #[cbor(n(1), with = "crate::cbor::u256")]
+    pub amount: U256,
+}
+
+/// Account a deposit is taken from.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub enum DepositSource {
+    /// Transferred from an account of the user with `icrc2_transfer_from`.
+    #[n(0)]
+    TransferFrom {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        owner: Principal,
+        #[n(1)]
+        subaccount: Option<Subaccount>,
+    },
+    /// Swept from the deposit subaccount of the user, paying `transfer_fee`.
+    #[n(1)]
+    Subaccount {
+        #[n(0)]
+        subaccount: Subaccount,
+        #[cbor(n(1), with = "crate::cbor::u256")]
+        transfer_fee: U256,
+    },
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub enum DepositStatus {
+    /// The ledger call was rejected without telling whether the transfer went through, nothing is
+    /// credited yet and the identical transfer is sent again at `next_retry_at`.
+    #[n(0)]
+    OutcomeUnknown {
+        #[n(0)]
+        next_retry_at: u64,
+    },
+    /// The outcome could not be resolved within the deduplication window of the ledger, nothing
+    /// is credited until a controller looks into it.
+    #[n(1)]
+    Unresolved,
+}
+
+/// A deposit sent without knowing whether the ledger executed it, credited to the user once the
+/// identical transfer sent again resolves to a block.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct PendingDeposit {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub token: Principal,
+    #[n(1)]
+    pub source: DepositSource,
+    #[cbor(n(2), with = "crate::cbor::u256")]
+    pub amount: U256,
+    #[n(3)]
+    pub memo: DepositMemo,
+    #[n(4)]
+    pub status: DepositStatus,
+    #[n(5)]
+    pub attempts: u32,
+    #[n(6)]
+    pub last_error: Option<String>,
+    #[n(7)]
+    pub created_at_time: u64,
+    #[n(8)]
+    pub transfer_index: u32,
+}
+
+impl PendingDeposit {
+    pub fn new(
+        token: Principal,
+        source: DepositSource,
+        amount: U256,
+        memo: DepositMemo,
+        dedup: &TransferDedup,
+    ) -> Self {
+        Self {
+            token,
+            source,
+            amount,
+            memo,
+            // rescheduled with backoff once journaled
+            status: DepositStatus::OutcomeUnknown {
+                next_retry_at: dedup.created_at_time,
+            },
+            attempts: 1,
+            last_error: None,
+            created_at_time: dedup.created_at_time,
+            transfer_index: dedup.transfer_index,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self.status, DepositStatus::OutcomeUnknown { .. })
+    }
+
+    /// Deduplication arguments of the transfer, sent again unchanged.
+    pub fn dedup(&self, operation_id: OperationId) -> TransferDedup {
+        TransferDedup {
+            created_at_time: self.created_at_time,
+            operation_id,
+            transfer_index: self.transfer_index,
+        }
+    }
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct JournalWithdrawal {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub token: Principal,
+    #[cbor(n(1), with = "crate::cbor::principal")]
+    pub to_owner: Principal,
+    #[n(2)]
+    pub to_subaccount: Option<Subaccount>,
+    /// Debited from the user balance, transfer fee included.
+    #[cbor(n(3), with = "crate::cbor::u256")]
+    pub amount: U256,
+    #[cbor(n(4), with = "crate::cbor::u256")]
+    pub transfer_fee: U256,
+    #[n(5)]
+    pub memo: WithdrawMemo,
+    #[n(6)]
+    pub status: WithdrawalStatus,
+    #[n(7)]
+    pub attempts: u32,
+    #[n(8)]
+    pub last_error: Option<String>,
+    /// Creation time of the last transfer sent, identical transfers are deduplicated by the ledger.
+    #[n(9)]
+    pub created_at_time: Option<u64>,
+}
+
+impl JournalWithdrawal {
+    pub fn new(
+        token: Principal,
+        to: &Account,
+        amount: U256,
+        transfer_fee: U256,
+        memo: WithdrawMemo,
+    ) -> Self {
+        Self {
+            token,
+            to_owner: to.owner,
+            to_subaccount: to.subaccount,
+            amount,
+            transfer_fee,
+            memo,
+            status: WithdrawalStatus::InFlight,
+            attempts: 0,
+            last_error: None,
+            created_at_time: None,
+        }
+    }
+
+    pub fn to(&self) -> Account {
+        Account {
+            owner: self.to_owner,
+            subaccount: self.to_subaccount,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(
+            self.status,
+            WithdrawalStatus::InFlight
+                | WithdrawalStatus::Retrying { .. }
+                | WithdrawalStatus::OutcomeUnknown { .. }
+        )
+    }
+
+    /// Whether the amount is still debited from the user balance without a known outcome.
+    pub fn is_outcome_unknown(&self) -> bool {
+        matches!(
+            self.status,
+            WithdrawalStatus::InFlight | WithdrawalStatus::OutcomeUnknown { .. }
+        )
+    }
+
+    /// Deduplication arguments of the last transfer sent, `None` for transfers sent before
+    /// withdrawals were deduplicated.
+    pub fn dedup(&self, operation_id: OperationId, index: usize) -> Option<TransferDedup> {
+        Some(TransferDedup {
+            created_at_time: self.created_at_time?,
+            operation_id,
+            transfer_index: index as u32,
+        })
+    }
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct Operation {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub owner: Principal,
+    #[n(1)]
+    pub kind: OperationKind,
+    #[n(2)]
+    pub status: OperationStatus,
+    #[n(3)]
+    pub started_at: u64,
+    #[n(4)]
+    pub updated_at: u64,
+    #[n(5)]
+    pub deposits: Vec<JournalDeposit>,
+    #[n(6)]
+    pub withdrawals: Vec<JournalWithdrawal>,
+    /// Ids of the events recorded by the operation, linking the journal to the event log.
+    #[n(7)]
+    pub event_ids: Vec<u64>,
+    #[n(8)]
+    pub pending_deposit: Option<PendingDeposit>,
+}
+
+impl Operation {
+    pub fn has_pending_withdrawal(&self) -> bool {
+        self.withdrawals.iter().any(|w| w.is_pending())
+    }
+
+    pub fn has_pending_deposit(&self) -> bool {
+        self.pending_deposit
+            .as_ref()
+            .is_some_and(|deposit| deposit.is_pending())
+    }
+
+    /// Status of an operation that should stay open, None once nothing is pending anymore.
+    pub fn pending_status(&self) -> Option<OperationStatus> {
+        if self.has_pending_deposit() {
+            Some(OperationStatus::DepositPending)
+        } else if self.has_pending_withdrawal() {
+            Some(OperationStatus::WithdrawalPending)
+        } else {
+            None
+        }
+    }
+
+    /// Final status of an operation once no deposit or withdrawal is pending anymore.
+    pub fn final_status(&self) -> OperationStatus {
+        if self.pending_deposit.is_some() {
+            OperationStatus::DepositUnresolved
+        } else if self.withdrawals.iter().any(|w| {
+            matches!(
+                w.status,
+                WithdrawalStatus::Failed | WithdrawalStatus::Unresolved
+            )
+        }) {
+            OperationStatus::WithdrawalFailed
+        } else {
+            OperationStatus::Completed
+        }
+    }
+
+    /// An operation that neither moved funds nor changed state is not worth keeping.
+    pub fn is_noop(&self) -> bool {
+        self.deposits.is_empty()
+            && self.withdrawals.is_empty()
+            && self.event_ids.is_empty()
+            && self.pending_deposit.is_none()
+    }
+}
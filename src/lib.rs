@@ -1,14 +1,20 @@
+pub mod archive;
 pub mod balances;
 pub mod burn;
 pub mod candid_types;
 pub mod cbor;
 pub mod collect_fees;
 pub mod decrease_liquidity;
+pub mod donate;
 pub mod events;
 pub mod guard;
 pub mod historical;
+pub mod icrc3;
 pub mod icrc_client;
+pub mod idempotency;
 pub mod increase_liquidity;
+pub mod internal_transfer;
+pub mod journal;
 pub mod libraries;
 pub mod logs;
 pub mod mint;
@@ -16,9 +22,12 @@ pub mod pool;
 pub mod position;
 pub mod proxy_canister;
 pub mod quote;
+pub mod reconciliation;
+pub mod replay;
 pub mod state;
 pub mod swap;
 pub mod tick;
+pub mod tokens;
 pub mod validation;
 
 #[cfg(test)]
@@ -18,3 +18,12 @@ lazy_static! {
 
 pub const MIN_TICK: i32 = -887272;
 pub const MAX_TICK: i32 = 887272;
+
+// fee levels in pips and their tick spacing, set on init
+pub const FEE_TO_TICK_SPACING: [(u32, i32); 5] = [
+    (100, 1),      // 0.01% fee, 1 tick spacing
+    (500, 10),     // 0.05% fee, 10 tick spacing
+    (1_000, 20),   // 0.1% fee, 20 tick spacing
+    (3_000, 60),   // 0.3% fee, 60 tick spacing
+    (10_000, 200), // 1% fee, 200 tick spacing
+];
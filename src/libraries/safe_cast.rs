@@ -1,6 +1,6 @@
-use candid::Nat;
+use candid::{Int, Nat};
 use ethnum::{I256, U256};
-use num_bigint::BigUint;
+use num_bigint::{BigInt, BigUint, Sign};
 
 pub fn big_uint_to_u256(biguint: BigUint) -> Result<U256, String> {
     let value_bytes = biguint.to_bytes_be();
@@ -33,3 +33,23 @@ pub fn u256_to_big_uint(value: U256) -> BigUint {
 pub fn u256_to_nat(value: U256) -> Nat {
     Nat::from(BigUint::from_bytes_be(&value.to_be_bytes()))
 }
+
+pub fn i256_to_int(value: I256) -> Int {
+    let magnitude = u256_to_big_uint(value.unsigned_abs());
+    let sign = if value < 0 { Sign::Minus } else { Sign::Plus };
+    Int::from(BigInt::from_biguint(sign, magnitude))
+}
+
+pub fn int_to_i256(value: Int) -> Result<I256, String> {
+    let (sign, magnitude) = value.0.into_parts();
+    let magnitude = big_uint_to_u256(magnitude)?;
+    if sign == Sign::Minus {
+        // the magnitude of I256::MIN does not fit in a I256
+        if magnitude > I256::MIN.unsigned_abs() {
+            return Err(String::from("does not fit in a I256"));
+        }
+        Ok(magnitude.wrapping_neg().as_i256())
+    } else {
+        I256::try_from(magnitude).map_err(|_| String::from("does not fit in a I256"))
+    }
+}
@@ -1,53 +1,121 @@
-use std::time::Duration;
+use std::{future::Future, time::Duration};
 
 use appic_dex::{
-    balances::types::{UserBalance, UserBalanceKey},
+    archive::{archive_events, archived_ranges, types::ArchiveConfig, ARCHIVE_WASM},
+    balances::{
+        deposit_subaccount,
+        types::{UserBalance, UserBalanceKey},
+    },
     burn::execute_burn_position,
     candid_types::{
-        events::{CandidEvent, GetEventsArg, GetEventsResult},
-        pool::{CandidPoolId, CandidPoolState, CreatePoolArgs, CreatePoolError},
-        pool_history::CandidPoolHistory,
+        analytics::{
+            CandidPoolAnalytics, CandidPoolSortBy, CandidPoolStats, CandidProtocolStats,
+            CandidTokenStats,
+        },
+        archive::{CandidArchiveConfig, SetArchiveConfigError},
+        events::{
+            ArchivedEventsFn, ArchivedEventsRange, CandidEvent, CandidIndexedEvent,
+            CandidUserTrade, GetEventsArg, GetEventsFilteredArgs, GetEventsFilteredResult,
+            GetEventsResult,
+        },
+        journal::CandidOperation,
+        pool::{
+            CandidDynamicFeeConfig, CandidInvariantReport, CandidPoolCreationPolicy, CandidPoolFee,
+            CandidPoolId, CandidPoolState, CreatePoolAndMintArgs, CreatePoolAndMintError,
+            CreatePoolAndMintResult, CreatePoolArgs, CreatePoolError, DonateArgs, DonateError,
+            InitialPositionArgs, SetDynamicFeeError, SetPoolCreationPolicyError,
+        },
+        pool_history::{CandidCandle, CandidHistoryBucket, CandidPoolHistory, CandidTimeFrame},
         position::{
             BurnPositionArgs, BurnPositionError, CandidPositionInfo, CandidPositionKey,
             CollectFeesError, CollectFeesSuccess, DecreaseLiquidityArgs, DecreaseLiquidityError,
             IncreaseLiquidityArgs, IncreaseLiquidityError, MintPositionArgs, MintPositionError,
         },
-        quote::{QuoteArgs, QuoteError},
+        quote::{CandidQuoteDetails, QuoteArgs, QuoteError},
+        reconciliation::CandidTokenReconciliation,
         swap::{CandidSwapSuccess, SwapArgs, SwapError},
         tick::CandidTickInfo,
-        Balance, DepositArgs, DepositError, UserBalanceArgs, WithdrawArgs, WithdrawError,
+        token::{
+            CandidToken, CandidTokenSettings, CandidTokenValidationConfig, SetTokenSettingsError,
+        },
+        ApproveInternalArgs, ApproveInternalError, Balance, CandidInternalAllowance, DepositArgs,
+        DepositError, InternalAllowanceArgs, InternalTransferArgs, InternalTransferError,
+        NotifyDepositError, TransferFromInternalArgs, UserBalanceArgs, WithdrawArgs, WithdrawError,
     },
     collect_fees::execute_collect_fees,
     decrease_liquidity::execute_decrease_liquidity,
-    guard::PrincipalGuard,
-    historical::capture_historical_data,
+    donate::execute_donate,
+    events::{index::EventFilter, Event, EventType},
+    guard::{PrincipalGuard, PrincipalGuardError, TokenGuard},
+    historical::{
+        aggregates::{pool_analytics, seed_aggregates, top_pools},
+        capture_historical_data,
+    },
+    icrc3::{
+        certification::tip_hash_tree, certify_tip, get_archives, get_blocks, supported_block_types,
+    },
     icrc_client::{
-        memo::{DepositMemo, WithdrawMemo},
-        LedgerClient, LedgerTransferError,
+        memo::{DepositMemo, MemoKind, WithdrawMemo},
+        LedgerClient, LedgerTransferError, MAX_TRANSFER_ATTEMPTS,
     },
+    idempotency::IdempotentRequest,
     increase_liquidity::execute_increase_liquidity,
+    internal_transfer::{
+        execute_approve_internal, execute_internal_transfer, get_internal_allowance,
+        validate_internal_transfer,
+    },
+    journal::{
+        self, deposit_dedup, deposits_due, finish_operation, get_due_deposit, get_due_withdrawal,
+        record_deposit, record_deposit_outcome_unknown, record_deposit_pending,
+        record_deposit_resent, record_deposit_resolved, record_deposit_unresolved,
+        record_execution, record_withdrawal_abandoned, record_withdrawal_completed,
+        record_withdrawal_failed, record_withdrawal_outcome_unknown, record_withdrawal_resent,
+        record_withdrawal_sent,
+        types::{
+            DepositSource, JournalWithdrawal, OperationId, OperationKind, PendingDeposit,
+            WithdrawalStatus,
+        },
+        withdrawals_due, JournaledOperation, WithdrawalEntry,
+    },
     libraries::{
         balance_delta::BalanceDelta,
+        constants::FEE_TO_TICK_SPACING,
+        fee_math::calculate_swap_fee,
         safe_cast::{big_uint_to_u256, u256_to_big_uint, u256_to_nat},
     },
-    logs::DEBUG,
-    mint::execute_mint_position,
+    logs::{DEBUG, INFO},
+    mint::{execute_create_pool_and_mint, execute_mint_position},
     pool::{
-        create_pool::create_pool_inner,
-        types::{PoolFee, PoolId, PoolTickSpacing},
+        create_pool::{creation_fee_not_paid, new_pool, record_pool_creation},
+        dynamic_fee::{
+            apply_dynamic_fee_config, effective_lp_fee, seed_volatility_windows,
+            validate_dynamic_fee_config,
+        },
+        invariants::check_invariants as check_pool_invariants,
+        policy::{charge_creation_fee, PoolCreationPolicy},
+        types::{DynamicFeeConfig, PoolFee, PoolId, PoolTickSpacing},
     },
     position::types::PositionKey,
-    proxy_canister::validate_icrc_ledger,
     quote::{
-        process_multi_hop_exact_input, process_multi_hop_exact_output,
+        process_multi_hop_exact_input, process_multi_hop_exact_output, process_quote_with_fees,
         process_single_hop_exact_input, process_single_hop_exact_output,
     },
+    reconciliation::{
+        self, is_token_paused, reconciled_tokens, record_reconciliation, token_liabilities,
+        types::TokenReconciliation,
+    },
     state::{mutate_state, read_state},
     swap::execute_swap,
+    tokens::{
+        accounting::{apply_transfer_haircut, observed_transfer_haircut},
+        metadata::{record_token_metadata, record_transfer_fee, refresh_token_metadata},
+        types::TokenSettings,
+        validation::{validate_token, ValidatedToken},
+    },
     validation::{
         burn_args::validate_burn_position_args, decrease_args::validate_decrease_liquidity_args,
-        increase_args::validate_increase_liquidity_args, mint_args::validate_mint_position_args,
-        swap_args::validate_swap_args,
+        donate_args::validate_donate_args, increase_args::validate_increase_liquidity_args,
+        mint_args::validate_mint_position_args, swap_args::validate_swap_args,
     },
 };
 
@@ -55,7 +123,14 @@ use candid::{Nat, Principal};
 use ethnum::{I256, U256};
 use ic_canister_log::log;
 use ic_cdk::{init, post_upgrade, query, update};
-use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::{
+    icrc1::account::Account,
+    icrc3::{
+        archive::{GetArchivesArgs, ICRC3ArchiveInfo},
+        blocks::{GetBlocksRequest, GetBlocksResult, ICRC3DataCertificate, SupportedBlockType},
+    },
+};
+use serde_bytes::ByteBuf;
 
 // Ensures caller is not anonymous, panics if anonymous to prevent unauthorized access
 fn validate_caller_not_anonymous() -> candid::Principal {
@@ -66,26 +141,53 @@ fn validate_caller_not_anonymous() -> candid::Principal {
     principal
 }
 
-// Schedules periodic capture of historical data every 10 minutes for analytics
+// Ensures caller is a controller of the canister, panics otherwise to protect admin endpoints
+fn validate_caller_is_controller() -> candid::Principal {
+    let principal = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&principal) {
+        panic!("only controllers are allowed to call this method");
+    }
+    principal
+}
+
+// Schedules periodic capture of historical data every 5 minutes for analytics, retries of
+// pending deposits and failed withdrawals every minute and reconciliation of token balances every
+// hour
 fn set_up_timers() {
-    ic_cdk_timers::set_timer_interval(Duration::from_secs(10 * 60), capture_historical_data);
+    if ARCHIVE_WASM.is_empty() {
+        log!(
+            INFO,
+            "[set_up_timers]: built without the archive wasm, events are not archived"
+        );
+    }
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(5 * 60), capture_historical_data);
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(60), || {
+        ic_cdk::spawn(async {
+            settle_pending_deposits().await;
+            retry_pending_withdrawals().await;
+        })
+    });
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(60 * 60), || {
+        ic_cdk::spawn(async {
+            _reconcile_balances(reconciled_tokens()).await;
+        })
+    });
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(10 * 60), || {
+        ic_cdk::spawn(archive_events())
+    });
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(10 * 60), || {
+        ic_cdk::spawn(refresh_token_metadata())
+    });
 }
 
 // Initializes canister with fee-to-tick-spacing mappings for pool configurations and sets timers
 #[init]
 fn init() {
-    let fee_to_tick_spacing = vec![
-        (100_u32, 1_i32),      // 0.01% fee, 1 tick spacing
-        (500_u32, 10i32),      // 0.05% fee, 10 tick spacing
-        (1_000_u32, 20i32),    // 0.1% fee, 20 tick spacing
-        (3_000_u32, 60_i32),   // 0.3% fee, 60 tick spacing
-        (10_000_u32, 200_i32), // 1% fee, 200 tick spacing
-    ];
-
-    for (fee, tick_spacing) in fee_to_tick_spacing {
+    for (fee, tick_spacing) in FEE_TO_TICK_SPACING {
         // Maps fee levels to tick spacings for pool creation
         mutate_state(|s| s.set_tick_spacing(PoolFee(fee), PoolTickSpacing(tick_spacing)));
     }
+    mutate_state(|s| seed_aggregates(s, ic_cdk::api::time() / 1_000_000_000));
 
     set_up_timers();
 }
@@ -93,6 +195,22 @@ fn init() {
 // Restarts timers after canister upgrade to maintain historical data collection
 #[post_upgrade]
 fn post_upgrade() {
+    mutate_state(|s| {
+        s.migrate_legacy_events();
+        s.index_held_events();
+        s.migrate_pool_history();
+        s.migrate_token_decimals();
+        s.seed_liability_totals();
+        seed_volatility_windows(s);
+        seed_aggregates(s, ic_cdk::api::time() / 1_000_000_000);
+    });
+
+    // certified data is cleared by upgrades, events recorded before the hash chain existed are
+    // chained here
+    if let Some((last_block_index, last_block_hash)) = mutate_state(|s| s.hash_pending_blocks()) {
+        certify_tip(last_block_index, last_block_hash);
+    }
+
     set_up_timers();
 }
 
@@ -101,18 +219,31 @@ fn post_upgrade() {
 fn get_pool(pool_id: CandidPoolId) -> Option<CandidPoolState> {
     let pool_id: PoolId = pool_id.try_into().ok()?;
     read_state(|s| {
-        s.get_pool(&pool_id)
-            .map(|pool_state| CandidPoolState::from(pool_state))
+        s.get_pool(&pool_id).map(|pool_state| CandidPoolState {
+            fee_on_transfer: s.is_fee_on_transfer_pool(&pool_id),
+            ..CandidPoolState::from(pool_state)
+        })
     })
 }
 
 // Retrieves all pools with their IDs and states, converted to Candid format
 #[query]
 fn get_pools() -> Vec<(CandidPoolId, CandidPoolState)> {
-    read_state(|s| s.get_pools())
-        .into_iter()
-        .map(|(id, state)| (CandidPoolId::from(id), CandidPoolState::from(state)))
-        .collect()
+    read_state(|s| {
+        s.get_pools()
+            .into_iter()
+            .map(|(id, state)| {
+                let fee_on_transfer = s.is_fee_on_transfer_pool(&id);
+                (
+                    CandidPoolId::from(id),
+                    CandidPoolState {
+                        fee_on_transfer,
+                        ..CandidPoolState::from(state)
+                    },
+                )
+            })
+            .collect()
+    })
 }
 
 #[query]
@@ -137,6 +268,90 @@ fn get_pool_history(pool_id: CandidPoolId) -> Option<CandidPoolHistory> {
     }
 }
 
+// Queries the buckets of a single timeframe of a pool starting in [from, to), in seconds
+#[query]
+fn get_pool_history_range(
+    pool_id: CandidPoolId,
+    timeframe: CandidTimeFrame,
+    from: u64,
+    to: u64,
+) -> Vec<CandidHistoryBucket> {
+    let Ok(pool_id) = PoolId::try_from(pool_id) else {
+        return vec![];
+    };
+    read_state(|s| s.get_history_buckets(&pool_id, timeframe.into(), from, to))
+        .into_iter()
+        .map(CandidHistoryBucket::from)
+        .collect()
+}
+
+// Queries the price candles of a pool for the buckets of a timeframe starting in [from, to), in
+// seconds. Prices are also given in whole tokens when the decimals of both tokens are known
+#[query]
+fn get_candles(
+    pool_id: CandidPoolId,
+    timeframe: CandidTimeFrame,
+    from: u64,
+    to: u64,
+) -> Vec<CandidCandle> {
+    let Ok(pool_id) = PoolId::try_from(pool_id) else {
+        return vec![];
+    };
+    let (buckets, decimals) = read_state(|s| {
+        let decimals0 = s.get_token_decimals(&pool_id.token0);
+        let decimals1 = s.get_token_decimals(&pool_id.token1);
+        (
+            s.get_history_buckets(&pool_id, timeframe.into(), from, to),
+            decimals0.zip(decimals1),
+        )
+    });
+
+    buckets
+        .into_iter()
+        .map(|bucket| CandidCandle::from_bucket(bucket, decimals))
+        .collect()
+}
+
+// Queries the activity of a token across all its pools, with its daily history
+#[query]
+fn get_token_stats(token: Principal) -> Option<CandidTokenStats> {
+    read_state(|s| {
+        let stats = s.get_token_stats(&token)?;
+        let daily_frame = s.get_token_buckets(&token, 0, u64::MAX);
+        Some(CandidTokenStats::new(token, stats, daily_frame))
+    })
+}
+
+// Queries protocol wide activity and the volume, fees and reserves of every token
+#[query]
+fn get_protocol_stats() -> CandidProtocolStats {
+    read_state(|s| CandidProtocolStats::new(s.get_protocol_stats(), s.get_all_token_stats()))
+}
+
+// Queries the most active pools over the last 24 hours, at most 100
+#[query]
+fn get_top_pools(sort_by: CandidPoolSortBy, limit: u32) -> Vec<CandidPoolStats> {
+    const MAX_TOP_POOLS: usize = 100;
+
+    let limit = (limit as usize).min(MAX_TOP_POOLS);
+    let timestamp_secs = ic_cdk::api::time() / 1_000_000_000;
+    read_state(|s| top_pools(s, sort_by.into(), limit, timestamp_secs))
+        .into_iter()
+        .map(CandidPoolStats::from)
+        .collect()
+}
+
+// Queries the usd tvl, volume, fees and fee apr of a pool, with the prices they are computed from
+// and whether those are fresh, stale or missing
+#[query]
+fn get_pool_analytics(pool_id: CandidPoolId) -> Option<CandidPoolAnalytics> {
+    let pool_id: PoolId = pool_id.try_into().ok()?;
+    read_state(|s| {
+        let pool = s.get_pool(&pool_id)?;
+        Some(pool_analytics(s, pool_id, &pool, ic_cdk::api::time()).into())
+    })
+}
+
 // Queries position details including fees owed, returns None if position not found
 #[query]
 fn get_position(position_key: CandidPositionKey) -> Option<CandidPositionInfo> {
@@ -193,6 +408,182 @@ pub fn quote(args: QuoteArgs) -> Result<Nat, QuoteError> {
     Ok(Nat::from(u256_to_big_uint(quote_amount)))
 }
 
+// Quotes like `quote` and also returns the fee in pips charged by each hop of the swap
+#[query]
+pub fn quote_with_fees(args: QuoteArgs) -> Result<CandidQuoteDetails, QuoteError> {
+    process_quote_with_fees(args).map(CandidQuoteDetails::from)
+}
+
+// Queries the fee tier of a pool, the fee the next swap would be charged and the dynamic fee config
+#[query]
+fn get_pool_fee(pool_id: CandidPoolId) -> Option<CandidPoolFee> {
+    let pool_id: PoolId = pool_id.try_into().ok()?;
+    let pool_state = read_state(|s| s.get_pool(&pool_id))?;
+    let current_lp_fee = effective_lp_fee(&pool_id, &pool_state);
+
+    Some(CandidPoolFee {
+        fee_tier: pool_id.fee.0.into(),
+        current_lp_fee: current_lp_fee.into(),
+        current_swap_fee: calculate_swap_fee(pool_state.fee_protocol, current_lp_fee).into(),
+        dynamic_fee: read_state(|s| s.get_dynamic_fee_config(&pool_id))
+            .map(CandidDynamicFeeConfig::from),
+    })
+}
+
+// Switches a pool to dynamic fee mode, or back to its static fee tier when config is None.
+// Restricted to controllers
+#[update]
+fn set_pool_dynamic_fee(
+    pool_id: CandidPoolId,
+    config: Option<CandidDynamicFeeConfig>,
+) -> Result<(), SetDynamicFeeError> {
+    validate_caller_is_controller();
+
+    let pool_id: PoolId = pool_id
+        .try_into()
+        .map_err(|_| SetDynamicFeeError::PoolNotFound)?;
+    if read_state(|s| s.get_pool(&pool_id)).is_none() {
+        return Err(SetDynamicFeeError::PoolNotFound);
+    }
+
+    let config = config.map(DynamicFeeConfig::try_from).transpose()?;
+    if let Some(config) = &config {
+        validate_dynamic_fee_config(config)?;
+    }
+
+    mutate_state(|s| apply_dynamic_fee_config(s, pool_id, config));
+    Ok(())
+}
+
+// Checks a pool against its ticks, tick bitmap and positions, returns None if the pool does not
+// exist. Restricted to controllers
+#[query]
+fn check_invariants(pool_id: CandidPoolId) -> Option<CandidInvariantReport> {
+    validate_caller_is_controller();
+
+    let pool_id: PoolId = pool_id.try_into().ok()?;
+    check_pool_invariants(&pool_id).map(CandidInvariantReport::from)
+}
+
+// Queries how deposits and withdrawals of a token are accounted
+#[query]
+fn get_token_settings(token: Principal) -> CandidTokenSettings {
+    read_state(|s| s.get_token_settings(&token)).into()
+}
+
+// Queries the token registry, every token a pool was created with
+#[query]
+fn get_tokens() -> Vec<CandidToken> {
+    read_state(|s| {
+        s.get_all_token_metadata()
+            .into_iter()
+            .map(|(token, metadata)| {
+                CandidToken::new(token, metadata, s.get_token_settings(&token))
+            })
+            .collect()
+    })
+}
+
+// Queries the registry entry of a token, returns None if no pool was created with it
+#[query]
+fn get_token(token: Principal) -> Option<CandidToken> {
+    read_state(|s| {
+        let metadata = s.get_token_metadata(&token)?;
+        Some(CandidToken::new(
+            token,
+            metadata,
+            s.get_token_settings(&token),
+        ))
+    })
+}
+
+// Switches a token between standard and balance difference accounting(fee-on-transfer and
+// rebasing tokens). Restricted to controllers
+#[update]
+fn set_token_settings(
+    token: Principal,
+    settings: CandidTokenSettings,
+) -> Result<(), SetTokenSettingsError> {
+    validate_caller_is_controller();
+
+    let settings = TokenSettings::try_from(settings)?;
+    // Pausing and delisting keep the time the token was paused or delisted at
+    let previous = read_state(|s| s.get_token_settings(&token));
+    let paused_at = settings
+        .paused_at
+        .map(|_| previous.paused_at.unwrap_or(ic_cdk::api::time()));
+    let delisted_at = settings
+        .delisted_at
+        .map(|_| previous.delisted_at.unwrap_or(ic_cdk::api::time()));
+    mutate_state(|s| {
+        s.set_token_settings(
+            token,
+            TokenSettings {
+                paused_at,
+                delisted_at,
+                ..settings
+            },
+        )
+    });
+    Ok(())
+}
+
+// Queries how token ledgers are validated at pool creation
+#[query]
+fn get_token_validation_config() -> CandidTokenValidationConfig {
+    read_state(|s| s.get_token_validation_config()).into()
+}
+
+// Switches pool creation between validating tokens through the proxy canister, by querying their
+// ledgers directly or both. Restricted to controllers
+#[update]
+fn set_token_validation_config(config: CandidTokenValidationConfig) {
+    validate_caller_is_controller();
+
+    mutate_state(|s| s.set_token_validation_config(config.into()));
+}
+
+// Queries the creation fee, token filter and minimum initial liquidity applied to new pools
+#[query]
+fn get_pool_creation_policy() -> CandidPoolCreationPolicy {
+    read_state(|s| s.get_pool_creation_policy()).into()
+}
+
+// Sets the rules new pools are created under, existing pools are not affected. Restricted to
+// controllers
+#[update]
+fn set_pool_creation_policy(
+    policy: CandidPoolCreationPolicy,
+) -> Result<(), SetPoolCreationPolicyError> {
+    validate_caller_is_controller();
+
+    let policy = PoolCreationPolicy::try_from(policy)?;
+    mutate_state(|s| s.set_pool_creation_policy(policy));
+    Ok(())
+}
+
+// Queries the last reconciliation of every token, liabilities against the canister ledger balance
+#[query]
+fn get_reconciliations() -> Vec<CandidTokenReconciliation> {
+    reconciliation::get_reconciliations()
+        .into_iter()
+        .map(CandidTokenReconciliation::from)
+        .collect()
+}
+
+// Reconciles a token, or every token held by the canister when None. Restricted to controllers
+#[update]
+async fn reconcile_balances(token: Option<Principal>) -> Vec<CandidTokenReconciliation> {
+    validate_caller_is_controller();
+
+    let tokens = token.map_or_else(reconciled_tokens, |token| vec![token]);
+    _reconcile_balances(tokens)
+        .await
+        .into_iter()
+        .map(CandidTokenReconciliation::from)
+        .collect()
+}
+
 // Queries all token balances for a user, returned as a list of token-amount pairs
 #[query]
 pub fn user_balances(user: Principal) -> Vec<Balance> {
@@ -205,6 +596,35 @@ pub fn user_balances(user: Principal) -> Vec<Balance> {
         .collect()
 }
 
+// ICRC-3 view of the events, block ids are the indexes used by `get_events`
+#[query]
+fn icrc3_get_blocks(args: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    get_blocks(args)
+}
+
+// Certificate of the last block hash and index, None if no event was recorded yet
+#[query]
+fn icrc3_get_tip_certificate() -> Option<ICRC3DataCertificate> {
+    let certificate = ByteBuf::from(ic_cdk::api::data_certificate()?);
+    let (last_block_index, last_block_hash) = read_state(|s| s.last_block_hash())?;
+    let hash_tree = tip_hash_tree(last_block_index, last_block_hash);
+    Some(ICRC3DataCertificate {
+        certificate,
+        hash_tree: ByteBuf::from(hash_tree.to_cbor()),
+    })
+}
+
+// Archive canisters storing the blocks no longer held by this canister
+#[query]
+fn icrc3_get_archives(args: GetArchivesArgs) -> Vec<ICRC3ArchiveInfo> {
+    get_archives(args.from)
+}
+
+#[query]
+fn icrc3_supported_block_types() -> Vec<SupportedBlockType> {
+    supported_block_types()
+}
+
 // Queries a specific token balance for a user, converted to Nat
 #[query]
 fn user_balance(args: UserBalanceArgs) -> Nat {
@@ -219,7 +639,19 @@ fn user_balance(args: UserBalanceArgs) -> Nat {
     )
 }
 
-// Retrieves paginated events, capped at 100 per response for performance
+// Queries the allowance a spender can use from a user's internal balance
+#[query]
+fn internal_allowance(args: InternalAllowanceArgs) -> CandidInternalAllowance {
+    let allowance =
+        get_internal_allowance(args.owner, args.spender, args.token, ic_cdk::api::time());
+    CandidInternalAllowance {
+        allowance: u256_to_nat(allowance.amount),
+        expires_at: allowance.expires_at,
+    }
+}
+
+// Retrieves paginated events, capped at 100 per response for performance. Parts of the range moved
+// to archive canisters are returned as callbacks to the archives
 #[query]
 fn get_events(args: GetEventsArg) -> GetEventsResult {
     const MAX_EVENTS_PER_RESPONSE: u64 = 100;
@@ -234,93 +666,423 @@ fn get_events(args: GetEventsArg) -> GetEventsResult {
         )
     });
 
+    let archived_events = archived_ranges(args.start, args.length)
+        .into_iter()
+        .map(|(canister_id, start, length)| ArchivedEventsRange {
+            start,
+            length,
+            callback: ArchivedEventsFn::new(canister_id, "get_events".to_string()),
+        })
+        .collect();
+
     GetEventsResult {
         events,
         total_event_count,
+        archived_events,
+    }
+}
+
+// Retrieves the held events matching all the given filters in index order, at most 100 per
+// response. Archived events are not indexed and never returned
+#[query]
+fn get_events_filtered(args: GetEventsFilteredArgs) -> GetEventsFilteredResult {
+    const MAX_EVENTS_PER_RESPONSE: u64 = 100;
+    // bounds the instructions of calls with filters matching few events
+    const MAX_SCANNED_EVENTS: usize = 10_000;
+
+    let filter = EventFilter {
+        principal: args.principal,
+        pool_id: args
+            .pool_id
+            .map(|pool_id| pool_id.try_into().expect("Invalid pool id")),
+        kinds: args
+            .event_types
+            .map(|kinds| kinds.into_iter().map(|kind| kind.into()).collect()),
+        start_time: args.start_time,
+        end_time: args.end_time,
+    };
+    let limit = args
+        .limit
+        .unwrap_or(MAX_EVENTS_PER_RESPONSE)
+        .min(MAX_EVENTS_PER_RESPONSE) as usize;
+
+    let (events, next_cursor) = read_state(|s| {
+        s.get_filtered_events(
+            &filter,
+            args.cursor.unwrap_or_default(),
+            limit,
+            MAX_SCANNED_EVENTS,
+        )
+    });
+
+    GetEventsFilteredResult {
+        events: events
+            .into_iter()
+            .map(|(index, event)| CandidIndexedEvent {
+                index,
+                event: event.into(),
+            })
+            .collect(),
+        next_cursor,
     }
 }
 
+// Retrieves the latest 1000 swaps of a user still held by the canister, most recent first
+#[query]
+fn get_user_trades(user: Principal) -> Vec<CandidUserTrade> {
+    const MAX_TRADES_PER_RESPONSE: usize = 1_000;
+
+    read_state(|s| s.get_user_swaps(user, MAX_TRADES_PER_RESPONSE))
+        .into_iter()
+        .filter_map(|(index, event)| CandidUserTrade::from_event(index, event))
+        .collect()
+}
+
+#[query]
+fn get_archive_config() -> CandidArchiveConfig {
+    read_state(|s| s.get_archive_config()).into()
+}
+
+// Sets when and how old events are moved to archive canisters. Restricted to controllers
+#[update]
+fn set_archive_config(config: CandidArchiveConfig) -> Result<(), SetArchiveConfigError> {
+    validate_caller_is_controller();
+
+    let config = ArchiveConfig::try_from(config)?;
+    mutate_state(|s| s.set_archive_config(config));
+    Ok(())
+}
+
 // Creates a new liquidity pool, validates tokens and fees, returns pool ID
 #[update]
 async fn create_pool(args: CreatePoolArgs) -> Result<CandidPoolId, CreatePoolError> {
-    // Prevents pool creation with identical tokens
-    if args.token_a == args.token_b {
-        return Err(CreatePoolError::DuplicatedTokens);
+    let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "create_pool");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
     }
 
-    let token_a_data = validate_icrc_ledger(args.token_a)
-        .await
-        .map_err(|_| CreatePoolError::InvalidToken(args.token_a))?;
+    // Locks principal so a retry can not create the pool again while the first call is running
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(CreatePoolError::RequestInProgress)
+            }
+            Err(_) => return Err(CreatePoolError::LockedPrincipal),
+        };
 
-    let token_b_data = validate_icrc_ledger(args.token_b)
-        .await
-        .map_err(|_| CreatePoolError::InvalidToken(args.token_b))?;
+    let result = validate_and_create_pool(caller, args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
 
-    // Fetches transfer fees to validate token standards and ensure compatibility
-    let token_a_fee = big_uint_to_u256(token_a_data.fee.0)
-        .map_err(|_| CreatePoolError::InvalidToken(args.token_a))?;
+async fn validate_and_create_pool(
+    creator: Principal,
+    args: CreatePoolArgs,
+) -> Result<CandidPoolId, CreatePoolError> {
+    // Pools without liquidity can not be created while a minimum initial liquidity is set
+    if read_state(|s| s.get_pool_creation_policy()).requires_initial_position() {
+        return Err(CreatePoolError::InitialPositionRequired);
+    }
 
-    let token_b_fee = big_uint_to_u256(token_b_data.fee.0)
-        .map_err(|_| CreatePoolError::InvalidToken(args.token_b))?;
+    let validated_tokens = validate_pool_tokens(&args).await?;
+    let [(_, _, token_a_fee), (_, _, token_b_fee)] = &validated_tokens;
 
     let timestamp = ic_cdk::api::time();
-    let pool_id = create_pool_inner(args, token_a_fee, token_b_fee, timestamp)?;
+    let (pool_id, pool_state) = new_pool(args, *token_a_fee, *token_b_fee, Some(creator))?;
+
+    // Reads the policy again, it may have changed while the tokens were validated
+    let policy = read_state(|s| s.get_pool_creation_policy());
+    let creation_fee = mutate_state(|s| charge_creation_fee(s, &policy, creator))
+        .map_err(creation_fee_not_paid)?;
+    mutate_state(|s| record_pool_creation(s, pool_id.clone(), pool_state, creation_fee, timestamp));
+
+    record_validated_tokens(validated_tokens, timestamp);
 
     Ok(pool_id.into())
 }
 
-// Mints a new liquidity position, deposits tokens if needed, returns liquidity amount
+// Creates a pool and mints its first position from the internal balance of the caller, the only
+// way to create a pool while the creation policy has a minimum initial liquidity
 #[update]
-async fn mint_position(args: MintPositionArgs) -> Result<Nat, MintPositionError> {
+async fn create_pool_and_mint(
+    args: CreatePoolAndMintArgs,
+) -> Result<CreatePoolAndMintResult, CreatePoolAndMintError> {
     let caller = validate_caller_not_anonymous();
+    create_pool_and_mint_for_request(caller, args, None, "create_pool_and_mint").await
+}
 
-    // Locks principal to prevent concurrent modifications, avoiding double-spending
-    let _principal_guard = match PrincipalGuard::new_general_guard(caller) {
-        Ok(guard) => guard,
-        Err(_) => return Err(MintPositionError::LockedPrincipal),
-    };
-
-    let validated_args = validate_mint_position_args(args.clone(), caller)?;
-
-    let pool_id = validated_args.pool_id.clone();
-    let token0 = args.pool.token0;
-    let token1 = args.pool.token1;
-    let max_deposit = BalanceDelta::new(validated_args.amount0_max, validated_args.amount1_max);
-
-    // Fetches user balances, using I256::MAX as fallback for overflow safety
-    let user_balance = read_state(|s| {
-        BalanceDelta::new(
-            s.get_user_balance(&UserBalanceKey {
-                user: caller,
-                token: token0,
-            })
-            .0
-            .try_into()
-            .unwrap_or(I256::MAX),
-            s.get_user_balance(&UserBalanceKey {
-                user: caller,
-                token: token1,
-            })
-            .0
-            .try_into()
-            .unwrap_or(I256::MAX),
-        )
-    });
+// Creates a pool and mints its first position in one call, depositing the amounts missing from the
+// internal balance of the caller first. Nobody can create the pool or mint into it between the
+// creation and the first mint, if the mint fails the pool is not created and the deposits stay in
+// the internal balance
+#[update]
+async fn create_and_initialize_pool_with_position(
+    args: CreatePoolArgs,
+    position: InitialPositionArgs,
+) -> Result<CreatePoolAndMintResult, CreatePoolAndMintError> {
+    let caller = validate_caller_not_anonymous();
 
     // Configures account with optional subaccount for token deposits
     let mut from: Account = caller.into();
-    if let Some(subaccount) = args.from_subaccount {
+    if let Some(subaccount) = position.from_subaccount {
         from.subaccount = Some(subaccount);
     }
 
-    // Deposits tokens if user balance is insufficient for max deposit amounts
-    _deposit_if_needed(
+    let args = CreatePoolAndMintArgs {
+        pool: args,
+        tick_lower: position.tick_lower,
+        tick_upper: position.tick_upper,
+        amount0_max: position.amount0_max,
+        amount1_max: position.amount1_max,
+    };
+    create_pool_and_mint_for_request(
         caller,
-        token0,
-        &from,
-        user_balance.amount0().as_u256(),
-        max_deposit.amount0().as_u256(),
-        &mut DepositMemo::MintPosition {
+        args,
+        Some(from),
+        "create_and_initialize_pool_with_position",
+    )
+    .await
+}
+
+// Runs `create_pool_and_mint_inner` under the principal guard, answering a repeated `request_id`
+// of `method` with its cached response
+async fn create_pool_and_mint_for_request(
+    caller: Principal,
+    args: CreatePoolAndMintArgs,
+    deposit_from: Option<Account>,
+    method: &'static str,
+) -> Result<CreatePoolAndMintResult, CreatePoolAndMintError> {
+    let request = IdempotentRequest::new(caller, args.pool.request_id.clone(), method);
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    // Locks principal so the balance the position is minted from does not change meanwhile
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(CreatePoolAndMintError::RequestInProgress)
+            }
+            Err(_) => return Err(CreatePoolAndMintError::LockedPrincipal),
+        };
+
+    let result = create_pool_and_mint_inner(caller, args, deposit_from).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+// Validates the pool, deposits the amounts of the first position from `deposit_from` when given,
+// then creates the pool and mints the position without an await in between
+async fn create_pool_and_mint_inner(
+    caller: Principal,
+    args: CreatePoolAndMintArgs,
+    deposit_from: Option<Account>,
+) -> Result<CreatePoolAndMintResult, CreatePoolAndMintError> {
+    let operation = JournaledOperation::start(
+        caller,
+        OperationKind::CreatePoolAndMint,
+        ic_cdk::api::time(),
+    );
+
+    let validated_tokens = validate_pool_tokens(&args.pool)
+        .await
+        .map_err(CreatePoolAndMintError::CreatePool)?;
+    let [(_, _, token_a_fee), (_, _, token_b_fee)] = &validated_tokens;
+
+    // Checks the price, fee and that the pool does not exist before anything is deposited
+    let (pool_id, pool_state) =
+        new_pool(args.pool.clone(), *token_a_fee, *token_b_fee, Some(caller))
+            .map_err(CreatePoolAndMintError::CreatePool)?;
+
+    if let Some(from) = deposit_from {
+        let amount0_max = big_uint_to_u256(args.amount0_max.0.clone())
+            .map_err(|_| CreatePoolAndMintError::MintPosition(MintPositionError::InvalidAmount))?;
+        let amount1_max = big_uint_to_u256(args.amount1_max.0.clone())
+            .map_err(|_| CreatePoolAndMintError::MintPosition(MintPositionError::InvalidAmount))?;
+
+        // Deposits up to the max amounts, plus the creation fee when it is paid in a pool token
+        let creation_fee = read_state(|s| s.get_pool_creation_policy()).creation_fee;
+        for (token, amount_max) in [(pool_id.token0, amount0_max), (pool_id.token1, amount1_max)] {
+            let fee = creation_fee
+                .filter(|fee| fee.token == token)
+                .map_or(U256::ZERO, |fee| fee.amount);
+            let user_balance = read_state(|s| {
+                s.get_user_balance(&UserBalanceKey {
+                    user: caller,
+                    token,
+                })
+            })
+            .0;
+            _deposit_if_needed(
+                caller,
+                operation.id(),
+                token,
+                &from,
+                user_balance,
+                amount_max.saturating_add(fee),
+                &mut DepositMemo::MintPosition { amount: U256::ZERO },
+            )
+            .await
+            .map_err(|e| {
+                CreatePoolAndMintError::MintPosition(MintPositionError::DepositError(e.into()))
+            })?;
+        }
+    }
+
+    // No await from here on, the pool, the creation fee and the position are committed together
+    let timestamp = ic_cdk::api::time();
+    let policy = read_state(|s| s.get_pool_creation_policy());
+    let liquidity = execute_create_pool_and_mint(
+        caller,
+        pool_id.clone(),
+        pool_state,
+        args,
+        &policy,
+        timestamp,
+    )?;
+    record_execution(operation.id(), timestamp);
+
+    record_validated_tokens(validated_tokens, timestamp);
+
+    Ok(CreatePoolAndMintResult {
+        pool_id: pool_id.into(),
+        liquidity: Nat::from(liquidity),
+    })
+}
+
+// Checks the tokens of a new pool against the delisted tokens and the creation policy, then
+// validates both ledgers, returns each token with its validation and transfer fee
+async fn validate_pool_tokens(
+    args: &CreatePoolArgs,
+) -> Result<[(Principal, ValidatedToken, U256); 2], CreatePoolError> {
+    // Prevents pool creation with identical tokens
+    if args.token_a == args.token_b {
+        return Err(CreatePoolError::DuplicatedTokens);
+    }
+
+    // Delisted tokens keep their pools but get no new one
+    let policy = read_state(|s| s.get_pool_creation_policy());
+    for token in [args.token_a, args.token_b] {
+        if read_state(|s| s.get_token_settings(&token)).is_delisted() {
+            return Err(CreatePoolError::DelistedToken(token));
+        }
+        if !policy.allows_token(&token) {
+            return Err(CreatePoolError::TokenNotAllowed(token));
+        }
+    }
+
+    // Validates both ledgers through the proxy canister or directly, see `TokenValidationConfig`
+    let config = read_state(|s| s.get_token_validation_config());
+    let validated_a = validate_token(args.token_a, config)
+        .await
+        .map_err(|_| CreatePoolError::InvalidToken(args.token_a))?;
+
+    let validated_b = validate_token(args.token_b, config)
+        .await
+        .map_err(|_| CreatePoolError::InvalidToken(args.token_b))?;
+
+    // Fetches transfer fees to validate token standards and ensure compatibility
+    let token_a_fee = big_uint_to_u256(validated_a.data.fee.0.clone())
+        .map_err(|_| CreatePoolError::InvalidToken(args.token_a))?;
+
+    let token_b_fee = big_uint_to_u256(validated_b.data.fee.0.clone())
+        .map_err(|_| CreatePoolError::InvalidToken(args.token_b))?;
+
+    Ok([
+        (args.token_a, validated_a, token_a_fee),
+        (args.token_b, validated_b, token_b_fee),
+    ])
+}
+
+// Registers the tokens of a new pool, standards that could not be fetched are filled in by the
+// next refresh
+fn record_validated_tokens(
+    validated_tokens: [(Principal, ValidatedToken, U256); 2],
+    timestamp: u64,
+) {
+    mutate_state(|s| {
+        for (token, validated, _) in validated_tokens {
+            record_token_metadata(s, token, &validated.data, validated.standards, timestamp);
+        }
+    });
+}
+
+// Mints a new liquidity position, deposits tokens if needed, returns liquidity amount
+#[update]
+async fn mint_position(args: MintPositionArgs) -> Result<Nat, MintPositionError> {
+    let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "mint_position");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    // Locks principal to prevent concurrent modifications, avoiding double-spending
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(MintPositionError::RequestInProgress)
+            }
+            Err(_) => return Err(MintPositionError::LockedPrincipal),
+        };
+
+    let result = mint_position_inner(caller, args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn mint_position_inner(
+    caller: Principal,
+    args: MintPositionArgs,
+) -> Result<Nat, MintPositionError> {
+    let operation =
+        JournaledOperation::start(caller, OperationKind::MintPosition, ic_cdk::api::time());
+
+    let validated_args = validate_mint_position_args(args.clone(), caller)?;
+
+    let pool_id = validated_args.pool_id.clone();
+    let token0 = args.pool.token0;
+    let token1 = args.pool.token1;
+    let max_deposit = BalanceDelta::new(validated_args.amount0_max, validated_args.amount1_max);
+
+    // Fetches user balances, using I256::MAX as fallback for overflow safety
+    let user_balance = read_state(|s| {
+        BalanceDelta::new(
+            s.get_user_balance(&UserBalanceKey {
+                user: caller,
+                token: token0,
+            })
+            .0
+            .try_into()
+            .unwrap_or(I256::MAX),
+            s.get_user_balance(&UserBalanceKey {
+                user: caller,
+                token: token1,
+            })
+            .0
+            .try_into()
+            .unwrap_or(I256::MAX),
+        )
+    });
+
+    // Configures account with optional subaccount for token deposits
+    let mut from: Account = caller.into();
+    if let Some(subaccount) = args.from_subaccount {
+        from.subaccount = Some(subaccount);
+    }
+
+    // Deposits tokens if user balance is insufficient for max deposit amounts
+    _deposit_if_needed(
+        caller,
+        operation.id(),
+        token0,
+        &from,
+        user_balance.amount0().as_u256(),
+        max_deposit.amount0().as_u256(),
+        &mut DepositMemo::MintPosition {
             // Typo: Should be MintPosition
             amount: U256::ZERO,
         },
@@ -330,6 +1092,7 @@ async fn mint_position(args: MintPositionArgs) -> Result<Nat, MintPositionError>
 
     _deposit_if_needed(
         caller,
+        operation.id(),
         token1,
         &from,
         user_balance.amount1().as_u256(),
@@ -345,20 +1108,46 @@ async fn mint_position(args: MintPositionArgs) -> Result<Nat, MintPositionError>
     let timestamp = ic_cdk::api::time();
 
     // Executes minting and converts liquidity amount to Nat
-    execute_mint_position(caller, pool_id, token0, token1, validated_args, timestamp)
-        .map(|mint_result| Nat::from(mint_result))
+    let liquidity =
+        execute_mint_position(caller, pool_id, token0, token1, validated_args, timestamp)?;
+    record_execution(operation.id(), timestamp);
+
+    Ok(Nat::from(liquidity))
 }
 
 // Increases liquidity in an existing position, deposits tokens if needed, returns liquidity delta
 #[update]
 async fn increase_liquidity(args: IncreaseLiquidityArgs) -> Result<Nat, IncreaseLiquidityError> {
     let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "increase_liquidity");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
 
     // Locks principal to prevent concurrent modifications
-    let _principal_guard = match PrincipalGuard::new_general_guard(caller) {
-        Ok(guard) => guard,
-        Err(_) => return Err(IncreaseLiquidityError::LockedPrincipal),
-    };
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(IncreaseLiquidityError::RequestInProgress)
+            }
+            Err(_) => return Err(IncreaseLiquidityError::LockedPrincipal),
+        };
+
+    let result = increase_liquidity_inner(caller, args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn increase_liquidity_inner(
+    caller: Principal,
+    args: IncreaseLiquidityArgs,
+) -> Result<Nat, IncreaseLiquidityError> {
+    let operation = JournaledOperation::start(
+        caller,
+        OperationKind::IncreaseLiquidity,
+        ic_cdk::api::time(),
+    );
 
     let validated_args = validate_increase_liquidity_args(args.clone(), caller)?;
 
@@ -395,6 +1184,7 @@ async fn increase_liquidity(args: IncreaseLiquidityArgs) -> Result<Nat, Increase
     // Deposits tokens if needed for both tokens
     _deposit_if_needed(
         caller,
+        operation.id(),
         token0,
         &from,
         user_balance.amount0().as_u256(),
@@ -409,6 +1199,7 @@ async fn increase_liquidity(args: IncreaseLiquidityArgs) -> Result<Nat, Increase
 
     _deposit_if_needed(
         caller,
+        operation.id(),
         token1,
         &from,
         user_balance.amount1().as_u256(),
@@ -424,20 +1215,39 @@ async fn increase_liquidity(args: IncreaseLiquidityArgs) -> Result<Nat, Increase
     let timestamp = ic_cdk::api::time();
 
     // Increases liquidity and returns the delta
-    execute_increase_liquidity(caller, pool_id, token0, token1, validated_args, timestamp)
-        .map(|liquidity_delta| Nat::from(liquidity_delta))
+    let liquidity_delta =
+        execute_increase_liquidity(caller, pool_id, token0, token1, validated_args, timestamp)?;
+    record_execution(operation.id(), timestamp);
+
+    Ok(Nat::from(liquidity_delta))
 }
 
 // Burns a liquidity position, withdraws tokens, returns success or error
 #[update]
 async fn burn(args: BurnPositionArgs) -> Result<(), BurnPositionError> {
     let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "burn");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
 
     // Locks principal to prevent concurrent modifications
-    let _principal_guard = match PrincipalGuard::new_general_guard(caller) {
-        Ok(guard) => guard,
-        Err(_) => return Err(BurnPositionError::LockedPrincipal),
-    };
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(BurnPositionError::RequestInProgress)
+            }
+            Err(_) => return Err(BurnPositionError::LockedPrincipal),
+        };
+
+    let result = burn_inner(caller, args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn burn_inner(caller: Principal, args: BurnPositionArgs) -> Result<(), BurnPositionError> {
+    let operation = JournaledOperation::start(caller, OperationKind::Burn, ic_cdk::api::time());
 
     let validated_args = validate_burn_position_args(args.clone(), caller)?;
 
@@ -456,6 +1266,7 @@ async fn burn(args: BurnPositionArgs) -> Result<(), BurnPositionError> {
         validated_args,
         timestamp,
     )?;
+    record_execution(operation.id(), timestamp);
 
     // Retrieves transfer fees for both tokens from pool state
     let (token0_transfer_fee, token1_transfer_fee) = read_state(|s| {
@@ -469,7 +1280,7 @@ async fn burn(args: BurnPositionArgs) -> Result<(), BurnPositionError> {
     let to_account = Account::from(caller);
 
     // Withdraws burned tokens for token0
-    let _ = _withdraw(
+    let withdrawal0 = _withdraw(
         caller,
         token0,
         user_balance_after_burn.amount0().as_u256(),
@@ -479,12 +1290,12 @@ async fn burn(args: BurnPositionArgs) -> Result<(), BurnPositionError> {
             amount: U256::ZERO,
         },
         token0_transfer_fee,
+        operation.new_withdrawal(),
     )
-    .await
-    .map_err(|e| BurnPositionError::BurntPositionWithdrawalFailed(e.into()))?;
+    .await;
 
-    // Withdraws burned tokens for token1
-    let _ = _withdraw(
+    // Withdraws burned tokens for token1 even if token0 failed, failed withdrawals are retried
+    let withdrawal1 = _withdraw(
         caller,
         token1,
         user_balance_after_burn.amount1().as_u256(),
@@ -494,9 +1305,13 @@ async fn burn(args: BurnPositionArgs) -> Result<(), BurnPositionError> {
             amount: U256::ZERO,
         },
         token1_transfer_fee,
+        operation.new_withdrawal(),
     )
-    .await
-    .map_err(|e| BurnPositionError::BurntPositionWithdrawalFailed(e.into()))?;
+    .await;
+
+    withdrawal0
+        .and(withdrawal1)
+        .map_err(|e| BurnPositionError::BurntPositionWithdrawalFailed(e.into()))?;
 
     Ok(())
 }
@@ -505,12 +1320,35 @@ async fn burn(args: BurnPositionArgs) -> Result<(), BurnPositionError> {
 #[update]
 async fn decrease_liquidity(args: DecreaseLiquidityArgs) -> Result<(), DecreaseLiquidityError> {
     let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "decrease_liquidity");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
 
     // Locks principal to prevent concurrent modifications
-    let _principal_guard = match PrincipalGuard::new_general_guard(caller) {
-        Ok(guard) => guard,
-        Err(_) => return Err(DecreaseLiquidityError::LockedPrincipal),
-    };
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(DecreaseLiquidityError::RequestInProgress)
+            }
+            Err(_) => return Err(DecreaseLiquidityError::LockedPrincipal),
+        };
+
+    let result = decrease_liquidity_inner(caller, args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn decrease_liquidity_inner(
+    caller: Principal,
+    args: DecreaseLiquidityArgs,
+) -> Result<(), DecreaseLiquidityError> {
+    let operation = JournaledOperation::start(
+        caller,
+        OperationKind::DecreaseLiquidity,
+        ic_cdk::api::time(),
+    );
 
     let validated_args = validate_decrease_liquidity_args(args.clone(), caller)?;
 
@@ -529,6 +1367,7 @@ async fn decrease_liquidity(args: DecreaseLiquidityArgs) -> Result<(), DecreaseL
         validated_args,
         timestamp,
     )?;
+    record_execution(operation.id(), timestamp);
 
     // Retrieves transfer fees for both tokens
     let (token0_transfer_fee, token1_transfer_fee) = read_state(|s| {
@@ -542,7 +1381,7 @@ async fn decrease_liquidity(args: DecreaseLiquidityArgs) -> Result<(), DecreaseL
     let to_account = Account::from(caller);
 
     // Withdraws decreased liquidity for token0
-    let _ = _withdraw(
+    let withdrawal0 = _withdraw(
         caller,
         token0,
         user_balance_after_burn.amount0().as_u256(),
@@ -552,12 +1391,12 @@ async fn decrease_liquidity(args: DecreaseLiquidityArgs) -> Result<(), DecreaseL
             amount: U256::ZERO,
         },
         token0_transfer_fee,
+        operation.new_withdrawal(),
     )
-    .await
-    .map_err(|e| DecreaseLiquidityError::DecreasedPositionWithdrawalFailed(e.into()))?;
+    .await;
 
-    // Withdraws decreased liquidity for token1
-    let _ = _withdraw(
+    // Withdraws decreased liquidity for token1 even if token0 failed
+    let withdrawal1 = _withdraw(
         caller,
         token1,
         user_balance_after_burn.amount1().as_u256(),
@@ -567,9 +1406,13 @@ async fn decrease_liquidity(args: DecreaseLiquidityArgs) -> Result<(), DecreaseL
             amount: U256::ZERO,
         },
         token1_transfer_fee,
+        operation.new_withdrawal(),
     )
-    .await
-    .map_err(|e| DecreaseLiquidityError::DecreasedPositionWithdrawalFailed(e.into()))?;
+    .await;
+
+    withdrawal0
+        .and(withdrawal1)
+        .map_err(|e| DecreaseLiquidityError::DecreasedPositionWithdrawalFailed(e.into()))?;
 
     Ok(())
 }
@@ -577,17 +1420,32 @@ async fn decrease_liquidity(args: DecreaseLiquidityArgs) -> Result<(), DecreaseL
 // Executes a token swap, deposits input, withdraws output, refunds on failure
 #[update]
 async fn swap(args: SwapArgs) -> Result<CandidSwapSuccess, SwapError> {
-    let validated_swap_args = validate_swap_args(args)?;
-    ic_cdk::println!("{:?}", validated_swap_args);
     let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id(), "swap");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
 
     // Uses swap-specific lock to allow concurrent swaps but blocks other operations by the same
     // user(mint, increase, decrease, collect fees, bunr)
-    let _guard = match PrincipalGuard::new_swap_guard(caller) {
+    let _guard = match PrincipalGuard::new_swap_guard_for_request(caller, request.request_id()) {
         Ok(guard) => guard,
+        Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+            return Err(SwapError::RequestInProgress)
+        }
         Err(_err) => return Err(SwapError::LockedPrincipal),
     };
 
+    let result = swap_inner(caller, args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn swap_inner(caller: Principal, args: SwapArgs) -> Result<CandidSwapSuccess, SwapError> {
+    let mut validated_swap_args = validate_swap_args(args)?;
+    ic_cdk::println!("{:?}", validated_swap_args);
+    let operation = JournaledOperation::start(caller, OperationKind::Swap, ic_cdk::api::time());
+
     // Configures user account with optional subaccount
     let mut user_address: Account = caller.into();
     if let Some(subaccount) = validated_swap_args.from_subaccount() {
@@ -599,8 +1457,9 @@ async fn swap(args: SwapArgs) -> Result<CandidSwapSuccess, SwapError> {
     let token_out = validated_swap_args.token_out();
 
     // Deposits input tokens for the swap
-    _deposit(
+    let received_amount = _deposit(
         caller,
+        operation.id(),
         token_in,
         &user_address,
         deposit_amount.as_u256(),
@@ -609,12 +1468,17 @@ async fn swap(args: SwapArgs) -> Result<CandidSwapSuccess, SwapError> {
     .await
     .map_err(|e| SwapError::DepositError(e))?;
 
+    // Fee-on-transfer tokens arrive reduced, only the received amount can be swapped
+    validated_swap_args.cap_deposit_amount(received_amount.as_i256());
+
     let timestamp = ic_cdk::api::time();
 
     let swap_result = execute_swap(&validated_swap_args, token_in, token_out, caller, timestamp);
 
     match swap_result {
         Ok(swap_delta) => {
+            record_execution(operation.id(), timestamp);
+
             // Withdraws output tokens after successful swap
             _withdraw(
                 caller,
@@ -623,6 +1487,7 @@ async fn swap(args: SwapArgs) -> Result<CandidSwapSuccess, SwapError> {
                 &user_address,
                 &mut WithdrawMemo::SwapOut { amount: U256::ZERO },
                 swap_delta.2,
+                operation.new_withdrawal(),
             )
             .await
             .map_err(|e| SwapError::FailedToWithdraw {
@@ -639,14 +1504,19 @@ async fn swap(args: SwapArgs) -> Result<CandidSwapSuccess, SwapError> {
         }
         Err(err) => {
             // Refunds input tokens if swap fails
-            let refunded_amount =
-                _refund(caller, token_in, deposit_amount.as_u256(), &user_address)
-                    .await
-                    .map_err(|e| SwapError::SwapFailedRefunded {
-                        refund_amount: None,
-                        failed_reason: err.clone(),
-                        refund_error: Some(e),
-                    })?;
+            let refunded_amount = _refund(
+                caller,
+                token_in,
+                received_amount,
+                &user_address,
+                operation.new_withdrawal(),
+            )
+            .await
+            .map_err(|e| SwapError::SwapFailedRefunded {
+                refund_amount: None,
+                failed_reason: err.clone(),
+                refund_error: Some(e),
+            })?;
 
             Err(SwapError::SwapFailedRefunded {
                 failed_reason: err,
@@ -659,12 +1529,36 @@ async fn swap(args: SwapArgs) -> Result<CandidSwapSuccess, SwapError> {
 
 // Collects fees from a position, withdraws them, returns collected amounts
 #[update]
-async fn collect_fees(position: CandidPositionKey) -> Result<CollectFeesSuccess, CollectFeesError> {
+async fn collect_fees(
+    position: CandidPositionKey,
+    request_id: Option<Vec<u8>>,
+) -> Result<CollectFeesSuccess, CollectFeesError> {
     let caller = validate_caller_not_anonymous();
-    let _principal_guard = match PrincipalGuard::new_general_guard(caller) {
-        Ok(guard) => guard,
-        Err(_) => return Err(CollectFeesError::LockedPrincipal),
-    };
+    let request = IdempotentRequest::new(caller, request_id, "collect_fees");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(CollectFeesError::RequestInProgress)
+            }
+            Err(_) => return Err(CollectFeesError::LockedPrincipal),
+        };
+
+    let result = collect_fees_inner(caller, position).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn collect_fees_inner(
+    caller: Principal,
+    position: CandidPositionKey,
+) -> Result<CollectFeesSuccess, CollectFeesError> {
+    let operation =
+        JournaledOperation::start(caller, OperationKind::CollectFees, ic_cdk::api::time());
 
     // Ensures position belongs to caller by setting owner
     let mut position_key: PositionKey = position
@@ -686,31 +1580,36 @@ async fn collect_fees(position: CandidPositionKey) -> Result<CollectFeesSuccess,
 
     // Executes fee collection and updates position state
     let fee_delta = execute_collect_fees(caller, &position_key, pool.tick_spacing)?;
+    record_execution(operation.id(), ic_cdk::api::time());
 
     if fee_delta != BalanceDelta::ZERO_DELTA {
         // Withdraws collected fees for token0
-        let _ = _withdraw(
+        let withdrawal0 = _withdraw(
             caller,
             position_key.pool_id.token0,
             fee_delta.amount0().as_u256(),
             &caller.into(),
             &mut WithdrawMemo::CollectFees { amount: U256::ZERO },
             pool.token0_transfer_fee,
+            operation.new_withdrawal(),
         )
-        .await
-        .map_err(|e| CollectFeesError::CollectedFeesWithdrawalFailed(e.into()))?;
+        .await;
 
         // Withdraws collected fees for token1, using token0_transfer_fee (likely a bug)
-        let _ = _withdraw(
+        let withdrawal1 = _withdraw(
             caller,
             position_key.pool_id.token1,
             fee_delta.amount1().as_u256(),
             &caller.into(),
             &mut WithdrawMemo::CollectFees { amount: U256::ZERO },
             pool.token0_transfer_fee, // Should likely be token1_transfer_fee
+            operation.new_withdrawal(),
         )
-        .await
-        .map_err(|e| CollectFeesError::CollectedFeesWithdrawalFailed(e.into()))?;
+        .await;
+
+        withdrawal0
+            .and(withdrawal1)
+            .map_err(|e| CollectFeesError::CollectedFeesWithdrawalFailed(e.into()))?;
 
         Ok(CollectFeesSuccess {
             token0_collected: u256_to_nat(fee_delta.amount0().as_u256()),
@@ -721,14 +1620,99 @@ async fn collect_fees(position: CandidPositionKey) -> Result<CollectFeesSuccess,
     }
 }
 
+// Donates tokens to in range liquidity providers of a pool, deposits tokens if needed
+#[update]
+async fn donate(args: DonateArgs) -> Result<(), DonateError> {
+    let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "donate");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(DonateError::RequestInProgress)
+            }
+            Err(_) => return Err(DonateError::LockedPrincipal),
+        };
+
+    let result = donate_inner(caller, args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn donate_inner(caller: Principal, args: DonateArgs) -> Result<(), DonateError> {
+    let operation = JournaledOperation::start(caller, OperationKind::Donate, ic_cdk::api::time());
+
+    let from_subaccount = args.from_subaccount;
+    let validated_args = validate_donate_args(args)?;
+    let token0 = validated_args.pool_id.token0;
+    let token1 = validated_args.pool_id.token1;
+
+    let mut from: Account = caller.into();
+    if let Some(subaccount) = from_subaccount {
+        from.subaccount = Some(subaccount);
+    }
+
+    // Deposits tokens if user balance is insufficient for the donated amounts
+    _deposit_if_needed(
+        caller,
+        operation.id(),
+        token0,
+        &from,
+        get_user_balance(caller, token0),
+        validated_args.amount0,
+        &mut DepositMemo::Donate { amount: U256::ZERO },
+    )
+    .await
+    .map_err(DonateError::DepositError)?;
+
+    _deposit_if_needed(
+        caller,
+        operation.id(),
+        token1,
+        &from,
+        get_user_balance(caller, token1),
+        validated_args.amount1,
+        &mut DepositMemo::Donate { amount: U256::ZERO },
+    )
+    .await
+    .map_err(DonateError::DepositError)?;
+
+    let timestamp = ic_cdk::api::time();
+    execute_donate(caller, validated_args, timestamp)?;
+    record_execution(operation.id(), timestamp);
+
+    Ok(())
+}
+
 // Deposits tokens into the canister, updates user balance
 #[update]
 async fn deposit(deposit_args: DepositArgs) -> Result<(), DepositError> {
     let caller = validate_caller_not_anonymous();
-    let _principal_guard = match PrincipalGuard::new_general_guard(caller) {
-        Ok(guard) => guard,
-        Err(_) => return Err(DepositError::LockedPrincipal),
-    };
+    let request = IdempotentRequest::new(caller, deposit_args.request_id.clone(), "deposit");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(DepositError::RequestInProgress)
+            }
+            Err(_) => return Err(DepositError::LockedPrincipal),
+        };
+
+    let result = deposit_inner(caller, deposit_args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn deposit_inner(caller: Principal, deposit_args: DepositArgs) -> Result<(), DepositError> {
+    let operation = JournaledOperation::start(caller, OperationKind::Deposit, ic_cdk::api::time());
 
     let mut from = Account::from(caller);
     if let Some(subaccount) = deposit_args.from_subaccount {
@@ -741,89 +1725,364 @@ async fn deposit(deposit_args: DepositArgs) -> Result<(), DepositError> {
 
     _deposit(
         caller,
+        operation.id(),
         deposit_args.token,
         &from,
         amount,
         &mut DepositMemo::Deposit { amount: U256::ZERO },
     )
     .await
+    .map(|_received_amount| ())
 }
 
-// Withdraws tokens, updates user balance, returns withdrawn amount
+// Returns the account of the canister where a user can deposit with a plain ICRC-1 transfer,
+// to be credited by calling notify_deposit
+#[query]
+fn get_deposit_account(user: Principal) -> Account {
+    Account {
+        owner: ic_cdk::id(),
+        subaccount: Some(deposit_subaccount(user)),
+    }
+}
+
+// Sweeps the caller's deposit subaccount into the main account and credits the swept amount,
+// deposit flow for ICRC-1 only tokens and wallets that can not approve
 #[update]
-async fn withdraw(withdraw_args: WithdrawArgs) -> Result<Nat, WithdrawError> {
+async fn notify_deposit(
+    token: Principal,
+    request_id: Option<Vec<u8>>,
+) -> Result<Nat, NotifyDepositError> {
     let caller = validate_caller_not_anonymous();
-    let _principal_guard = match PrincipalGuard::new_general_guard(caller) {
-        Ok(guard) => guard,
-        Err(_) => return Err(WithdrawError::LockedPrincipal),
-    };
-
-    // Fetches token transfer fee from ledger
-    let transfer_fee = big_uint_to_u256(
-        LedgerClient::new(withdraw_args.token)
-            .icrc_fee()
-            .await
-            .map_err(|_| WithdrawError::FeeUnknown)?
-            .0,
-    )
-    .map_err(|_| WithdrawError::FeeUnknown)?;
+    let request = IdempotentRequest::new(caller, request_id, "notify_deposit");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
 
-    let amount =
-        big_uint_to_u256(withdraw_args.amount.0).map_err(|_| WithdrawError::AmountOverflow)?;
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(NotifyDepositError::RequestInProgress)
+            }
+            Err(_) => return Err(NotifyDepositError::LockedPrincipal),
+        };
 
-    _withdraw(
-        caller,
-        withdraw_args.token,
-        amount,
-        &caller.into(),
-        &mut WithdrawMemo::Withdraw { amount: U256::ZERO },
-        transfer_fee,
-    )
-    .await
-    .map(|ledger_index| u256_to_nat(ledger_index))
+    let result = notify_deposit_inner(caller, token).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
 }
 
-// Internal function to deposit tokens and update user balance
-async fn _deposit(
+async fn notify_deposit_inner(
     caller: Principal,
     token: Principal,
-    from: &Account,
-    amount: U256,
-    memo: &mut DepositMemo,
-) -> Result<(), DepositError> {
-    // Sets deposit amount in memo for ledger tracking
+) -> Result<Nat, NotifyDepositError> {
+    let operation = JournaledOperation::start(caller, OperationKind::Deposit, ic_cdk::api::time());
 
-    log!(
-        DEBUG,
-        "Depositing token {:?} with amount {:?} from user {:?}",
-        token.to_text(),
+    let ledger = LedgerClient::new(token);
+    let subaccount = deposit_subaccount(caller);
+
+    let deposited_amount = ledger
+        .balance_of(Account {
+            owner: ic_cdk::id(),
+            subaccount: Some(subaccount),
+        })
+        .await
+        .map_err(NotifyDepositError::TemporarilyUnavailable)?;
+    let deposited_amount =
+        big_uint_to_u256(deposited_amount.0).map_err(|_| NotifyDepositError::AmountOverflow)?;
+
+    let transfer_fee = ledger
+        .icrc_fee()
+        .await
+        .map_err(NotifyDepositError::TemporarilyUnavailable)?;
+    let transfer_fee =
+        big_uint_to_u256(transfer_fee.0).map_err(|_| NotifyDepositError::AmountOverflow)?;
+
+    // The sweep pays one transfer fee, nothing is left to credit otherwise
+    if deposited_amount <= transfer_fee {
+        return Err(NotifyDepositError::AmountTooLow {
+            balance: u256_to_nat(deposited_amount),
+            transfer_fee: u256_to_nat(transfer_fee),
+        });
+    }
+
+    let sweep_amount = deposited_amount - transfer_fee;
+    let memo = DepositMemo::NotifiedDeposit {
+        amount: sweep_amount,
+    };
+    let dedup = deposit_dedup(operation.id(), ic_cdk::api::time());
+    let (received_amount, block_index) = _credit_transfer_in(token, sweep_amount, async {
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            // Sending the identical sweep again is deduplicated by the ledger
+            match ledger
+                .sweep_subaccount(
+                    subaccount,
+                    u256_to_big_uint(sweep_amount),
+                    memo.clone(),
+                    u256_to_big_uint(transfer_fee),
+                    &dedup,
+                )
+                .await
+            {
+                Err(LedgerTransferError::OutcomeUnknown { .. })
+                    if attempts < MAX_TRANSFER_ATTEMPTS =>
+                {
+                    continue
+                }
+                result => break result,
+            }
+        };
+        match result {
+            Ok(block_index) => Ok(block_index.as_u64()),
+            Err(LedgerTransferError::BadFee { expected_fee }) => {
+                // Updates transfer fee across all pools, the next notification uses the new fee
+                if let Ok(new_transfer_fee) = big_uint_to_u256(expected_fee.0) {
+                    _update_transfer_fee(token, new_transfer_fee);
+                }
+                Err(DepositError::TemporarilyUnavailable(format!(
+                    "{} transfer fee changed, try again",
+                    token.to_text()
+                )))
+            }
+            Err(err @ LedgerTransferError::OutcomeUnknown { .. }) => {
+                // The sweep might have gone through, the timer sends it again and credits the
+                // user once its outcome is known
+                record_deposit_pending(
+                    operation.id(),
+                    PendingDeposit::new(
+                        token,
+                        DepositSource::Subaccount {
+                            subaccount,
+                            transfer_fee,
+                        },
+                        sweep_amount,
+                        memo.clone(),
+                        &dedup,
+                    ),
+                    format!("{err:?}"),
+                    ic_cdk::api::time(),
+                );
+                Err(DepositError::OutcomeUnknown {
+                    operation_id: operation.id(),
+                })
+            }
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await
+    .map_err(NotifyDepositError::DepositError)?;
+
+    // Updates user balance, caps at U256::MAX to prevent overflow
+    let latest_user_balance = get_user_balance(caller, token);
+    _credit_deposit(
+        caller,
+        token,
+        received_amount,
+        block_index,
+        MemoKind::NotifiedDeposit,
+        latest_user_balance
+            .checked_add(received_amount)
+            .unwrap_or(U256::MAX),
+    );
+    record_deposit(operation.id(), token, received_amount, ic_cdk::api::time());
+
+    Ok(u256_to_nat(received_amount))
+}
+
+// Withdraws tokens, updates user balance, returns withdrawn amount
+#[update]
+async fn withdraw(withdraw_args: WithdrawArgs) -> Result<Nat, WithdrawError> {
+    let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, withdraw_args.request_id.clone(), "withdraw");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(WithdrawError::RequestInProgress)
+            }
+            Err(_) => return Err(WithdrawError::LockedPrincipal),
+        };
+
+    let result = withdraw_inner(caller, withdraw_args).await;
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+async fn withdraw_inner(
+    caller: Principal,
+    withdraw_args: WithdrawArgs,
+) -> Result<Nat, WithdrawError> {
+    let operation = JournaledOperation::start(caller, OperationKind::Withdraw, ic_cdk::api::time());
+
+    let transfer_fee = _transfer_fee(withdraw_args.token).await?;
+
+    let amount =
+        big_uint_to_u256(withdraw_args.amount.0).map_err(|_| WithdrawError::AmountOverflow)?;
+
+    _withdraw(
+        caller,
+        withdraw_args.token,
+        amount,
+        &caller.into(),
+        &mut WithdrawMemo::Withdraw { amount: U256::ZERO },
+        transfer_fee,
+        operation.new_withdrawal(),
+    )
+    .await
+    .map(|ledger_index| u256_to_nat(ledger_index))
+}
+
+// Moves internal balance from the caller to another principal without going through the token
+// ledger, returns the index of the recorded event
+#[update]
+fn transfer_internal(args: InternalTransferArgs) -> Result<Nat, InternalTransferError> {
+    let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "transfer_internal");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    let _principal_guard =
+        match PrincipalGuard::new_general_guard_for_request(caller, request.request_id()) {
+            Ok(guard) => guard,
+            Err(PrincipalGuardError::DuplicateRequest { .. }) => {
+                return Err(InternalTransferError::RequestInProgress)
+            }
+            Err(_) => return Err(InternalTransferError::LockedPrincipal),
+        };
+
+    let result = _transfer_internal(caller, args.to, args.token, args.amount, None);
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+// Sets the amount of the caller's internal balance a spender can move with
+// `transfer_from_internal`, replacing any previous allowance
+#[update]
+fn approve_internal(args: ApproveInternalArgs) -> Result<(), ApproveInternalError> {
+    let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "approve_internal");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    let result = approve_internal_inner(caller, args);
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+fn approve_internal_inner(
+    caller: Principal,
+    args: ApproveInternalArgs,
+) -> Result<(), ApproveInternalError> {
+    let amount =
+        big_uint_to_u256(args.amount.0).map_err(|_| ApproveInternalError::AmountOverflow)?;
+    let expected_allowance = args
+        .expected_allowance
+        .map(|allowance| big_uint_to_u256(allowance.0))
+        .transpose()
+        .map_err(|_| ApproveInternalError::AmountOverflow)?;
+
+    execute_approve_internal(
+        caller,
+        args.spender,
+        args.token,
+        amount,
+        expected_allowance,
+        args.expires_at,
+        ic_cdk::api::time(),
+    )
+}
+
+// Moves internal balance of `from` to another principal using the allowance `from` gave to the
+// caller, returns the index of the recorded event
+#[update]
+fn transfer_from_internal(args: TransferFromInternalArgs) -> Result<Nat, InternalTransferError> {
+    let caller = validate_caller_not_anonymous();
+    let request = IdempotentRequest::new(caller, args.request_id.clone(), "transfer_from_internal");
+    if let Some(response) = request.cached_response(ic_cdk::api::time()) {
+        return response;
+    }
+
+    // the spender's balance is not touched, so the owner is the one guarded. The request id
+    // belongs to the spender and does not tag the guard of the owner, the call does not await so
+    // no retry can run while it is in flight
+    let _principal_guard = PrincipalGuard::new_general_guard(args.from)
+        .map_err(|_| InternalTransferError::LockedPrincipal)?;
+
+    let result = _transfer_internal(args.from, args.to, args.token, args.amount, Some(caller));
+    request.record_response(&result, ic_cdk::api::time());
+    result
+}
+
+// Internal function to move internal balance between two principals, the sender must already be
+// guarded, the recipient is guarded here so no operation of either party is in flight
+fn _transfer_internal(
+    from: Principal,
+    to: Principal,
+    token: Principal,
+    amount: Nat,
+    spender: Option<Principal>,
+) -> Result<Nat, InternalTransferError> {
+    let amount = big_uint_to_u256(amount.0).map_err(|_| InternalTransferError::AmountOverflow)?;
+    validate_internal_transfer(from, to, amount)?;
+
+    let _recipient_guard = PrincipalGuard::new_general_guard(to)
+        .map_err(|_| InternalTransferError::LockedPrincipal)?;
+
+    execute_internal_transfer(from, to, token, amount, spender, ic_cdk::api::time()).map(Nat::from)
+}
+
+// Internal function to deposit tokens and update user balance, returns the received amount
+async fn _deposit(
+    caller: Principal,
+    operation_id: OperationId,
+    token: Principal,
+    from: &Account,
+    amount: U256,
+    memo: &mut DepositMemo,
+) -> Result<U256, DepositError> {
+    // Sets deposit amount in memo for ledger tracking
+
+    log!(
+        DEBUG,
+        "Depositing token {:?} with amount {:?} from user {:?}",
+        token.to_text(),
         amount,
         caller.to_text(),
     );
 
     memo.set_amount(amount);
-    LedgerClient::new(token)
-        .deposit(*from, u256_to_big_uint(amount), memo.clone())
-        .await?;
+    let (received_amount, block_index) =
+        _transfer_in(operation_id, token, from, amount, memo).await?;
 
     // Updates user balance, caps at U256::MAX to prevent overflow
     let latest_user_balance = get_user_balance(caller, token);
-    mutate_state(|s| {
-        s.update_user_balance(
-            UserBalanceKey {
-                user: caller,
-                token,
-            },
-            UserBalance(latest_user_balance.checked_add(amount).unwrap_or(U256::MAX)),
-        );
-    });
+    _credit_deposit(
+        caller,
+        token,
+        received_amount,
+        block_index,
+        memo.kind(),
+        latest_user_balance
+            .checked_add(received_amount)
+            .unwrap_or(U256::MAX),
+    );
+    record_deposit(operation_id, token, received_amount, ic_cdk::api::time());
 
-    Ok(())
+    Ok(received_amount)
 }
 
 // Deposits tokens if current balance is insufficient, returns updated balance
 async fn _deposit_if_needed(
     caller: Principal,
+    operation_id: OperationId,
     token: Principal,
     from: &Account,
     user_current_balance: U256,
@@ -843,23 +2102,238 @@ async fn _deposit_if_needed(
         );
 
         memo.set_amount(deposit_amount);
-        LedgerClient::new(token)
-            .deposit(*from, u256_to_big_uint(deposit_amount), memo.clone())
-            .await?;
-
-        // Updates user balance to desired amount
-        mutate_state(|s| {
-            s.update_user_balance(
-                UserBalanceKey {
-                    user: caller,
+        let (received_amount, block_index) =
+            _transfer_in(operation_id, token, from, deposit_amount, memo).await?;
+
+        // Updates user balance to desired amount, or less for fee-on-transfer tokens
+        let new_user_balance = user_current_balance + received_amount;
+        _credit_deposit(
+            caller,
+            token,
+            received_amount,
+            block_index,
+            memo.kind(),
+            new_user_balance,
+        );
+        record_deposit(operation_id, token, received_amount, ic_cdk::api::time());
+        return Ok(new_user_balance);
+    }
+    Ok(user_current_balance)
+}
+
+// Transfers tokens from the user to the canister, returns the amount actually received and the
+// ledger block of the transfer. Tokens with balance difference accounting are credited with the
+// measured change of the canister balance instead of the requested amount
+async fn _transfer_in(
+    operation_id: OperationId,
+    token: Principal,
+    from: &Account,
+    amount: U256,
+    memo: &DepositMemo,
+) -> Result<(U256, Option<u64>), DepositError> {
+    let ledger = LedgerClient::new(token);
+    let dedup = deposit_dedup(operation_id, ic_cdk::api::time());
+    _credit_transfer_in(token, amount, async {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            // Sending the identical transfer again is deduplicated by the ledger
+            match ledger
+                .deposit(*from, u256_to_big_uint(amount), memo.clone(), &dedup)
+                .await
+            {
+                Err(LedgerTransferError::OutcomeUnknown { .. })
+                    if attempts < MAX_TRANSFER_ATTEMPTS =>
+                {
+                    continue
+                }
+                Err(err @ LedgerTransferError::OutcomeUnknown { .. }) => {
+                    // The ledger might have debited the user, the timer sends the identical
+                    // transfer again and credits the user once its outcome is known
+                    record_deposit_pending(
+                        operation_id,
+                        PendingDeposit::new(
+                            token,
+                            DepositSource::TransferFrom {
+                                owner: from.owner,
+                                subaccount: from.subaccount,
+                            },
+                            amount,
+                            memo.clone(),
+                            &dedup,
+                        ),
+                        format!("{err:?}"),
+                        ic_cdk::api::time(),
+                    );
+                    return Err(DepositError::OutcomeUnknown { operation_id });
+                }
+                result => {
+                    return result
+                        .map(|block_index| block_index.as_u64())
+                        .map_err(DepositError::from)
+                }
+            }
+        }
+    })
+    .await
+}
+
+// Runs a transfer of `amount` into the canister main account, returns the amount actually received
+// and the block index the transfer resolved to.
+// The transfer future is only awaited once the canister balance before it is known
+async fn _credit_transfer_in(
+    token: Principal,
+    amount: U256,
+    transfer: impl Future<Output = Result<Option<u64>, DepositError>>,
+) -> Result<(U256, Option<u64>), DepositError> {
+    let ledger = LedgerClient::new(token);
+    let settings = read_state(|s| s.get_token_settings(&token));
+
+    if settings.is_paused() {
+        return Err(DepositError::TokenPaused);
+    }
+
+    if !settings.is_balance_difference() {
+        let block_index = transfer.await?;
+        return Ok((amount, block_index));
+    }
+
+    // Serializes transfers of the token, so the measured difference only contains this deposit.
+    // Concurrent transfers of the token wait in line
+    let _token_guard = TokenGuard::acquire(token).await;
+
+    let balance_before = _canister_balance(&ledger)
+        .await
+        .map_err(DepositError::TemporarilyUnavailable)?;
+
+    let block_index = transfer.await?;
+
+    let received_amount = match _canister_balance(&ledger).await {
+        Ok(balance_after) => {
+            let received_amount = balance_after.saturating_sub(balance_before).min(amount);
+            // Settings are read again, the token might have been paused meanwhile
+            mutate_state(|s| {
+                let latest_settings = s.get_token_settings(&token);
+                s.set_token_settings(
                     token,
-                },
-                UserBalance(desired_user_balance),
-            );
+                    TokenSettings {
+                        transfer_haircut: observed_transfer_haircut(amount, received_amount),
+                        ..latest_settings
+                    },
+                )
+            });
+            received_amount
+        }
+        // The transfer went through, falls back to the last known haircut
+        Err(_) => apply_transfer_haircut(amount, settings.transfer_haircut),
+    };
+
+    log!(
+        DEBUG,
+        "Received {:?} of {:?} deposited {:?}",
+        received_amount,
+        amount,
+        token.to_text(),
+    );
+
+    Ok((received_amount, block_index))
+}
+
+// Sets the balance of the user after a deposit of `amount` and records the deposit
+fn _credit_deposit(
+    user: Principal,
+    token: Principal,
+    amount: U256,
+    block_index: Option<u64>,
+    memo: MemoKind,
+    balance: U256,
+) {
+    mutate_state(|s| {
+        s.update_user_balance(UserBalanceKey { user, token }, UserBalance(balance));
+        s.record_event(Event {
+            timestamp: ic_cdk::api::time(),
+            payload: EventType::Deposited {
+                token,
+                user,
+                amount,
+                block_index,
+                memo,
+                balance,
+            },
         });
-        return Ok(desired_user_balance);
+    });
+}
+
+// Transfer fee of `token` cached in the token registry, fetched from the ledger for tokens without
+// an entry. A stale cached fee is refreshed by the `BadFee` error of the transfer using it
+async fn _transfer_fee(token: Principal) -> Result<U256, WithdrawError> {
+    if let Some(transfer_fee) =
+        read_state(|s| s.get_token_metadata(&token)).and_then(|metadata| metadata.transfer_fee)
+    {
+        return Ok(transfer_fee);
     }
-    Ok(user_current_balance)
+
+    let transfer_fee = LedgerClient::new(token)
+        .icrc_fee()
+        .await
+        .map_err(|_| WithdrawError::FeeUnknown)?;
+    big_uint_to_u256(transfer_fee.0).map_err(|_| WithdrawError::FeeUnknown)
+}
+
+// Stores the transfer fee the ledger expects for `token` in all its pools and in the token registry
+// after a `BadFee` error
+fn _update_transfer_fee(token: Principal, transfer_fee: U256) {
+    mutate_state(|s| {
+        s.update_token_transfer_fee_across_all_pools(token, transfer_fee);
+        record_transfer_fee(s, token, transfer_fee);
+        s.record_event(Event {
+            timestamp: ic_cdk::api::time(),
+            payload: EventType::TransferFeeUpdated {
+                token,
+                transfer_fee,
+            },
+        });
+    });
+}
+
+// Reconciles the liabilities of every token with the canister balance on its ledger. The balance is
+// read around the computation of the liabilities and the larger read is kept, so transfers in
+// flight can not show up as a deficit
+async fn _reconcile_balances(tokens: Vec<Principal>) -> Vec<(Principal, TokenReconciliation)> {
+    let mut reconciliations = vec![];
+    for token in tokens {
+        let ledger = LedgerClient::new(token);
+        let balance_before = match _canister_balance(&ledger).await {
+            Ok(balance) => balance,
+            Err(err) => {
+                log!(INFO, "Failed to reconcile {:?}: {err}", token.to_text());
+                continue;
+            }
+        };
+        let liabilities = token_liabilities(token);
+        let balance_after = match _canister_balance(&ledger).await {
+            Ok(balance) => balance,
+            Err(err) => {
+                log!(INFO, "Failed to reconcile {:?}: {err}", token.to_text());
+                continue;
+            }
+        };
+
+        let reconciliation = record_reconciliation(
+            token,
+            liabilities,
+            balance_before.max(balance_after),
+            ic_cdk::api::time(),
+        );
+        reconciliations.push((token, reconciliation));
+    }
+    reconciliations
+}
+
+// Reads the canister balance on a token ledger
+async fn _canister_balance(ledger: &LedgerClient) -> Result<U256, String> {
+    let balance = ledger.canister_balance().await?;
+    big_uint_to_u256(balance.0).map_err(|_| "canister balance overflow".to_string())
 }
 
 // Refunds tokens to user, returns refunded amount after fees
@@ -868,16 +2342,10 @@ async fn _refund(
     token: Principal,
     amount: U256,
     to: &Account,
+    operation: WithdrawalEntry,
 ) -> Result<U256, WithdrawError> {
-    // Fetches transfer fee for refund calculation
-    let transfer_fee = big_uint_to_u256(
-        LedgerClient::new(token)
-            .icrc_fee()
-            .await
-            .map_err(|_| WithdrawError::FeeUnknown)?
-            .0,
-    )
-    .map_err(|_| WithdrawError::FeeUnknown)?;
+    // Transfer fee for refund calculation
+    let transfer_fee = _transfer_fee(token).await?;
 
     _withdraw(
         caller,
@@ -886,11 +2354,13 @@ async fn _refund(
         to,
         &mut WithdrawMemo::Refund { amount: U256::ZERO },
         transfer_fee,
+        operation,
     )
     .await
 }
 
-// Withdraws tokens, updates balance, handles transfer errors with rollback
+// Withdraws tokens, updates balance, handles transfer errors with rollback.
+// The withdrawal is journaled under `operation` so failed transfers can be retried
 async fn _withdraw(
     caller: Principal,
     token: Principal,
@@ -898,7 +2368,32 @@ async fn _withdraw(
     to: &Account,
     memo: &mut WithdrawMemo,
     transfer_fee: U256,
+    operation: WithdrawalEntry,
 ) -> Result<U256, WithdrawError> {
+    let ledger = LedgerClient::new(token);
+    let settings = read_state(|s| s.get_token_settings(&token));
+    if settings.is_paused() {
+        return Err(WithdrawError::TokenPaused);
+    }
+    let is_balance_difference = settings.is_balance_difference();
+
+    // Tokens with balance difference accounting are measured around the transfer, so nothing else
+    // should move the canister balance meanwhile, concurrent transfers of the token wait in line
+    let _token_guard = if is_balance_difference {
+        Some(TokenGuard::acquire(token).await)
+    } else {
+        None
+    };
+    let balance_before = if is_balance_difference {
+        Some(
+            _canister_balance(&ledger)
+                .await
+                .map_err(WithdrawError::TemporarilyUnavailable)?,
+        )
+    } else {
+        None
+    };
+
     let user_balance = get_user_balance(caller, token);
 
     log!(
@@ -935,28 +2430,80 @@ async fn _withdraw(
     let withdrawal_amount = amount - transfer_fee;
     let icrc_fee = u256_to_big_uint(transfer_fee);
     memo.set_amount(amount);
-    match LedgerClient::new(token)
-        .withdraw(
-            *to,
-            u256_to_big_uint(withdrawal_amount),
-            memo.clone(),
-            icrc_fee,
-        )
-        .await
-    {
-        Ok(_) => Ok(withdrawal_amount),
+    let dedup = record_withdrawal_sent(
+        operation,
+        JournalWithdrawal::new(token, to, amount, transfer_fee, memo.clone()),
+        ic_cdk::api::time(),
+    );
+    let (operation_id, withdrawal_index) = (dedup.operation_id, dedup.transfer_index as usize);
+
+    // Sends the identical transfer again while its outcome is unknown, the ledger deduplicates it
+    let mut attempts = 0;
+    let result = loop {
+        attempts += 1;
+        match ledger
+            .withdraw(
+                *to,
+                u256_to_big_uint(withdrawal_amount),
+                memo.clone(),
+                icrc_fee.clone(),
+                &dedup,
+            )
+            .await
+        {
+            Err(LedgerTransferError::OutcomeUnknown { .. }) if attempts < MAX_TRANSFER_ATTEMPTS => {
+                continue
+            }
+            result => break result,
+        }
+    };
+
+    match result {
+        Ok(block_index) => {
+            record_withdrawal_completed(
+                operation_id,
+                withdrawal_index,
+                block_index.as_u64().unwrap_or_default(),
+                ic_cdk::api::time(),
+            );
+            let (charged, uncovered) = match balance_before {
+                Some(balance_before) => {
+                    _settle_transfer_out(caller, token, &ledger, amount, balance_before).await
+                }
+                None => (U256::ZERO, U256::ZERO),
+            };
+            _record_withdrawal(
+                caller,
+                token,
+                amount.saturating_add(charged),
+                transfer_fee,
+                block_index.as_u64(),
+                memo.kind(),
+                (uncovered > U256::ZERO).then_some(uncovered),
+            );
+            Ok(withdrawal_amount)
+        }
+        Err(err @ LedgerTransferError::OutcomeUnknown { .. }) => {
+            // The transfer might have gone through, the amount stays debited until the timer
+            // resolves the outcome by sending the identical transfer again
+            record_withdrawal_outcome_unknown(
+                operation_id,
+                withdrawal_index,
+                format!("{err:?}"),
+                ic_cdk::api::time(),
+            );
+            _record_withdrawal(caller, token, amount, transfer_fee, None, memo.kind(), None);
+            Err(WithdrawError::OutcomeUnknown { operation_id })
+        }
         Err(err) => {
             // Restores balance on transfer failure
-            let latest_user_balance = get_user_balance(caller, token);
-            mutate_state(|s| {
-                s.update_user_balance(
-                    UserBalanceKey {
-                        user: caller,
-                        token,
-                    },
-                    UserBalance(latest_user_balance.checked_add(amount).unwrap_or(U256::MAX)),
-                );
-            });
+            _rollback_withdrawal(caller, token, amount, memo.kind(), format!("{err:?}"));
+            record_withdrawal_failed(
+                operation_id,
+                withdrawal_index,
+                format!("{err:?}"),
+                ic_cdk::api::time(),
+            );
 
             // Handles fee mismatch by updating pool fees
             match err {
@@ -965,9 +2512,7 @@ async fn _withdraw(
                         big_uint_to_u256(expected_fee.0).map_err(|_| WithdrawError::FeeUnknown)?;
 
                     // Updates transfer fee across all pools for consistency
-                    mutate_state(|s| {
-                        s.update_token_transfer_fee_across_all_pools(token, new_transfer_fee)
-                    });
+                    _update_transfer_fee(token, new_transfer_fee);
                     Err(WithdrawError::FeeUnknown)
                 }
                 _ => Err(err.into()),
@@ -976,6 +2521,406 @@ async fn _withdraw(
     }
 }
 
+// Records a withdrawal of `amount` already debited from the user balance, `uncovered_cost` is what
+// the transfer cost on top of it that the user balance could not cover
+fn _record_withdrawal(
+    user: Principal,
+    token: Principal,
+    amount: U256,
+    transfer_fee: U256,
+    block_index: Option<u64>,
+    memo: MemoKind,
+    uncovered_cost: Option<U256>,
+) {
+    let balance = get_user_balance(user, token);
+    mutate_state(|s| {
+        s.record_event(Event {
+            timestamp: ic_cdk::api::time(),
+            payload: EventType::Withdrawn {
+                token,
+                user,
+                amount,
+                transfer_fee,
+                block_index,
+                memo,
+                balance,
+                uncovered_cost,
+            },
+        })
+    });
+}
+
+// Credits back a withdrawal the ledger rejected, caps at U256::MAX to prevent overflow
+fn _rollback_withdrawal(
+    user: Principal,
+    token: Principal,
+    amount: U256,
+    memo: MemoKind,
+    reason: String,
+) {
+    let balance = get_user_balance(user, token)
+        .checked_add(amount)
+        .unwrap_or(U256::MAX);
+    mutate_state(|s| {
+        s.update_user_balance(UserBalanceKey { user, token }, UserBalance(balance));
+        s.record_event(Event {
+            timestamp: ic_cdk::api::time(),
+            payload: EventType::WithdrawalRolledBack {
+                token,
+                user,
+                amount,
+                memo,
+                balance,
+                reason,
+            },
+        });
+    });
+}
+
+// Charges the user for anything the ledger took from the canister on top of the withdrawn amount,
+// so transfer burns of fee-on-transfer tokens never come out of other users' funds. Returns the
+// charged amount and the part of the excess the user balance could not cover, which is left as a
+// deficit of the token
+async fn _settle_transfer_out(
+    caller: Principal,
+    token: Principal,
+    ledger: &LedgerClient,
+    amount: U256,
+    balance_before: U256,
+) -> (U256, U256) {
+    let balance_after = match _canister_balance(ledger).await {
+        Ok(balance) => balance,
+        Err(err) => {
+            log!(
+                DEBUG,
+                "Failed to measure withdrawal of {:?}: {err}",
+                token.to_text()
+            );
+            return (U256::ZERO, U256::ZERO);
+        }
+    };
+
+    let spent = balance_before.saturating_sub(balance_after);
+    if spent <= amount {
+        return (U256::ZERO, U256::ZERO);
+    }
+
+    let excess = spent - amount;
+    let latest_user_balance = get_user_balance(caller, token);
+    log!(
+        DEBUG,
+        "Withdrawal of {:?} cost {:?} more than expected, charging user {:?}",
+        token.to_text(),
+        excess,
+        caller.to_text(),
+    );
+    let charged = excess.min(latest_user_balance);
+    let uncovered = excess - charged;
+    if uncovered > U256::ZERO {
+        log!(
+            INFO,
+            "Withdrawal of {:?} by {:?} left a deficit of {:?} the user balance could not cover",
+            token.to_text(),
+            caller.to_text(),
+            uncovered,
+        );
+    }
+    mutate_state(|s| {
+        s.update_user_balance(
+            UserBalanceKey {
+                user: caller,
+                token,
+            },
+            UserBalance(latest_user_balance - charged),
+        );
+    });
+    (charged, uncovered)
+}
+
+// Sends deposits with an unknown outcome again and credits the users once the ledger tells
+// whether they went through, called by a timer
+async fn settle_pending_deposits() {
+    for (operation_id, owner) in deposits_due(ic_cdk::api::time()) {
+        // Skips users with a call in progress, the deposit is picked up by the next run
+        let _principal_guard = match PrincipalGuard::new_general_guard(owner) {
+            Ok(guard) => guard,
+            Err(_) => continue,
+        };
+
+        // An earlier run might have resolved this deposit meanwhile
+        let Some(deposit) = get_due_deposit(operation_id, ic_cdk::api::time()) else {
+            continue;
+        };
+
+        _resend_deposit(owner, operation_id, deposit).await;
+        finish_operation(operation_id);
+    }
+}
+
+// Sends a deposit with an unknown outcome again with the same created_at_time, memo and amount,
+// the ledger either executes it or reports the block of the earlier transfer as a duplicate
+async fn _resend_deposit(owner: Principal, operation_id: OperationId, deposit: PendingDeposit) {
+    let token = deposit.token;
+    let ledger = LedgerClient::new(token);
+    let dedup = deposit.dedup(operation_id);
+    // The resent transfer should not show up in the canister balance another call is measuring
+    let settings = read_state(|s| s.get_token_settings(&token));
+    let _token_guard = if settings.is_balance_difference() {
+        Some(TokenGuard::acquire(token).await)
+    } else {
+        None
+    };
+
+    record_deposit_resent(operation_id, ic_cdk::api::time());
+    let result = match deposit.source {
+        DepositSource::TransferFrom { owner, subaccount } => {
+            ledger
+                .deposit(
+                    Account { owner, subaccount },
+                    u256_to_big_uint(deposit.amount),
+                    deposit.memo.clone(),
+                    &dedup,
+                )
+                .await
+        }
+        DepositSource::Subaccount {
+            subaccount,
+            transfer_fee,
+        } => {
+            ledger
+                .sweep_subaccount(
+                    subaccount,
+                    u256_to_big_uint(deposit.amount),
+                    deposit.memo.clone(),
+                    u256_to_big_uint(transfer_fee),
+                    &dedup,
+                )
+                .await
+        }
+    };
+
+    let now = ic_cdk::api::time();
+    match result {
+        Ok(block_index) => {
+            // Other transfers moved the canister balance since, tokens with balance difference
+            // accounting are credited with the last known haircut
+            let received_amount = if settings.is_balance_difference() {
+                apply_transfer_haircut(deposit.amount, settings.transfer_haircut)
+            } else {
+                deposit.amount
+            };
+            let balance = get_user_balance(owner, token)
+                .checked_add(received_amount)
+                .unwrap_or(U256::MAX);
+            _credit_deposit(
+                owner,
+                token,
+                received_amount,
+                block_index.as_u64(),
+                deposit.memo.kind(),
+                balance,
+            );
+            record_deposit(operation_id, token, received_amount, now);
+            record_deposit_resolved(operation_id, now);
+        }
+        Err(
+            LedgerTransferError::OutcomeUnknown { message, .. }
+            | LedgerTransferError::TemporarilyUnavailable { message, .. },
+        ) => record_deposit_outcome_unknown(operation_id, message, now),
+        Err(LedgerTransferError::BadFee { expected_fee }) => {
+            // The earlier sweep might have gone through before the fee changed, the outcome stays
+            // unknown
+            if let Ok(new_transfer_fee) = big_uint_to_u256(expected_fee.0) {
+                _update_transfer_fee(token, new_transfer_fee);
+            }
+            record_deposit_outcome_unknown(operation_id, "fee changed".to_string(), now)
+        }
+        Err(err @ LedgerTransferError::TooOld { .. }) => {
+            record_deposit_unresolved(operation_id, format!("{err:?}"), now)
+        }
+        Err(err) => {
+            // The ledger rejected the transfer, so the earlier one did not go through either
+            log!(
+                DEBUG,
+                "Pending deposit of operation {operation_id} was not executed: {err:?}"
+            );
+            record_deposit_resolved(operation_id, now);
+        }
+    }
+}
+
+// Retries withdrawals of journaled operations that failed and are due, called by a timer.
+// Failed transfers were credited back to the user, so their retry only goes out if the amount is
+// still in the user balance. Transfers with an unknown outcome are still debited and sent again
+// with identical arguments
+async fn retry_pending_withdrawals() {
+    for (operation_id, index, owner) in withdrawals_due(ic_cdk::api::time()) {
+        // Skips users with a call in progress, the withdrawal is picked up by the next run
+        let _principal_guard = match PrincipalGuard::new_general_guard(owner) {
+            Ok(guard) => guard,
+            Err(_) => continue,
+        };
+
+        // An earlier run might have retried this withdrawal meanwhile
+        let Some(withdrawal) = get_due_withdrawal(operation_id, index, ic_cdk::api::time()) else {
+            continue;
+        };
+
+        if withdrawal.is_outcome_unknown() {
+            _resend_withdrawal(owner, operation_id, index, withdrawal).await;
+            finish_operation(operation_id);
+            continue;
+        }
+
+        let token = withdrawal.token;
+        // Stays due until the token is unpaused
+        if is_token_paused(&token) {
+            continue;
+        }
+
+        if get_user_balance(owner, token) < withdrawal.amount {
+            record_withdrawal_abandoned(
+                operation_id,
+                index,
+                WithdrawalStatus::Cancelled,
+                None,
+                ic_cdk::api::time(),
+            );
+            finish_operation(operation_id);
+            continue;
+        }
+
+        // Transfer fee might have changed since the failed attempt
+        let transfer_fee = match LedgerClient::new(token).icrc_fee().await {
+            Ok(fee) => big_uint_to_u256(fee.0).unwrap_or(withdrawal.transfer_fee),
+            Err(err) => {
+                record_withdrawal_failed(operation_id, index, err, ic_cdk::api::time());
+                continue;
+            }
+        };
+
+        let result = _withdraw(
+            owner,
+            token,
+            withdrawal.amount,
+            &withdrawal.to(),
+            &mut withdrawal.memo.clone(),
+            transfer_fee,
+            WithdrawalEntry::Retry(operation_id, index),
+        )
+        .await;
+
+        // The amount no longer covers the transfer fee, nothing to retry anymore
+        if let Err(err @ WithdrawError::AmountTooLow { .. }) = result {
+            record_withdrawal_abandoned(
+                operation_id,
+                index,
+                WithdrawalStatus::Failed,
+                Some(format!("{err:?}")),
+                ic_cdk::api::time(),
+            );
+        }
+
+        finish_operation(operation_id);
+    }
+}
+
+// Sends a withdrawal with an unknown outcome again with the same created_at_time, memo, amount and
+// fee, the ledger either executes it or reports the block of the earlier transfer as a duplicate
+async fn _resend_withdrawal(
+    owner: Principal,
+    operation_id: OperationId,
+    index: usize,
+    withdrawal: JournalWithdrawal,
+) {
+    let token = withdrawal.token;
+    // The resent transfer should not show up in the canister balance another call is measuring
+    let _token_guard = if read_state(|s| s.get_token_settings(&token)).is_balance_difference() {
+        Some(TokenGuard::acquire(token).await)
+    } else {
+        None
+    };
+    let Some(dedup) = withdrawal.dedup(operation_id, index) else {
+        // Sent without deduplication, sending it again could pay twice
+        record_withdrawal_abandoned(
+            operation_id,
+            index,
+            WithdrawalStatus::Unresolved,
+            Some("transfer sent without deduplication".to_string()),
+            ic_cdk::api::time(),
+        );
+        return;
+    };
+
+    record_withdrawal_resent(operation_id, index, ic_cdk::api::time());
+    let result = LedgerClient::new(token)
+        .withdraw(
+            withdrawal.to(),
+            u256_to_big_uint(withdrawal.amount - withdrawal.transfer_fee),
+            withdrawal.memo.clone(),
+            u256_to_big_uint(withdrawal.transfer_fee),
+            &dedup,
+        )
+        .await;
+
+    let now = ic_cdk::api::time();
+    match result {
+        Ok(block_index) => record_withdrawal_completed(
+            operation_id,
+            index,
+            block_index.as_u64().unwrap_or_default(),
+            now,
+        ),
+        // The ledger did not check the transfer against earlier ones
+        Err(
+            LedgerTransferError::OutcomeUnknown { message, .. }
+            | LedgerTransferError::TemporarilyUnavailable { message, .. },
+        ) => record_withdrawal_outcome_unknown(operation_id, index, message, now),
+        Err(LedgerTransferError::BadFee { expected_fee }) => {
+            // The earlier transfer might have gone through before the fee changed, the outcome
+            // stays unknown
+            if let Ok(new_transfer_fee) = big_uint_to_u256(expected_fee.0) {
+                _update_transfer_fee(token, new_transfer_fee);
+            }
+            record_withdrawal_outcome_unknown(operation_id, index, "fee changed".to_string(), now)
+        }
+        Err(err @ LedgerTransferError::TooOld { .. }) => record_withdrawal_abandoned(
+            operation_id,
+            index,
+            WithdrawalStatus::Unresolved,
+            Some(format!("{err:?}")),
+            now,
+        ),
+        Err(err) => {
+            // The ledger rejected the transfer, so the earlier one did not go through either
+            _rollback_withdrawal(
+                owner,
+                token,
+                withdrawal.amount,
+                withdrawal.memo.kind(),
+                format!("{err:?}"),
+            );
+            record_withdrawal_failed(operation_id, index, format!("{err:?}"), now);
+        }
+    }
+}
+
+// Lists operations of a user that still have a withdrawal in flight or waiting for a retry
+#[query]
+fn get_pending_operations(user: Principal) -> Vec<CandidOperation> {
+    journal::get_pending_operations(user)
+        .into_iter()
+        .map(CandidOperation::from)
+        .collect()
+}
+
+// Returns a journaled operation by id, open or closed
+#[query]
+fn get_operation(operation_id: u64) -> Option<CandidOperation> {
+    journal::get_operation(operation_id)
+        .map(|operation| CandidOperation::from((operation_id, operation)))
+}
+
 // Retrieves user's token balance from state
 pub fn get_user_balance(user: Principal, token: Principal) -> U256 {
     read_state(|s| s.get_user_balance(&UserBalanceKey { user, token }).0)
@@ -1,23 +1,41 @@
-use candid::Principal;
+use candid::{Nat, Principal};
 use ethnum::{I256, U256};
 use num_traits::ToPrimitive;
 
 use crate::{
     balances::types::{UserBalance, UserBalanceKey},
-    candid_types::position::MintPositionError,
+    candid_types::{
+        pool::{CreatePoolAndMintArgs, CreatePoolAndMintError, CreatePoolError},
+        position::{MintPositionArgs, MintPositionError},
+    },
     events::{Event, EventType},
+    historical::{pools_before, update_pool_history},
     libraries::{
         balance_delta::BalanceDelta, liquidity_amounts, slippage_check::validate_max_in,
         tick_math::TickMath,
     },
     pool::{
-        modify_liquidity::{modify_liquidity, ModifyLiquidityError, ModifyLiquidityParams},
-        types::PoolId,
+        create_pool::{creation_fee_not_paid, record_pool_creation},
+        modify_liquidity::{
+            modify_liquidity, ModifyLiquidityBufferState, ModifyLiquidityError,
+            ModifyLiquidityParams,
+        },
+        policy::{charge_creation_fee, refund_creation_fee, PoolCreationPolicy},
+        types::{PoolId, PoolState},
     },
-    state::{mutate_state, read_state},
-    validation::mint_args::ValidatedMintPositionArgs,
+    state::{mutate_state, read_state, State},
+    validation::mint_args::{validate_mint_position_args, ValidatedMintPositionArgs},
 };
 
+/// A mint computed against the current state, applied by `apply_mint_position`.
+#[derive(Clone)]
+pub struct PreparedMint {
+    pub liquidity: u128,
+    balances: [(UserBalanceKey, UserBalance); 2],
+    buffer_state: ModifyLiquidityBufferState,
+    event: Event,
+}
+
 /// Executes the minting logic by computing liquidity and updating pool state.
 pub fn execute_mint_position(
     caller: Principal,
@@ -27,6 +45,22 @@ pub fn execute_mint_position(
     validated_args: ValidatedMintPositionArgs,
     timestamp: u64,
 ) -> Result<u128, MintPositionError> {
+    let prepared =
+        prepare_mint_position(caller, pool_id, token0, token1, validated_args, timestamp)?;
+    let liquidity = prepared.liquidity;
+    mutate_state(|s| apply_mint_position(s, prepared));
+    Ok(liquidity)
+}
+
+/// Computes the liquidity, balances and pool state after a mint without changing the state.
+pub fn prepare_mint_position(
+    caller: Principal,
+    pool_id: PoolId,
+    token0: Principal,
+    token1: Principal,
+    validated_args: ValidatedMintPositionArgs,
+    timestamp: u64,
+) -> Result<PreparedMint, MintPositionError> {
     // Fetch pool state
     let pool = read_state(|s| s.get_pool(&pool_id)).ok_or(MintPositionError::PoolNotInitialized)?;
 
@@ -51,8 +85,6 @@ pub fn execute_mint_position(
 
     let success_result = modify_liquidity(modify_params).map_err(map_modify_liquidity_error)?;
 
-    println!("{:?}", success_result);
-
     // Update user balances
     let user_balance = read_state(|s| {
         BalanceDelta::new(
@@ -85,6 +117,12 @@ pub fn execute_mint_position(
         .add(success_result.balance_delta)
         .map_err(|_| MintPositionError::AmountOverflow)?;
 
+    // balances are only deposited up to the max amounts by callers that deposit, others mint from
+    // the existing balance
+    if final_balance.amount0().is_negative() || final_balance.amount1().is_negative() {
+        return Err(MintPositionError::InsufficientBalance);
+    }
+
     // safe operation no overflow can happen since the balance_delta is always negative
     let amount0_paid = success_result.balance_delta.amount0().abs().as_u256();
     let amount1_paid = success_result.balance_delta.amount1().abs().as_u256();
@@ -100,29 +138,116 @@ pub fn execute_mint_position(
         },
     };
 
-    //Batch state updates
-    mutate_state(|s| {
-        s.update_user_balance(
-            UserBalanceKey {
-                user: caller,
-                token: token0,
-            },
-            UserBalance(final_balance.amount0().as_u256()),
-        );
-        s.update_user_balance(
-            UserBalanceKey {
-                user: caller,
-                token: token1,
-            },
-            UserBalance(final_balance.amount1().as_u256()),
-        );
+    Ok(PreparedMint {
+        liquidity: liquidity_delta as u128,
+        balances: [
+            (
+                UserBalanceKey {
+                    user: caller,
+                    token: token0,
+                },
+                UserBalance(final_balance.amount0().as_u256()),
+            ),
+            (
+                UserBalanceKey {
+                    user: caller,
+                    token: token1,
+                },
+                UserBalance(final_balance.amount1().as_u256()),
+            ),
+        ],
+        buffer_state: success_result.buffer_state,
+        event,
+    })
+}
 
-        s.apply_modify_liquidity_buffer_state(success_result.buffer_state);
+/// Applies a mint prepared against the current state.
+pub fn apply_mint_position(s: &mut State, prepared: PreparedMint) {
+    for (key, balance) in prepared.balances {
+        s.update_user_balance(key, balance);
+    }
 
-        s.record_event(event);
-    });
+    let pools_before = pools_before(s, &prepared.event);
+    s.apply_modify_liquidity_buffer_state(prepared.buffer_state);
+    update_pool_history(s, pools_before, &prepared.event);
+
+    s.record_event(prepared.event);
+}
+
+/// Creates a pool built by `new_pool` and mints its first position from the balance of the creator
+/// in one state transition. The pool is staged so the mint can be computed against it and removed
+/// again, with the creation fee refunded, when the mint fails or mints less than
+/// `min_initial_liquidity`.
+pub fn execute_create_pool_and_mint(
+    caller: Principal,
+    pool_id: PoolId,
+    pool_state: PoolState,
+    args: CreatePoolAndMintArgs,
+    policy: &PoolCreationPolicy,
+    timestamp: u64,
+) -> Result<u128, CreatePoolAndMintError> {
+    // the pool may have been created while the caller was awaiting
+    if read_state(|s| s.get_pool(&pool_id)).is_some() {
+        return Err(CreatePoolAndMintError::CreatePool(
+            CreatePoolError::PoolAlreadyExists,
+        ));
+    }
 
-    Ok(liquidity_delta as u128)
+    let creation_fee = mutate_state(|s| charge_creation_fee(s, policy, caller))
+        .map_err(|fee| CreatePoolAndMintError::CreatePool(creation_fee_not_paid(fee)))?;
+
+    mutate_state(|s| s.set_pool(pool_id.clone(), pool_state.clone()));
+
+    // amounts follow the sorted tokens of the pool
+    let mint_args = MintPositionArgs {
+        pool: pool_id.clone().into(),
+        tick_lower: args.tick_lower,
+        tick_upper: args.tick_upper,
+        amount0_max: args.amount0_max,
+        amount1_max: args.amount1_max,
+        from_subaccount: None,
+        request_id: None,
+    };
+    let prepared = validate_mint_position_args(mint_args, caller)
+        .and_then(|validated_args| {
+            prepare_mint_position(
+                caller,
+                pool_id.clone(),
+                pool_id.token0,
+                pool_id.token1,
+                validated_args,
+                timestamp,
+            )
+        })
+        .map_err(CreatePoolAndMintError::MintPosition)
+        .and_then(|prepared| match policy.min_initial_liquidity {
+            Some(min_liquidity) if prepared.liquidity < min_liquidity => {
+                Err(CreatePoolAndMintError::LiquidityBelowMinimum {
+                    min_liquidity: Nat::from(min_liquidity),
+                })
+            }
+            _ => Ok(prepared),
+        });
+
+    let prepared = match prepared {
+        Ok(prepared) => prepared,
+        Err(error) => {
+            mutate_state(|s| {
+                s.remove_pool(&pool_id);
+                if let Some(fee) = creation_fee {
+                    refund_creation_fee(s, fee, caller);
+                }
+            });
+            return Err(error);
+        }
+    };
+
+    let liquidity = prepared.liquidity;
+    mutate_state(|s| {
+        record_pool_creation(s, pool_id.clone(), pool_state, creation_fee, timestamp);
+        apply_mint_position(s, prepared);
+    });
+    Ok(liquidity)
 }
 
 /// Computes liquidity for the given amounts and tick range.
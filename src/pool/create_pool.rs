@@ -1,25 +1,45 @@
+use candid::Principal;
 use ethnum::U256;
 
 use crate::{
     candid_types::pool::{CreatePoolArgs, CreatePoolError},
-    events::Event,
+    events::{Event, EventType},
+    historical::aggregates::record_created_pool,
     libraries::{
         constants::{DEFAULT_PROTOCOL_FEE, MAX_SQRT_RATIO, MIN_SQRT_RATIO},
-        safe_cast::big_uint_to_u256,
+        safe_cast::{big_uint_to_u256, u256_to_nat},
         tick_math::TickMath,
     },
-    state::{mutate_state, read_state},
+    state::{mutate_state, read_state, State},
     tick::tick_spacing_to_max_liquidity_per_tick,
 };
 
-use super::types::{PoolFee, PoolId, PoolState};
+use super::{
+    policy::CreationFee,
+    types::{PoolFee, PoolId, PoolState},
+};
 
 pub fn create_pool_inner(
     args: CreatePoolArgs,
     token_a_transfer_fee: U256,
     token_b_transfer_fee: U256,
+    creator: Option<Principal>,
+    creation_fee: Option<CreationFee>,
     timestamp: u64,
 ) -> Result<PoolId, CreatePoolError> {
+    let (pool_id, pool_state) =
+        new_pool(args, token_a_transfer_fee, token_b_transfer_fee, creator)?;
+    mutate_state(|s| record_pool_creation(s, pool_id.clone(), pool_state, creation_fee, timestamp));
+    Ok(pool_id)
+}
+
+/// Validates the arguments and builds the state of a new pool without storing it.
+pub fn new_pool(
+    args: CreatePoolArgs,
+    token_a_transfer_fee: U256,
+    token_b_transfer_fee: U256,
+    creator: Option<Principal>,
+) -> Result<(PoolId, PoolState), CreatePoolError> {
     let sqrt_price_x96 = big_uint_to_u256(args.sqrt_price_x96.0)
         .map_err(|_e| CreatePoolError::InvalidSqrtPriceX96)?;
 
@@ -74,21 +94,43 @@ pub fn create_pool_inner(
         pool_reserve1: U256::ZERO,
         generated_swap_fee0: U256::ZERO,
         generated_swap_fee1: U256::ZERO,
+        creator,
     };
 
+    Ok((pool_id, pool_state))
+}
+
+/// Stores a pool built by `new_pool` with its history and `CreatedPool` event, a pool staged with
+/// `set_pool` while its first position is minted is overwritten.
+pub fn record_pool_creation(
+    s: &mut State,
+    pool_id: PoolId,
+    pool_state: PoolState,
+    creation_fee: Option<CreationFee>,
+    timestamp: u64,
+) {
     let event = Event {
         timestamp,
-        payload: crate::events::EventType::CreatedPool {
-            token0,
-            token1,
-            pool_fee: fee.0,
+        payload: EventType::CreatedPool {
+            token0: pool_id.token0,
+            token1: pool_id.token1,
+            pool_fee: pool_id.fee.0,
+            sqrt_price_x96: Some(pool_state.sqrt_price_x96),
+            token0_transfer_fee: Some(pool_state.token0_transfer_fee),
+            token1_transfer_fee: Some(pool_state.token1_transfer_fee),
+            creator: pool_state.creator,
+            creation_fee,
         },
     };
 
-    mutate_state(|s| {
-        s.set_pool(pool_id.clone(), pool_state);
-        s.record_event(event);
-    });
+    s.set_pool(pool_id.clone(), pool_state);
+    record_created_pool(s, &pool_id);
+    s.record_event(event);
+}
 
-    return Ok(pool_id);
+pub fn creation_fee_not_paid(fee: CreationFee) -> CreatePoolError {
+    CreatePoolError::CreationFeeNotPaid {
+        token: fee.token,
+        amount: u256_to_nat(fee.amount),
+    }
 }
@@ -0,0 +1,295 @@
+// Dynamic fee mode, instead of charging the static fee tier of the pool id, the LP fee follows the
+// recent volatility of the pool. Volatility is measured as the average absolute tick movement
+// between the hourly history buckets, so a calm pool charges close to `min_fee` and a volatile
+// pool approaches `max_fee`.
+// The closing ticks of the closed hourly buckets are kept in a `VolatilityWindow` rolled over as
+// the buckets open, a swap only reads the window and the latest hourly bucket.
+
+use crate::{
+    historical::{types::HistoryBucketKey, TimeFrame},
+    state::{read_state, State},
+};
+
+use super::types::{DynamicFeeConfig, PoolId, PoolState, VolatilityWindow};
+
+/// Highest LP fee a dynamic fee pool can charge, 10% in pips.
+pub const MAX_DYNAMIC_LP_FEE: u32 = 100_000;
+
+/// Lookback is limited by the number of hourly buckets kept in history.
+pub const MAX_LOOKBACK_HOURS: u32 = 48;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DynamicFeeConfigError {
+    InvalidFeeBounds,
+    InvalidLookback,
+}
+
+pub fn validate_dynamic_fee_config(config: &DynamicFeeConfig) -> Result<(), DynamicFeeConfigError> {
+    if config.min_fee > config.max_fee || config.max_fee > MAX_DYNAMIC_LP_FEE {
+        return Err(DynamicFeeConfigError::InvalidFeeBounds);
+    }
+
+    if config.lookback_hours == 0 || config.lookback_hours > MAX_LOOKBACK_HOURS {
+        return Err(DynamicFeeConfigError::InvalidLookback);
+    }
+
+    Ok(())
+}
+
+/// Returns the LP fee in pips charged by a swap in the given pool, for static pools this is the
+/// fee tier of the pool id.
+pub fn effective_lp_fee(pool_id: &PoolId, pool_state: &PoolState) -> u32 {
+    read_state(|s| match s.get_dynamic_fee_config(pool_id) {
+        Some(config) => {
+            let window = s.get_volatility_window(pool_id).unwrap_or_default();
+            let latest_tick = window.open_bucket_start.and_then(|start_timestamp| {
+                s.get_history_bucket(&hourly_bucket_key(pool_id, start_timestamp))
+                    .map(|bucket| bucket.active_tick)
+            });
+            window.lp_fee(&config, latest_tick, pool_state.tick)
+        }
+        None => pool_id.fee.0,
+    })
+}
+
+/// Sets or clears the dynamic fee config of a pool along with its volatility window, the window
+/// is rebuilt from the hourly buckets since the lookback may have changed.
+pub fn apply_dynamic_fee_config(s: &mut State, pool_id: PoolId, config: Option<DynamicFeeConfig>) {
+    s.set_dynamic_fee_config(pool_id.clone(), config);
+    let window = config.map(|config| build_volatility_window(s, &pool_id, &config));
+    s.set_volatility_window(pool_id, window);
+}
+
+/// Builds the windows of dynamic fee pools configured before windows were kept, runs on upgrade.
+pub fn seed_volatility_windows(s: &mut State) {
+    for (pool_id, config) in s.get_dynamic_fee_configs() {
+        if s.get_volatility_window(&pool_id).is_none() {
+            let window = build_volatility_window(s, &pool_id, &config);
+            s.set_volatility_window(pool_id, Some(window));
+        }
+    }
+}
+
+/// Closes the latest hourly bucket of a dynamic fee pool in its window when the bucket starting at
+/// `start_timestamp` opens.
+pub fn roll_volatility_window(s: &mut State, pool_id: &PoolId, start_timestamp: u64) {
+    let Some(config) = s.get_dynamic_fee_config(pool_id) else {
+        return;
+    };
+    let mut window = s.get_volatility_window(pool_id).unwrap_or_default();
+    match window.open_bucket_start {
+        // a replaced bucket closes nothing
+        Some(open_start) if open_start >= start_timestamp => return,
+        Some(open_start) => {
+            if let Some(closed) = s.get_history_bucket(&hourly_bucket_key(pool_id, open_start)) {
+                window.close_tick(closed.active_tick, config.lookback_hours);
+            }
+        }
+        None => {}
+    }
+    window.open_bucket_start = Some(start_timestamp);
+    s.set_volatility_window(pool_id.clone(), Some(window));
+}
+
+/// Window over the latest `lookback_hours` hourly buckets, the last of them being the open one.
+fn build_volatility_window(
+    s: &State,
+    pool_id: &PoolId,
+    config: &DynamicFeeConfig,
+) -> VolatilityWindow {
+    let hourly_frame = s.get_history_buckets(pool_id, TimeFrame::Hourly, 0, u64::MAX);
+    let mut window = VolatilityWindow::default();
+    let skip = hourly_frame
+        .len()
+        .saturating_sub(config.lookback_hours as usize);
+    if let Some((open, closed)) = hourly_frame[skip..].split_last() {
+        for bucket in closed {
+            window.close_tick(bucket.active_tick, config.lookback_hours);
+        }
+        window.open_bucket_start = Some(open.start_timestamp);
+    }
+    window
+}
+
+fn hourly_bucket_key(pool_id: &PoolId, start_timestamp: u64) -> HistoryBucketKey {
+    HistoryBucketKey {
+        pool_id: pool_id.clone(),
+        timeframe: TimeFrame::Hourly,
+        start_timestamp,
+    }
+}
+
+impl VolatilityWindow {
+    /// Appends the closing tick of a bucket, dropping the oldest beyond `lookback_hours - 1` so
+    /// that with the open bucket the window covers `lookback_hours` buckets.
+    pub fn close_tick(&mut self, tick: i32, lookback_hours: u32) {
+        if let Some(&last) = self.closed_ticks.last() {
+            self.movement = self.movement.saturating_add(tick_distance(last, tick));
+        }
+        self.closed_ticks.push(tick);
+
+        let max_closed = lookback_hours.saturating_sub(1) as usize;
+        while self.closed_ticks.len() > max_closed {
+            let oldest = self.closed_ticks.remove(0);
+            if let Some(&next) = self.closed_ticks.first() {
+                self.movement = self.movement.saturating_sub(tick_distance(oldest, next));
+            }
+        }
+    }
+
+    /// LP fee from the closed ticks followed by the tick of the open bucket, if any, and the
+    /// current tick of the pool, the same observations as `compute_dynamic_fee`.
+    pub fn lp_fee(
+        &self,
+        config: &DynamicFeeConfig,
+        latest_tick: Option<i32>,
+        current_tick: i32,
+    ) -> u32 {
+        let mut total = self.movement;
+        let mut moves = self.closed_ticks.len().saturating_sub(1) as u64;
+        let mut previous = self.closed_ticks.last().copied();
+        for tick in latest_tick.into_iter().chain(std::iter::once(current_tick)) {
+            if let Some(from) = previous {
+                total = total.saturating_add(tick_distance(from, tick));
+                moves += 1;
+            }
+            previous = Some(tick);
+        }
+
+        let movement = if moves == 0 { 0 } else { total / moves };
+        fee_for_movement(config, movement)
+    }
+}
+
+/// Calculates the LP fee from the observed ticks and the current tick of the pool, clamped
+/// between `min_fee` and `max_fee`.
+pub fn compute_dynamic_fee(
+    config: &DynamicFeeConfig,
+    observed_ticks: &[i32],
+    current_tick: i32,
+) -> u32 {
+    let mut observations = observed_ticks.to_vec();
+    observations.push(current_tick);
+
+    fee_for_movement(config, average_tick_movement(&observations))
+}
+
+/// `min_fee` plus `fee_per_tick` for every tick of average movement, clamped to `max_fee`.
+fn fee_for_movement(config: &DynamicFeeConfig, movement: u64) -> u32 {
+    let fee =
+        (config.min_fee as u64).saturating_add(movement.saturating_mul(config.fee_per_tick as u64));
+
+    fee.clamp(config.min_fee as u64, config.max_fee as u64) as u32
+}
+
+fn tick_distance(from: i32, to: i32) -> u64 {
+    (to as i64 - from as i64).unsigned_abs()
+}
+
+/// Average absolute tick change between consecutive observations, zero if there is less than
+/// two observations.
+fn average_tick_movement(observations: &[i32]) -> u64 {
+    if observations.len() < 2 {
+        return 0;
+    }
+
+    let total: u64 = observations
+        .windows(2)
+        .map(|pair| tick_distance(pair[0], pair[1]))
+        .sum();
+
+    total / (observations.len() as u64 - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DynamicFeeConfig {
+        DynamicFeeConfig {
+            min_fee: 500,
+            max_fee: 10_000,
+            fee_per_tick: 10,
+            lookback_hours: 24,
+        }
+    }
+
+    #[test]
+    fn should_charge_min_fee_without_movement() {
+        assert_eq!(compute_dynamic_fee(&config(), &[], 100), 500);
+        assert_eq!(compute_dynamic_fee(&config(), &[100, 100, 100], 100), 500);
+    }
+
+    #[test]
+    fn should_increase_fee_with_tick_movement() {
+        // movements: 100, 100, 200 -> average 133 ticks
+        assert_eq!(
+            compute_dynamic_fee(&config(), &[0, 100, 0], 200),
+            500 + 133 * 10
+        );
+
+        // direction of the movement does not matter
+        assert_eq!(
+            compute_dynamic_fee(&config(), &[0, -100, 0], -200),
+            compute_dynamic_fee(&config(), &[0, 100, 0], 200)
+        );
+    }
+
+    #[test]
+    fn should_clamp_fee_to_max() {
+        assert_eq!(
+            compute_dynamic_fee(&config(), &[-887_272, 887_272], -887_272),
+            10_000
+        );
+    }
+
+    #[test]
+    fn window_should_keep_the_latest_closed_ticks() {
+        let mut window = VolatilityWindow::default();
+        for tick in [0, 100, 0, 200] {
+            window.close_tick(tick, 3);
+        }
+        assert_eq!(window.closed_ticks, vec![0, 200]);
+        assert_eq!(window.movement, 200);
+
+        // the open bucket and the current tick complete the observations
+        assert_eq!(
+            window.lp_fee(&config(), Some(100), 150),
+            compute_dynamic_fee(&config(), &[0, 200, 100], 150)
+        );
+        assert_eq!(
+            VolatilityWindow::default().lp_fee(&config(), None, 150),
+            config().min_fee
+        );
+    }
+
+    #[test]
+    fn should_validate_config() {
+        assert_eq!(validate_dynamic_fee_config(&config()), Ok(()));
+
+        assert_eq!(
+            validate_dynamic_fee_config(&DynamicFeeConfig {
+                min_fee: 3000,
+                max_fee: 1000,
+                ..config()
+            }),
+            Err(DynamicFeeConfigError::InvalidFeeBounds)
+        );
+
+        assert_eq!(
+            validate_dynamic_fee_config(&DynamicFeeConfig {
+                max_fee: MAX_DYNAMIC_LP_FEE + 1,
+                ..config()
+            }),
+            Err(DynamicFeeConfigError::InvalidFeeBounds)
+        );
+
+        assert_eq!(
+            validate_dynamic_fee_config(&DynamicFeeConfig {
+                lookback_hours: 0,
+                ..config()
+            }),
+            Err(DynamicFeeConfigError::InvalidLookback)
+        );
+    }
+}
@@ -0,0 +1,203 @@
+// Consistency checks between a pool, its ticks, its tick bitmap and its positions. Every state
+// transition goes through `apply_swap_buffer_state` or `apply_modify_liquidity_buffer_state`, a bug
+// in either of them shows up here as a violation instead of silently corrupting the pool.
+
+use std::collections::BTreeMap;
+
+use candid::Principal;
+use ethnum::U256;
+
+use crate::{libraries::tick_bitmap::compress, state::read_state};
+
+use super::types::PoolId;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// Every position adds its liquidity at its lower tick and removes it at its upper tick.
+    LiquidityNetNotZero {
+        sum: i128,
+    },
+    LiquidityNetOverflow,
+    /// An initialized tick whose bit is not set in the tick bitmap.
+    TickNotInBitmap {
+        tick: i32,
+        liquidity_gross: u128,
+    },
+    /// A bit set in the tick bitmap for a tick without liquidity.
+    UninitializedTickInBitmap {
+        tick: i32,
+    },
+    /// The active liquidity of the pool differs from the liquidity net of the ticks at or below
+    /// the current tick.
+    ActiveLiquidityMismatch {
+        pool_liquidity: u128,
+        ticks_liquidity: i128,
+    },
+    /// The liquidity of the positions referencing a tick differs from the tick liquidity.
+    PositionLiquidityMismatch {
+        tick: i32,
+        tick_liquidity_gross: u128,
+        positions_liquidity_gross: u128,
+        tick_liquidity_net: i128,
+        positions_liquidity_net: i128,
+    },
+    /// Reserves are unsigned, a reserve this large can only come from an underflow.
+    NegativeReserve {
+        token: Principal,
+        reserve: U256,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvariantReport {
+    pub pool_id: PoolId,
+    pub checked_ticks: u64,
+    pub checked_positions: u64,
+    pub violations: Vec<InvariantViolation>,
+}
+
+impl InvariantReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Liquidity referenced at a tick, (liquidity_gross, liquidity_net).
+type TickLiquidity = (u128, i128);
+
+/// Checks the pool against its ticks, bitmap and positions, returns None if the pool does not
+/// exist.
+pub fn check_invariants(pool_id: &PoolId) -> Option<InvariantReport> {
+    let pool = read_state(|s| s.get_pool(pool_id))?;
+    let tick_spacing = pool.tick_spacing.0;
+    let ticks = read_state(|s| s.get_ticks_for_pool(pool_id.clone()));
+    let bitmap_words = read_state(|s| s.get_bitmap_words_for_pool(pool_id));
+    let positions = read_state(|s| s.get_positions_for_pool(pool_id));
+
+    let mut violations = vec![];
+
+    // liquidity net sums to zero and the active liquidity matches the ticks at or below the
+    // current tick
+    let mut liquidity_net_sum: Option<i128> = Some(0);
+    let mut active_liquidity: Option<i128> = Some(0);
+    for (tick_key, tick_info) in &ticks {
+        liquidity_net_sum =
+            liquidity_net_sum.and_then(|sum| sum.checked_add(tick_info.liquidity_net));
+        if tick_key.tick <= pool.tick {
+            active_liquidity =
+                active_liquidity.and_then(|sum| sum.checked_add(tick_info.liquidity_net));
+        }
+    }
+    match (liquidity_net_sum, active_liquidity) {
+        (Some(sum), Some(active_liquidity)) => {
+            if sum != 0 {
+                violations.push(InvariantViolation::LiquidityNetNotZero { sum });
+            }
+            if active_liquidity != pool.liquidity as i128 {
+                violations.push(InvariantViolation::ActiveLiquidityMismatch {
+                    pool_liquidity: pool.liquidity,
+                    ticks_liquidity: active_liquidity,
+                });
+            }
+        }
+        _ => violations.push(InvariantViolation::LiquidityNetOverflow),
+    }
+
+    // a tick has liquidity iff its bit is set
+    let is_bit_set = |tick: i32| {
+        let compressed = compress(tick, tick_spacing);
+        let word_pos = (compressed >> 8) as i16;
+        let bit_pos = (compressed & 0xff) as u32;
+        bitmap_words
+            .get(&word_pos)
+            .is_some_and(|word| word & (U256::ONE << bit_pos) != U256::ZERO)
+    };
+    for (tick_key, tick_info) in &ticks {
+        if tick_info.liquidity_gross > 0 && !is_bit_set(tick_key.tick) {
+            violations.push(InvariantViolation::TickNotInBitmap {
+                tick: tick_key.tick,
+                liquidity_gross: tick_info.liquidity_gross,
+            });
+        }
+    }
+    let liquidity_gross: BTreeMap<i32, u128> = ticks
+        .iter()
+        .map(|(tick_key, tick_info)| (tick_key.tick, tick_info.liquidity_gross))
+        .collect();
+    for (word_pos, word) in &bitmap_words {
+        for bit_pos in 0..256_u32 {
+            if word & (U256::ONE << bit_pos) == U256::ZERO {
+                continue;
+            }
+            let tick = ((*word_pos as i32) * 256 + bit_pos as i32) * tick_spacing;
+            if liquidity_gross.get(&tick).copied().unwrap_or_default() == 0 {
+                violations.push(InvariantViolation::UninitializedTickInBitmap { tick });
+            }
+        }
+    }
+
+    // positions add up to the liquidity of their ticks
+    let mut positions_liquidity: BTreeMap<i32, TickLiquidity> = BTreeMap::new();
+    for (position_key, position_info) in &positions {
+        let liquidity = position_info.liquidity;
+        let lower = positions_liquidity
+            .entry(position_key.tick_lower)
+            .or_default();
+        *lower = (
+            lower.0.saturating_add(liquidity),
+            lower.1.saturating_add(liquidity as i128),
+        );
+        let upper = positions_liquidity
+            .entry(position_key.tick_upper)
+            .or_default();
+        *upper = (
+            upper.0.saturating_add(liquidity),
+            upper.1.saturating_sub(liquidity as i128),
+        );
+    }
+    let mut referenced_ticks: BTreeMap<i32, TickLiquidity> = ticks
+        .iter()
+        .map(|(tick_key, tick_info)| {
+            (
+                tick_key.tick,
+                (tick_info.liquidity_gross, tick_info.liquidity_net),
+            )
+        })
+        .collect();
+    for tick in positions_liquidity.keys() {
+        referenced_ticks.entry(*tick).or_default();
+    }
+    for (tick, (tick_liquidity_gross, tick_liquidity_net)) in referenced_ticks {
+        let (positions_liquidity_gross, positions_liquidity_net) =
+            positions_liquidity.get(&tick).copied().unwrap_or_default();
+        if tick_liquidity_gross != positions_liquidity_gross
+            || tick_liquidity_net != positions_liquidity_net
+        {
+            violations.push(InvariantViolation::PositionLiquidityMismatch {
+                tick,
+                tick_liquidity_gross,
+                positions_liquidity_gross,
+                tick_liquidity_net,
+                positions_liquidity_net,
+            });
+        }
+    }
+
+    // reserves did not underflow
+    let max_reserve = U256::MAX >> 1;
+    for (token, reserve) in [
+        (pool_id.token0, pool.pool_reserve0),
+        (pool_id.token1, pool.pool_reserve1),
+    ] {
+        if reserve > max_reserve {
+            violations.push(InvariantViolation::NegativeReserve { token, reserve });
+        }
+    }
+
+    Some(InvariantReport {
+        pool_id: pool_id.clone(),
+        checked_ticks: ticks.len() as u64,
+        checked_positions: positions.len() as u64,
+        violations,
+    })
+}
@@ -1,5 +1,8 @@
 pub mod create_pool;
+pub mod dynamic_fee;
+pub mod invariants;
 pub mod modify_liquidity;
+pub mod policy;
 pub mod swap;
 pub mod types;
 
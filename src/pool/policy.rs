@@ -0,0 +1,102 @@
+// Rules on new pools set by the controllers against spam and mispriced pools: a creation fee paid
+// from the internal balance of the creator, a token allowlist or denylist, and a minimum liquidity
+// the first position should have, which leaves `create_pool_and_mint` as the only way to create a
+// pool so no pool exists without liquidity at the price its creator chose
+
+use candid::Principal;
+use ethnum::U256;
+use minicbor::{Decode, Encode};
+
+use crate::{
+    balances::types::{UserBalance, UserBalanceKey},
+    state::State,
+};
+
+/// Fee charged to the creator of a pool, credited to the protocol balance.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CreationFee {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub token: Principal,
+    #[cbor(n(1), with = "crate::cbor::u256")]
+    pub amount: U256,
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub enum TokenFilter {
+    /// Both tokens of a new pool should be listed.
+    #[n(0)]
+    Allowlist(#[cbor(n(0), with = "crate::cbor::principal::vec")] Vec<Principal>),
+    /// Neither token of a new pool should be listed.
+    #[n(1)]
+    Denylist(#[cbor(n(0), with = "crate::cbor::principal::vec")] Vec<Principal>),
+}
+
+impl TokenFilter {
+    pub fn allows(&self, token: &Principal) -> bool {
+        match self {
+            TokenFilter::Allowlist(tokens) => tokens.contains(token),
+            TokenFilter::Denylist(tokens) => !tokens.contains(token),
+        }
+    }
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, Default)]
+pub struct PoolCreationPolicy {
+    #[n(0)]
+    pub creation_fee: Option<CreationFee>,
+    #[n(1)]
+    pub token_filter: Option<TokenFilter>,
+    /// Liquidity the first position of a new pool should have at least, `create_pool` is rejected
+    /// while it is set.
+    #[cbor(n(2), with = "crate::cbor::u128::option")]
+    pub min_initial_liquidity: Option<u128>,
+}
+
+impl PoolCreationPolicy {
+    pub fn allows_token(&self, token: &Principal) -> bool {
+        self.token_filter
+            .as_ref()
+            .is_none_or(|filter| filter.allows(token))
+    }
+
+    pub fn requires_initial_position(&self) -> bool {
+        self.min_initial_liquidity.is_some()
+    }
+}
+
+/// Moves the creation fee from the balance of the creator to the protocol balance, None when the
+/// policy has no fee. Fails with the missing fee when the balance does not cover it.
+pub fn charge_creation_fee(
+    s: &mut State,
+    policy: &PoolCreationPolicy,
+    creator: Principal,
+) -> Result<Option<CreationFee>, CreationFee> {
+    let Some(fee) = policy.creation_fee else {
+        return Ok(None);
+    };
+    let key = UserBalanceKey {
+        user: creator,
+        token: fee.token,
+    };
+    let balance = s.get_user_balance(&key).0;
+    if balance < fee.amount {
+        return Err(fee);
+    }
+    s.update_user_balance(key, UserBalance(balance - fee.amount));
+    let protocol_balance = s.get_protocol_balance(&fee.token);
+    s.update_protocol_fee_for_token(fee.token, UserBalance(protocol_balance + fee.amount));
+    Ok(Some(fee))
+}
+
+/// Gives a charged creation fee back to the creator, for pools whose creation failed after the fee
+/// was charged.
+pub fn refund_creation_fee(s: &mut State, fee: CreationFee, creator: Principal) {
+    let key = UserBalanceKey {
+        user: creator,
+        token: fee.token,
+    };
+    let balance = s.get_user_balance(&key).0;
+    s.update_user_balance(key, UserBalance(balance + fee.amount));
+    let protocol_balance = s.get_protocol_balance(&fee.token);
+    s.update_protocol_fee_for_token(fee.token, UserBalance(protocol_balance - fee.amount));
+}
@@ -19,7 +19,10 @@ use crate::{
     },
 };
 
-use super::types::{PoolId, PoolState};
+use super::{
+    dynamic_fee::effective_lp_fee,
+    types::{PoolId, PoolState},
+};
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct SwapParams {
@@ -104,7 +107,9 @@ pub fn swap_inner(params: SwapParams) -> Result<SwapSuccess, InnerSwapError> {
     };
 
     let protocol_fee = pool_state_initial.fee_protocol;
-    let swap_fee = calculate_swap_fee(protocol_fee, params.pool_id.fee.0);
+    // pools in dynamic fee mode charge a volatility based LP fee instead of the fee tier
+    let lp_fee = effective_lp_fee(&params.pool_id, &pool_state_initial);
+    let swap_fee = calculate_swap_fee(protocol_fee, lp_fee);
 
     // A 100% fee (MAX_SWAP_FEE) consumes all input, making exact output swaps impossible.
     if swap_fee >= MAX_SWAP_FEE && params.amount_specified > 0 {
@@ -1,7 +1,7 @@
 mod modify_liquidity_tests {
     use candid::Principal;
     use ethnum::U256;
-    use proptest::{prop_assert, prop_assert_eq, proptest};
+    use proptest::{collection::vec, prop_assert, prop_assert_eq, proptest};
 
     use crate::{
         libraries::{
@@ -11,6 +11,7 @@ mod modify_liquidity_tests {
             tick_math,
         },
         pool::{
+            invariants::{check_invariants, InvariantViolation},
             modify_liquidity::{modify_liquidity, ModifyLiquidityError, ModifyLiquidityParams},
             types::{PoolFee, PoolId, PoolState, PoolTickSpacing},
         },
@@ -72,6 +73,7 @@ mod modify_liquidity_tests {
                     pool_reserve1: U256::ZERO,
                     generated_swap_fee0: U256::ZERO,
                     generated_swap_fee1: U256::ZERO,
+                    creator: None,
                 },
             )
         });
@@ -98,6 +100,7 @@ mod modify_liquidity_tests {
                     pool_reserve1: U256::ZERO,
                     generated_swap_fee0: U256::ZERO,
                     generated_swap_fee1: U256::ZERO,
+                    creator: None,
                 },
             )
         });
@@ -155,6 +158,8 @@ mod modify_liquidity_tests {
             position.liquidity,
             test_modify_liquidity_params().liquidity_delta as u128
         );
+
+        assert!(check_invariants(&test_pool_id()).unwrap().is_ok());
     }
 
     #[test]
@@ -323,6 +328,111 @@ mod modify_liquidity_tests {
 
         assert_eq!(pool_state.pool_reserve0, U256::from(5001_u128));
         assert_eq!(pool_state.pool_reserve1, U256::from(5001_u128));
+
+        assert!(check_invariants(&test_pool_3000()).unwrap().is_ok());
+    }
+
+    #[test]
+    fn check_invariants_should_report_corrupted_state() {
+        assert_eq!(check_invariants(&test_pool_id()), None);
+
+        initialize_test_pool(10);
+        let result = modify_liquidity(test_modify_liquidity_params()).unwrap();
+        mutate_state(|s| s.apply_modify_liquidity_buffer_state(result.buffer_state.clone()));
+
+        let report = check_invariants(&test_pool_id()).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.checked_ticks, 2);
+        assert_eq!(report.checked_positions, 1);
+
+        // active liquidity out of sync with the ticks
+        let mut pool = read_state(|s| s.get_pool(&test_pool_id())).unwrap();
+        pool.liquidity += 1;
+        mutate_state(|s| s.set_pool(test_pool_id(), pool.clone()));
+
+        let report = check_invariants(&test_pool_id()).unwrap();
+        assert_eq!(
+            report.violations,
+            vec![InvariantViolation::ActiveLiquidityMismatch {
+                pool_liquidity: pool.liquidity,
+                ticks_liquidity: test_modify_liquidity_params().liquidity_delta,
+            }]
+        );
+
+        // tick removed while its bit stays set in the bitmap
+        pool.liquidity -= 1;
+        mutate_state(|s| {
+            s.set_pool(test_pool_id(), pool);
+            s.clear_tick(&TickKey {
+                pool_id: test_pool_id(),
+                tick: 120,
+            })
+        });
+
+        let report = check_invariants(&test_pool_id()).unwrap();
+        assert!(report
+            .violations
+            .contains(&InvariantViolation::LiquidityNetNotZero {
+                sum: test_modify_liquidity_params().liquidity_delta
+            }));
+        assert!(report
+            .violations
+            .contains(&InvariantViolation::UninitializedTickInBitmap { tick: 120 }));
+        assert!(report
+            .violations
+            .contains(&InvariantViolation::PositionLiquidityMismatch {
+                tick: 120,
+                tick_liquidity_gross: 0,
+                positions_liquidity_gross: test_modify_liquidity_params().liquidity_delta as u128,
+                tick_liquidity_net: 0,
+                positions_liquidity_net: -test_modify_liquidity_params().liquidity_delta,
+            }));
+    }
+
+    proptest! {
+        #[test]
+        fn test_fuzz_invariants_hold_after_modify_liquidity(
+            operations in vec((-100i32..100i32, 1i32..100i32, 1u64..1_000_000_000_000u64, 0u8..4u8), 1..20),
+        ) {
+            let pool_id = test_pool_id();
+            if read_state(|s| s.get_pool(&pool_id)).is_none() {
+                initialize_test_pool(10);
+            }
+
+            for (tick_lower, width, liquidity, burn_share) in operations {
+                let tick_lower = tick_lower * 10;
+                let tick_upper = tick_lower + width * 10;
+                let position_key = PositionKey {
+                    owner: Principal::management_canister(),
+                    pool_id: pool_id.clone(),
+                    tick_lower,
+                    tick_upper,
+                };
+
+                // burn a share of the position if it exists, otherwise mint
+                let position_liquidity = read_state(|s| s.get_position(&position_key)).liquidity;
+                let liquidity_delta = if position_liquidity > 0 && burn_share > 0 {
+                    -((position_liquidity / burn_share as u128) as i128)
+                } else {
+                    liquidity as i128
+                };
+
+                let params = ModifyLiquidityParams {
+                    owner: position_key.owner,
+                    pool_id: pool_id.clone(),
+                    tick_lower,
+                    tick_upper,
+                    liquidity_delta,
+                    tick_spacing: PoolTickSpacing(10),
+                };
+                if let Ok(result) = modify_liquidity(params) {
+                    mutate_state(|s| s.apply_modify_liquidity_buffer_state(result.buffer_state));
+                }
+
+                let report = check_invariants(&pool_id).unwrap();
+                prop_assert!(report.is_ok(), "{:?}", report.violations);
+            }
+        }
     }
 
     proptest! {
@@ -382,6 +492,9 @@ mod modify_liquidity_tests {
         let is_lower_initialized=is_initialized(&TickKey { pool_id: pool_id.clone(), tick: tick_lower }, tick_spacing);
         let is_upper_initialized=is_initialized(&TickKey { pool_id: pool_id.clone(), tick: tick_upper }, tick_spacing);
 
+        let report = check_invariants(&pool_id).unwrap();
+        prop_assert!(report.is_ok(), "{:?}", report.violations);
+
         prop_assert_eq!(pool_after.clone().unwrap().tick,0);
 
         prop_assert_eq!(position_after.liquidity - position_before.liquidity, liquidity_delta as u128);
@@ -63,4 +63,33 @@ pub struct PoolState {
     pub generated_swap_fee0: U256, // Cumulative swap fee for token0
     #[cbor(n(15), with = "crate::cbor::u256")]
     pub generated_swap_fee1: U256, // Cumulative swap fee for token1
+    #[cbor(n(16), with = "crate::cbor::principal::option")]
+    pub creator: Option<Principal>, // None for pools created before creators were recorded
+}
+
+/// Configuration for pools running in dynamic-fee mode, the LP fee charged on a swap moves
+/// between `min_fee` and `max_fee` depending on how much the pool's tick moved recently.
+#[derive(Encode, Decode, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DynamicFeeConfig {
+    #[n(0)]
+    pub min_fee: u32, // lower bound of the LP fee in pips
+    #[n(1)]
+    pub max_fee: u32, // upper bound of the LP fee in pips
+    #[n(2)]
+    pub fee_per_tick: u32, // pips added to min_fee for every tick of average hourly movement
+    #[n(3)]
+    pub lookback_hours: u32, // number of hourly buckets used to measure volatility
+}
+
+/// Running volatility of a dynamic fee pool, the closing ticks of its latest closed hourly buckets
+/// (at most `lookback_hours - 1`, oldest first) and their summed tick movement. Rolled over when an
+/// hourly bucket opens, so a swap reads this instead of the whole hourly frame.
+#[derive(Encode, Decode, Clone, Debug, Default, Eq, PartialEq)]
+pub struct VolatilityWindow {
+    #[n(0)]
+    pub closed_ticks: Vec<i32>,
+    #[n(1)]
+    pub movement: u64, // sum of the absolute tick changes between consecutive closed ticks
+    #[n(2)]
+    pub open_bucket_start: Option<u64>, // start of the latest hourly bucket, None before the first
 }
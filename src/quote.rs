@@ -1,10 +1,10 @@
-use candid::Nat;
+use candid::{Nat, Principal};
 use ethnum::{I256, U256};
 
 use crate::{
     candid_types::{
         pool::CandidPoolId,
-        quote::{QuoteError, QuoteExactParams, QuoteExactSingleParams},
+        quote::{QuoteArgs, QuoteError, QuoteExactParams, QuoteExactSingleParams},
     },
     libraries::{
         balance_delta::BalanceDelta,
@@ -16,14 +16,56 @@ use crate::{
         swap::{swap_inner, SwapParams},
         types::PoolId,
     },
+    swap::get_token_in_out,
+    tokens::accounting::{apply_transfer_haircut, gross_up_for_transfer_haircut, transfer_haircut},
     validation::swap_args::{MAX_PATH_LENGTH, MIN_PATH_LENGTH},
 };
 
+/// Quoted amount together with the total fee in pips charged by each hop, in path order.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QuoteDetails {
+    pub amount: U256,
+    pub swap_fees: Vec<u32>,
+}
+
 /// Processes a single-hop exact input quote, calculating the output amount.
 pub fn process_single_hop_exact_input(params: QuoteExactSingleParams) -> Result<U256, QuoteError> {
+    quote_single_hop_exact_input(params).map(|quote| quote.amount)
+}
+
+/// Processes a multi-hop exact input quote, iterating through the path.
+pub fn process_multi_hop_exact_input(params: QuoteExactParams) -> Result<U256, QuoteError> {
+    quote_multi_hop_exact_input(params).map(|quote| quote.amount)
+}
+
+/// Processes a single-hop exact output quote, calculating the input amount.
+pub fn process_single_hop_exact_output(params: QuoteExactSingleParams) -> Result<U256, QuoteError> {
+    quote_single_hop_exact_output(params).map(|quote| quote.amount)
+}
+
+/// Processes a multi-hop exact output quote, iterating through the path in reverse.
+pub fn process_multi_hop_exact_output(params: QuoteExactParams) -> Result<U256, QuoteError> {
+    quote_multi_hop_exact_output(params).map(|quote| quote.amount)
+}
+
+/// Quotes any kind of swap, including the fee charged by each hop.
+pub fn process_quote_with_fees(args: QuoteArgs) -> Result<QuoteDetails, QuoteError> {
+    match args {
+        QuoteArgs::QuoteExactInputSingleParams(params) => quote_single_hop_exact_input(params),
+        QuoteArgs::QuoteExactInputParams(params) => quote_multi_hop_exact_input(params),
+        QuoteArgs::QuoteExactOutputSingleParams(params) => quote_single_hop_exact_output(params),
+        QuoteArgs::QuoteExactOutput(params) => quote_multi_hop_exact_output(params),
+    }
+}
+
+fn quote_single_hop_exact_input(
+    params: QuoteExactSingleParams,
+) -> Result<QuoteDetails, QuoteError> {
     let pool_id = validate_pool_id(params.pool_id)?;
+    let (token_in, token_out) = get_token_in_out(&pool_id, params.zero_for_one);
 
-    let exact_amount = convert_amount_to_i256(params.exact_amount)?;
+    // fee-on-transfer tokens lose part of the deposit before reaching the pool
+    let exact_amount = after_haircut(&token_in, convert_amount_to_i256(params.exact_amount)?);
 
     let swap_direction = params.zero_for_one;
     let sqrt_price_limit_x96 = get_sqrt_price_limit(swap_direction);
@@ -37,19 +79,26 @@ pub fn process_single_hop_exact_input(params: QuoteExactSingleParams) -> Result<
     let swap_result = swap_inner(swap_params)?;
     println!("{:?}", swap_result);
 
-    let amount_out = select_amount(swap_result.swap_delta, swap_direction, false);
-    Ok(amount_out.as_u256())
+    let amount_out = after_haircut(
+        &token_out,
+        select_amount(swap_result.swap_delta, swap_direction, false),
+    );
+    Ok(QuoteDetails {
+        amount: amount_out.as_u256(),
+        swap_fees: vec![swap_result.swap_fee],
+    })
 }
 
-/// Processes a multi-hop exact input quote, iterating through the path.
-pub fn process_multi_hop_exact_input(params: QuoteExactParams) -> Result<U256, QuoteError> {
+fn quote_multi_hop_exact_input(params: QuoteExactParams) -> Result<QuoteDetails, QuoteError> {
     let path_length = params.path.len() as u8;
     if path_length < MIN_PATH_LENGTH || path_length > MAX_PATH_LENGTH {
         return Err(QuoteError::InvalidPathLength);
     }
 
     let mut input_token = params.exact_token;
-    let mut input_amount = convert_amount_to_i256(params.exact_amount)?;
+    let mut input_amount =
+        after_haircut(&input_token, convert_amount_to_i256(params.exact_amount)?);
+    let mut swap_fees = Vec::with_capacity(params.path.len());
 
     for candid_path in params.path {
         let path_key = PathKey::try_from(candid_path).map_err(|_| QuoteError::InvalidFee)?;
@@ -69,17 +118,25 @@ pub fn process_multi_hop_exact_input(params: QuoteExactParams) -> Result<U256, Q
         // Update amount and token for next hop
         input_amount = select_amount(swap_result.swap_delta, swap.zero_for_one, false);
         input_token = path_key.intermediary_token;
+        swap_fees.push(swap_result.swap_fee);
     }
 
-    // Final input_amount is the output amount
-    Ok(input_amount.as_u256())
+    // Final input_amount is the output amount, reduced by the withdrawal haircut
+    let amount_out = after_haircut(&input_token, input_amount);
+    Ok(QuoteDetails {
+        amount: amount_out.as_u256(),
+        swap_fees,
+    })
 }
 
-/// Processes a single-hop exact output quote, calculating the input amount.
-pub fn process_single_hop_exact_output(params: QuoteExactSingleParams) -> Result<U256, QuoteError> {
+fn quote_single_hop_exact_output(
+    params: QuoteExactSingleParams,
+) -> Result<QuoteDetails, QuoteError> {
     let pool_id = validate_pool_id(params.pool_id)?;
+    let (token_in, token_out) = get_token_in_out(&pool_id, params.zero_for_one);
 
-    let exact_amount = convert_amount_to_i256(params.exact_amount)?;
+    // the pool has to send more of a fee-on-transfer token for the exact amount to arrive
+    let exact_amount = before_haircut(&token_out, convert_amount_to_i256(params.exact_amount)?)?;
 
     let swap_direction = params.zero_for_one;
     let sqrt_price_limit_x96 = get_sqrt_price_limit(swap_direction);
@@ -94,19 +151,26 @@ pub fn process_single_hop_exact_output(params: QuoteExactSingleParams) -> Result
 
     println!("{:?}", swap_result);
 
-    let amount_in = select_amount(swap_result.swap_delta, swap_direction, true);
-    Ok((-amount_in).as_u256())
+    let amount_in = before_haircut(
+        &token_in,
+        -select_amount(swap_result.swap_delta, swap_direction, true),
+    )?;
+    Ok(QuoteDetails {
+        amount: amount_in.as_u256(),
+        swap_fees: vec![swap_result.swap_fee],
+    })
 }
 
-/// Processes a multi-hop exact output quote, iterating through the path in reverse.
-pub fn process_multi_hop_exact_output(params: QuoteExactParams) -> Result<U256, QuoteError> {
+fn quote_multi_hop_exact_output(params: QuoteExactParams) -> Result<QuoteDetails, QuoteError> {
     let path_length = params.path.len() as u8;
     if path_length < MIN_PATH_LENGTH || path_length > MAX_PATH_LENGTH {
         return Err(QuoteError::InvalidPathLength);
     }
 
     let mut output_token = params.exact_token;
-    let mut output_amount = convert_amount_to_i256(params.exact_amount)?;
+    let mut output_amount =
+        before_haircut(&output_token, convert_amount_to_i256(params.exact_amount)?)?;
+    let mut swap_fees = Vec::with_capacity(params.path.len());
 
     for candid_path in params.path.into_iter().rev() {
         let path_key = PathKey::try_from(candid_path).map_err(|_| QuoteError::InvalidFee)?;
@@ -129,10 +193,16 @@ pub fn process_multi_hop_exact_output(params: QuoteExactParams) -> Result<U256,
         output_amount = select_amount(swap_result.swap_delta, !one_for_zero, true);
         output_amount = -output_amount;
         output_token = path_key.intermediary_token;
+        // hops are quoted in reverse, keep fees in path order
+        swap_fees.insert(0, swap_result.swap_fee);
     }
 
-    // Final current_amount is the input amount
-    Ok(output_amount.as_u256())
+    // Final current_amount is the input amount, increased by the deposit haircut
+    let amount_in = before_haircut(&output_token, output_amount)?;
+    Ok(QuoteDetails {
+        amount: amount_in.as_u256(),
+        swap_fees,
+    })
 }
 
 fn validate_pool_id(pool_id: CandidPoolId) -> Result<PoolId, QuoteError> {
@@ -141,6 +211,18 @@ fn validate_pool_id(pool_id: CandidPoolId) -> Result<PoolId, QuoteError> {
         .map_err(|_| QuoteError::PoolNotInitialized)
 }
 
+/// Amount left once the transfer haircut of a fee-on-transfer token is taken.
+fn after_haircut(token: &Principal, amount: I256) -> I256 {
+    apply_transfer_haircut(amount.as_u256(), transfer_haircut(token)).as_i256()
+}
+
+/// Amount to transfer so that `amount` is left after the haircut of a fee-on-transfer token.
+fn before_haircut(token: &Principal, amount: I256) -> Result<I256, QuoteError> {
+    gross_up_for_transfer_haircut(amount.as_u256(), transfer_haircut(token))
+        .and_then(|amount| I256::try_from(amount).ok())
+        .ok_or(QuoteError::InvalidAmount)
+}
+
 /// Converts a `Nat` amount to `I256` safely.
 fn convert_amount_to_i256(amount: Nat) -> Result<I256, QuoteError> {
     let u256_amount = big_uint_to_u256(amount.0).map_err(|_| QuoteError::InvalidAmount)?;
@@ -0,0 +1,111 @@
+// a module for checking that the DEX holds what it owes. For every pooled or registered token, the
+// liabilities recorded in the state (user balances, pool reserves and protocol fees) are compared
+// with the balance of the canister on the token ledger, and the surplus or deficit is stored with
+// the time of the check. Tokens configured with `pause_on_deficit` are paused when a deficit is
+// found.
+// The ledger balance is read before and after the liabilities are computed and the larger read is
+// kept, so a transfer in flight during the check can only show up as a surplus: withdrawals are
+// debited before they are sent and deposits are credited after they arrived.
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+use ethnum::U256;
+use ic_canister_log::log;
+
+use crate::{
+    logs::INFO,
+    state::{mutate_state, read_state},
+    tokens::types::TokenSettings,
+};
+use types::{TokenLiabilities, TokenReconciliation};
+
+pub mod types;
+
+#[cfg(test)]
+mod tests;
+
+/// The tokens of every pool and every token in the registry. Deposits accept any ledger, balances
+/// in a token that was never validated are not reconciled.
+pub fn reconciled_tokens() -> Vec<Principal> {
+    read_state(|s| {
+        let mut tokens = BTreeSet::new();
+        for (pool_id, _) in s.get_pools() {
+            tokens.insert(pool_id.token0);
+            tokens.insert(pool_id.token1);
+        }
+        tokens.extend(
+            s.get_all_token_metadata()
+                .into_iter()
+                .map(|(token, _)| token),
+        );
+        tokens.into_iter().collect()
+    })
+}
+
+/// What the canister owes in `token`, from the totals kept with every balance and pool write.
+pub fn token_liabilities(token: Principal) -> TokenLiabilities {
+    read_state(|s| {
+        let totals = s.get_liability_totals(&token);
+        TokenLiabilities {
+            user_balances: totals.user_balances,
+            pool_reserves: totals.pool_reserves,
+            protocol_balance: s.get_protocol_balance(&token),
+        }
+    })
+}
+
+/// Stores the result of a reconciliation of `token` and pauses the token on a deficit if it is
+/// configured to.
+pub fn record_reconciliation(
+    token: Principal,
+    liabilities: TokenLiabilities,
+    ledger_balance: U256,
+    now: u64,
+) -> TokenReconciliation {
+    let previous = read_state(|s| s.get_reconciliation(&token));
+    let mut reconciliation = TokenReconciliation {
+        checked_at: now,
+        user_balances: liabilities.user_balances,
+        pool_reserves: liabilities.pool_reserves,
+        protocol_balance: liabilities.protocol_balance,
+        ledger_balance,
+        last_deficit_at: previous.and_then(|previous| previous.last_deficit_at),
+    };
+
+    if reconciliation.deficit() > U256::ZERO {
+        reconciliation.last_deficit_at = Some(now);
+        log!(
+            INFO,
+            "Reconciliation of {:?}: liabilities {:?} exceed ledger balance {:?}",
+            token.to_text(),
+            reconciliation.liabilities(),
+            ledger_balance,
+        );
+
+        let settings = read_state(|s| s.get_token_settings(&token));
+        if settings.pauses_on_deficit() && !settings.is_paused() {
+            log!(INFO, "Pausing {:?} after a deficit", token.to_text());
+            mutate_state(|s| {
+                s.set_token_settings(
+                    token,
+                    TokenSettings {
+                        paused_at: Some(now),
+                        ..settings
+                    },
+                )
+            });
+        }
+    }
+
+    mutate_state(|s| s.set_reconciliation(token, reconciliation.clone()));
+    reconciliation
+}
+
+pub fn get_reconciliations() -> Vec<(Principal, TokenReconciliation)> {
+    read_state(|s| s.get_reconciliations())
+}
+
+pub fn is_token_paused(token: &Principal) -> bool {
+    read_state(|s| s.get_token_settings(token)).is_paused()
+}
@@ -0,0 +1,218 @@
+use candid::Principal;
+use ethnum::U256;
+
+use super::*;
+use crate::{
+    balances::types::{UserBalance, UserBalanceKey},
+    libraries::sqrt_price_math::tests::SQRT_PRICE_1_1,
+    pool::types::{PoolFee, PoolId, PoolState, PoolTickSpacing},
+    tokens::types::TokenMetadata,
+};
+
+const NOW: u64 = 1_000_000_000_000;
+
+fn token0() -> Principal {
+    Principal::from_slice(&[1_u8; 29])
+}
+
+fn token1() -> Principal {
+    Principal::from_slice(&[2_u8; 29])
+}
+
+fn user(id: u8) -> Principal {
+    Principal::from_slice(&[id; 20])
+}
+
+fn set_up_state() {
+    mutate_state(|s| {
+        s.set_pool(
+            PoolId {
+                token0: token0(),
+                token1: token1(),
+                fee: PoolFee(3_000),
+            },
+            PoolState {
+                sqrt_price_x96: *SQRT_PRICE_1_1,
+                tick: 0,
+                fee_growth_global_0_x128: U256::ZERO,
+                fee_growth_global_1_x128: U256::ZERO,
+                liquidity: 0,
+                tick_spacing: PoolTickSpacing(60),
+                max_liquidity_per_tick: u128::MAX,
+                fee_protocol: 0,
+                token0_transfer_fee: U256::ZERO,
+                token1_transfer_fee: U256::ZERO,
+                swap_volume0_all_time: U256::ZERO,
+                swap_volume1_all_time: U256::ZERO,
+                pool_reserve0: U256::from(1_000_u32),
+                pool_reserve1: U256::from(2_000_u32),
+                generated_swap_fee0: U256::ZERO,
+                generated_swap_fee1: U256::ZERO,
+                creator: None,
+            },
+        );
+        for (id, amount) in [(3, 100_u32), (4, 200_u32)] {
+            s.update_user_balance(
+                UserBalanceKey {
+                    user: user(id),
+                    token: token0(),
+                },
+                UserBalance(U256::from(amount)),
+            );
+        }
+        s.update_protocol_fee_for_token(token0(), UserBalance(U256::from(10_u8)));
+    });
+}
+
+#[test]
+fn liabilities_should_sum_balances_reserves_and_protocol_fees() {
+    set_up_state();
+
+    assert_eq!(reconciled_tokens(), vec![token0(), token1()]);
+    let liabilities = token_liabilities(token0());
+    assert_eq!(
+        liabilities,
+        TokenLiabilities {
+            user_balances: U256::from(300_u32),
+            pool_reserves: U256::from(1_000_u32),
+            protocol_balance: U256::from(10_u8),
+        }
+    );
+    assert_eq!(liabilities.total(), U256::from(1_310_u32));
+    assert_eq!(token_liabilities(token1()).total(), U256::from(2_000_u32));
+}
+
+#[test]
+fn liability_totals_should_follow_balance_and_pool_writes() {
+    set_up_state();
+    let pool_id = PoolId {
+        token0: token0(),
+        token1: token1(),
+        fee: PoolFee(3_000),
+    };
+
+    mutate_state(|s| {
+        s.update_user_balance(
+            UserBalanceKey {
+                user: user(3),
+                token: token0(),
+            },
+            UserBalance(U256::from(40_u8)),
+        );
+        let mut pool = s.get_pool(&pool_id).unwrap();
+        pool.pool_reserve0 = U256::from(1_500_u32);
+        pool.pool_reserve1 = U256::from(500_u32);
+        s.set_pool(pool_id.clone(), pool);
+    });
+    assert_eq!(
+        token_liabilities(token0()),
+        TokenLiabilities {
+            user_balances: U256::from(240_u32),
+            pool_reserves: U256::from(1_500_u32),
+            protocol_balance: U256::from(10_u8),
+        }
+    );
+    assert_eq!(
+        token_liabilities(token1()).pool_reserves,
+        U256::from(500_u32)
+    );
+
+    mutate_state(|s| s.remove_pool(&pool_id));
+    assert_eq!(token_liabilities(token0()).pool_reserves, U256::ZERO);
+    assert_eq!(token_liabilities(token1()).pool_reserves, U256::ZERO);
+}
+
+#[test]
+fn only_pooled_or_registered_tokens_should_be_reconciled() {
+    set_up_state();
+    let deposited = Principal::from_slice(&[5_u8; 29]);
+    let registered = Principal::from_slice(&[6_u8; 29]);
+    mutate_state(|s| {
+        s.update_user_balance(
+            UserBalanceKey {
+                user: user(3),
+                token: deposited,
+            },
+            UserBalance(U256::from(100_u8)),
+        );
+        s.set_token_metadata(registered, TokenMetadata::default());
+    });
+
+    assert_eq!(reconciled_tokens(), vec![token0(), token1(), registered]);
+}
+
+#[test]
+fn surplus_should_be_recorded_without_pausing() {
+    set_up_state();
+    mutate_state(|s| {
+        s.set_token_settings(
+            token0(),
+            TokenSettings {
+                pause_on_deficit: Some(true),
+                ..Default::default()
+            },
+        )
+    });
+
+    let reconciliation = record_reconciliation(
+        token0(),
+        token_liabilities(token0()),
+        U256::from(1_400_u32),
+        NOW,
+    );
+
+    assert_eq!(reconciliation.surplus(), U256::from(90_u8));
+    assert_eq!(reconciliation.deficit(), U256::ZERO);
+    assert_eq!(reconciliation.last_deficit_at, None);
+    assert!(!is_token_paused(&token0()));
+    assert_eq!(get_reconciliations(), vec![(token0(), reconciliation)]);
+}
+
+#[test]
+fn deficit_should_pause_token_only_if_configured() {
+    set_up_state();
+    mutate_state(|s| {
+        s.set_token_settings(
+            token0(),
+            TokenSettings {
+                pause_on_deficit: Some(true),
+                ..Default::default()
+            },
+        )
+    });
+
+    let reconciliation = record_reconciliation(
+        token0(),
+        token_liabilities(token0()),
+        U256::from(1_300_u32),
+        NOW,
+    );
+    assert_eq!(reconciliation.deficit(), U256::from(10_u8));
+    assert_eq!(reconciliation.last_deficit_at, Some(NOW));
+    assert_eq!(
+        read_state(|s| s.get_token_settings(&token0())).paused_at,
+        Some(NOW)
+    );
+
+    // token1 does not pause on deficit
+    record_reconciliation(token1(), token_liabilities(token1()), U256::ZERO, NOW);
+    assert!(!is_token_paused(&token1()));
+}
+
+#[test]
+fn last_deficit_should_be_kept_once_solvent_again() {
+    set_up_state();
+
+    record_reconciliation(token0(), token_liabilities(token0()), U256::ZERO, NOW);
+    let reconciliation = record_reconciliation(
+        token0(),
+        token_liabilities(token0()),
+        U256::from(1_310_u32),
+        NOW + 1,
+    );
+
+    assert_eq!(reconciliation.checked_at, NOW + 1);
+    assert_eq!(reconciliation.deficit(), U256::ZERO);
+    assert_eq!(reconciliation.surplus(), U256::ZERO);
+    assert_eq!(reconciliation.last_deficit_at, Some(NOW));
+}
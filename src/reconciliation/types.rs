@@ -0,0 +1,69 @@
+use ethnum::U256;
+use minicbor::{Decode, Encode};
+
+/// What the canister owes in a token, as recorded in its state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct TokenLiabilities {
+    pub user_balances: U256,
+    pub pool_reserves: U256,
+    pub protocol_balance: U256,
+}
+
+impl TokenLiabilities {
+    pub fn total(&self) -> U256 {
+        self.user_balances
+            .saturating_add(self.pool_reserves)
+            .saturating_add(self.protocol_balance)
+    }
+}
+
+/// Running totals of the user balances and pool reserves in a token, updated with every write to
+/// a balance or a pool so a reconciliation does not scan them.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct LiabilityTotals {
+    #[cbor(n(0), with = "crate::cbor::u256")]
+    pub user_balances: U256,
+    #[cbor(n(1), with = "crate::cbor::u256")]
+    pub pool_reserves: U256,
+}
+
+/// Result of the last reconciliation of a token, liabilities against the balance of the canister
+/// on the token ledger.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct TokenReconciliation {
+    #[n(0)]
+    pub checked_at: u64,
+    #[cbor(n(1), with = "crate::cbor::u256")]
+    pub user_balances: U256,
+    #[cbor(n(2), with = "crate::cbor::u256")]
+    pub pool_reserves: U256,
+    #[cbor(n(3), with = "crate::cbor::u256")]
+    pub protocol_balance: U256,
+    #[cbor(n(4), with = "crate::cbor::u256")]
+    pub ledger_balance: U256,
+    /// Last time a deficit was found, kept once the deficit is gone.
+    #[n(5)]
+    pub last_deficit_at: Option<u64>,
+}
+
+impl TokenReconciliation {
+    pub fn liabilities(&self) -> U256 {
+        TokenLiabilities {
+            user_balances: self.user_balances,
+            pool_reserves: self.pool_reserves,
+            protocol_balance: self.protocol_balance,
+        }
+        .total()
+    }
+
+    /// Amount held on top of the liabilities, e.g. transfer fees paid by users or tokens sent
+    /// directly to the canister.
+    pub fn surplus(&self) -> U256 {
+        self.ledger_balance.saturating_sub(self.liabilities())
+    }
+
+    /// Amount owed that the canister does not hold.
+    pub fn deficit(&self) -> U256 {
+        self.liabilities().saturating_sub(self.ledger_balance)
+    }
+}
@@ -0,0 +1,574 @@
+// a module for rebuilding the state of the DEX from its event log, used by the `replay_events`
+// binary to audit the canister and reproduce incidents offline.
+// Events are applied in order to the thread local state with the functions used by the canister
+// (`create_pool_inner`, `modify_liquidity` and `swap_inner`), the amounts recorded in each event
+// are checked against the recomputed ones, and the rebuilt pools, ticks and balances are then
+// compared with a snapshot of the live canister.
+// Dynamic fee configs and protocol fee changes are not recorded as events, so swaps in pools using
+// them diverge. Pools created and swaps executed before events carried the initial price and the
+// hops can not be replayed.
+
+use std::collections::BTreeMap;
+
+use candid::{Int, Nat, Principal};
+use ethnum::{I256, U256};
+use num_bigint::BigInt;
+
+use crate::{
+    balances::types::{UserBalance, UserBalanceKey},
+    candid_types::{
+        events::{CandidEvent, CandidEventType},
+        pool::{CandidPoolId, CandidPoolState, CreatePoolArgs},
+        position::CandidPositionKey,
+        tick::CandidTickInfo,
+        Balance,
+    },
+    donate::execute_donate,
+    libraries::{
+        balance_delta::BalanceDelta,
+        constants::FEE_TO_TICK_SPACING,
+        safe_cast::{big_uint_to_u256, i256_to_int, int_to_i256, u256_to_big_uint, u256_to_nat},
+    },
+    pool::{
+        create_pool::create_pool_inner,
+        modify_liquidity::{modify_liquidity, ModifyLiquidityParams},
+        policy::CreationFee,
+        swap::{swap_inner, SwapParams},
+        types::{PoolFee, PoolId, PoolTickSpacing},
+    },
+    position::types::PositionKey,
+    quote::get_sqrt_price_limit,
+    state::{mutate_state, read_state},
+    swap::apply_swap_hops,
+    validation::donate_args::ValidatedDonateArgs,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The live state to compare the replayed one with, built from the replies of `get_pools`,
+/// `get_active_ticks` and `user_balances`. Only the listed pools' ticks and users' balances are
+/// compared.
+#[derive(Debug, Clone, Default)]
+pub struct StateSnapshot {
+    pub pools: Vec<(CandidPoolId, CandidPoolState)>,
+    pub ticks: Vec<(CandidPoolId, Vec<CandidTickInfo>)>,
+    pub balances: Vec<(Principal, Vec<Balance>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// The event could not be applied, the pools and balances it touches diverge from then on.
+    Unreplayable { event: u64, reason: String },
+    /// An amount recorded in the event differs from the one computed by the replay.
+    Amount {
+        event: u64,
+        field: String,
+        recorded: Int,
+        replayed: Int,
+    },
+    /// The event debits more than the replayed balance, the balance is set to zero.
+    InsufficientBalance {
+        event: u64,
+        user: Principal,
+        token: Principal,
+        balance: Nat,
+        debit: Nat,
+    },
+    Pool {
+        pool_id: PoolId,
+        live: Option<CandidPoolState>,
+        replayed: Option<CandidPoolState>,
+    },
+    Tick {
+        pool_id: PoolId,
+        tick: i32,
+        live: Option<CandidTickInfo>,
+        replayed: Option<CandidTickInfo>,
+    },
+    Balance {
+        user: Principal,
+        token: Principal,
+        live: Nat,
+        replayed: Nat,
+    },
+}
+
+/// Applies `events` in order to the thread local state, `first_index` being the index of the
+/// first one in the event log, and returns the divergences found on the way.
+pub fn replay_events(first_index: u64, events: &[CandidEvent]) -> Vec<Divergence> {
+    // the fee levels set on init are not recorded as events
+    mutate_state(|s| {
+        for (fee, tick_spacing) in FEE_TO_TICK_SPACING {
+            if s.get_tick_spacing(&PoolFee(fee)).is_none() {
+                s.set_tick_spacing(PoolFee(fee), PoolTickSpacing(tick_spacing));
+            }
+        }
+    });
+
+    let mut replay = Replay {
+        event: first_index,
+        divergences: vec![],
+    };
+    for event in events {
+        if let Err(reason) = replay.apply(event) {
+            replay.divergences.push(Divergence::Unreplayable {
+                event: replay.event,
+                reason,
+            });
+        }
+        replay.event += 1;
+    }
+    replay.divergences
+}
+
+/// Compares the thread local state with `snapshot`, fee on transfer flags are not event sourced
+/// and taken from the snapshot.
+pub fn compare_with_snapshot(snapshot: &StateSnapshot) -> Vec<Divergence> {
+    let mut divergences = vec![];
+
+    let mut replayed_pools: BTreeMap<PoolId, _> =
+        read_state(|s| s.get_pools()).into_iter().collect();
+    for (pool_id, live) in &snapshot.pools {
+        let Ok(pool_id) = PoolId::try_from(pool_id.clone()) else {
+            continue;
+        };
+        let replayed = replayed_pools
+            .remove(&pool_id)
+            .map(|state| CandidPoolState {
+                fee_on_transfer: live.fee_on_transfer,
+                ..CandidPoolState::from(state)
+            });
+        if replayed.as_ref() != Some(live) {
+            divergences.push(Divergence::Pool {
+                pool_id,
+                live: Some(live.clone()),
+                replayed,
+            });
+        }
+    }
+    for (pool_id, state) in replayed_pools {
+        divergences.push(Divergence::Pool {
+            pool_id,
+            live: None,
+            replayed: Some(CandidPoolState::from(state)),
+        });
+    }
+
+    for (pool_id, live_ticks) in &snapshot.ticks {
+        let Ok(pool_id) = PoolId::try_from(pool_id.clone()) else {
+            continue;
+        };
+        let mut replayed_ticks: BTreeMap<i32, CandidTickInfo> =
+            read_state(|s| s.get_ticks_for_pool(pool_id.clone()))
+                .into_iter()
+                .map(|(key, info)| (key.tick, CandidTickInfo::from((key, info))))
+                .collect();
+        for live in live_ticks {
+            let Ok(tick) = i32::try_from(live.tick.0.clone()) else {
+                continue;
+            };
+            let replayed = replayed_ticks.remove(&tick);
+            if replayed.as_ref() != Some(live) {
+                divergences.push(Divergence::Tick {
+                    pool_id: pool_id.clone(),
+                    tick,
+                    live: Some(live.clone()),
+                    replayed,
+                });
+            }
+        }
+        for (tick, replayed) in replayed_ticks {
+            divergences.push(Divergence::Tick {
+                pool_id: pool_id.clone(),
+                tick,
+                live: None,
+                replayed: Some(replayed),
+            });
+        }
+    }
+
+    for (user, live_balances) in &snapshot.balances {
+        let mut replayed_balances: BTreeMap<Principal, Nat> =
+            read_state(|s| s.get_user_balances(*user))
+                .into_iter()
+                .map(|(token, balance)| (token, u256_to_nat(balance)))
+                .collect();
+        for live in live_balances {
+            let replayed = replayed_balances
+                .remove(&live.token)
+                .unwrap_or(Nat::from(0_u8));
+            if replayed != live.amount {
+                divergences.push(Divergence::Balance {
+                    user: *user,
+                    token: live.token,
+                    live: live.amount.clone(),
+                    replayed,
+                });
+            }
+        }
+        for (token, replayed) in replayed_balances {
+            if replayed != Nat::from(0_u8) {
+                divergences.push(Divergence::Balance {
+                    user: *user,
+                    token,
+                    live: Nat::from(0_u8),
+                    replayed,
+                });
+            }
+        }
+    }
+
+    divergences
+}
+
+// replays events one at a time, `event` is the index of the event being replayed
+struct Replay {
+    event: u64,
+    divergences: Vec<Divergence>,
+}
+
+impl Replay {
+    fn apply(&mut self, event: &CandidEvent) -> Result<(), String> {
+        match &event.payload {
+            CandidEventType::CreatedPool {
+                token0,
+                token1,
+                pool_fee,
+                sqrt_price_x96,
+                token0_transfer_fee,
+                token1_transfer_fee,
+                creator,
+                creation_fee,
+            } => {
+                let sqrt_price_x96 = sqrt_price_x96
+                    .clone()
+                    .ok_or("pool created before events carried its initial price")?;
+                let transfer_fee = |fee: &Option<Nat>| {
+                    fee.as_ref()
+                        .map_or(Ok(U256::ZERO), |fee| to_u256(fee, "transfer_fee"))
+                };
+                let creation_fee = match creation_fee {
+                    Some(fee) => Some(CreationFee {
+                        token: fee.token,
+                        amount: to_u256(&fee.amount, "creation_fee")?,
+                    }),
+                    None => None,
+                };
+                create_pool_inner(
+                    CreatePoolArgs {
+                        token_a: *token0,
+                        token_b: *token1,
+                        fee: pool_fee.clone(),
+                        sqrt_price_x96,
+                        request_id: None,
+                    },
+                    transfer_fee(token0_transfer_fee)?,
+                    transfer_fee(token1_transfer_fee)?,
+                    *creator,
+                    creation_fee,
+                    event.timestamp,
+                )
+                .map_err(|e| format!("failed to create the pool: {e:?}"))?;
+                // the creation fee is paid from the internal balance of the creator
+                if let (Some(creator), Some(fee)) = (creator, creation_fee) {
+                    self.debit(*creator, fee.token, fee.amount);
+                }
+            }
+            CandidEventType::MintedPosition {
+                created_position: position,
+                liquidity,
+                amount0_paid: amount0,
+                amount1_paid: amount1,
+                ..
+            }
+            | CandidEventType::IncreasedLiquidity {
+                modified_position: position,
+                liquidity_delta: liquidity,
+                amount0_paid: amount0,
+                amount1_paid: amount1,
+                ..
+            } => {
+                let (balance_delta, _fee_delta) =
+                    self.modify_position(position, to_i128(liquidity)?)?;
+                self.check_amounts(balance_delta, amount0, amount1);
+            }
+            CandidEventType::BurntPosition {
+                burnt_position: position,
+                liquidity,
+                amount0_received: amount0,
+                amount1_received: amount1,
+                ..
+            }
+            | CandidEventType::DecreasedLiquidity {
+                modified_position: position,
+                liquidity_delta: liquidity,
+                amount0_received: amount0,
+                amount1_received: amount1,
+                ..
+            } => {
+                let (balance_delta, _fee_delta) =
+                    self.modify_position(position, -to_i128(liquidity)?)?;
+                self.check_amounts(balance_delta, amount0, amount1);
+            }
+            CandidEventType::CollectedFees {
+                position,
+                amount0_collected,
+                amount1_collected,
+                ..
+            } => {
+                let (_balance_delta, fee_delta) = self.modify_position(position, 0)?;
+                self.check_amounts(fee_delta, amount0_collected, amount1_collected);
+            }
+            CandidEventType::Swap {
+                final_amount_in,
+                final_amount_out,
+                token_in,
+                token_out,
+                principal,
+                swap_fees,
+                hops,
+                ..
+            } => {
+                if hops.is_empty() {
+                    return Err(String::from("swap executed before events carried its hops"));
+                }
+                // like the canister, every hop is computed before any of them is applied
+                let mut swap_success_list = vec![];
+                for (i, hop) in hops.iter().enumerate() {
+                    let swap_params = SwapParams {
+                        pool_id: PoolId::try_from(hop.pool_id.clone())?,
+                        amount_specified: int_to_i256(hop.amount_specified.clone())?,
+                        zero_for_one: hop.zero_for_one,
+                        sqrt_price_limit_x96: get_sqrt_price_limit(hop.zero_for_one),
+                    };
+                    let hop_result =
+                        swap_inner(swap_params).map_err(|e| format!("hop {i} failed: {e:?}"))?;
+                    self.check(
+                        format!("hops[{i}].amount0_delta"),
+                        hop.amount0_delta.clone(),
+                        i256_to_int(hop_result.swap_delta.amount0()),
+                    );
+                    self.check(
+                        format!("hops[{i}].amount1_delta"),
+                        hop.amount1_delta.clone(),
+                        i256_to_int(hop_result.swap_delta.amount1()),
+                    );
+                    if let Some(swap_fee) = swap_fees.get(i) {
+                        self.check(
+                            format!("swap_fees[{i}]"),
+                            nat_to_int(swap_fee),
+                            nat_to_int(&Nat::from(hop_result.swap_fee)),
+                        );
+                    }
+                    swap_success_list.push(hop_result);
+                }
+                mutate_state(|s| apply_swap_hops(s, &swap_success_list));
+
+                let amount_in = to_u256(final_amount_in, "final_amount_in")?;
+                let amount_out = to_u256(final_amount_out, "final_amount_out")?;
+                self.debit(*principal, *token_in, amount_in);
+                self.credit(*principal, *token_out, amount_out);
+            }
+            CandidEventType::Donated {
+                pool_id,
+                amount0,
+                amount1,
+                principal,
+            } => {
+                let validated_args = ValidatedDonateArgs {
+                    pool_id: PoolId::try_from(pool_id.clone())?,
+                    amount0: to_u256(amount0, "amount0")?,
+                    amount1: to_u256(amount1, "amount1")?,
+                };
+                execute_donate(*principal, validated_args, event.timestamp)
+                    .map_err(|e| format!("failed to donate: {e:?}"))?;
+            }
+            CandidEventType::InternalTransfer {
+                token,
+                from,
+                to,
+                amount,
+                ..
+            } => {
+                let amount = to_u256(amount, "amount")?;
+                self.debit(*from, *token, amount);
+                self.credit(*to, *token, amount);
+            }
+            CandidEventType::Deposited {
+                token,
+                user,
+                amount,
+                balance,
+                ..
+            }
+            | CandidEventType::WithdrawalRolledBack {
+                token,
+                user,
+                amount,
+                balance,
+                ..
+            } => {
+                let replayed = self.credit(*user, *token, to_u256(amount, "amount")?);
+                self.check_balance(*user, *token, balance, replayed)?;
+            }
+            CandidEventType::Withdrawn {
+                token,
+                user,
+                amount,
+                balance,
+                ..
+            } => {
+                let replayed = self.debit(*user, *token, to_u256(amount, "amount")?);
+                self.check_balance(*user, *token, balance, replayed)?;
+            }
+            CandidEventType::TransferFeeUpdated {
+                token,
+                transfer_fee,
+            } => {
+                let transfer_fee = to_u256(transfer_fee, "transfer_fee")?;
+                mutate_state(|s| {
+                    s.update_token_transfer_fee_across_all_pools(*token, transfer_fee)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // modifies the liquidity of a position like the canister does and credits its owner, returns
+    // the balance and fee deltas
+    fn modify_position(
+        &mut self,
+        position: &CandidPositionKey,
+        liquidity_delta: i128,
+    ) -> Result<(BalanceDelta, BalanceDelta), String> {
+        let position_key = PositionKey::try_from(position.clone())?;
+        let pool = read_state(|s| s.get_pool(&position_key.pool_id))
+            .ok_or("the pool of the position is not initialized")?;
+
+        let success_result = modify_liquidity(ModifyLiquidityParams {
+            owner: position_key.owner,
+            pool_id: position_key.pool_id.clone(),
+            tick_lower: position_key.tick_lower,
+            tick_upper: position_key.tick_upper,
+            liquidity_delta,
+            tick_spacing: pool.tick_spacing,
+        })
+        .map_err(|e| format!("failed to modify liquidity: {e:?}"))?;
+
+        let credit = success_result
+            .balance_delta
+            .add(success_result.fee_delta)
+            .map_err(|_| String::from("amount overflow"))?;
+        self.update_balance(
+            position_key.owner,
+            position_key.pool_id.token0,
+            credit.amount0(),
+        );
+        self.update_balance(
+            position_key.owner,
+            position_key.pool_id.token1,
+            credit.amount1(),
+        );
+        mutate_state(|s| s.apply_modify_liquidity_buffer_state(success_result.buffer_state));
+
+        Ok((success_result.balance_delta, success_result.fee_delta))
+    }
+
+    fn credit(&mut self, user: Principal, token: Principal, amount: U256) -> U256 {
+        let balance = read_state(|s| s.get_user_balance(&UserBalanceKey { user, token })).0;
+        self.set_balance(user, token, balance.saturating_add(amount))
+    }
+
+    fn debit(&mut self, user: Principal, token: Principal, amount: U256) -> U256 {
+        let balance = read_state(|s| s.get_user_balance(&UserBalanceKey { user, token })).0;
+        let balance_after = balance.checked_sub(amount).unwrap_or_else(|| {
+            self.divergences.push(Divergence::InsufficientBalance {
+                event: self.event,
+                user,
+                token,
+                balance: u256_to_nat(balance),
+                debit: u256_to_nat(amount),
+            });
+            U256::ZERO
+        });
+        self.set_balance(user, token, balance_after)
+    }
+
+    fn update_balance(&mut self, user: Principal, token: Principal, delta: I256) -> U256 {
+        if delta < 0 {
+            self.debit(user, token, delta.unsigned_abs())
+        } else {
+            self.credit(user, token, delta.as_u256())
+        }
+    }
+
+    fn set_balance(&mut self, user: Principal, token: Principal, balance: U256) -> U256 {
+        mutate_state(|s| {
+            s.update_user_balance(UserBalanceKey { user, token }, UserBalance(balance))
+        });
+        balance
+    }
+
+    // the balance recorded by deposits and withdrawals is authoritative, the replayed one is
+    // reported and replaced when they differ so that a divergence is only reported once
+    fn check_balance(
+        &mut self,
+        user: Principal,
+        token: Principal,
+        recorded: &Nat,
+        replayed: U256,
+    ) -> Result<(), String> {
+        let recorded = to_u256(recorded, "balance")?;
+        if recorded != replayed {
+            self.check(
+                String::from("balance"),
+                u256_to_int(recorded),
+                u256_to_int(replayed),
+            );
+            self.set_balance(user, token, recorded);
+        }
+        Ok(())
+    }
+
+    // the amounts recorded by position events are the absolute values of the deltas
+    fn check_amounts(&mut self, delta: BalanceDelta, amount0: &Nat, amount1: &Nat) {
+        self.check(
+            String::from("amount0"),
+            nat_to_int(amount0),
+            u256_to_int(delta.amount0().unsigned_abs()),
+        );
+        self.check(
+            String::from("amount1"),
+            nat_to_int(amount1),
+            u256_to_int(delta.amount1().unsigned_abs()),
+        );
+    }
+
+    fn check(&mut self, field: String, recorded: Int, replayed: Int) {
+        if recorded != replayed {
+            self.divergences.push(Divergence::Amount {
+                event: self.event,
+                field,
+                recorded,
+                replayed,
+            });
+        }
+    }
+}
+
+fn to_u256(value: &Nat, field: &str) -> Result<U256, String> {
+    big_uint_to_u256(value.0.clone()).map_err(|e| format!("invalid {field}: {e}"))
+}
+
+fn to_i128(liquidity: &Nat) -> Result<i128, String> {
+    i128::try_from(liquidity.0.clone()).map_err(|_| String::from("invalid liquidity"))
+}
+
+fn nat_to_int(value: &Nat) -> Int {
+    Int::from(BigInt::from(value.0.clone()))
+}
+
+fn u256_to_int(value: U256) -> Int {
+    Int::from(BigInt::from(u256_to_big_uint(value)))
+}
@@ -0,0 +1,273 @@
+use candid::{Int, Nat, Principal};
+use ethnum::{I256, U256};
+
+use super::{compare_with_snapshot, replay_events, Divergence, StateSnapshot};
+use crate::{
+    balances::types::{UserBalance, UserBalanceKey},
+    candid_types::{
+        events::{CandidEvent, CandidEventType},
+        pool::{CandidPoolId, CandidPoolState, CreatePoolArgs},
+        tick::CandidTickInfo,
+        Balance,
+    },
+    collect_fees::execute_collect_fees,
+    events::{Event, EventType},
+    icrc_client::memo::MemoKind,
+    libraries::{
+        constants::FEE_TO_TICK_SPACING, safe_cast::u256_to_nat,
+        sqrt_price_math::tests::SQRT_PRICE_1_1,
+    },
+    mint::execute_mint_position,
+    pool::{
+        create_pool::create_pool_inner,
+        types::{PoolFee, PoolId, PoolTickSpacing},
+    },
+    position::types::PositionKey,
+    state::{mutate_state, read_state},
+    swap::execute_swap,
+    validation::{mint_args::ValidatedMintPositionArgs, swap_args::ValidatedSwapArgs},
+};
+
+fn user() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn token0() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn token1() -> Principal {
+    Principal::from_slice(&[3])
+}
+
+fn pool_id() -> PoolId {
+    PoolId {
+        token0: token0(),
+        token1: token1(),
+        fee: PoolFee(3_000),
+    }
+}
+
+fn position_key() -> PositionKey {
+    PositionKey {
+        owner: user(),
+        pool_id: pool_id(),
+        tick_lower: -600,
+        tick_upper: 600,
+    }
+}
+
+// credits the user like a deposit does
+fn deposit(token: Principal, amount: u128) {
+    mutate_state(|s| {
+        let key = UserBalanceKey {
+            user: user(),
+            token,
+        };
+        let balance = s.get_user_balance(&key).0 + U256::from(amount);
+        s.update_user_balance(key, UserBalance(balance));
+        s.record_event(Event {
+            timestamp: 0,
+            payload: EventType::Deposited {
+                token,
+                user: user(),
+                amount: U256::from(amount),
+                block_index: Some(0),
+                memo: MemoKind::Deposit,
+                balance,
+            },
+        });
+    });
+}
+
+// runs a pool through its life with the canister functions, returns the recorded events and a
+// snapshot of the resulting state
+fn live_history() -> (Vec<CandidEvent>, StateSnapshot) {
+    mutate_state(|s| {
+        for (fee, tick_spacing) in FEE_TO_TICK_SPACING {
+            s.set_tick_spacing(PoolFee(fee), PoolTickSpacing(tick_spacing));
+        }
+    });
+
+    create_pool_inner(
+        CreatePoolArgs {
+            token_a: token1(),
+            token_b: token0(),
+            fee: Nat::from(3_000_u32),
+            sqrt_price_x96: u256_to_nat(*SQRT_PRICE_1_1),
+            request_id: None,
+        },
+        U256::from(10_u8),
+        U256::from(20_u8),
+        None,
+        None,
+        0,
+    )
+    .unwrap();
+    deposit(token0(), 1_000_000_000_000);
+    deposit(token1(), 1_000_000_000_000);
+
+    execute_mint_position(
+        user(),
+        pool_id(),
+        token0(),
+        token1(),
+        ValidatedMintPositionArgs {
+            tick_spacing: PoolTickSpacing(60),
+            lower_tick: -600,
+            upper_tick: 600,
+            pool_id: pool_id(),
+            amount0_max: I256::from(100_000_000_000_i64),
+            amount1_max: I256::from(100_000_000_000_i64),
+        },
+        1,
+    )
+    .unwrap();
+    execute_swap(
+        &ValidatedSwapArgs::ExactInputSingle {
+            pool_id: pool_id(),
+            zero_for_one: true,
+            amount_in: I256::from(1_000_000_000_i64),
+            amount_out_minimum: I256::ZERO,
+            from_subaccount: None,
+            token_in: token0(),
+            token_out: token1(),
+        },
+        token0(),
+        token1(),
+        user(),
+        2,
+    )
+    .unwrap();
+    execute_swap(
+        &ValidatedSwapArgs::ExactOutputSingle {
+            pool_id: pool_id(),
+            zero_for_one: false,
+            amount_out: I256::from(500_000_000_i64),
+            amount_in_maximum: I256::from(1_000_000_000_i64),
+            from_subaccount: None,
+            token_in: token1(),
+            token_out: token0(),
+        },
+        token1(),
+        token0(),
+        user(),
+        3,
+    )
+    .unwrap();
+    execute_collect_fees(user(), &position_key(), PoolTickSpacing(60)).unwrap();
+
+    let events = read_state(|s| s.get_events(0, 100))
+        .into_iter()
+        .map(CandidEvent::from)
+        .collect();
+    let snapshot = StateSnapshot {
+        pools: read_state(|s| s.get_pools())
+            .into_iter()
+            .map(|(pool_id, state)| (CandidPoolId::from(pool_id), CandidPoolState::from(state)))
+            .collect(),
+        ticks: vec![(
+            CandidPoolId::from(pool_id()),
+            read_state(|s| s.get_ticks_for_pool(pool_id()))
+                .into_iter()
+                .map(CandidTickInfo::from)
+                .collect(),
+        )],
+        balances: vec![(
+            user(),
+            read_state(|s| s.get_user_balances(user()))
+                .into_iter()
+                .map(|(token, amount)| Balance {
+                    token,
+                    amount: u256_to_nat(amount),
+                })
+                .collect(),
+        )],
+    };
+    (events, snapshot)
+}
+
+// replays on a fresh thread local state
+fn replay(events: Vec<CandidEvent>, snapshot: StateSnapshot) -> (Vec<Divergence>, Vec<Divergence>) {
+    std::thread::spawn(move || (replay_events(0, &events), compare_with_snapshot(&snapshot)))
+        .join()
+        .expect("replay should not panic")
+}
+
+#[test]
+fn replaying_the_event_log_should_rebuild_the_state() {
+    let (events, snapshot) = live_history();
+    assert!(matches!(
+        events.last().map(|event| &event.payload),
+        Some(CandidEventType::CollectedFees { .. })
+    ));
+
+    assert_eq!(replay(events, snapshot), (vec![], vec![]));
+}
+
+#[test]
+fn tampered_events_should_be_reported() {
+    let (mut events, mut snapshot) = live_history();
+
+    let swap_index = events
+        .iter()
+        .position(|event| matches!(event.payload, CandidEventType::Swap { .. }))
+        .unwrap();
+    let CandidEventType::Swap { hops, .. } = &mut events[swap_index].payload else {
+        unreachable!()
+    };
+    let recorded_delta = hops[0].amount1_delta.clone();
+    hops[0].amount1_delta = Int::from(1);
+    let live_balance = &mut snapshot.balances[0].1[0];
+    live_balance.amount = live_balance.amount.clone() + Nat::from(1_u8);
+
+    let (divergences, comparison) = replay(events, snapshot.clone());
+    assert_eq!(
+        divergences,
+        vec![Divergence::Amount {
+            event: swap_index as u64,
+            field: String::from("hops[0].amount1_delta"),
+            recorded: Int::from(1),
+            replayed: recorded_delta,
+        }]
+    );
+    assert_eq!(
+        comparison,
+        vec![Divergence::Balance {
+            user: user(),
+            token: snapshot.balances[0].1[0].token,
+            live: snapshot.balances[0].1[0].amount.clone(),
+            replayed: snapshot.balances[0].1[0].amount.clone() - Nat::from(1_u8),
+        }]
+    );
+}
+
+#[test]
+fn events_without_replay_data_should_be_unreplayable() {
+    let (mut events, snapshot) = live_history();
+
+    for event in events.iter_mut() {
+        match &mut event.payload {
+            CandidEventType::CreatedPool { sqrt_price_x96, .. } => *sqrt_price_x96 = None,
+            CandidEventType::Swap { hops, .. } => hops.clear(),
+            _ => {}
+        }
+    }
+
+    let (divergences, comparison) = replay(events, snapshot);
+    assert!(matches!(
+        divergences[0],
+        Divergence::Unreplayable { event: 0, .. }
+    ));
+    assert!(divergences
+        .iter()
+        .any(|divergence| matches!(divergence, Divergence::Unreplayable { event: 4, .. })));
+    assert!(comparison.iter().any(|divergence| matches!(
+        divergence,
+        Divergence::Pool {
+            live: Some(_),
+            replayed: None,
+            ..
+        }
+    )));
+}
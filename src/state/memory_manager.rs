@@ -72,3 +72,153 @@ const EVENTS_DATA_MEMORY_ID: MemoryId = MemoryId::new(9);
 pub fn events_data_memory_id() -> StableMemory {
     MEMORY_MANAGER.with(|m| m.borrow().get(EVENTS_DATA_MEMORY_ID))
 }
+
+const DYNAMIC_FEE_CONFIGS_MEMORY_ID: MemoryId = MemoryId::new(10);
+
+pub fn dynamic_fee_configs_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(DYNAMIC_FEE_CONFIGS_MEMORY_ID))
+}
+
+const TOKEN_SETTINGS_MEMORY_ID: MemoryId = MemoryId::new(11);
+
+pub fn token_settings_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(TOKEN_SETTINGS_MEMORY_ID))
+}
+
+const OPEN_OPERATIONS_MEMORY_ID: MemoryId = MemoryId::new(12);
+
+pub fn open_operations_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(OPEN_OPERATIONS_MEMORY_ID))
+}
+
+const CLOSED_OPERATIONS_MEMORY_ID: MemoryId = MemoryId::new(13);
+
+pub fn closed_operations_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(CLOSED_OPERATIONS_MEMORY_ID))
+}
+
+const REQUEST_RESPONSES_MEMORY_ID: MemoryId = MemoryId::new(14);
+
+pub fn request_responses_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(REQUEST_RESPONSES_MEMORY_ID))
+}
+
+const REQUEST_EXPIRY_MEMORY_ID: MemoryId = MemoryId::new(15);
+
+pub fn request_expiry_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(REQUEST_EXPIRY_MEMORY_ID))
+}
+
+const RECONCILIATIONS_MEMORY_ID: MemoryId = MemoryId::new(16);
+
+pub fn reconciliations_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(RECONCILIATIONS_MEMORY_ID))
+}
+
+const INTERNAL_ALLOWANCES_MEMORY_ID: MemoryId = MemoryId::new(17);
+
+pub fn internal_allowances_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(INTERNAL_ALLOWANCES_MEMORY_ID))
+}
+
+const BLOCK_HASHES_MEMORY_ID: MemoryId = MemoryId::new(18);
+
+pub fn block_hashes_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(BLOCK_HASHES_MEMORY_ID))
+}
+
+const EVENTS_MEMORY_ID: MemoryId = MemoryId::new(19);
+
+pub fn events_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(EVENTS_MEMORY_ID))
+}
+
+const ARCHIVES_MEMORY_ID: MemoryId = MemoryId::new(20);
+
+pub fn archives_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(ARCHIVES_MEMORY_ID))
+}
+
+const ARCHIVE_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(21);
+
+pub fn archive_config_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(ARCHIVE_CONFIG_MEMORY_ID))
+}
+
+const EVENT_INDEX_MEMORY_ID: MemoryId = MemoryId::new(22);
+
+pub fn event_index_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(EVENT_INDEX_MEMORY_ID))
+}
+
+const TOKEN_DECIMALS_MEMORY_ID: MemoryId = MemoryId::new(23);
+
+pub fn token_decimals_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(TOKEN_DECIMALS_MEMORY_ID))
+}
+
+const HISTORY_BUCKETS_MEMORY_ID: MemoryId = MemoryId::new(24);
+
+pub fn history_buckets_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(HISTORY_BUCKETS_MEMORY_ID))
+}
+
+const BUCKET_TRADERS_MEMORY_ID: MemoryId = MemoryId::new(25);
+
+pub fn bucket_traders_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(BUCKET_TRADERS_MEMORY_ID))
+}
+
+const TOKEN_STATS_MEMORY_ID: MemoryId = MemoryId::new(26);
+
+pub fn token_stats_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(TOKEN_STATS_MEMORY_ID))
+}
+
+const TOKEN_BUCKETS_MEMORY_ID: MemoryId = MemoryId::new(27);
+
+pub fn token_buckets_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(TOKEN_BUCKETS_MEMORY_ID))
+}
+
+const PROTOCOL_STATS_MEMORY_ID: MemoryId = MemoryId::new(28);
+
+pub fn protocol_stats_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(PROTOCOL_STATS_MEMORY_ID))
+}
+
+const PROTOCOL_USERS_MEMORY_ID: MemoryId = MemoryId::new(29);
+
+pub fn protocol_users_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(PROTOCOL_USERS_MEMORY_ID))
+}
+
+const TOKEN_METADATA_MEMORY_ID: MemoryId = MemoryId::new(30);
+
+pub fn token_metadata_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(TOKEN_METADATA_MEMORY_ID))
+}
+
+const TOKEN_VALIDATION_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(31);
+
+pub fn token_validation_config_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(TOKEN_VALIDATION_CONFIG_MEMORY_ID))
+}
+
+const POOL_CREATION_POLICY_MEMORY_ID: MemoryId = MemoryId::new(32);
+
+pub fn pool_creation_policy_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(POOL_CREATION_POLICY_MEMORY_ID))
+}
+
+const LIABILITY_TOTALS_MEMORY_ID: MemoryId = MemoryId::new(33);
+
+pub fn liability_totals_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(LIABILITY_TOTALS_MEMORY_ID))
+}
+
+const VOLATILITY_WINDOWS_MEMORY_ID: MemoryId = MemoryId::new(34);
+
+pub fn volatility_windows_memory_id() -> StableMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(VOLATILITY_WINDOWS_MEMORY_ID))
+}
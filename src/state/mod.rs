@@ -8,27 +8,53 @@
 //  └──
 
 use crate::{
-    balances::types::{UserBalance, UserBalanceKey},
+    archive::types::{ArchiveConfig, ArchiveInfo},
+    balances::types::{InternalAllowance, InternalAllowanceKey, UserBalance, UserBalanceKey},
     candid_types::pool,
-    events::Event,
-    historical::types::PoolHistory,
+    events::{
+        index::{EventFilter, EventIndexKey, EventKind, IndexedBy},
+        Event,
+    },
+    historical::{
+        types::{
+            BucketTraderKey, HistoryBucket, HistoryBucketKey, PoolHistory, ProtocolStats,
+            TokenBucket, TokenBucketKey, TokenStats,
+        },
+        TimeFrame,
+    },
+    icrc3::{self, types::BlockHash},
+    idempotency::types::{CachedResponse, RequestExpiryKey, RequestKey},
+    journal::types::{Operation, OperationId},
     libraries::{constants::Q128, full_math::mul_div},
     pool::{
         modify_liquidity::ModifyLiquidityBufferState,
+        policy::PoolCreationPolicy,
         swap::SwapBufferState,
-        types::{PoolFee, PoolId, PoolState, PoolTickSpacing},
+        types::{
+            DynamicFeeConfig, PoolFee, PoolId, PoolState, PoolTickSpacing, VolatilityWindow,
+        },
     },
     position::types::{PositionInfo, PositionKey},
+    reconciliation::types::{LiabilityTotals, TokenReconciliation},
     tick::types::{BitmapWord, TickBitmapKey, TickInfo, TickKey},
+    tokens::types::{TokenMetadata, TokenSettings, TokenValidationConfig},
 };
 
 use candid::Principal;
 use ethnum::U256;
-use ic_stable_structures::{BTreeMap, Log};
+use ic_stable_structures::{BTreeMap, Cell, Log};
 use memory_manager::{
-    events_data_memory_id, events_index_memory_id, pool_history_memory_id, pools_memory_id,
-    positions_memory_id, protocol_balance_memory_id, tick_bitmaps_memory_id,
-    tick_spacings_memory_id, ticks_memory_id, user_balances_memory_id, StableMemory,
+    archive_config_memory_id, archives_memory_id, block_hashes_memory_id, bucket_traders_memory_id,
+    closed_operations_memory_id, dynamic_fee_configs_memory_id, event_index_memory_id,
+    events_data_memory_id, events_index_memory_id, events_memory_id, history_buckets_memory_id,
+    internal_allowances_memory_id, liability_totals_memory_id, open_operations_memory_id,
+    pool_creation_policy_memory_id, pool_history_memory_id, pools_memory_id, positions_memory_id,
+    protocol_balance_memory_id, protocol_stats_memory_id, protocol_users_memory_id,
+    reconciliations_memory_id, request_expiry_memory_id, request_responses_memory_id,
+    tick_bitmaps_memory_id, tick_spacings_memory_id, ticks_memory_id, token_buckets_memory_id,
+    token_decimals_memory_id, token_metadata_memory_id, token_settings_memory_id,
+    token_stats_memory_id, token_validation_config_memory_id, user_balances_memory_id,
+    volatility_windows_memory_id, StableMemory,
 };
 use std::cell::RefCell;
 
@@ -44,8 +70,33 @@ thread_local! {
         ticks: BTreeMap::init(ticks_memory_id()),
         tick_bitmaps: BTreeMap::init(tick_bitmaps_memory_id()),
         tick_spacings:BTreeMap::init(tick_spacings_memory_id()),
-        pool_history:BTreeMap::init(pool_history_memory_id()),
-        events:Log::init(events_data_memory_id(), events_index_memory_id()).expect("Failed to initialize events log")
+        dynamic_fee_configs: BTreeMap::init(dynamic_fee_configs_memory_id()),
+        volatility_windows: BTreeMap::init(volatility_windows_memory_id()),
+        token_settings: BTreeMap::init(token_settings_memory_id()),
+        token_metadata: BTreeMap::init(token_metadata_memory_id()),
+        token_validation_config: Cell::init(token_validation_config_memory_id(), TokenValidationConfig::default()).expect("Failed to initialize token validation config"),
+        pool_creation_policy: Cell::init(pool_creation_policy_memory_id(), PoolCreationPolicy::default()).expect("Failed to initialize pool creation policy"),
+        legacy_pool_history: BTreeMap::init(pool_history_memory_id()),
+        history_buckets: BTreeMap::init(history_buckets_memory_id()),
+        bucket_traders: BTreeMap::init(bucket_traders_memory_id()),
+        token_stats: BTreeMap::init(token_stats_memory_id()),
+        token_buckets: BTreeMap::init(token_buckets_memory_id()),
+        protocol_stats: Cell::init(protocol_stats_memory_id(), ProtocolStats::default()).expect("Failed to initialize protocol stats"),
+        protocol_users: BTreeMap::init(protocol_users_memory_id()),
+        legacy_token_decimals: BTreeMap::init(token_decimals_memory_id()),
+        open_operations: BTreeMap::init(open_operations_memory_id()),
+        closed_operations: BTreeMap::init(closed_operations_memory_id()),
+        request_responses: BTreeMap::init(request_responses_memory_id()),
+        request_expiry: BTreeMap::init(request_expiry_memory_id()),
+        reconciliations: BTreeMap::init(reconciliations_memory_id()),
+        liability_totals: BTreeMap::init(liability_totals_memory_id()),
+        internal_allowances: BTreeMap::init(internal_allowances_memory_id()),
+        block_hashes: BTreeMap::init(block_hashes_memory_id()),
+        legacy_events:Log::init(events_data_memory_id(), events_index_memory_id()).expect("Failed to initialize events log"),
+        events: BTreeMap::init(events_memory_id()),
+        event_index: BTreeMap::init(event_index_memory_id()),
+        archives: BTreeMap::init(archives_memory_id()),
+        archive_config: Cell::init(archive_config_memory_id(), ArchiveConfig::default()).expect("Failed to initialize archive config"),
     }));
 }
 
@@ -57,10 +108,47 @@ pub struct State {
     ticks: BTreeMap<TickKey, TickInfo, StableMemory>,
     tick_bitmaps: BTreeMap<TickBitmapKey, BitmapWord, StableMemory>,
     tick_spacings: BTreeMap<PoolFee, PoolTickSpacing, StableMemory>,
+    dynamic_fee_configs: BTreeMap<PoolId, DynamicFeeConfig, StableMemory>, // pools running in dynamic fee mode
+    volatility_windows: BTreeMap<PoolId, VolatilityWindow, StableMemory>, // of the dynamic fee pools
+    token_settings: BTreeMap<Principal, TokenSettings, StableMemory>, // tokens with non standard accounting
+    token_metadata: BTreeMap<Principal, TokenMetadata, StableMemory>, // the token registry
+    token_validation_config: Cell<TokenValidationConfig, StableMemory>,
+    pool_creation_policy: Cell<PoolCreationPolicy, StableMemory>,
 
     // historical data storage
-    pool_history: BTreeMap<PoolId, PoolHistory, StableMemory>,
-    events: Log<Event, StableMemory, StableMemory>,
+    legacy_pool_history: BTreeMap<PoolId, PoolHistory, StableMemory>, // migrated into `history_buckets`
+    history_buckets: BTreeMap<HistoryBucketKey, HistoryBucket, StableMemory>,
+    bucket_traders: BTreeMap<BucketTraderKey, (), StableMemory>, // swappers of each bucket
+    token_stats: BTreeMap<Principal, TokenStats, StableMemory>,
+    token_buckets: BTreeMap<TokenBucketKey, TokenBucket, StableMemory>, // daily token activity
+    protocol_stats: Cell<ProtocolStats, StableMemory>,
+    protocol_users: BTreeMap<Principal, (), StableMemory>, // users counted in `protocol_stats`
+    legacy_token_decimals: BTreeMap<Principal, u8, StableMemory>, // migrated into `token_metadata`
+    legacy_events: Log<Event, StableMemory, StableMemory>, // migrated into `events` on upgrade
+    events: BTreeMap<u64, Event, StableMemory>, // events not archived yet, keyed by event index
+    event_index: BTreeMap<EventIndexKey, (), StableMemory>, // held events by principal, pool and type
+
+    // operation journal, open operations still have a withdrawal in flight or waiting for a retry
+    open_operations: BTreeMap<OperationId, Operation, StableMemory>,
+    closed_operations: BTreeMap<OperationId, Operation, StableMemory>,
+
+    // responses of update calls made with a request id, indexed by creation time for expiry
+    request_responses: BTreeMap<RequestKey, CachedResponse, StableMemory>,
+    request_expiry: BTreeMap<RequestExpiryKey, (), StableMemory>,
+
+    // last solvency check of every token held by the canister
+    reconciliations: BTreeMap<Principal, TokenReconciliation, StableMemory>,
+    liability_totals: BTreeMap<Principal, LiabilityTotals, StableMemory>, // per token, see `insert_pool`
+
+    // allowances given to spenders over internal balances, see `approve_internal`
+    internal_allowances: BTreeMap<InternalAllowanceKey, InternalAllowance, StableMemory>,
+
+    // ICRC-3 hash chain over the events, keyed by event index
+    block_hashes: BTreeMap<u64, BlockHash, StableMemory>,
+
+    // archive canisters keyed by the index of their first event, see `archive::archive_events`
+    archives: BTreeMap<u64, ArchiveInfo, StableMemory>,
+    archive_config: Cell<ArchiveConfig, StableMemory>,
 }
 
 impl State {
@@ -141,6 +229,13 @@ impl State {
         Some((position, token0_owed, token1_owed))
     }
 
+    pub fn get_positions_for_pool(&self, pool_id: &PoolId) -> Vec<(PositionKey, PositionInfo)> {
+        self.positions
+            .iter()
+            .filter(|(key, _)| key.pool_id == *pool_id)
+            .collect()
+    }
+
     pub fn update_position(&mut self, key: PositionKey, info: PositionInfo) {
         self.positions.insert(key, info);
     }
@@ -166,7 +261,95 @@ impl State {
     }
 
     pub fn set_pool(&mut self, pool_id: PoolId, pool_state: PoolState) {
-        self.pools.insert(pool_id, pool_state);
+        self.insert_pool(pool_id, pool_state);
+    }
+
+    // only for pools whose creation was rolled back, nothing else references them yet
+    pub fn remove_pool(&mut self, pool_id: &PoolId) {
+        if let Some(pool) = self.pools.remove(pool_id) {
+            let reserves = (pool.pool_reserve0, pool.pool_reserve1);
+            self.update_reserve_totals(pool_id, reserves, (U256::ZERO, U256::ZERO));
+        }
+    }
+
+    // every write to a pool goes through here to keep the reserve totals in `liability_totals`
+    fn insert_pool(&mut self, pool_id: PoolId, pool_state: PoolState) {
+        let reserves = (pool_state.pool_reserve0, pool_state.pool_reserve1);
+        let previous = self
+            .pools
+            .insert(pool_id.clone(), pool_state)
+            .map_or((U256::ZERO, U256::ZERO), |pool| {
+                (pool.pool_reserve0, pool.pool_reserve1)
+            });
+        self.update_reserve_totals(&pool_id, previous, reserves);
+    }
+
+    fn update_reserve_totals(
+        &mut self,
+        pool_id: &PoolId,
+        (previous0, previous1): (U256, U256),
+        (reserve0, reserve1): (U256, U256),
+    ) {
+        for (token, previous, reserve) in [
+            (pool_id.token0, previous0, reserve0),
+            (pool_id.token1, previous1, reserve1),
+        ] {
+            if previous != reserve {
+                let mut totals = self.get_liability_totals(&token);
+                totals.pool_reserves = totals
+                    .pool_reserves
+                    .saturating_sub(previous)
+                    .saturating_add(reserve);
+                self.liability_totals.insert(token, totals);
+            }
+        }
+    }
+
+    pub fn get_dynamic_fee_config(&self, pool_id: &PoolId) -> Option<DynamicFeeConfig> {
+        self.dynamic_fee_configs.get(pool_id)
+    }
+
+    pub fn set_dynamic_fee_config(&mut self, pool_id: PoolId, config: Option<DynamicFeeConfig>) {
+        match config {
+            Some(config) => self.dynamic_fee_configs.insert(pool_id, config),
+            None => self.dynamic_fee_configs.remove(&pool_id),
+        };
+    }
+
+    pub fn get_dynamic_fee_configs(&self) -> Vec<(PoolId, DynamicFeeConfig)> {
+        self.dynamic_fee_configs.iter().collect()
+    }
+
+    pub fn get_volatility_window(&self, pool_id: &PoolId) -> Option<VolatilityWindow> {
+        self.volatility_windows.get(pool_id)
+    }
+
+    pub fn set_volatility_window(&mut self, pool_id: PoolId, window: Option<VolatilityWindow>) {
+        match window {
+            Some(window) => self.volatility_windows.insert(pool_id, window),
+            None => self.volatility_windows.remove(&pool_id),
+        };
+    }
+
+    pub fn get_token_settings(&self, token: &Principal) -> TokenSettings {
+        self.token_settings.get(token).unwrap_or_default()
+    }
+
+    pub fn set_token_settings(&mut self, token: Principal, settings: TokenSettings) {
+        if settings == TokenSettings::default() {
+            self.token_settings.remove(&token);
+        } else {
+            self.token_settings.insert(token, settings);
+        }
+    }
+
+    // a pool is flagged if any of its tokens needs balance difference accounting
+    pub fn is_fee_on_transfer_pool(&self, pool_id: &PoolId) -> bool {
+        self.get_token_settings(&pool_id.token0)
+            .is_balance_difference()
+            || self
+                .get_token_settings(&pool_id.token1)
+                .is_balance_difference()
     }
 
     pub fn get_bitmap_word(&self, bitmap_key: &TickBitmapKey) -> BitmapWord {
@@ -175,6 +358,18 @@ impl State {
             .unwrap_or(BitmapWord(U256::ZERO))
     }
 
+    // non empty words of the tick bitmap of a pool, keyed by word position
+    pub fn get_bitmap_words_for_pool(
+        &self,
+        pool_id: &PoolId,
+    ) -> std::collections::BTreeMap<i16, U256> {
+        self.tick_bitmaps
+            .iter()
+            .filter(|(key, word)| key.pool_id == *pool_id && word.0 != U256::ZERO)
+            .map(|(key, word)| (key.word_pos, word.0))
+            .collect()
+    }
+
     pub fn set_bitmap_word(&mut self, bitmap_key: TickBitmapKey, bitmap_word: BitmapWord) {
         self.tick_bitmaps.insert(bitmap_key, bitmap_word);
     }
@@ -198,8 +393,53 @@ impl State {
             .collect()
     }
 
+    pub fn get_total_user_balance(&self, token: &Principal) -> U256 {
+        self.get_liability_totals(token).user_balances
+    }
+
     pub fn update_user_balance(&mut self, key: UserBalanceKey, value: UserBalance) {
-        self.user_balances.insert(key, value);
+        let (token, balance) = (key.token, value.0);
+        let previous = self
+            .user_balances
+            .insert(key, value)
+            .map_or(U256::ZERO, |previous| previous.0);
+        if previous != balance {
+            let mut totals = self.get_liability_totals(&token);
+            totals.user_balances = totals
+                .user_balances
+                .saturating_sub(previous)
+                .saturating_add(balance);
+            self.liability_totals.insert(token, totals);
+        }
+    }
+
+    pub fn get_liability_totals(&self, token: &Principal) -> LiabilityTotals {
+        self.liability_totals.get(token).unwrap_or_default()
+    }
+
+    // computes the totals of the balances and pools stored before they were kept, runs once on the
+    // first upgrade with `liability_totals`
+    pub fn seed_liability_totals(&mut self) {
+        if !self.liability_totals.is_empty() {
+            return;
+        }
+        let mut totals = std::collections::BTreeMap::<Principal, LiabilityTotals>::new();
+        for (key, balance) in self.user_balances.iter() {
+            let total = totals.entry(key.token).or_default();
+            total.user_balances = total.user_balances.saturating_add(balance.0);
+        }
+        for (pool_id, pool) in self.pools.iter() {
+            for (token, reserve) in [
+                (pool_id.token0, pool.pool_reserve0),
+                (pool_id.token1, pool.pool_reserve1),
+            ] {
+                let total = totals.entry(token).or_default();
+                total.pool_reserves = total.pool_reserves.saturating_add(reserve);
+            }
+        }
+        for (token, total) in totals {
+            self.liability_totals.insert(token, total);
+        }
     }
 
     pub fn get_protocol_fee_for_token(&mut self, token: &Principal) -> UserBalance {
@@ -212,13 +452,19 @@ impl State {
         self.protocol_balance.insert(token, value);
     }
 
+    pub fn get_protocol_balance(&self, token: &Principal) -> U256 {
+        self.protocol_balance
+            .get(token)
+            .map_or(U256::ZERO, |balance| balance.0)
+    }
+
     pub fn apply_modify_liquidity_buffer_state(
         &mut self,
         buffer_state: ModifyLiquidityBufferState,
     ) {
         // pool state transition
         let pool_id = buffer_state.pool.0;
-        self.pools.insert(pool_id, buffer_state.pool.1);
+        self.insert_pool(pool_id, buffer_state.pool.1);
 
         //ticks state transition
         if buffer_state.tick_lower.1 == TickInfo::default() {
@@ -259,7 +505,7 @@ impl State {
     pub fn apply_swap_buffer_state(&mut self, buffer_state: SwapBufferState) {
         // pool state transition
         let pool_id = buffer_state.pool.0;
-        self.pools.insert(pool_id, buffer_state.pool.1);
+        self.insert_pool(pool_id, buffer_state.pool.1);
 
         for tick in buffer_state.shifted_ticks.into_iter() {
             if tick.1 == TickInfo::default() {
@@ -287,35 +533,625 @@ impl State {
             } else {
                 new_pool_state.token1_transfer_fee = transfer_fee
             };
-            self.pools.insert(pool.0, new_pool_state);
+            self.insert_pool(pool.0, new_pool_state);
         }
     }
 
+    // all the buckets of a pool, oldest first in every frame
     pub fn get_pool_history(&self, pool_id: &PoolId) -> PoolHistory {
-        self.pool_history.get(pool_id).unwrap_or_default()
+        let mut pool_history = PoolHistory::default();
+        let buckets = self
+            .history_buckets
+            .range(Self::history_range(pool_id, None));
+        for (key, bucket) in buckets {
+            pool_history.get_frame_mut(key.timeframe).push(bucket);
+        }
+        pool_history
+    }
+
+    // buckets of a frame starting in `[from, to)`, oldest first
+    pub fn get_history_buckets(
+        &self,
+        pool_id: &PoolId,
+        timeframe: TimeFrame,
+        from: u64,
+        to: u64,
+    ) -> Vec<HistoryBucket> {
+        let key = |start_timestamp| HistoryBucketKey {
+            pool_id: pool_id.clone(),
+            timeframe,
+            start_timestamp,
+        };
+        self.history_buckets
+            .range(key(from)..key(to.max(from)))
+            .map(|(_, bucket)| bucket)
+            .collect()
+    }
+
+    pub fn get_history_bucket_starts(&self, pool_id: &PoolId, timeframe: TimeFrame) -> Vec<u64> {
+        self.history_buckets
+            .range(Self::history_range(pool_id, Some(timeframe)))
+            .map(|(key, _)| key.start_timestamp)
+            .collect()
+    }
+
+    pub fn get_history_bucket(&self, key: &HistoryBucketKey) -> Option<HistoryBucket> {
+        self.history_buckets.get(key)
+    }
+
+    pub fn set_history_bucket(&mut self, key: HistoryBucketKey, bucket: HistoryBucket) {
+        self.history_buckets.insert(key, bucket);
+    }
+
+    // removes the bucket along with its traders
+    pub fn remove_history_bucket(&mut self, key: &HistoryBucketKey) {
+        self.history_buckets.remove(key);
+        let traders: Vec<BucketTraderKey> = self
+            .bucket_traders
+            .range(
+                BucketTraderKey {
+                    bucket: key.clone(),
+                    trader: Principal::management_canister(),
+                }..,
+            )
+            .take_while(|(trader_key, _)| &trader_key.bucket == key)
+            .map(|(trader_key, _)| trader_key)
+            .collect();
+        for trader_key in traders {
+            self.bucket_traders.remove(&trader_key);
+        }
+    }
+
+    // true when the trader had not swapped in the bucket yet
+    pub fn add_bucket_trader(&mut self, bucket: HistoryBucketKey, trader: Principal) -> bool {
+        self.bucket_traders
+            .insert(BucketTraderKey { bucket, trader }, ())
+            .is_none()
+    }
+
+    pub fn get_bucket_traders(&self, bucket: &HistoryBucketKey) -> Vec<Principal> {
+        self.bucket_traders
+            .range(
+                BucketTraderKey {
+                    bucket: bucket.clone(),
+                    trader: Principal::management_canister(),
+                }..,
+            )
+            .take_while(|(trader_key, _)| &trader_key.bucket == bucket)
+            .map(|(trader_key, _)| trader_key.trader)
+            .collect()
+    }
+
+    pub fn get_token_stats(&self, token: &Principal) -> Option<TokenStats> {
+        self.token_stats.get(token)
+    }
+
+    pub fn get_all_token_stats(&self) -> Vec<(Principal, TokenStats)> {
+        self.token_stats.iter().collect()
+    }
+
+    pub fn set_token_stats(&mut self, token: Principal, stats: TokenStats) {
+        self.token_stats.insert(token, stats);
+    }
+
+    // days of a token starting in `[from, to)`, oldest first
+    pub fn get_token_buckets(&self, token: &Principal, from: u64, to: u64) -> Vec<TokenBucket> {
+        let key = |start_timestamp| TokenBucketKey {
+            token: *token,
+            start_timestamp,
+        };
+        self.token_buckets
+            .range(key(from)..key(to.max(from)))
+            .map(|(_, bucket)| bucket)
+            .collect()
+    }
+
+    pub fn get_token_bucket(&self, key: &TokenBucketKey) -> Option<TokenBucket> {
+        self.token_buckets.get(key)
+    }
+
+    pub fn set_token_bucket(&mut self, key: TokenBucketKey, bucket: TokenBucket) {
+        self.token_buckets.insert(key, bucket);
+    }
+
+    pub fn remove_token_bucket(&mut self, key: &TokenBucketKey) {
+        self.token_buckets.remove(key);
+    }
+
+    pub fn get_protocol_stats(&self) -> ProtocolStats {
+        self.protocol_stats.get().clone()
+    }
+
+    pub fn set_protocol_stats(&mut self, stats: ProtocolStats) {
+        self.protocol_stats
+            .set(stats)
+            .expect("Setting the protocol stats should be successful");
+    }
+
+    // true when the user had not been counted in the protocol stats yet
+    pub fn add_protocol_user(&mut self, user: Principal) -> bool {
+        self.protocol_users.insert(user, ()).is_none()
+    }
+
+    // keys of the buckets of a pool, or of one of its frames
+    fn history_range(
+        pool_id: &PoolId,
+        timeframe: Option<TimeFrame>,
+    ) -> std::ops::RangeInclusive<HistoryBucketKey> {
+        let key = |timeframe, start_timestamp| HistoryBucketKey {
+            pool_id: pool_id.clone(),
+            timeframe,
+            start_timestamp,
+        };
+        match timeframe {
+            Some(timeframe) => key(timeframe, 0)..=key(timeframe, u64::MAX),
+            None => key(TimeFrame::FiveMinutes, 0)..=key(TimeFrame::Yearly, u64::MAX),
+        }
+    }
+
+    // moves the histories stored as one value per pool into the bucket map, runs once on the first
+    // upgrade with the bucket map
+    pub fn migrate_pool_history(&mut self) {
+        if self.legacy_pool_history.is_empty() {
+            return;
+        }
+        for (pool_id, pool_history) in self.legacy_pool_history.iter() {
+            for (timeframe, frame) in pool_history.into_frames() {
+                for bucket in frame {
+                    let key = HistoryBucketKey {
+                        pool_id: pool_id.clone(),
+                        timeframe,
+                        start_timestamp: bucket.start_timestamp,
+                    };
+                    self.history_buckets.insert(key, bucket);
+                }
+            }
+        }
+        self.legacy_pool_history = BTreeMap::new(pool_history_memory_id());
+    }
+
+    #[cfg(test)]
+    pub fn set_legacy_pool_history(&mut self, pool_id: PoolId, pool_history: PoolHistory) {
+        self.legacy_pool_history.insert(pool_id, pool_history);
+    }
+
+    // None for tokens of pools created before decimals were stored and not refreshed since
+    pub fn get_token_decimals(&self, token: &Principal) -> Option<u8> {
+        self.token_metadata
+            .get(token)
+            .map(|metadata| metadata.decimals)
+    }
+
+    pub fn get_token_metadata(&self, token: &Principal) -> Option<TokenMetadata> {
+        self.token_metadata.get(token)
+    }
+
+    pub fn set_token_metadata(&mut self, token: Principal, metadata: TokenMetadata) {
+        self.token_metadata.insert(token, metadata);
+    }
+
+    pub fn get_all_token_metadata(&self) -> Vec<(Principal, TokenMetadata)> {
+        self.token_metadata.iter().collect()
+    }
+
+    pub fn get_token_validation_config(&self) -> TokenValidationConfig {
+        *self.token_validation_config.get()
     }
 
-    pub fn set_pool_history(&mut self, pool_id: PoolId, pool_history: PoolHistory) {
-        self.pool_history.insert(pool_id, pool_history);
+    pub fn set_token_validation_config(&mut self, config: TokenValidationConfig) {
+        self.token_validation_config
+            .set(config)
+            .expect("Setting the token validation config should be successful");
+    }
+
+    pub fn get_pool_creation_policy(&self) -> PoolCreationPolicy {
+        self.pool_creation_policy.get().clone()
+    }
+
+    pub fn set_pool_creation_policy(&mut self, policy: PoolCreationPolicy) {
+        self.pool_creation_policy
+            .set(policy)
+            .expect("Setting the pool creation policy should be successful");
+    }
+
+    // moves the decimals stored before metadata was cached into the metadata, runs once on the
+    // first upgrade with metadata
+    pub fn migrate_token_decimals(&mut self) {
+        if self.legacy_token_decimals.is_empty() {
+            return;
+        }
+        for (token, decimals) in self.legacy_token_decimals.iter() {
+            if !self.token_metadata.contains_key(&token) {
+                let metadata = TokenMetadata {
+                    decimals,
+                    ..Default::default()
+                };
+                self.token_metadata.insert(token, metadata);
+            }
+        }
+        self.legacy_token_decimals = BTreeMap::new(token_decimals_memory_id());
+    }
+
+    #[cfg(test)]
+    pub fn set_legacy_token_decimals(&mut self, token: Principal, decimals: u8) {
+        self.legacy_token_decimals.insert(token, decimals);
     }
 
     pub fn record_event(&mut self, event: Event) {
-        self.events
-            .append(&event)
-            .expect("Recording an event should be successful");
+        let event_index = self.total_event_count();
+        self.index_event(event_index, &event);
+        self.events.insert(event_index, event);
+        if let Some((last_block_index, last_block_hash)) = self.hash_pending_blocks() {
+            icrc3::certify_tip(last_block_index, last_block_hash);
+        }
+    }
+
+    // moves the events of the append-only log used before archiving into the event map, runs
+    // once on the first upgrade with archiving
+    pub fn migrate_legacy_events(&mut self) {
+        if self.legacy_events.len() == 0 {
+            return;
+        }
+        assert!(
+            self.events.is_empty() && self.archives.is_empty(),
+            "BUG: legacy events should be migrated before any new event is recorded"
+        );
+        for (index, event) in self.legacy_events.iter().enumerate() {
+            self.events.insert(index as u64, event);
+        }
+        self.legacy_events = Log::new(events_data_memory_id(), events_index_memory_id());
+    }
+
+    fn index_event(&mut self, event_index: u64, event: &Event) {
+        for indexed_by in event.payload.indexed_by() {
+            self.event_index.insert(
+                EventIndexKey {
+                    indexed_by,
+                    event_index,
+                },
+                (),
+            );
+        }
+    }
+
+    // indexes the events recorded before the event index existed, runs once on the first upgrade
+    // with the index
+    pub fn index_held_events(&mut self) {
+        if !self.event_index.is_empty() {
+            return;
+        }
+        let held: Vec<(u64, Event)> = self.events.iter().collect();
+        for (event_index, event) in held {
+            self.index_event(event_index, &event);
+        }
+    }
+
+    // the held events matching `filter` with an index of at least `cursor`, at most `limit` of
+    // them and at most `max_scanned` events read. Returns the index to resume from when more
+    // events could match
+    pub fn get_filtered_events(
+        &self,
+        filter: &EventFilter,
+        cursor: u64,
+        limit: usize,
+        max_scanned: usize,
+    ) -> (Vec<(u64, Event)>, Option<u64>) {
+        // timestamps of events never decrease with their index
+        let start = match filter.start_time {
+            Some(start_time) => cursor.max(self.first_event_at_or_after(start_time)),
+            None => cursor,
+        };
+
+        let candidates: Box<dyn Iterator<Item = u64> + '_> = match filter.indexes() {
+            Some(indexes) => Box::new(self.indexed_events(indexes, start)),
+            None => Box::new(self.events.range(start..).map(|(index, _)| index)),
+        };
+
+        let mut events = vec![];
+        for (scanned, index) in candidates.enumerate() {
+            if events.len() >= limit || scanned >= max_scanned {
+                return (events, Some(index));
+            }
+            let Some(event) = self.events.get(&index) else {
+                continue;
+            };
+            if filter
+                .end_time
+                .is_some_and(|end_time| event.timestamp >= end_time)
+            {
+                break;
+            }
+            if filter.matches(&event) {
+                events.push((index, event));
+            }
+        }
+        (events, None)
+    }
+
+    // indexes of the events listed in any of `indexes` from `start`, in ascending order
+    fn indexed_events(
+        &self,
+        indexes: Vec<IndexedBy>,
+        start: u64,
+    ) -> impl Iterator<Item = u64> + '_ {
+        let mut ranges: Vec<_> = indexes
+            .into_iter()
+            .map(|indexed_by| {
+                self.event_index
+                    .range(
+                        EventIndexKey {
+                            indexed_by: indexed_by.clone(),
+                            event_index: start,
+                        }..,
+                    )
+                    .take_while(move |(key, _)| key.indexed_by == indexed_by)
+                    .map(|(key, _)| key.event_index)
+                    .peekable()
+            })
+            .collect();
+        std::iter::from_fn(move || {
+            let (_, next) = ranges
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(position, range)| range.peek().map(|index| (*index, position)))
+                .min()?;
+            let index = ranges[next].next()?;
+            // an event listed in several of the indexes is returned once
+            for range in ranges.iter_mut() {
+                range.next_if_eq(&index);
+            }
+            Some(index)
+        })
+    }
+
+    // the most recent held swaps of `user`, at most `limit` of them starting with the latest
+    pub fn get_user_swaps(&self, user: Principal, limit: usize) -> Vec<(u64, Event)> {
+        let user_events: Vec<u64> = self
+            .indexed_events(vec![IndexedBy::Principal(user)], 0)
+            .collect();
+        user_events
+            .into_iter()
+            .rev()
+            .filter_map(|index| self.events.get(&index).map(|event| (index, event)))
+            .filter(|(_, event)| event.payload.kind() == EventKind::Swap)
+            .take(limit)
+            .collect()
+    }
+
+    // index of the first held event recorded at or after `timestamp`, the number of events when
+    // there is none
+    pub fn first_event_at_or_after(&self, timestamp: u64) -> u64 {
+        let (mut low, mut high) = (self.first_held_event_index(), self.total_event_count());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.events.get(&middle) {
+                Some(event) if event.timestamp < timestamp => low = middle + 1,
+                _ => high = middle,
+            }
+        }
+        low
+    }
+
+    // None for archived events
+    pub fn get_event(&self, index: u64) -> Option<Event> {
+        self.events.get(&index)
+    }
+
+    pub fn get_block_hash(&self, index: u64) -> Option<BlockHash> {
+        self.block_hashes.get(&index)
+    }
+
+    pub fn last_block_hash(&self) -> Option<(u64, BlockHash)> {
+        self.block_hashes.last_key_value()
+    }
+
+    // chains the events recorded since the last hashed block, events recorded before the hash
+    // chain existed are hashed on the first call, returns the new tip
+    pub fn hash_pending_blocks(&mut self) -> Option<(u64, BlockHash)> {
+        let mut tip = self.last_block_hash();
+        let next_index = tip.map_or(0, |(index, _)| index + 1);
+        let pending: Vec<(u64, Event)> = self.events.range(next_index..).collect();
+        for (index, event) in pending {
+            let hash = icrc3::block_hash(&event, tip.map(|(_, hash)| hash));
+            self.block_hashes.insert(index, hash);
+            tip = Some((index, hash));
+        }
+        tip
     }
 
+    // events held by this canister with an index in `[start, start + length)`
     pub fn get_events(&self, start: u64, length: u64) -> Vec<Event> {
         self.events
-            .iter()
-            .skip(start as usize)
-            .take(length as usize)
+            .range(start..start.saturating_add(length))
+            .map(|(_, event)| event)
             .collect()
     }
 
     pub fn total_event_count(&self) -> u64 {
+        let held = self.events.last_key_value().map(|(index, _)| index + 1);
+        held.unwrap_or_default().max(self.first_held_event_index())
+    }
+
+    // events below this index live in archive canisters
+    pub fn first_held_event_index(&self) -> u64 {
+        self.archives
+            .last_key_value()
+            .map(|(_, archive)| archive.end)
+            .unwrap_or_default()
+    }
+
+    pub fn held_event_count(&self) -> u64 {
         self.events.len()
     }
+
+    pub fn get_archives(&self) -> Vec<(u64, ArchiveInfo)> {
+        self.archives.iter().collect()
+    }
+
+    pub fn last_archive(&self) -> Option<(u64, ArchiveInfo)> {
+        self.archives.last_key_value()
+    }
+
+    pub fn set_archive(&mut self, start: u64, archive: ArchiveInfo) {
+        self.archives.insert(start, archive);
+    }
+
+    // removes the events sent to the archive starting at `archive_start` up to `end` and the
+    // hashes no longer needed to chain the held events
+    pub fn record_archived_events(&mut self, archive_start: u64, end: u64) {
+        let mut archive = self
+            .archives
+            .get(&archive_start)
+            .expect("BUG: events should only be sent to known archives");
+        let archived: Vec<u64> = self
+            .events
+            .range(..end)
+            .map(|(index, _)| index)
+            .collect();
+        for index in archived {
+            if let Some(event) = self.events.remove(&index) {
+                for indexed_by in event.payload.indexed_by() {
+                    self.event_index.remove(&EventIndexKey {
+                        indexed_by,
+                        event_index: index,
+                    });
+                }
+            }
+        }
+        archive.end = archive.end.max(end);
+        self.archives.insert(archive_start, archive);
+
+        // the hash of the last archived block is kept as the parent of the first held block
+        let pruned: Vec<u64> = self
+            .block_hashes
+            .range(..end.saturating_sub(1))
+            .map(|(index, _)| index)
+            .collect();
+        for index in pruned {
+            self.block_hashes.remove(&index);
+        }
+    }
+
+    pub fn get_archive_config(&self) -> ArchiveConfig {
+        self.archive_config.get().clone()
+    }
+
+    pub fn set_archive_config(&mut self, config: ArchiveConfig) {
+        self.archive_config
+            .set(config)
+            .expect("Setting the archive config should be successful");
+    }
+
+    pub fn next_operation_id(&self) -> OperationId {
+        let last_open = self.open_operations.last_key_value().map(|(id, _)| id);
+        let last_closed = self.closed_operations.last_key_value().map(|(id, _)| id);
+        last_open
+            .max(last_closed)
+            .map(|id| id + 1)
+            .unwrap_or_default()
+    }
+
+    pub fn get_open_operation(&self, operation_id: OperationId) -> Option<Operation> {
+        self.open_operations.get(&operation_id)
+    }
+
+    pub fn get_open_operations(&self) -> Vec<(OperationId, Operation)> {
+        self.open_operations.iter().collect()
+    }
+
+    pub fn upsert_open_operation(&mut self, operation_id: OperationId, operation: Operation) {
+        self.open_operations.insert(operation_id, operation);
+    }
+
+    pub fn remove_open_operation(&mut self, operation_id: OperationId) {
+        self.open_operations.remove(&operation_id);
+    }
+
+    pub fn get_closed_operation(&self, operation_id: OperationId) -> Option<Operation> {
+        self.closed_operations.get(&operation_id)
+    }
+
+    pub fn insert_closed_operation(&mut self, operation_id: OperationId, operation: Operation) {
+        self.closed_operations.insert(operation_id, operation);
+    }
+
+    pub fn remove_closed_operation(&mut self, operation_id: OperationId) {
+        self.closed_operations.remove(&operation_id);
+    }
+
+    pub fn oldest_closed_operation(&self) -> Option<(OperationId, Operation)> {
+        self.closed_operations.first_key_value()
+    }
+
+    pub fn last_closed_operation_id(&self) -> Option<OperationId> {
+        self.closed_operations.last_key_value().map(|(id, _)| id)
+    }
+
+    pub fn closed_operation_count(&self) -> u64 {
+        self.closed_operations.len()
+    }
+
+    pub fn get_cached_response(&self, key: &RequestKey) -> Option<CachedResponse> {
+        self.request_responses.get(key)
+    }
+
+    pub fn insert_cached_response(&mut self, key: RequestKey, response: CachedResponse) {
+        self.remove_cached_response(&key);
+        self.request_expiry.insert(
+            RequestExpiryKey {
+                created_at: response.created_at,
+                key: key.clone(),
+            },
+            (),
+        );
+        self.request_responses.insert(key, response);
+    }
+
+    pub fn remove_cached_response(&mut self, key: &RequestKey) {
+        if let Some(response) = self.request_responses.remove(key) {
+            self.request_expiry.remove(&RequestExpiryKey {
+                created_at: response.created_at,
+                key: key.clone(),
+            });
+        }
+    }
+
+    pub fn oldest_cached_response(&self) -> Option<RequestExpiryKey> {
+        self.request_expiry.first_key_value().map(|(key, _)| key)
+    }
+
+    pub fn cached_response_count(&self) -> u64 {
+        self.request_responses.len()
+    }
+
+    pub fn get_reconciliation(&self, token: &Principal) -> Option<TokenReconciliation> {
+        self.reconciliations.get(token)
+    }
+
+    pub fn get_reconciliations(&self) -> Vec<(Principal, TokenReconciliation)> {
+        self.reconciliations.iter().collect()
+    }
+
+    pub fn set_reconciliation(&mut self, token: Principal, reconciliation: TokenReconciliation) {
+        self.reconciliations.insert(token, reconciliation);
+    }
+
+    pub fn get_internal_allowance(&self, key: &InternalAllowanceKey) -> InternalAllowance {
+        self.internal_allowances.get(key).unwrap_or_default()
+    }
+
+    // an allowance with nothing left to spend is removed instead of being stored
+    pub fn set_internal_allowance(
+        &mut self,
+        key: InternalAllowanceKey,
+        allowance: InternalAllowance,
+    ) {
+        if allowance.amount == U256::ZERO {
+            self.internal_allowances.remove(&key);
+        } else {
+            self.internal_allowances.insert(key, allowance);
+        }
+    }
 }
 
 pub fn read_state<R>(f: impl FnOnce(&State) -> R) -> R {
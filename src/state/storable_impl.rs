@@ -2,12 +2,26 @@ use ic_stable_structures::{storable::Bound, storable::Storable};
 use std::borrow::Cow;
 
 use crate::{
-    balances::types::{UserBalance, UserBalanceKey},
-    events::{Event, EventType},
-    historical::types::{HistoryBucket, PoolHistory},
-    pool::types::{PoolFee, PoolId, PoolState, PoolTickSpacing},
+    archive::types::{ArchiveConfig, ArchiveInfo, ArchivedEvent},
+    balances::types::{InternalAllowance, InternalAllowanceKey, UserBalance, UserBalanceKey},
+    events::{index::EventIndexKey, Event, EventType},
+    historical::types::{
+        BucketTraderKey, HistoryBucket, HistoryBucketKey, PoolHistory, ProtocolStats, TokenBucket,
+        TokenBucketKey, TokenStats,
+    },
+    icrc3::types::BlockHash,
+    idempotency::types::{CachedResponse, RequestExpiryKey, RequestKey},
+    journal::types::Operation,
+    pool::{
+        policy::PoolCreationPolicy,
+        types::{
+            DynamicFeeConfig, PoolFee, PoolId, PoolState, PoolTickSpacing, VolatilityWindow,
+        },
+    },
     position::types::{PositionInfo, PositionKey},
+    reconciliation::types::{LiabilityTotals, TokenReconciliation},
     tick::types::{BitmapWord, TickBitmapKey, TickInfo, TickKey},
+    tokens::types::{TokenMetadata, TokenSettings, TokenValidationConfig},
 };
 
 macro_rules! impl_storable_minicbor {
@@ -50,3 +64,28 @@ impl_storable_minicbor!(HistoryBucket);
 impl_storable_minicbor!(PoolHistory);
 impl_storable_minicbor!(EventType);
 impl_storable_minicbor!(Event);
+impl_storable_minicbor!(DynamicFeeConfig);
+impl_storable_minicbor!(VolatilityWindow);
+impl_storable_minicbor!(TokenSettings);
+impl_storable_minicbor!(TokenMetadata);
+impl_storable_minicbor!(TokenValidationConfig);
+impl_storable_minicbor!(PoolCreationPolicy);
+impl_storable_minicbor!(Operation);
+impl_storable_minicbor!(RequestKey);
+impl_storable_minicbor!(CachedResponse);
+impl_storable_minicbor!(RequestExpiryKey);
+impl_storable_minicbor!(TokenReconciliation);
+impl_storable_minicbor!(LiabilityTotals);
+impl_storable_minicbor!(InternalAllowanceKey);
+impl_storable_minicbor!(InternalAllowance);
+impl_storable_minicbor!(BlockHash);
+impl_storable_minicbor!(ArchiveConfig);
+impl_storable_minicbor!(ArchiveInfo);
+impl_storable_minicbor!(ArchivedEvent);
+impl_storable_minicbor!(EventIndexKey);
+impl_storable_minicbor!(HistoryBucketKey);
+impl_storable_minicbor!(BucketTraderKey);
+impl_storable_minicbor!(TokenStats);
+impl_storable_minicbor!(TokenBucket);
+impl_storable_minicbor!(TokenBucketKey);
+impl_storable_minicbor!(ProtocolStats);
@@ -4,13 +4,14 @@ use ethnum::{I256, U256};
 use crate::{
     balances::types::{UserBalance, UserBalanceKey},
     candid_types::swap::SwapFailedReason,
-    events::{Event, EventType},
+    events::{Event, EventType, SwapHop},
+    historical::{pools_before, update_pool_history},
     pool::{
         swap::{swap_inner, SwapParams, SwapSuccess},
         types::PoolId,
     },
     quote::{get_sqrt_price_limit, select_amount},
-    state::{mutate_state, read_state},
+    state::{mutate_state, read_state, State},
     validation::swap_args::ValidatedSwapArgs,
 };
 
@@ -20,6 +21,7 @@ pub struct SwapSuccessfulResult {
     amount_out: I256,                    // always >= 0
     token_out_transfer_fee: U256,        // used to know transfer fee at the time of withdrawal
     swap_success_list: Vec<SwapSuccess>, // contains buffer state for each hop
+    swap_params_list: Vec<SwapParams>,   // parameters of each hop, in the same order
 }
 
 /// Executes a swap based on validated arguments, updating user balances and pool states.
@@ -66,7 +68,7 @@ pub fn execute_swap(
             let swap_params = build_swap_params(pool_id.clone(), -amount_in, *zero_for_one);
 
             // Execute swap
-            let hop_result = swap_inner(swap_params).map_err(SwapFailedReason::from)?;
+            let hop_result = swap_inner(swap_params.clone()).map_err(SwapFailedReason::from)?;
 
             // Calculate amounts
             let amount_out = select_amount(hop_result.swap_delta, *zero_for_one, false);
@@ -80,6 +82,7 @@ pub fn execute_swap(
                 amount_out,
                 token_out_transfer_fee: hop_result.token_out_transfer_fee,
                 swap_success_list: vec![hop_result],
+                swap_params_list: vec![swap_params],
             }
         }
         //  Multi-Hop Exact Input
@@ -96,6 +99,7 @@ pub fn execute_swap(
 
             let mut current_amount = *amount_in;
             let mut swap_success_list = Vec::new();
+            let mut swap_params_list = Vec::new();
             let mut token_out_transfer_fee = U256::ZERO;
 
             // Process each hop
@@ -103,10 +107,11 @@ pub fn execute_swap(
                 let swap_params =
                     build_swap_params(swap.pool_id.clone(), -current_amount, swap.zero_for_one);
 
-                let hop_result = swap_inner(swap_params).map_err(SwapFailedReason::from)?;
+                let hop_result = swap_inner(swap_params.clone()).map_err(SwapFailedReason::from)?;
                 token_out_transfer_fee = hop_result.token_out_transfer_fee;
                 current_amount = select_amount(hop_result.swap_delta, swap.zero_for_one, false);
                 swap_success_list.push(hop_result);
+                swap_params_list.push(swap_params);
             }
 
             // Final current_amount is the output amount
@@ -121,6 +126,7 @@ pub fn execute_swap(
                 amount_out,
                 token_out_transfer_fee,
                 swap_success_list,
+                swap_params_list,
             }
         }
         //  Single-Hop Exact Output
@@ -140,7 +146,7 @@ pub fn execute_swap(
             let swap_params = build_swap_params(pool_id.clone(), *amount_out, *zero_for_one);
 
             // Execute swap
-            let hop_result = swap_inner(swap_params).map_err(SwapFailedReason::from)?;
+            let hop_result = swap_inner(swap_params.clone()).map_err(SwapFailedReason::from)?;
 
             // Calculate amounts
             let amount_out = amount_out;
@@ -154,6 +160,7 @@ pub fn execute_swap(
                 amount_out: *amount_out,
                 token_out_transfer_fee: hop_result.token_out_transfer_fee,
                 swap_success_list: vec![hop_result],
+                swap_params_list: vec![swap_params],
             }
         }
         //  Multi-Hop Exact Output
@@ -170,6 +177,7 @@ pub fn execute_swap(
 
             let mut current_amount = *amount_out;
             let mut swap_success_list = Vec::new();
+            let mut swap_params_list = Vec::new();
             let mut token_out_transfer_fee = U256::ZERO;
 
             // Process each hop in reverse
@@ -181,7 +189,7 @@ pub fn execute_swap(
 
                 ic_cdk::println!("swap params {:?}", swap_params);
 
-                let hop_result = swap_inner(swap_params).map_err(SwapFailedReason::from)?;
+                let hop_result = swap_inner(swap_params.clone()).map_err(SwapFailedReason::from)?;
 
                 ic_cdk::println!("hop result {:?}", hop_result);
 
@@ -192,6 +200,7 @@ pub fn execute_swap(
 
                 ic_cdk::println!("current_amount {:?}", current_amount);
                 swap_success_list.insert(0, hop_result);
+                swap_params_list.insert(0, swap_params);
                 i += 1;
             }
 
@@ -207,6 +216,7 @@ pub fn execute_swap(
                 token_out_transfer_fee,
                 amount_out: *amount_out,
                 swap_success_list,
+                swap_params_list,
             }
         }
     };
@@ -218,6 +228,27 @@ pub fn execute_swap(
             final_amount_out: swap_result.amount_out.as_u256(),
             swap_args: validated_swap_args.clone(),
             principal: caller,
+            swap_fees: Some(
+                swap_result
+                    .swap_success_list
+                    .iter()
+                    .map(|hop| hop.swap_fee)
+                    .collect(),
+            ),
+            hops: Some(
+                swap_result
+                    .swap_params_list
+                    .iter()
+                    .zip(swap_result.swap_success_list.iter())
+                    .map(|(params, hop)| SwapHop {
+                        pool_id: params.pool_id.clone(),
+                        zero_for_one: params.zero_for_one,
+                        amount_specified: params.amount_specified,
+                        amount0_delta: hop.swap_delta.amount0(),
+                        amount1_delta: hop.swap_delta.amount1(),
+                    })
+                    .collect(),
+            ),
         },
     };
 
@@ -321,17 +352,10 @@ fn update_balances_and_states(
     mutate_state(|s| {
         s.update_user_balance(token_in_key, token_in_balance_after);
         s.update_user_balance(token_out_key, token_out_balance_after);
-        for swap_success in &swap_result.swap_success_list {
-            s.apply_swap_buffer_state(swap_success.buffer_state.clone());
-
-            // add to accumulated protocol fee
-            let fee_accumulated = s
-                .get_protocol_fee_for_token(&swap_success.fee_token)
-                .0
-                .checked_add(swap_success.amount_to_protocol)
-                .unwrap_or(U256::MAX);
-            s.update_protocol_fee_for_token(swap_success.fee_token, UserBalance(fee_accumulated));
-        }
+
+        let pools_before = pools_before(s, &event);
+        apply_swap_hops(s, &swap_result.swap_success_list);
+        update_pool_history(s, pools_before, &event);
 
         s.record_event(event);
     });
@@ -339,6 +363,21 @@ fn update_balances_and_states(
     Ok(())
 }
 
+/// Applies the pool state changes of each hop and adds their protocol fee to the protocol balance.
+pub fn apply_swap_hops(s: &mut State, swap_success_list: &[SwapSuccess]) {
+    for swap_success in swap_success_list {
+        s.apply_swap_buffer_state(swap_success.buffer_state.clone());
+
+        // add to accumulated protocol fee
+        let fee_accumulated = s
+            .get_protocol_fee_for_token(&swap_success.fee_token)
+            .0
+            .checked_add(swap_success.amount_to_protocol)
+            .unwrap_or(U256::MAX);
+        s.update_protocol_fee_for_token(swap_success.fee_token, UserBalance(fee_accumulated));
+    }
+}
+
 /// Selects the appropriate token for (token_in, token_out) based on direction.
 pub fn get_token_in_out(pool_id: &PoolId, zero_for_one: bool) -> (Principal, Principal) {
     if zero_for_one {
@@ -0,0 +1,261 @@
+pub mod create_pool_and_mint {
+    use candid::{Int, Nat, Principal};
+    use ethnum::U256;
+
+    use crate::{
+        balances::types::{UserBalance, UserBalanceKey},
+        candid_types::{
+            pool::{
+                CreatePoolAndMintArgs, CreatePoolAndMintError, CreatePoolArgs, CreatePoolError,
+            },
+            position::MintPositionError,
+        },
+        events::EventType,
+        libraries::{safe_cast::u256_to_big_uint, sqrt_price_math::tests::SQRT_PRICE_1_1},
+        mint::execute_create_pool_and_mint,
+        pool::{
+            create_pool::new_pool,
+            policy::{CreationFee, PoolCreationPolicy, TokenFilter},
+            types::{PoolFee, PoolId, PoolTickSpacing},
+        },
+        position::types::PositionKey,
+        state::{mutate_state, read_state},
+        tests::quoter::quoter::generate_token_address,
+    };
+
+    fn creator() -> Principal {
+        Principal::from_slice(&[9])
+    }
+
+    fn token0() -> Principal {
+        generate_token_address(1)
+    }
+
+    fn token1() -> Principal {
+        generate_token_address(2)
+    }
+
+    fn pool_id() -> PoolId {
+        PoolId {
+            token0: token0(),
+            token1: token1(),
+            fee: PoolFee(3000),
+        }
+    }
+
+    fn set_up(balance0: u128, balance1: u128) {
+        mutate_state(|s| {
+            s.set_tick_spacing(PoolFee(3000), PoolTickSpacing(60));
+            for (token, amount) in [(token0(), balance0), (token1(), balance1)] {
+                s.update_user_balance(
+                    UserBalanceKey {
+                        user: creator(),
+                        token,
+                    },
+                    UserBalance(U256::from(amount)),
+                );
+            }
+        });
+    }
+
+    fn balance(token: Principal) -> U256 {
+        read_state(|s| {
+            s.get_user_balance(&UserBalanceKey {
+                user: creator(),
+                token,
+            })
+            .0
+        })
+    }
+
+    fn args(amount0_max: u128, amount1_max: u128) -> CreatePoolAndMintArgs {
+        CreatePoolAndMintArgs {
+            pool: CreatePoolArgs {
+                token_a: token1(),
+                token_b: token0(),
+                fee: Nat::from(3000_u32),
+                sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+                request_id: None,
+            },
+            tick_lower: Int::from(-600),
+            tick_upper: Int::from(600),
+            amount0_max: Nat::from(amount0_max),
+            amount1_max: Nat::from(amount1_max),
+        }
+    }
+
+    fn execute(
+        args: CreatePoolAndMintArgs,
+        policy: &PoolCreationPolicy,
+    ) -> Result<(PoolId, u128), CreatePoolAndMintError> {
+        let (pool_id, pool_state) =
+            new_pool(args.pool.clone(), U256::ZERO, U256::ZERO, Some(creator()))
+                .map_err(CreatePoolAndMintError::CreatePool)?;
+        let liquidity = execute_create_pool_and_mint(
+            creator(),
+            pool_id.clone(),
+            pool_state,
+            args,
+            policy,
+            1748619822,
+        )?;
+        Ok((pool_id, liquidity))
+    }
+
+    fn policy() -> PoolCreationPolicy {
+        PoolCreationPolicy {
+            creation_fee: Some(CreationFee {
+                token: token0(),
+                amount: U256::from(1_000_u32),
+            }),
+            token_filter: None,
+            min_initial_liquidity: Some(1_000_000),
+        }
+    }
+
+    #[test]
+    fn should_create_pool_with_first_position_and_charge_fee() {
+        set_up(1_000_000_000, 1_000_000_000);
+
+        let (pool_id, liquidity) = execute(args(100_000_000, 100_000_000), &policy())
+            .expect("pool and position should be created");
+        assert_eq!(pool_id, pool_id());
+        assert!(liquidity >= 1_000_000);
+
+        let pool = read_state(|s| s.get_pool(&pool_id)).unwrap();
+        assert_eq!(pool.creator, Some(creator()));
+        assert_eq!(pool.liquidity, liquidity);
+        let position = read_state(|s| {
+            s.get_position(&PositionKey {
+                owner: creator(),
+                pool_id: pool_id.clone(),
+                tick_lower: -600,
+                tick_upper: 600,
+            })
+        });
+        assert_eq!(position.liquidity, liquidity);
+
+        // the fee and the paid amounts left the balance of the creator
+        let fee = U256::from(1_000_u32);
+        assert_eq!(
+            balance(token0()) + pool.pool_reserve0 + fee,
+            U256::from(1_000_000_000_u32)
+        );
+        assert_eq!(read_state(|s| s.get_protocol_balance(&token0())), fee);
+
+        // the pool is created before its first position
+        let events = read_state(|s| s.get_events(0, 10));
+        assert!(matches!(
+            events[0].payload,
+            EventType::CreatedPool {
+                creator: Some(principal),
+                creation_fee: Some(_),
+                ..
+            } if principal == creator()
+        ));
+        assert!(matches!(
+            events[1].payload,
+            EventType::MintedPosition { .. }
+        ));
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn should_roll_back_pool_and_refund_fee_when_liquidity_is_below_minimum() {
+        set_up(1_000_000_000, 1_000_000_000);
+
+        let result = execute(args(100, 100), &policy());
+        assert!(matches!(
+            result,
+            Err(CreatePoolAndMintError::LiquidityBelowMinimum { .. })
+        ));
+
+        assert!(read_state(|s| s.get_pool(&pool_id())).is_none());
+        assert_eq!(balance(token0()), U256::from(1_000_000_000_u32));
+        assert_eq!(balance(token1()), U256::from(1_000_000_000_u32));
+        assert_eq!(
+            read_state(|s| s.get_protocol_balance(&token0())),
+            U256::ZERO
+        );
+        assert!(read_state(|s| s.get_events(0, 10)).is_empty());
+    }
+
+    #[test]
+    fn should_roll_back_pool_when_mint_fails() {
+        // the balance covers the creation fee but not the position
+        set_up(1_000, 0);
+
+        let result = execute(args(100_000_000, 100_000_000), &policy());
+        assert!(matches!(
+            result,
+            Err(CreatePoolAndMintError::MintPosition(
+                MintPositionError::InsufficientBalance
+            ))
+        ));
+
+        assert!(read_state(|s| s.get_pool(&pool_id())).is_none());
+        assert_eq!(balance(token0()), U256::from(1_000_u32));
+        assert_eq!(
+            read_state(|s| s.get_protocol_balance(&token0())),
+            U256::ZERO
+        );
+    }
+
+    #[test]
+    fn should_fail_without_balance_for_creation_fee() {
+        set_up(999, 1_000_000_000);
+
+        let result = execute(args(100, 100), &policy());
+        assert!(matches!(
+            result,
+            Err(CreatePoolAndMintError::CreatePool(
+                CreatePoolError::CreationFeeNotPaid { token, .. }
+            )) if token == token0()
+        ));
+        assert!(read_state(|s| s.get_pool(&pool_id())).is_none());
+        assert_eq!(balance(token0()), U256::from(999_u32));
+    }
+
+    #[test]
+    fn should_fail_when_pool_was_created_after_it_was_built() {
+        set_up(1_000_000_000, 1_000_000_000);
+
+        // the pool is built before the deposits and created by someone else meanwhile
+        let (pool_id, pool_state) =
+            new_pool(args(0, 0).pool, U256::ZERO, U256::ZERO, Some(creator())).unwrap();
+        mutate_state(|s| s.set_pool(pool_id.clone(), pool_state.clone()));
+
+        let result = execute_create_pool_and_mint(
+            creator(),
+            pool_id,
+            pool_state,
+            args(100_000_000, 100_000_000),
+            &policy(),
+            1748619822,
+        );
+        assert!(matches!(
+            result,
+            Err(CreatePoolAndMintError::CreatePool(
+                CreatePoolError::PoolAlreadyExists
+            ))
+        ));
+        assert_eq!(balance(token0()), U256::from(1_000_000_000_u32));
+        assert_eq!(
+            read_state(|s| s.get_protocol_balance(&token0())),
+            U256::ZERO
+        );
+    }
+
+    #[test]
+    fn token_filter_should_allow_listed_tokens() {
+        let allowlist = TokenFilter::Allowlist(vec![token0()]);
+        assert!(allowlist.allows(&token0()));
+        assert!(!allowlist.allows(&token1()));
+
+        let denylist = TokenFilter::Denylist(vec![token0()]);
+        assert!(!denylist.allows(&token0()));
+        assert!(denylist.allows(&token1()));
+
+        assert!(PoolCreationPolicy::default().allows_token(&token1()));
+    }
+}
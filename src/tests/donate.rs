@@ -0,0 +1,138 @@
+pub mod donate {
+    use candid::{Nat, Principal};
+    use ethnum::U256;
+
+    use crate::{
+        balances::types::{UserBalance, UserBalanceKey},
+        candid_types::pool::{CreatePoolArgs, DonateArgs, DonateError},
+        donate::execute_donate,
+        events::EventType,
+        libraries::{safe_cast::u256_to_big_uint, sqrt_price_math::tests::SQRT_PRICE_1_1},
+        pool::types::PoolId,
+        position::types::PositionKey,
+        state::{mutate_state, read_state},
+        tests::quoter::quoter::{create_pool, generate_token_address, set_up},
+        validation::donate_args::validate_donate_args,
+    };
+
+    const FULL_RANGE_LOWER_TICK: i32 = -887220;
+    const FULL_RANGE_UPPER_TICK: i32 = 887220;
+
+    fn donor() -> Principal {
+        Principal::from_slice(&[9])
+    }
+
+    fn set_balance(token: Principal, amount: u128) {
+        mutate_state(|s| {
+            s.update_user_balance(
+                UserBalanceKey {
+                    user: donor(),
+                    token,
+                },
+                UserBalance(U256::from(amount)),
+            )
+        });
+    }
+
+    fn donate_args(pool_id: PoolId, amount0: u128, amount1: u128) -> DonateArgs {
+        DonateArgs {
+            pool: pool_id.into(),
+            amount0: Nat::from(amount0),
+            amount1: Nat::from(amount1),
+            from_subaccount: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn donation_should_be_collectable_by_in_range_positions() {
+        let (pool1, _pool12, _pool2) = set_up();
+
+        set_balance(pool1.token0, 1_000);
+        set_balance(pool1.token1, 500);
+
+        let validated_args =
+            validate_donate_args(donate_args(pool1.clone(), 1_000, 500)).expect("valid donation");
+        execute_donate(donor(), validated_args, 1748619822).expect("donation should succeed");
+
+        // the only position is the full range one, it owns all the donated amounts (rounded down)
+        let (_position, fees0_owed, fees1_owed) = read_state(|s| {
+            s.get_position_with_fees_owed(&PositionKey {
+                owner: Principal::management_canister(),
+                pool_id: pool1.clone(),
+                tick_lower: FULL_RANGE_LOWER_TICK,
+                tick_upper: FULL_RANGE_UPPER_TICK,
+            })
+        })
+        .expect("position should exist");
+        assert!(fees0_owed <= U256::from(1_000_u32) && fees0_owed >= U256::from(999_u32));
+        assert!(fees1_owed <= U256::from(500_u32) && fees1_owed >= U256::from(499_u32));
+
+        let (balance0, balance1) = read_state(|s| {
+            (
+                s.get_user_balance(&UserBalanceKey {
+                    user: donor(),
+                    token: pool1.token0,
+                })
+                .0,
+                s.get_user_balance(&UserBalanceKey {
+                    user: donor(),
+                    token: pool1.token1,
+                })
+                .0,
+            )
+        });
+        assert_eq!(balance0, U256::ZERO);
+        assert_eq!(balance1, U256::ZERO);
+
+        let last_event = read_state(|s| {
+            let count = s.total_event_count();
+            s.get_events(count - 1, 1).pop().unwrap()
+        });
+        assert_eq!(
+            last_event.payload,
+            EventType::Donated {
+                pool_id: pool1,
+                amount0: U256::from(1_000_u32),
+                amount1: U256::from(500_u32),
+                principal: donor(),
+            }
+        );
+    }
+
+    #[test]
+    fn donation_should_fail_without_in_range_liquidity() {
+        set_up();
+
+        let empty_pool = create_pool(CreatePoolArgs {
+            token_a: generate_token_address(3),
+            token_b: generate_token_address(4),
+            fee: Nat::from(3000_u32),
+            sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+            request_id: None,
+        });
+
+        assert_eq!(
+            validate_donate_args(donate_args(empty_pool, 1_000, 0)).err(),
+            Some(DonateError::NoInRangeLiquidity)
+        );
+    }
+
+    #[test]
+    fn donation_should_fail_with_zero_amounts_or_insufficient_balance() {
+        let (pool1, _pool12, _pool2) = set_up();
+
+        assert_eq!(
+            validate_donate_args(donate_args(pool1.clone(), 0, 0)).err(),
+            Some(DonateError::InvalidAmount)
+        );
+
+        set_balance(pool1.token0, 10);
+        let validated_args =
+            validate_donate_args(donate_args(pool1, 1_000, 0)).expect("valid donation");
+        assert_eq!(
+            execute_donate(donor(), validated_args, 1748619822),
+            Err(DonateError::InsufficientBalance)
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use icrc_ledger_types::icrc3::archive::{GetArchivesArgs, ICRC3ArchiveInfo};
+
+use crate::candid_types::{
+    archive::{CandidArchiveConfig, SetArchiveConfigError},
+    events::{CandidEvent, GetEventsArg, GetEventsResult},
+};
+
+use super::*;
+
+fn get_events(pic: &PocketIc) -> GetEventsResult {
+    query_call::<GetEventsArg, GetEventsResult>(
+        pic,
+        appic_dex_canister_id(),
+        "get_events",
+        GetEventsArg {
+            start: 0,
+            length: 100,
+        },
+    )
+}
+
+#[test]
+fn test_old_events_are_served_by_archives() {
+    let pic = set_up();
+
+    let events_before = get_events(&pic);
+    assert!(events_before.total_event_count >= 6);
+    assert!(events_before.archived_events.is_empty());
+
+    // archive the 4 oldest events, at most 3 per archive so a second archive is spawned
+    let set_config_result = update_call::<CandidArchiveConfig, Result<(), SetArchiveConfigError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "set_archive_config",
+        CandidArchiveConfig {
+            trigger_threshold: 4,
+            num_events_to_archive: 4,
+            max_events_per_call: 2,
+            max_events_per_archive: 3,
+            cycles_for_archive_creation: Nat::from(2_000_000_000_000_u64),
+        },
+        Some(sender_principal()),
+    );
+    assert_eq!(set_config_result, Ok(()));
+
+    // advancing time for more than 10 min to trigger archiving
+    pic.advance_time(Duration::from_secs(700));
+    for _ in 0..4 {
+        five_ticks(&pic);
+    }
+
+    let archives = query_call::<GetArchivesArgs, Vec<ICRC3ArchiveInfo>>(
+        &pic,
+        appic_dex_canister_id(),
+        "icrc3_get_archives",
+        GetArchivesArgs { from: None },
+    );
+    assert_eq!(archives.len(), 2);
+    assert_eq!(archives[0].start, Nat::from(0_u8));
+    assert_eq!(archives[0].end, Nat::from(2_u8));
+    assert_eq!(archives[1].start, Nat::from(3_u8));
+
+    let events_after = get_events(&pic);
+    assert_eq!(
+        events_after.total_event_count,
+        events_before.total_event_count
+    );
+    assert_eq!(
+        events_after.events.len() as u64,
+        events_before.total_event_count - 4
+    );
+    assert_eq!(events_after.archived_events.len(), 2);
+
+    // following the callbacks gives back the archived events
+    let mut archived: Vec<CandidEvent> = vec![];
+    for range in events_after.archived_events {
+        archived.extend(query_call::<GetEventsArg, Vec<CandidEvent>>(
+            &pic,
+            range.callback.0.principal,
+            &range.callback.0.method,
+            GetEventsArg {
+                start: range.start,
+                length: range.length,
+            },
+        ));
+    }
+    archived.extend(events_after.events);
+
+    assert_eq!(archived.len(), events_before.events.len());
+    for (event, expected) in archived.iter().zip(events_before.events.iter()) {
+        assert_eq!(event.timestamp, expected.timestamp);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::candid_types::{
+    events::{CandidEventType, CandidMemoKind, GetEventsArg, GetEventsResult},
+    DepositArgs, DepositError, WithdrawArgs, WithdrawError,
+};
+
+use super::*;
+
+#[test]
+fn test_deposits_and_withdrawals_are_recorded_as_events() {
+    let pic = set_up();
+
+    five_ticks(&pic);
+
+    let deposit_result = update_call::<DepositArgs, Result<Nat, DepositError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "deposit",
+        DepositArgs {
+            token: token0_principal(),
+            amount: Nat::from(1_000_000_000_000_000_000_u128),
+            from_subaccount: None,
+            request_id: None,
+        },
+        Some(sender_principal()),
+    );
+    assert!(deposit_result.is_ok());
+    let balance_after_deposit = query_call::<UserBalanceArgs, Nat>(
+        &pic,
+        appic_dex_canister_id(),
+        "user_balance",
+        UserBalanceArgs {
+            token: token0_principal(),
+            user: sender_principal(),
+        },
+    );
+
+    let withdraw_result = update_call::<WithdrawArgs, Result<Nat, WithdrawError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "withdraw",
+        WithdrawArgs {
+            token: token0_principal(),
+            amount: Nat::from(400_000_000_000_000_000_u128),
+            request_id: None,
+        },
+        Some(sender_principal()),
+    );
+    assert!(withdraw_result.is_ok());
+
+    let events = query_call::<GetEventsArg, GetEventsResult>(
+        &pic,
+        appic_dex_canister_id(),
+        "get_events",
+        GetEventsArg {
+            start: 0_u64,
+            length: 100u64,
+        },
+    );
+    let mut payloads = events.events.into_iter().map(|event| event.payload).rev();
+
+    match payloads.next() {
+        Some(CandidEventType::Withdrawn {
+            token,
+            user,
+            amount,
+            block_index,
+            memo,
+            balance,
+            ..
+        }) => {
+            assert_eq!(token, token0_principal());
+            assert_eq!(user, sender_principal());
+            assert_eq!(amount, Nat::from(400_000_000_000_000_000_u128));
+            assert!(block_index.is_some());
+            assert_eq!(memo, CandidMemoKind::Withdraw);
+            assert_eq!(balance, balance_after_deposit.clone() - amount);
+        }
+        other => panic!("expected a withdrawal event, got {other:?}"),
+    }
+
+    match payloads.next() {
+        Some(CandidEventType::Deposited {
+            token,
+            user,
+            amount,
+            block_index,
+            memo,
+            balance,
+        }) => {
+            assert_eq!(token, token0_principal());
+            assert_eq!(user, sender_principal());
+            assert_eq!(amount, Nat::from(1_000_000_000_000_000_000_u128));
+            assert!(block_index.is_some());
+            assert_eq!(memo, CandidMemoKind::Deposit);
+            assert_eq!(balance, balance_after_deposit);
+        }
+        other => panic!("expected a deposit event, got {other:?}"),
+    }
+}
@@ -0,0 +1,169 @@
+use crate::candid_types::pool::{
+    CandidPoolState, CreatePoolAndMintError, CreatePoolAndMintResult, InitialPositionArgs,
+};
+
+use super::*;
+
+const ONE_ETH: u128 = 1_000_000_000_000_000_000_u128;
+
+fn approve(pic: &PocketIc, token: Principal) {
+    update_call::<ApproveArgs, Result<Nat, ApproveError>>(
+        pic,
+        token,
+        "icrc2_approve",
+        ApproveArgs {
+            from_subaccount: None,
+            spender: LedgerAccount {
+                owner: appic_dex_canister_id(),
+                subaccount: None,
+            },
+            amount: Nat::from(TWO_HUNDRED_ETH),
+            expected_allowance: None,
+            expires_at: None,
+            fee: None,
+            memo: None,
+            created_at_time: None,
+        },
+        None,
+    )
+    .unwrap();
+}
+
+fn create_and_initialize_pool_with_position(
+    pic: &PocketIc,
+    token_a: Principal,
+    token_b: Principal,
+    tick_lower: i32,
+    tick_upper: i32,
+) -> Result<CreatePoolAndMintResult, CreatePoolAndMintError> {
+    let args = CreatePoolArgs {
+        token_a,
+        token_b,
+        fee: Nat::from(3000_u32),
+        sqrt_price_x96: u256_to_nat(*SQRT_PRICE_1_1),
+        request_id: None,
+    };
+    let position = InitialPositionArgs {
+        tick_lower: candid::Int::from(tick_lower),
+        tick_upper: candid::Int::from(tick_upper),
+        amount0_max: Nat::from(ONE_ETH),
+        amount1_max: Nat::from(ONE_ETH),
+        from_subaccount: None,
+    };
+    // the endpoint takes two arguments
+    let wasm_result = pic.update_call(
+        appic_dex_canister_id(),
+        sender_principal(),
+        "create_and_initialize_pool_with_position",
+        candid::encode_args((args, position)).unwrap(),
+    );
+    decode_wasm_result(wasm_result).unwrap()
+}
+
+fn get_pool(pic: &PocketIc, token_0: Principal, token_1: Principal) -> Option<CandidPoolState> {
+    query_call::<CandidPoolId, Option<CandidPoolState>>(
+        pic,
+        appic_dex_canister_id(),
+        "get_pool",
+        CandidPoolId {
+            token0: token_0,
+            token1: token_1,
+            fee: Nat::from(3000_u32),
+        },
+    )
+}
+
+fn user_balance(pic: &PocketIc, token: Principal) -> Nat {
+    query_call::<UserBalanceArgs, Nat>(
+        pic,
+        appic_dex_canister_id(),
+        "user_balance",
+        UserBalanceArgs {
+            token,
+            user: sender_principal(),
+        },
+    )
+}
+
+#[test]
+fn test_create_and_initialize_pool_with_position() {
+    let pic = create_pic();
+    create_and_install_canisters(&pic);
+    approve(&pic, token0_principal());
+    approve(&pic, token1_principal());
+
+    let result = create_and_initialize_pool_with_position(
+        &pic,
+        token1_principal(),
+        token0_principal(),
+        -600,
+        600,
+    )
+    .expect("pool and position should be created");
+
+    // the pool exists with the first position's liquidity, the deposits left in the balance are
+    // what the position did not use
+    let pool = get_pool(&pic, token0_principal(), token1_principal()).unwrap();
+    assert_eq!(result.pool_id.token0, token0_principal());
+    assert_eq!(pool.liquidity, result.liquidity);
+    assert_eq!(pool.creator, Some(sender_principal()));
+    assert_eq!(
+        user_balance(&pic, token0_principal()) + pool.pool_reserves0,
+        Nat::from(ONE_ETH)
+    );
+    assert_pool_invariants(&pic, token0_principal(), token1_principal());
+
+    let result = create_and_initialize_pool_with_position(
+        &pic,
+        token0_principal(),
+        token1_principal(),
+        -600,
+        600,
+    );
+    assert!(
+        matches!(
+            result,
+            Err(CreatePoolAndMintError::CreatePool(
+                CreatePoolError::PoolAlreadyExists
+            ))
+        ),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn test_create_and_initialize_pool_with_position_should_roll_back_pool() {
+    let pic = create_pic();
+    create_and_install_canisters(&pic);
+    approve(&pic, token2_principal());
+    approve(&pic, token3_principal());
+
+    // ticks not aligned with the tick spacing of 60, the mint fails after the deposits
+    let result = create_and_initialize_pool_with_position(
+        &pic,
+        token2_principal(),
+        token3_principal(),
+        -601,
+        600,
+    );
+    assert!(
+        matches!(
+            result,
+            Err(CreatePoolAndMintError::MintPosition(
+                MintPositionError::TickNotAlignedWithTickSpacing
+            ))
+        ),
+        "{result:?}"
+    );
+
+    let (token_0, token_1) = if token2_principal() < token3_principal() {
+        (token2_principal(), token3_principal())
+    } else {
+        (token3_principal(), token2_principal())
+    };
+    assert_eq!(get_pool(&pic, token_0, token_1), None);
+
+    // the deposits stay in the internal balance
+    assert_eq!(user_balance(&pic, token2_principal()), Nat::from(ONE_ETH));
+    assert_eq!(user_balance(&pic, token3_principal()), Nat::from(ONE_ETH));
+}
@@ -0,0 +1,165 @@
+use crate::{
+    balances::deposit_subaccount,
+    candid_types::{NotifyDepositError, WithdrawArgs, WithdrawError},
+    libraries::sqrt_price_math::tests::ONE_ETHER,
+};
+
+use super::*;
+
+#[test]
+fn test_deposit_with_icrc1_transfer_to_deposit_subaccount() {
+    let pic = create_pic();
+    create_and_install_canisters(&pic);
+    let token = create_icrc1_only_token(&pic);
+
+    five_ticks(&pic);
+
+    let deposit_account = query_call::<Principal, LedgerAccount>(
+        &pic,
+        appic_dex_canister_id(),
+        "get_deposit_account",
+        sender_principal(),
+    );
+    assert_eq!(
+        deposit_account,
+        LedgerAccount {
+            owner: appic_dex_canister_id(),
+            subaccount: Some(deposit_subaccount(sender_principal())),
+        }
+    );
+
+    // Plain ICRC-1 transfer, no approval involved
+    let _transfer_result = update_call::<TransferArg, Result<Nat, TransferError>>(
+        &pic,
+        token,
+        "icrc1_transfer",
+        TransferArg {
+            from_subaccount: None,
+            to: deposit_account,
+            fee: None,
+            created_at_time: None,
+            memo: None,
+            amount: u256_to_nat(*ONE_ETHER),
+        },
+        Some(sender_principal()),
+    )
+    .unwrap();
+
+    five_ticks(&pic);
+
+    let notify_result = update_call::<Principal, Result<Nat, NotifyDepositError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "notify_deposit",
+        token,
+        Some(sender_principal()),
+    );
+
+    // The sweep into the main account pays one transfer fee
+    let expected_credit = *ONE_ETHER - U256::from(TOKEN_TRANSFER_FEE);
+    assert_eq!(notify_result, Ok(u256_to_nat(expected_credit)));
+
+    let user_balance = query_call::<UserBalanceArgs, Nat>(
+        &pic,
+        appic_dex_canister_id(),
+        "user_balance",
+        UserBalanceArgs {
+            token,
+            user: sender_principal(),
+        },
+    );
+    assert_eq!(user_balance, u256_to_nat(expected_credit));
+
+    let deposit_account_balance =
+        query_call::<LedgerAccount, Nat>(&pic, token, "icrc1_balance_of", deposit_account);
+    assert_eq!(deposit_account_balance, Nat::from(0_u8));
+
+    // Nothing left to sweep, notifying again should not credit anything
+    let second_notify_result = update_call::<Principal, Result<Nat, NotifyDepositError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "notify_deposit",
+        token,
+        Some(sender_principal()),
+    );
+    assert_eq!(
+        second_notify_result,
+        Err(NotifyDepositError::AmountTooLow {
+            balance: Nat::from(0_u8),
+            transfer_fee: Nat::from(TOKEN_TRANSFER_FEE),
+        })
+    );
+
+    // Withdrawals only need ICRC-1 transfers
+    let withdraw_result = update_call::<WithdrawArgs, Result<Nat, WithdrawError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "withdraw",
+        WithdrawArgs {
+            token,
+            amount: u256_to_nat(expected_credit),
+            request_id: None,
+        },
+        Some(sender_principal()),
+    );
+    assert_eq!(
+        withdraw_result,
+        Ok(u256_to_nat(
+            expected_credit - U256::from(TOKEN_TRANSFER_FEE)
+        ))
+    );
+}
+
+#[test]
+fn test_deposit_subaccounts_are_not_shared_between_users() {
+    let pic = create_pic();
+    create_and_install_canisters(&pic);
+    let token = create_icrc1_only_token(&pic);
+
+    five_ticks(&pic);
+
+    let deposit_account = query_call::<Principal, LedgerAccount>(
+        &pic,
+        appic_dex_canister_id(),
+        "get_deposit_account",
+        sender_principal(),
+    );
+
+    let _transfer_result = update_call::<TransferArg, Result<Nat, TransferError>>(
+        &pic,
+        token,
+        "icrc1_transfer",
+        TransferArg {
+            from_subaccount: None,
+            to: deposit_account,
+            fee: None,
+            created_at_time: None,
+            memo: None,
+            amount: u256_to_nat(*ONE_ETHER),
+        },
+        Some(sender_principal()),
+    )
+    .unwrap();
+
+    five_ticks(&pic);
+
+    // Another user can not claim funds sent to the sender's deposit subaccount
+    let notify_result = update_call::<Principal, Result<Nat, NotifyDepositError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "notify_deposit",
+        token,
+        Some(liquidity_provider_principal()),
+    );
+    assert_eq!(
+        notify_result,
+        Err(NotifyDepositError::AmountTooLow {
+            balance: Nat::from(0_u8),
+            transfer_fee: Nat::from(TOKEN_TRANSFER_FEE),
+        })
+    );
+
+    let deposit_account_balance =
+        query_call::<LedgerAccount, Nat>(&pic, token, "icrc1_balance_of", deposit_account);
+    assert_eq!(deposit_account_balance, u256_to_nat(*ONE_ETHER));
+}
@@ -0,0 +1,161 @@
+use crate::candid_types::{
+    events::{CandidEventType, GetEventsArg, GetEventsResult},
+    ApproveInternalArgs, ApproveInternalError, CandidInternalAllowance, DepositArgs, DepositError,
+    InternalAllowanceArgs, InternalTransferArgs, InternalTransferError, TransferFromInternalArgs,
+};
+
+use super::*;
+
+fn internal_balance(pic: &PocketIc, user: Principal) -> Nat {
+    query_call::<UserBalanceArgs, Nat>(
+        pic,
+        appic_dex_canister_id(),
+        "user_balance",
+        UserBalanceArgs {
+            token: token0_principal(),
+            user,
+        },
+    )
+}
+
+#[test]
+fn test_internal_transfers_move_balances_without_ledger() {
+    let pic = set_up();
+
+    five_ticks(&pic);
+
+    let deposit_result = update_call::<DepositArgs, Result<Nat, DepositError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "deposit",
+        DepositArgs {
+            token: token0_principal(),
+            amount: Nat::from(1_000_000_000_000_000_000_u128),
+            from_subaccount: None,
+            request_id: None,
+        },
+        Some(sender_principal()),
+    );
+    assert!(deposit_result.is_ok());
+
+    let sender_balance = internal_balance(&pic, sender_principal());
+    let provider_balance = internal_balance(&pic, liquidity_provider_principal());
+    let dex_ledger_balance = get_balance(&pic, token0_principal(), appic_dex_canister_id());
+
+    let transfer_result = update_call::<InternalTransferArgs, Result<Nat, InternalTransferError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "transfer_internal",
+        InternalTransferArgs {
+            token: token0_principal(),
+            to: liquidity_provider_principal(),
+            amount: Nat::from(400_000_000_000_000_000_u128),
+            request_id: None,
+        },
+        Some(sender_principal()),
+    );
+    assert!(transfer_result.is_ok());
+
+    let approve_result = update_call::<ApproveInternalArgs, Result<(), ApproveInternalError>>(
+        &pic,
+        appic_dex_canister_id(),
+        "approve_internal",
+        ApproveInternalArgs {
+            token: token0_principal(),
+            spender: liquidity_provider_principal(),
+            amount: Nat::from(300_000_000_000_000_000_u128),
+            expected_allowance: Some(Nat::from(0_u8)),
+            expires_at: None,
+            request_id: None,
+        },
+        Some(sender_principal()),
+    );
+    assert_eq!(approve_result, Ok(()));
+
+    let transfer_from_result =
+        update_call::<TransferFromInternalArgs, Result<Nat, InternalTransferError>>(
+            &pic,
+            appic_dex_canister_id(),
+            "transfer_from_internal",
+            TransferFromInternalArgs {
+                token: token0_principal(),
+                from: sender_principal(),
+                to: liquidity_provider_principal(),
+                amount: Nat::from(200_000_000_000_000_000_u128),
+                request_id: None,
+            },
+            Some(liquidity_provider_principal()),
+        );
+    assert!(transfer_from_result.is_ok());
+
+    // Going over the allowance fails
+    let transfer_from_result =
+        update_call::<TransferFromInternalArgs, Result<Nat, InternalTransferError>>(
+            &pic,
+            appic_dex_canister_id(),
+            "transfer_from_internal",
+            TransferFromInternalArgs {
+                token: token0_principal(),
+                from: sender_principal(),
+                to: liquidity_provider_principal(),
+                amount: Nat::from(200_000_000_000_000_000_u128),
+                request_id: None,
+            },
+            Some(liquidity_provider_principal()),
+        );
+    assert_eq!(
+        transfer_from_result,
+        Err(InternalTransferError::InsufficientAllowance {
+            allowance: Nat::from(100_000_000_000_000_000_u128)
+        })
+    );
+
+    let allowance = query_call::<InternalAllowanceArgs, CandidInternalAllowance>(
+        &pic,
+        appic_dex_canister_id(),
+        "internal_allowance",
+        InternalAllowanceArgs {
+            token: token0_principal(),
+            owner: sender_principal(),
+            spender: liquidity_provider_principal(),
+        },
+    );
+    assert_eq!(
+        allowance,
+        CandidInternalAllowance {
+            allowance: Nat::from(100_000_000_000_000_000_u128),
+            expires_at: None,
+        }
+    );
+
+    let moved = Nat::from(600_000_000_000_000_000_u128);
+    assert_eq!(
+        internal_balance(&pic, sender_principal()),
+        sender_balance - moved.clone()
+    );
+    assert_eq!(
+        internal_balance(&pic, liquidity_provider_principal()),
+        provider_balance + moved
+    );
+    // nothing went through the ledger
+    assert_eq!(
+        get_balance(&pic, token0_principal(), appic_dex_canister_id()),
+        dex_ledger_balance
+    );
+
+    let events = query_call::<GetEventsArg, GetEventsResult>(
+        &pic,
+        appic_dex_canister_id(),
+        "get_events",
+        GetEventsArg {
+            start: 0_u64,
+            length: 100u64,
+        },
+    );
+    let internal_transfers = events
+        .events
+        .iter()
+        .filter(|event| matches!(event.payload, CandidEventType::InternalTransfer { .. }))
+        .count();
+    assert_eq!(internal_transfers, 2);
+}
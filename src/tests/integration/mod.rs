@@ -13,8 +13,14 @@ const TOKEN_TRANSFER_FEE: u128 = 10_000_000_000_000_u128;
 
 const TWO_HUNDRED_ETH: u128 = 200_000_000_000_000_000_000_u128;
 
+pub mod archive;
+pub mod balance_events;
+pub mod create_pool_with_position;
+pub mod deposit_account;
+pub mod internal_transfer;
 pub mod modify_liquidity;
 pub mod swap_tests;
+pub mod token_validation;
 
 use std::panic;
 
@@ -37,7 +43,7 @@ use ic_icrc1_ledger::{ArchiveOptions, InitArgs as LedgerInitArgs, LedgerArgument
 
 use crate::{
     candid_types::{
-        pool::{CandidPoolId, CreatePoolArgs, CreatePoolError},
+        pool::{CandidInvariantReport, CandidPoolId, CreatePoolArgs, CreatePoolError},
         position::{MintPositionArgs, MintPositionError},
         UserBalanceArgs,
     },
@@ -373,6 +379,55 @@ fn install_token3_canister(pic: &PocketIc, canister_id: Principal) {
     );
 }
 
+// Ledger without ICRC-2, tokens can only be deposited through the deposit subaccount flow
+fn install_icrc1_only_token_canister(pic: &PocketIc, canister_id: Principal) {
+    const MAX_MEMO_LENGTH: u16 = 80;
+    const NO_ICRC2_FEATURE: LedgerFeatureFlags = LedgerFeatureFlags { icrc2: false };
+
+    const THREE_GIGA_BYTES: u64 = 3_221_225_472;
+
+    let ledger_init_bytes = LedgerArgument::Init(LedgerInitArgs {
+        minting_account: LedgerAccount::from(minting_principal()),
+        fee_collector_account: None,
+        initial_balances: vec![],
+        transfer_fee: Nat::from(TOKEN_TRANSFER_FEE),
+        decimals: Some(18_u8),
+        token_name: "icrc1 only".to_string(),
+        token_symbol: "ICRC1".to_string(),
+        metadata: vec![],
+        archive_options: ArchiveOptions {
+            trigger_threshold: 2_000,
+            num_blocks_to_archive: 1_000,
+            node_max_memory_size_bytes: Some(THREE_GIGA_BYTES),
+            max_message_size_bytes: None,
+            controller_id: Principal::from_text("kmcdp-4yaaa-aaaag-ats3q-cai")
+                .unwrap()
+                .into(),
+            more_controller_ids: Some(vec![sender_principal().into()]),
+            cycles_for_archive_creation: Some(2_000_000_000_000_u64),
+            max_transactions_per_response: None,
+        },
+        max_memo_length: Some(MAX_MEMO_LENGTH),
+        feature_flags: Some(NO_ICRC2_FEATURE),
+    });
+    pic.install_canister(
+        canister_id,
+        LEDGER_WASM_BYTES.to_vec(),
+        encode_call_args(ledger_init_bytes).unwrap(),
+        Some(sender_principal()),
+    );
+}
+
+pub fn create_icrc1_only_token(pic: &PocketIc) -> Principal {
+    let canister_id = pic.create_canister_with_settings(Some(sender_principal()), None);
+    pic.add_cycles(canister_id, TWENTY_TRILLIONS.into());
+    install_icrc1_only_token_canister(pic, canister_id);
+    five_ticks(pic);
+
+    mint_tokens(pic, canister_id);
+    canister_id
+}
+
 pub fn mint_tokens(pic: &PocketIc, token: Principal) {
     let transfer_args = TransferArg {
         from_subaccount: None,
@@ -470,6 +525,7 @@ pub fn create_pool_with_liquidity(pic: &PocketIc, token_0: Principal, token_1: P
         token_b: token_1,
         fee: Nat::from(3000_u32),
         sqrt_price_x96: u256_to_nat(*SQRT_PRICE_1_1),
+        request_id: None,
     };
 
     let create_pool_result = update_call::<CreatePoolArgs, Result<CandidPoolId, CreatePoolError>>(
@@ -612,6 +668,7 @@ pub fn create_pool_with_liquidity(pic: &PocketIc, token_0: Principal, token_1: P
         amount0_max: u256_to_nat(U256::from(TWO_HUNDRED_ETH)),
         amount1_max: u256_to_nat(U256::from(TWO_HUNDRED_ETH)),
         from_subaccount: None,
+        request_id: None,
     };
 
     let _mint_result = update_call::<MintPositionArgs, Result<Nat, MintPositionError>>(
@@ -638,6 +695,23 @@ pub fn set_up() -> PocketIc {
     pic
 }
 
+// Asserts that the pool's ticks, tick bitmap and positions are consistent with each other
+pub fn assert_pool_invariants(pic: &PocketIc, token_0: Principal, token_1: Principal) {
+    let report = query_call::<CandidPoolId, Option<CandidInvariantReport>>(
+        pic,
+        appic_dex_canister_id(),
+        "check_invariants",
+        CandidPoolId {
+            token0: token_0,
+            token1: token_1,
+            fee: Nat::from(3000_u32),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(report.violations, vec![]);
+}
+
 pub fn get_balance(pic: &PocketIc, token: Principal, user_principal: Principal) -> Nat {
     query_call(
         pic,
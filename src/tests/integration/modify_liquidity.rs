@@ -28,6 +28,7 @@ fn flow_test() {
         token_b: token1_principal(),
         fee: Nat::from(3000_u32),
         sqrt_price_x96: u256_to_nat(*SQRT_PRICE_1_1),
+        request_id: None,
     };
 
     let pool_id = update_call::<CreatePoolArgs, Result<CandidPoolId, CreatePoolError>>(
@@ -126,6 +127,7 @@ fn flow_test() {
         amount0_max: u256_to_nat(U256::from(TWO_HUNDRED_ETH)),
         amount1_max: u256_to_nat(U256::from(TWO_HUNDRED_ETH)),
         from_subaccount: None,
+        request_id: None,
     };
 
     println!("{:?}", mint_args);
@@ -175,6 +177,7 @@ fn flow_test() {
                 amount0_max: Nat::from(TWO_HUNDRED_ETH / 2),
                 amount1_max: Nat::from(TWO_HUNDRED_ETH / 2),
                 from_subaccount: None,
+                request_id: None,
             },
             Some(liquidity_provider_principal()),
         )
@@ -268,6 +271,7 @@ fn flow_test() {
             from_subaccount: None,
             pool_id: pool_id.clone(),
             zero_for_one,
+            request_id: None,
         });
 
         let _swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -416,6 +420,7 @@ fn flow_test() {
             liquidity: (TWO_HUNDRED_ETH / 4).into(),
             amount0_min: Nat::from(0_u8),
             amount1_min: Nat::from(0_u8),
+            request_id: None,
         },
         Some(liquidity_provider_principal()),
     )
@@ -455,6 +460,7 @@ fn flow_test() {
             tick_upper: candid::Int::from(887220),
             amount0_min: Nat::from(0_u8),
             amount1_min: Nat::from(0_u8),
+            request_id: None,
         },
         Some(liquidity_provider_principal()),
     )
@@ -475,6 +481,8 @@ fn flow_test() {
 
     assert_eq!(position.liquidity, Nat::from(0_u8));
 
+    assert_pool_invariants(&pic, token0_principal(), token1_principal());
+
     let events = query_call::<GetEventsArg, GetEventsResult>(
         &pic,
         appic_dex_canister_id(),
@@ -32,6 +32,7 @@ fn test_exact_input_single_amount_fails_for_amount_out() {
         amount_in: u256_to_nat(amount_in),
         amount_out_minimum: u256_to_nat(expected_amount_out + 1),
         from_subaccount: None,
+        request_id: None,
     });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -76,6 +77,7 @@ fn test_exact_input_single_amount_check_swap_fees() {
         amount_in: u256_to_nat(amount_in),
         amount_out_minimum: u256_to_nat(expected_amount_out),
         from_subaccount: None,
+        request_id: None,
     });
 
     let _swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -161,6 +163,7 @@ fn test_exact_input_single_amount() {
         amount_in: u256_to_nat(amount_in),
         amount_out_minimum: u256_to_nat(expected_amount_out),
         from_subaccount: None,
+        request_id: None,
     });
 
     // pool state before swap
@@ -275,6 +278,7 @@ fn test_exact_input_1_hop_one_for_zero() {
             intermediary_token: token1_principal(),
             fee: Nat::from(3000_u32),
         }],
+        request_id: None,
     });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -323,6 +327,7 @@ fn test_exact_input_2_hops() {
                 fee: Nat::from(3000_u32),
             },
         ],
+        request_id: None,
     });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -375,6 +380,7 @@ fn test_exact_input_3_hops() {
                 fee: Nat::from(3000_u32),
             },
         ],
+        request_id: None,
     });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -396,6 +402,10 @@ fn test_exact_input_3_hops() {
             amount_out: u256_to_nat(expected_amount_out)
         })
     );
+
+    assert_pool_invariants(&pic, token0_principal(), token1_principal());
+    assert_pool_invariants(&pic, token1_principal(), token2_principal());
+    assert_pool_invariants(&pic, token2_principal(), token3_principal());
 }
 
 ///////////////////////////////////////////////////////////////
@@ -422,6 +432,7 @@ fn test_exact_output_single_amount_fails_for_amount_in() {
             amount_out: u256_to_nat(amount_out),
             amount_in_maximum: u256_to_nat(expected_amount_in - 1),
             from_subaccount: None,
+            request_id: None,
         });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -467,6 +478,7 @@ fn test_exact_output_single_amount() {
             amount_out: u256_to_nat(amount_out),
             amount_in_maximum: u256_to_nat(expected_amount_in),
             from_subaccount: None,
+            request_id: None,
         });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -539,6 +551,7 @@ fn test_exact_output_single_balances() {
             from_subaccount: None,
             amount_out: u256_to_nat(amount_out),
             amount_in_maximum: u256_to_nat(expected_amount_in),
+            request_id: None,
         });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -637,6 +650,7 @@ fn test_exact_output_1_hop_one_for_zero() {
             intermediary_token: token0_principal(),
             fee: Nat::from(3000_u32),
         }],
+        request_id: None,
     });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -685,6 +699,7 @@ fn test_exact_output_2_hops() {
                 fee: Nat::from(3000_u32),
             },
         ],
+        request_id: None,
     });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
@@ -737,6 +752,7 @@ fn test_exact_output_3_hops() {
                 fee: Nat::from(3000_u32),
             },
         ],
+        request_id: None,
     });
 
     let swap_result = update_call::<SwapArgs, Result<CandidSwapSuccess, SwapError>>(
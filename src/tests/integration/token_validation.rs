@@ -0,0 +1,108 @@
+use crate::candid_types::token::{
+    CandidToken, CandidTokenValidationConfig, CandidValidationStrategy,
+};
+
+use super::*;
+
+fn set_token_validation_config(
+    pic: &PocketIc,
+    strategy: CandidValidationStrategy,
+    probe_allowance: bool,
+) {
+    update_call::<CandidTokenValidationConfig, ()>(
+        pic,
+        appic_dex_canister_id(),
+        "set_token_validation_config",
+        CandidTokenValidationConfig {
+            strategy,
+            probe_allowance,
+        },
+        None,
+    );
+}
+
+fn create_pool(
+    pic: &PocketIc,
+    token_a: Principal,
+    token_b: Principal,
+) -> Result<CandidPoolId, CreatePoolError> {
+    update_call::<CreatePoolArgs, Result<CandidPoolId, CreatePoolError>>(
+        pic,
+        appic_dex_canister_id(),
+        "create_pool",
+        CreatePoolArgs {
+            token_a,
+            token_b,
+            fee: Nat::from(3000_u32),
+            sqrt_price_x96: u256_to_nat(*SQRT_PRICE_1_1),
+            request_id: None,
+        },
+        None,
+    )
+}
+
+#[test]
+fn test_create_pool_with_direct_ledger_validation() {
+    let pic = create_pic();
+    create_and_install_canisters(&pic);
+
+    let config = query_call::<(), CandidTokenValidationConfig>(
+        &pic,
+        appic_dex_canister_id(),
+        "get_token_validation_config",
+        (),
+    );
+    assert_eq!(
+        config,
+        CandidTokenValidationConfig {
+            strategy: CandidValidationStrategy::ProxyThenDirect,
+            probe_allowance: false,
+        }
+    );
+
+    set_token_validation_config(&pic, CandidValidationStrategy::Direct, true);
+
+    let create_pool_result = create_pool(&pic, token0_principal(), token1_principal());
+    assert!(create_pool_result.is_ok(), "{create_pool_result:?}");
+
+    // The registry entry comes from the ledger, without a usd price
+    let token = query_call::<Principal, Option<CandidToken>>(
+        &pic,
+        appic_dex_canister_id(),
+        "get_token",
+        token0_principal(),
+    )
+    .unwrap();
+    assert_eq!(token.name, Some("icUSDT.bsc".to_string()));
+    assert_eq!(token.symbol, Some("icUSDT.bsc".to_string()));
+    assert_eq!(token.decimals, 18);
+    assert_eq!(token.transfer_fee, Some(Nat::from(TOKEN_TRANSFER_FEE)));
+    assert!(token.standards.unwrap().contains(&"ICRC-2".to_string()));
+    assert_eq!(token.usd_price, None);
+}
+
+#[test]
+fn test_direct_ledger_validation_should_require_icrc2() {
+    let pic = create_pic();
+    create_and_install_canisters(&pic);
+    let icrc1_only_token = create_icrc1_only_token(&pic);
+
+    set_token_validation_config(&pic, CandidValidationStrategy::Direct, false);
+
+    let create_pool_result = create_pool(&pic, token0_principal(), icrc1_only_token);
+    assert!(
+        matches!(
+            create_pool_result,
+            Err(CreatePoolError::InvalidToken(token)) if token == icrc1_only_token
+        ),
+        "{create_pool_result:?}"
+    );
+
+    let token = query_call::<Principal, Option<CandidToken>>(
+        &pic,
+        appic_dex_canister_id(),
+        "get_token",
+        icrc1_only_token,
+    );
+    assert_eq!(token, None);
+}
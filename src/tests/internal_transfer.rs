@@ -0,0 +1,277 @@
+pub mod internal_transfer {
+    use candid::{Nat, Principal};
+    use ethnum::U256;
+
+    use crate::{
+        balances::types::{UserBalance, UserBalanceKey},
+        candid_types::{ApproveInternalError, InternalTransferError},
+        events::EventType,
+        internal_transfer::{
+            execute_approve_internal, execute_internal_transfer, get_internal_allowance,
+        },
+        state::{mutate_state, read_state},
+    };
+
+    const NOW: u64 = 1748619822;
+
+    fn token() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    fn alice() -> Principal {
+        Principal::from_slice(&[2])
+    }
+
+    fn bob() -> Principal {
+        Principal::from_slice(&[3])
+    }
+
+    fn spender() -> Principal {
+        Principal::from_slice(&[4])
+    }
+
+    fn set_balance(user: Principal, amount: u128) {
+        mutate_state(|s| {
+            s.update_user_balance(
+                UserBalanceKey {
+                    user,
+                    token: token(),
+                },
+                UserBalance(U256::from(amount)),
+            )
+        });
+    }
+
+    fn balance(user: Principal) -> U256 {
+        read_state(|s| {
+            s.get_user_balance(&UserBalanceKey {
+                user,
+                token: token(),
+            })
+            .0
+        })
+    }
+
+    #[test]
+    fn transfer_internal_should_move_balance_and_record_event() {
+        set_balance(alice(), 1_000);
+        set_balance(bob(), 50);
+
+        let event_index =
+            execute_internal_transfer(alice(), bob(), token(), U256::from(400_u32), None, NOW)
+                .expect("transfer should succeed");
+
+        assert_eq!(balance(alice()), U256::from(600_u32));
+        assert_eq!(balance(bob()), U256::from(450_u32));
+
+        let events = read_state(|s| s.get_events(event_index, 1));
+        assert_eq!(events[0].timestamp, NOW);
+        assert_eq!(
+            events[0].payload,
+            EventType::InternalTransfer {
+                token: token(),
+                from: alice(),
+                to: bob(),
+                amount: U256::from(400_u32),
+                spender: None,
+            }
+        );
+    }
+
+    #[test]
+    fn transfer_internal_should_fail_without_changing_state() {
+        set_balance(alice(), 100);
+
+        assert_eq!(
+            execute_internal_transfer(alice(), bob(), token(), U256::from(101_u32), None, NOW),
+            Err(InternalTransferError::InsufficientBalance {
+                balance: Nat::from(100_u32)
+            })
+        );
+        assert_eq!(
+            execute_internal_transfer(alice(), alice(), token(), U256::from(1_u32), None, NOW),
+            Err(InternalTransferError::InvalidRecipient)
+        );
+        assert_eq!(
+            execute_internal_transfer(
+                alice(),
+                Principal::anonymous(),
+                token(),
+                U256::from(1_u32),
+                None,
+                NOW
+            ),
+            Err(InternalTransferError::InvalidRecipient)
+        );
+        assert_eq!(
+            execute_internal_transfer(alice(), bob(), token(), U256::ZERO, None, NOW),
+            Err(InternalTransferError::ZeroAmount)
+        );
+
+        assert_eq!(balance(alice()), U256::from(100_u32));
+        assert_eq!(balance(bob()), U256::ZERO);
+        assert_eq!(read_state(|s| s.total_event_count()), 0);
+    }
+
+    #[test]
+    fn transfer_from_internal_should_spend_allowance() {
+        set_balance(alice(), 1_000);
+
+        // no allowance yet
+        assert_eq!(
+            execute_internal_transfer(
+                alice(),
+                bob(),
+                token(),
+                U256::from(1_u32),
+                Some(spender()),
+                NOW
+            ),
+            Err(InternalTransferError::InsufficientAllowance {
+                allowance: Nat::from(0_u8)
+            })
+        );
+
+        execute_approve_internal(
+            alice(),
+            spender(),
+            token(),
+            U256::from(300_u32),
+            None,
+            None,
+            NOW,
+        )
+        .expect("approve should succeed");
+
+        execute_internal_transfer(
+            alice(),
+            bob(),
+            token(),
+            U256::from(200_u32),
+            Some(spender()),
+            NOW,
+        )
+        .expect("transfer from should succeed");
+
+        assert_eq!(balance(alice()), U256::from(800_u32));
+        assert_eq!(balance(bob()), U256::from(200_u32));
+        assert_eq!(
+            get_internal_allowance(alice(), spender(), token(), NOW).amount,
+            U256::from(100_u32)
+        );
+
+        assert_eq!(
+            execute_internal_transfer(
+                alice(),
+                bob(),
+                token(),
+                U256::from(101_u32),
+                Some(spender()),
+                NOW
+            ),
+            Err(InternalTransferError::InsufficientAllowance {
+                allowance: Nat::from(100_u32)
+            })
+        );
+
+        // spending the whole allowance removes it
+        execute_internal_transfer(
+            alice(),
+            bob(),
+            token(),
+            U256::from(100_u32),
+            Some(spender()),
+            NOW,
+        )
+        .expect("transfer from should succeed");
+        assert_eq!(
+            get_internal_allowance(alice(), spender(), token(), NOW).amount,
+            U256::ZERO
+        );
+
+        let last_event = read_state(|s| s.get_events(s.total_event_count() - 1, 1));
+        assert_eq!(
+            last_event[0].payload,
+            EventType::InternalTransfer {
+                token: token(),
+                from: alice(),
+                to: bob(),
+                amount: U256::from(100_u32),
+                spender: Some(spender()),
+            }
+        );
+    }
+
+    #[test]
+    fn approve_internal_should_check_expected_allowance_and_expiry() {
+        execute_approve_internal(
+            alice(),
+            spender(),
+            token(),
+            U256::from(300_u32),
+            None,
+            Some(NOW + 10),
+            NOW,
+        )
+        .expect("approve should succeed");
+
+        assert_eq!(
+            execute_approve_internal(
+                alice(),
+                spender(),
+                token(),
+                U256::from(500_u32),
+                Some(U256::from(200_u32)),
+                None,
+                NOW,
+            ),
+            Err(ApproveInternalError::AllowanceChanged {
+                current_allowance: Nat::from(300_u32)
+            })
+        );
+        assert_eq!(
+            execute_approve_internal(
+                alice(),
+                spender(),
+                token(),
+                U256::from(500_u32),
+                None,
+                Some(NOW),
+                NOW,
+            ),
+            Err(ApproveInternalError::Expired { ledger_time: NOW })
+        );
+        assert_eq!(
+            execute_approve_internal(
+                alice(),
+                alice(),
+                token(),
+                U256::from(500_u32),
+                None,
+                None,
+                NOW
+            ),
+            Err(ApproveInternalError::InvalidSpender)
+        );
+
+        // the allowance can not be spent once expired
+        set_balance(alice(), 1_000);
+        assert_eq!(
+            get_internal_allowance(alice(), spender(), token(), NOW + 10).amount,
+            U256::ZERO
+        );
+        assert_eq!(
+            execute_internal_transfer(
+                alice(),
+                bob(),
+                token(),
+                U256::from(1_u32),
+                Some(spender()),
+                NOW + 10
+            ),
+            Err(InternalTransferError::InsufficientAllowance {
+                allowance: Nat::from(0_u8)
+            })
+        );
+    }
+}
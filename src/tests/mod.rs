@@ -1,3 +1,6 @@
+pub mod create_pool_and_mint;
+pub mod donate;
 pub mod integration;
+pub mod internal_transfer;
 pub mod quoter;
 pub mod swap_args_validation;
@@ -19,7 +19,7 @@ pub mod quoter {
         },
         pool::{
             create_pool::create_pool_inner,
-            modify_liquidity::{ModifyLiquidityParams, modify_liquidity},
+            modify_liquidity::{modify_liquidity, ModifyLiquidityParams},
             types::{PoolId, PoolTickSpacing},
         },
         quote::{
@@ -27,6 +27,10 @@ pub mod quoter {
             process_single_hop_exact_input, process_single_hop_exact_output,
         },
         state::mutate_state,
+        tokens::{
+            accounting::gross_up_for_transfer_haircut,
+            types::{AccountingMode, TokenSettings},
+        },
     };
 
     #[test]
@@ -171,6 +175,7 @@ pub mod quoter {
             token_b: generate_token_address(2),
             fee: Nat::from(3000_u32),
             sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+            request_id: None,
         });
         set_up_pool_with_0_ticks_initialized(pool_id);
 
@@ -254,6 +259,7 @@ pub mod quoter {
             token_b: generate_token_address(2),
             fee: Nat::from(3000_u32),
             sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+            request_id: None,
         });
         set_up_pool_with_0_ticks_initialized(pool_id);
 
@@ -489,6 +495,7 @@ pub mod quoter {
             token_b: generate_token_address(2),
             fee: Nat::from(3000_u32),
             sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+            request_id: None,
         });
         set_up_pool_with_0_ticks_initialized(pool_id);
 
@@ -632,6 +639,63 @@ pub mod quoter {
         assert_eq!(amount_in, expected_amount_in);
     }
 
+    #[test]
+    fn test_quoter_exact_input_single_applies_fee_on_transfer_haircut() {
+        let (pool1, _pool12, _pool2) = set_up();
+
+        let params = |amount: u32| QuoteExactSingleParams {
+            pool_id: pool1.clone().into(),
+            zero_for_one: true,
+            exact_amount: Nat::from(amount),
+        };
+
+        // 1% of every token0 transfer is burnt, so only 9_900 out of 10_000 reach the pool
+        let expected_amount_out = process_single_hop_exact_input(params(9_900)).unwrap();
+        mutate_state(|s| {
+            s.set_token_settings(
+                pool1.token0,
+                TokenSettings {
+                    accounting_mode: AccountingMode::BalanceDifference,
+                    transfer_haircut: 10_000,
+                    ..Default::default()
+                },
+            )
+        });
+
+        let amount_out = process_single_hop_exact_input(params(10_000)).unwrap();
+        assert_eq!(amount_out, expected_amount_out);
+    }
+
+    #[test]
+    fn test_quoter_exact_output_single_applies_fee_on_transfer_haircut() {
+        let (pool1, _pool12, _pool2) = set_up();
+
+        let params = |amount: u32| QuoteExactSingleParams {
+            pool_id: pool1.clone().into(),
+            zero_for_one: true,
+            exact_amount: Nat::from(amount),
+        };
+
+        let amount_in_without_haircut = process_single_hop_exact_output(params(10_000)).unwrap();
+        mutate_state(|s| {
+            s.set_token_settings(
+                pool1.token0,
+                TokenSettings {
+                    accounting_mode: AccountingMode::BalanceDifference,
+                    transfer_haircut: 10_000,
+                    ..Default::default()
+                },
+            )
+        });
+
+        // the deposit has to cover the 1% burnt on the way in
+        let amount_in = process_single_hop_exact_output(params(10_000)).unwrap();
+        assert_eq!(
+            amount_in,
+            gross_up_for_transfer_haircut(amount_in_without_haircut, 10_000).unwrap()
+        );
+    }
+
     pub fn generate_token_address(token_id: u8) -> Principal {
         Principal::from_slice(&[token_id])
     }
@@ -655,6 +719,7 @@ pub mod quoter {
             token_b: token_1,
             fee: Nat::from(3000_u32),
             sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+            request_id: None,
         });
 
         // pool 2
@@ -663,6 +728,7 @@ pub mod quoter {
             token_b: token_2,
             fee: Nat::from(3000_u32),
             sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+            request_id: None,
         });
 
         // pool 12
@@ -671,6 +737,7 @@ pub mod quoter {
             token_b: token_2,
             fee: Nat::from(3000_u32),
             sqrt_price_x96: Nat::from(u256_to_big_uint(*SQRT_PRICE_1_1)),
+            request_id: None,
         });
 
         set_up_pool(pool1.clone());
@@ -681,7 +748,8 @@ pub mod quoter {
     }
 
     pub fn create_pool(args: CreatePoolArgs) -> PoolId {
-        create_pool_inner(args, U256::ZERO, U256::ZERO, 1748619822).expect("Pool creation failed")
+        create_pool_inner(args, U256::ZERO, U256::ZERO, None, None, 1748619822)
+            .expect("Pool creation failed")
     }
 
     pub fn set_up_pool(pool_id: PoolId) {
@@ -56,6 +56,7 @@ mod swap_args_tests {
                     pool_reserve1: U256::ZERO,
                     generated_swap_fee0: U256::ZERO,
                     generated_swap_fee1: U256::ZERO,
+                    creator: None,
                 },
             )
         });
@@ -80,6 +81,7 @@ mod swap_args_tests {
                     pool_reserve1: U256::ZERO,
                     generated_swap_fee0: U256::ZERO,
                     generated_swap_fee1: U256::ZERO,
+                    creator: None,
                 },
             )
         });
@@ -112,6 +114,7 @@ mod swap_args_tests {
             amount_in: Nat::from(1000u64),
             amount_out_minimum: Nat::from(500u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         let result = validate_swap_args(args).unwrap();
@@ -146,6 +149,7 @@ mod swap_args_tests {
             amount_in: Nat::from(1000u64),
             amount_out_minimum: Nat::from(500u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         assert_eq!(validate_swap_args(args), Err(SwapError::PoolNotInitialized));
@@ -164,6 +168,7 @@ mod swap_args_tests {
             amount_in: Nat::from(1000u64),
             amount_out_minimum: Nat::from(500u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         let result = validate_swap_args(args).unwrap();
@@ -203,6 +208,7 @@ mod swap_args_tests {
             amount_in: Nat::from(1000u64),
             amount_out_minimum: Nat::from(500u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         assert_eq!(
@@ -229,6 +235,7 @@ mod swap_args_tests {
             amount_in: Nat::from(1000u64),
             amount_out_minimum: Nat::from(500u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         assert_eq!(
@@ -250,6 +257,7 @@ mod swap_args_tests {
             amount_out: Nat::from(500u64),
             amount_in_maximum: Nat::from(1000u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         let result = validate_swap_args(args).unwrap();
@@ -284,6 +292,7 @@ mod swap_args_tests {
             amount_out: Nat::from(500u64),
             from_subaccount: None,
             amount_in_maximum: Nat::from(1000u64),
+            request_id: None,
         });
 
         assert_eq!(validate_swap_args(args), Err(SwapError::InvalidPoolFee));
@@ -302,6 +311,7 @@ mod swap_args_tests {
             amount_out: Nat::from(500u64),
             amount_in_maximum: Nat::from(1000u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         let result = validate_swap_args(args).unwrap();
@@ -350,6 +360,7 @@ mod swap_args_tests {
             amount_out: Nat::from(500u64),
             amount_in_maximum: Nat::from(1000u64),
             from_subaccount: None,
+            request_id: None,
         });
 
         assert_eq!(validate_swap_args(args), Err(SwapError::InvalidPoolFee));
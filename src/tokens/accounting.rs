@@ -0,0 +1,106 @@
+use ethnum::U256;
+
+use crate::{libraries::full_math::mul_div_rounding_up, state::read_state};
+
+/// Denominator of transfer haircuts, same as the one used for fees.
+pub const HAIRCUT_DENOMINATOR: u32 = 1_000_000;
+
+/// Amount that arrives at the destination once the token haircut is taken, rounded down.
+pub fn apply_transfer_haircut(amount: U256, haircut: u32) -> U256 {
+    if haircut == 0 {
+        return amount;
+    }
+    let haircut = haircut.min(HAIRCUT_DENOMINATOR);
+    // amount - amount * haircut / denominator, rounding the haircut up
+    let lost = mul_div_rounding_up(amount, U256::from(haircut), U256::from(HAIRCUT_DENOMINATOR))
+        .unwrap_or(amount);
+    amount.saturating_sub(lost)
+}
+
+/// Amount that has to be sent so that at least `amount` arrives at the destination, rounded up.
+/// Returns None if no amount can survive the haircut or the result overflows.
+pub fn gross_up_for_transfer_haircut(amount: U256, haircut: u32) -> Option<U256> {
+    if haircut == 0 {
+        return Some(amount);
+    }
+    if haircut >= HAIRCUT_DENOMINATOR {
+        return None;
+    }
+    mul_div_rounding_up(
+        amount,
+        U256::from(HAIRCUT_DENOMINATOR),
+        U256::from(HAIRCUT_DENOMINATOR - haircut),
+    )
+    .ok()
+}
+
+/// Haircut in pips observed when `sent` was transferred and only `received` arrived.
+pub fn observed_transfer_haircut(sent: U256, received: U256) -> u32 {
+    if sent == U256::ZERO || received >= sent {
+        return 0;
+    }
+    mul_div_rounding_up(sent - received, U256::from(HAIRCUT_DENOMINATOR), sent)
+        .map(|haircut| haircut.min(U256::from(HAIRCUT_DENOMINATOR)).as_u32())
+        .unwrap_or(HAIRCUT_DENOMINATOR)
+}
+
+/// Current haircut of a token, zero for tokens using standard accounting.
+pub fn transfer_haircut(token: &candid::Principal) -> u32 {
+    read_state(|s| {
+        let settings = s.get_token_settings(token);
+        if settings.is_balance_difference() {
+            settings.transfer_haircut
+        } else {
+            0
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn haircut_should_round_in_favor_of_the_canister() {
+        // 1% haircut
+        assert_eq!(
+            apply_transfer_haircut(U256::from(1_000_u32), 10_000),
+            U256::from(990_u32)
+        );
+        assert_eq!(
+            apply_transfer_haircut(U256::from(999_u32), 10_000),
+            U256::from(989_u32)
+        );
+        assert_eq!(
+            gross_up_for_transfer_haircut(U256::from(990_u32), 10_000),
+            Some(U256::from(1_000_u32))
+        );
+        assert_eq!(
+            gross_up_for_transfer_haircut(U256::from(989_u32), 10_000),
+            Some(U256::from(999_u32))
+        );
+        assert_eq!(
+            gross_up_for_transfer_haircut(U256::from(1_u32), HAIRCUT_DENOMINATOR),
+            None
+        );
+    }
+
+    #[test]
+    fn zero_haircut_should_not_change_amounts() {
+        let amount = U256::from(123_456_u32);
+        assert_eq!(apply_transfer_haircut(amount, 0), amount);
+        assert_eq!(gross_up_for_transfer_haircut(amount, 0), Some(amount));
+        assert_eq!(observed_transfer_haircut(amount, amount), 0);
+    }
+
+    #[test]
+    fn observed_haircut_should_match_the_applied_one() {
+        let sent = U256::from(1_000_000_u32);
+        let received = apply_transfer_haircut(sent, 25_000);
+        assert_eq!(observed_transfer_haircut(sent, received), 25_000);
+        assert_eq!(
+            observed_transfer_haircut(sent, U256::ZERO),
+            HAIRCUT_DENOMINATOR
+        );
+    }
+}
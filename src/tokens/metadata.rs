@@ -0,0 +1,232 @@
+// Token registry, metadata and usd prices cached from the proxy canister (the metadata falls back
+// to the ledger itself, see `validation`) along with the standards and transfer fee of the token
+// ledger. An entry is stored at pool creation and refreshed for every token with a pool by a timer,
+// a token whose refresh fails keeps its last entry and its price becomes stale once older than
+// `MAX_PRICE_AGE_NANOS`
+
+use std::cell::Cell;
+
+use candid::Principal;
+use ethnum::U256;
+use ic_canister_log::log;
+
+use crate::{
+    icrc_client::LedgerClient,
+    libraries::safe_cast::big_uint_to_u256,
+    logs::INFO,
+    proxy_canister::CandidIcpToken,
+    state::{mutate_state, read_state, State},
+};
+
+use super::{types::TokenMetadata, validation::refresh_token};
+
+/// Prices older than this are reported as stale, three missed refreshes.
+pub const MAX_PRICE_AGE_NANOS: u64 = 30 * 60 * 1_000_000_000;
+
+thread_local! {
+    static IS_REFRESHING: Cell<bool> = const { Cell::new(false) };
+}
+
+// makes sure only one refresh is in flight
+struct RefreshGuard;
+
+impl RefreshGuard {
+    fn new() -> Option<Self> {
+        IS_REFRESHING.with(|is_refreshing| {
+            if is_refreshing.get() {
+                return None;
+            }
+            is_refreshing.set(true);
+            Some(RefreshGuard)
+        })
+    }
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        IS_REFRESHING.with(|is_refreshing| is_refreshing.set(false));
+    }
+}
+
+/// How usable the cached price of a token is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceStatus {
+    Fresh,
+    /// Fetched more than `MAX_PRICE_AGE_NANOS` ago, still used for usd values.
+    Stale,
+    /// Never fetched or not provided by the proxy, usd values depending on it are not computed.
+    Missing,
+}
+
+impl TokenMetadata {
+    pub fn price_status(&self, now_nanos: u64) -> PriceStatus {
+        match (self.usd_price, self.price_updated_at) {
+            (Some(_), Some(updated_at))
+                if now_nanos.saturating_sub(updated_at) <= MAX_PRICE_AGE_NANOS =>
+            {
+                PriceStatus::Fresh
+            }
+            (Some(_), _) => PriceStatus::Stale,
+            (None, _) => PriceStatus::Missing,
+        }
+    }
+}
+
+/// Stores the metadata returned by the proxy and the standards of the ledger, keeping the previous
+/// price when the proxy has no valid one and the previous standards when they could not be fetched.
+pub fn record_token_metadata(
+    s: &mut State,
+    token: Principal,
+    data: &CandidIcpToken,
+    standards: Option<Vec<String>>,
+    now: u64,
+) {
+    let previous = s.get_token_metadata(&token).unwrap_or_default();
+    let (usd_price, price_updated_at) = match parse_usd_price(&data.usd_price) {
+        Some(usd_price) => (Some(usd_price), Some(now)),
+        None => (previous.usd_price, previous.price_updated_at),
+    };
+    s.set_token_metadata(
+        token,
+        TokenMetadata {
+            symbol: Some(data.symbol.clone()),
+            decimals: data.decimals,
+            usd_price,
+            price_updated_at,
+            name: Some(data.name.clone()),
+            logo: Some(data.logo.clone()),
+            standards: standards.or(previous.standards),
+            transfer_fee: big_uint_to_u256(data.fee.0.clone())
+                .ok()
+                .or(previous.transfer_fee),
+            refreshed_at: Some(now),
+        },
+    );
+}
+
+/// Stores the fee expected by the ledger of a token already in the registry.
+pub fn record_transfer_fee(s: &mut State, token: Principal, transfer_fee: U256) {
+    if let Some(metadata) = s.get_token_metadata(&token) {
+        s.set_token_metadata(
+            token,
+            TokenMetadata {
+                transfer_fee: Some(transfer_fee),
+                ..metadata
+            },
+        );
+    }
+}
+
+/// Fetches the standards implemented by the ledger of a token, None when the ledger can not tell.
+pub async fn fetch_token_standards(token: Principal) -> Option<Vec<String>> {
+    match LedgerClient::new(token).supported_standards().await {
+        Ok(standards) => Some(standards),
+        Err(error) => {
+            log!(
+                INFO,
+                "[fetch_token_standards]: failed to fetch the standards of {token}: {error}"
+            );
+            None
+        }
+    }
+}
+
+/// Refreshes the registry entry and price of every token with a pool.
+pub async fn refresh_token_metadata() {
+    let Some(_guard) = RefreshGuard::new() else {
+        return;
+    };
+
+    let mut tokens: Vec<Principal> = read_state(|s| s.get_pools())
+        .into_iter()
+        .flat_map(|(pool_id, _)| [pool_id.token0, pool_id.token1])
+        .collect();
+    tokens.sort();
+    tokens.dedup();
+
+    for token in tokens {
+        let config = read_state(|s| s.get_token_validation_config());
+        match refresh_token(token, config).await {
+            Ok(validated) => mutate_state(|s| {
+                let now = ic_cdk::api::time();
+                record_token_metadata(s, token, &validated.data, validated.standards, now)
+            }),
+            Err(error) => log!(
+                INFO,
+                "[refresh_token_metadata]: failed to refresh {token}: {error}"
+            ),
+        }
+    }
+}
+
+// the proxy reports prices as decimal strings, empty when unknown
+fn parse_usd_price(usd_price: &str) -> Option<f64> {
+    usd_price
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|price| price.is_finite() && *price > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Nat;
+
+    use crate::proxy_canister::IcpTokenType;
+
+    use super::*;
+
+    fn icp_token(ledger_id: Principal, usd_price: &str, fee: u32) -> CandidIcpToken {
+        CandidIcpToken {
+            ledger_id,
+            name: "Token A".to_string(),
+            decimals: 8,
+            symbol: "AAA".to_string(),
+            token_type: IcpTokenType::ICRC2,
+            logo: "https://logo".to_string(),
+            usd_price: usd_price.to_string(),
+            fee: Nat::from(fee),
+            rank: None,
+        }
+    }
+
+    #[test]
+    fn registry_entries_should_keep_known_values_on_refresh() {
+        let token = Principal::from_slice(&[7]);
+        let standards = vec!["ICRC-1".to_string(), "ICRC-2".to_string()];
+        mutate_state(|s| {
+            record_token_metadata(
+                s,
+                token,
+                &icp_token(token, "1.5", 10_000),
+                Some(standards.clone()),
+                1,
+            )
+        });
+
+        // the proxy has no price and the ledger does not answer
+        mutate_state(|s| record_token_metadata(s, token, &icp_token(token, "", 20_000), None, 2));
+        let metadata = read_state(|s| s.get_token_metadata(&token)).unwrap();
+        assert_eq!(metadata.name, Some("Token A".to_string()));
+        assert_eq!(metadata.logo, Some("https://logo".to_string()));
+        assert_eq!(metadata.usd_price, Some(1.5));
+        assert_eq!(metadata.price_updated_at, Some(1));
+        assert_eq!(metadata.standards, Some(standards));
+        assert_eq!(metadata.transfer_fee, Some(U256::from(20_000_u32)));
+        assert_eq!(metadata.refreshed_at, Some(2));
+
+        // a `BadFee` error updates the cached fee of registered tokens only
+        let unknown = Principal::from_slice(&[8]);
+        mutate_state(|s| {
+            record_transfer_fee(s, token, U256::from(30_000_u32));
+            record_transfer_fee(s, unknown, U256::from(30_000_u32));
+        });
+        assert_eq!(
+            read_state(|s| s.get_token_metadata(&token))
+                .unwrap()
+                .transfer_fee,
+            Some(U256::from(30_000_u32))
+        );
+        assert_eq!(read_state(|s| s.get_token_metadata(&unknown)), None);
+    }
+}
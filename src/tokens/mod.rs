@@ -0,0 +1,4 @@
+pub mod accounting;
+pub mod metadata;
+pub mod types;
+pub mod validation;
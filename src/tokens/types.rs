@@ -0,0 +1,112 @@
+use ethnum::U256;
+use minicbor::{Decode, Encode};
+
+/// How transfers of a token are credited to users.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AccountingMode {
+    /// Users are credited exactly the transferred amount.
+    #[default]
+    #[n(0)]
+    Standard,
+    /// Users are credited the change of the canister balance measured around every transfer, used
+    /// for tokens that burn part of each transfer (fee-on-transfer) or rebase.
+    #[n(1)]
+    BalanceDifference,
+}
+
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct TokenSettings {
+    #[n(0)]
+    pub accounting_mode: AccountingMode,
+    /// Share of every transfer lost on the way in pips (1_000_000 = 100%), initially set by the
+    /// controllers and refreshed with the haircut measured on each deposit.
+    #[n(1)]
+    pub transfer_haircut: u32,
+    /// Set while the token is paused, no deposit or withdrawal of the token goes out to its
+    /// ledger until the controllers unpause it.
+    #[n(2)]
+    pub paused_at: Option<u64>,
+    /// Pauses the token as soon as a reconciliation finds that the canister holds less than it
+    /// owes, see `reconciliation`.
+    #[n(3)]
+    pub pause_on_deficit: Option<bool>,
+    /// Set while the token is delisted, no new pool can be created with it. Existing pools keep
+    /// working so liquidity can still be withdrawn.
+    #[n(4)]
+    pub delisted_at: Option<u64>,
+}
+
+impl TokenSettings {
+    pub fn is_balance_difference(&self) -> bool {
+        self.accounting_mode == AccountingMode::BalanceDifference
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn is_delisted(&self) -> bool {
+        self.delisted_at.is_some()
+    }
+
+    pub fn pauses_on_deficit(&self) -> bool {
+        self.pause_on_deficit.unwrap_or_default()
+    }
+}
+
+/// Registry entry of a token, learned from the proxy canister and the token ledger at pool creation
+/// and on every refresh.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Default)]
+pub struct TokenMetadata {
+    /// Missing for tokens whose decimals were stored before metadata was cached.
+    #[n(0)]
+    pub symbol: Option<String>,
+    #[n(1)]
+    pub decimals: u8,
+    /// Price of one whole token, kept from the last successful refresh when the proxy has none.
+    #[n(2)]
+    pub usd_price: Option<f64>,
+    /// When `usd_price` was fetched, in nanoseconds.
+    #[n(3)]
+    pub price_updated_at: Option<u64>,
+    #[n(4)]
+    pub name: Option<String>,
+    #[n(5)]
+    pub logo: Option<String>,
+    /// Names of the standards reported by `icrc1_supported_standards`, e.g. "ICRC-2".
+    #[n(6)]
+    pub standards: Option<Vec<String>>,
+    /// Fee charged by the ledger on every transfer, used for withdrawals and refunds and updated
+    /// whenever the ledger rejects a transfer with `BadFee`.
+    #[cbor(n(7), with = "crate::cbor::u256::option")]
+    pub transfer_fee: Option<U256>,
+    /// Last successful refresh of the entry, in nanoseconds.
+    #[n(8)]
+    pub refreshed_at: Option<u64>,
+}
+
+/// Where the ledger of a token is validated before a pool is created with it.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ValidationStrategy {
+    /// Only through the proxy canister, which also provides the usd price of the token.
+    #[n(0)]
+    Proxy,
+    /// Only by querying the ledger, the token has no usd price until the proxy knows it.
+    #[n(1)]
+    Direct,
+    /// Through the proxy canister, falling back to the ledger when the proxy is unavailable or
+    /// does not know the token.
+    #[default]
+    #[n(2)]
+    ProxyThenDirect,
+}
+
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct TokenValidationConfig {
+    #[n(0)]
+    pub strategy: ValidationStrategy,
+    /// Queries a zero allowance from the ledger during direct validation, making sure the ICRC-2
+    /// endpoints the DEX uses answer and not only the reported standards.
+    #[n(1)]
+    pub probe_allowance: bool,
+}
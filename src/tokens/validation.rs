@@ -0,0 +1,140 @@
+// Validation of a token ledger before a pool is created with it, through the proxy canister or by
+// querying the ledger directly when the proxy is unavailable or does not know the token, see
+// `TokenValidationConfig`
+
+use candid::{Nat, Principal};
+use ic_canister_log::log;
+use icrc_ledger_types::{
+    icrc::generic_metadata_value::MetadataValue, icrc2::allowance::AllowanceArgs,
+};
+
+use crate::{
+    icrc_client::LedgerClient,
+    logs::INFO,
+    proxy_canister::{validate_icrc_ledger, CandidIcpToken, IcpTokenType},
+};
+
+use super::{
+    metadata::fetch_token_standards,
+    types::{TokenValidationConfig, ValidationStrategy},
+};
+
+/// The standard every directly validated ledger should report, deposits rely on `transfer_from`.
+pub const ICRC2_STANDARD: &str = "ICRC-2";
+
+/// A token that passed validation, with the standards reported by its ledger when it answered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidatedToken {
+    pub data: CandidIcpToken,
+    pub standards: Option<Vec<String>>,
+}
+
+/// Validates the ledger of a token with the configured strategy.
+pub async fn validate_token(
+    token: Principal,
+    config: TokenValidationConfig,
+) -> Result<ValidatedToken, String> {
+    match config.strategy {
+        ValidationStrategy::Proxy => validate_with_proxy(token).await,
+        ValidationStrategy::Direct => validate_with_ledger(token, config.probe_allowance).await,
+        ValidationStrategy::ProxyThenDirect => match validate_with_proxy(token).await {
+            Ok(validated) => Ok(validated),
+            Err(error) => {
+                log!(
+                    INFO,
+                    "[validate_token]: proxy failed to validate {token}, querying the ledger: {error}"
+                );
+                validate_with_ledger(token, config.probe_allowance).await
+            }
+        },
+    }
+}
+
+/// Fetches the registry entry of a token on refresh. The usd price is only known by the proxy, so
+/// the proxy is asked first whatever the strategy, and the ledger is only queried for the metadata,
+/// fee and standards when the proxy fails and the strategy allows it, keeping the previous price.
+pub async fn refresh_token(
+    token: Principal,
+    config: TokenValidationConfig,
+) -> Result<ValidatedToken, String> {
+    match validate_with_proxy(token).await {
+        Ok(validated) => Ok(validated),
+        Err(error) if config.strategy == ValidationStrategy::Proxy => Err(error),
+        Err(error) => {
+            log!(
+                INFO,
+                "[refresh_token]: proxy failed to refresh {token}, querying the ledger: {error}"
+            );
+            validate_with_ledger(token, config.probe_allowance).await
+        }
+    }
+}
+
+async fn validate_with_proxy(token: Principal) -> Result<ValidatedToken, String> {
+    let data = validate_icrc_ledger(token)
+        .await
+        .map_err(|error| error.to_string())?;
+    Ok(ValidatedToken {
+        data,
+        standards: fetch_token_standards(token).await,
+    })
+}
+
+// The ledger should report ICRC-2 and answer `icrc1_fee`, `icrc1_decimals` and `icrc1_metadata`
+// with a name and symbol. The token has no usd price, `refresh_token` asks the proxy for it
+async fn validate_with_ledger(
+    token: Principal,
+    probe_allowance: bool,
+) -> Result<ValidatedToken, String> {
+    let ledger = LedgerClient::new(token);
+
+    let standards = ledger.supported_standards().await?;
+    if !standards.iter().any(|standard| standard == ICRC2_STANDARD) {
+        return Err(format!("{token} does not support {ICRC2_STANDARD}"));
+    }
+
+    let fee = ledger.icrc_fee().await?;
+    let decimals = ledger.decimals().await?;
+    let metadata = ledger.metadata().await?;
+    let name = text_metadata(&metadata, "icrc1:name")
+        .ok_or_else(|| format!("{token} has no icrc1:name metadata"))?;
+    let symbol = text_metadata(&metadata, "icrc1:symbol")
+        .ok_or_else(|| format!("{token} has no icrc1:symbol metadata"))?;
+    let logo = text_metadata(&metadata, "icrc1:logo").unwrap_or_default();
+
+    // nobody approved the anonymous principal on behalf of the DEX, anything but zero means the
+    // ledger does not implement allowances properly
+    if probe_allowance {
+        let allowance = ledger
+            .allowance(AllowanceArgs {
+                account: ic_cdk::id().into(),
+                spender: Principal::anonymous().into(),
+            })
+            .await?;
+        if allowance.allowance != Nat::from(0_u8) {
+            return Err(format!("{token} reported a non zero allowance"));
+        }
+    }
+
+    Ok(ValidatedToken {
+        data: CandidIcpToken {
+            ledger_id: token,
+            name,
+            decimals,
+            symbol,
+            token_type: IcpTokenType::ICRC2,
+            logo,
+            usd_price: String::new(),
+            fee,
+            rank: None,
+        },
+        standards: Some(standards),
+    })
+}
+
+fn text_metadata(metadata: &[(String, MetadataValue)], key: &str) -> Option<String> {
+    metadata.iter().find_map(|(name, value)| match value {
+        MetadataValue::Text(text) if name == key => Some(text.clone()),
+        _ => None,
+    })
+}
@@ -0,0 +1,46 @@
+use ethnum::{I256, U256};
+
+use crate::{
+    candid_types::pool::{DonateArgs, DonateError},
+    libraries::safe_cast::big_uint_to_u256,
+    pool::types::PoolId,
+    state::read_state,
+};
+
+pub struct ValidatedDonateArgs {
+    pub pool_id: PoolId,
+    pub amount0: U256,
+    pub amount1: U256,
+}
+
+pub fn validate_donate_args(args: DonateArgs) -> Result<ValidatedDonateArgs, DonateError> {
+    let pool_id: PoolId = args
+        .pool
+        .try_into()
+        .map_err(|_e| DonateError::InvalidPoolFee)?;
+
+    let pool = read_state(|s| s.get_pool(&pool_id)).ok_or(DonateError::PoolNotInitialized)?;
+
+    let amount0 = big_uint_to_u256(args.amount0.0).map_err(|_e| DonateError::InvalidAmount)?;
+    let amount1 = big_uint_to_u256(args.amount1.0).map_err(|_e| DonateError::InvalidAmount)?;
+
+    // amounts should fit in I256 like every other balance change
+    if I256::try_from(amount0).is_err() || I256::try_from(amount1).is_err() {
+        return Err(DonateError::InvalidAmount);
+    }
+
+    if amount0 == 0 && amount1 == 0 {
+        return Err(DonateError::InvalidAmount);
+    }
+
+    // donations are distributed to in range liquidity through fee growth
+    if pool.liquidity == 0 {
+        return Err(DonateError::NoInRangeLiquidity);
+    }
+
+    Ok(ValidatedDonateArgs {
+        pool_id,
+        amount0,
+        amount1,
+    })
+}
@@ -1,5 +1,6 @@
 pub mod burn_args;
 pub mod decrease_args;
+pub mod donate_args;
 pub mod increase_args;
 pub mod mint_args;
 pub mod swap_args;
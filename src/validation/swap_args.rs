@@ -99,6 +99,24 @@ impl ValidatedSwapArgs {
             } => *amount_in_maximum,
         }
     }
+
+    /// Lowers the input amount, or the maximum input for exact output swaps, to the amount that
+    /// was actually received, since fee-on-transfer tokens arrive reduced.
+    pub fn cap_deposit_amount(&mut self, received_amount: I256) {
+        match self {
+            ValidatedSwapArgs::ExactInputSingle { amount_in, .. }
+            | ValidatedSwapArgs::ExactInput { amount_in, .. } => {
+                *amount_in = (*amount_in).min(received_amount)
+            }
+            ValidatedSwapArgs::ExactOutputSingle {
+                amount_in_maximum, ..
+            }
+            | ValidatedSwapArgs::ExactOutput {
+                amount_in_maximum, ..
+            } => *amount_in_maximum = (*amount_in_maximum).min(received_amount),
+        }
+    }
+
     pub fn token_in(&self) -> Principal {
         match self {
             ValidatedSwapArgs::ExactInputSingle { token_in, .. } => *token_in,
@@ -117,6 +135,18 @@ impl ValidatedSwapArgs {
         }
     }
 
+    /// Pools the swap goes through, in path order.
+    pub fn pool_ids(&self) -> Vec<PoolId> {
+        match self {
+            ValidatedSwapArgs::ExactInputSingle { pool_id, .. }
+            | ValidatedSwapArgs::ExactOutputSingle { pool_id, .. } => vec![pool_id.clone()],
+            ValidatedSwapArgs::ExactInput { path, .. }
+            | ValidatedSwapArgs::ExactOutput { path, .. } => {
+                path.iter().map(|swap| swap.pool_id.clone()).collect()
+            }
+        }
+    }
+
     pub fn from_subaccount(&self) -> Option<Subaccount> {
         match self {
             ValidatedSwapArgs::ExactInputSingle {